@@ -0,0 +1,316 @@
+//! Resolution of the `extends` field of agent configs.
+//!
+//! An agent config can extend another config, given either by agent name or by path. Parents are
+//! merged into their children at the json level, before the config is deserialized into an
+//! [Agent](super::Agent):
+//!
+//! - `tools`, `allowedTools` and `resources` are appended to the inherited lists. An entry of the
+//!   form `"!value"` removes `value` from the inherited list instead.
+//! - `hooks` are appended to the inherited hooks of the same trigger.
+//! - `mcpServers` and `toolAliases` replace inherited entries of the same name.
+//! - `toolsSettings` are merged recursively. Nested lists and values are replaced.
+//! - Every other field overrides the inherited one.
+//!
+//! Entries of `mcpServers`, `toolAliases`, `hooks` and `toolsSettings` that are set to `null`
+//! remove the inherited entry.
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::AgentConfigError;
+use crate::os::Os;
+use crate::util::directories;
+
+/// Reads the agent config at `path` and merges every config it extends into it. The returned config
+/// no longer has an `extends` field.
+pub async fn resolve(os: &Os, path: &Path) -> Result<Value, AgentConfigError> {
+    let mut configs = chain(os, path).await?.into_iter().rev().map(|(_, config)| config);
+    let root = configs.next().unwrap_or_default();
+    let mut merged = configs.fold(root, merge);
+    merged.remove("extends");
+
+    Ok(Value::Object(merged))
+}
+
+/// Reads the agent config at `path` and every config it extends, as they are written. The configs
+/// go from the given one up to the root of the chain, along with their canonical paths.
+pub async fn chain(os: &Os, path: &Path) -> Result<Vec<(PathBuf, Map<String, Value>)>, AgentConfigError> {
+    let mut chain = Vec::<(PathBuf, Map<String, Value>)>::new();
+    let mut path = path.to_path_buf();
+
+    loop {
+        let content = os.fs.read(&path).await?;
+        let config =
+            serde_json::from_slice::<Map<String, Value>>(&content).map_err(|error| AgentConfigError::InvalidJson {
+                error,
+                path: path.clone(),
+            })?;
+        let canonical_path = os.fs.canonicalize(&path).await.unwrap_or_else(|_err| path.clone());
+        let parent = match config.get("extends") {
+            Some(Value::String(parent)) => Some(parent.clone()),
+            _ => None,
+        };
+        chain.push((canonical_path, config));
+
+        match parent {
+            Some(parent) => path = locate(os, &path, &parent, &chain).await?,
+            None => return Ok(chain),
+        }
+    }
+}
+
+/// Finds the config that `parent` refers to from the config at `path`.
+///
+/// Paths are relative to the directory of the extending config. Names are looked up next to the
+/// extending config first, then in the workspace and global agent directories. Configs that are
+/// already part of the chain are skipped, which lets a workspace agent extend the global agent of
+/// the same name.
+async fn locate(
+    os: &Os,
+    path: &Path,
+    parent: &str,
+    chain: &[(PathBuf, Map<String, Value>)],
+) -> Result<PathBuf, AgentConfigError> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let is_path = parent.ends_with(".json") || parent.contains('/') || parent.contains(std::path::MAIN_SEPARATOR);
+
+    let candidates = if is_path {
+        vec![dir.join(directories::canonicalizes_path(os, parent)?)]
+    } else {
+        let file_name = format!("{parent}.json");
+        let mut candidates = vec![dir.join(&file_name)];
+        if let Ok(local_dir) = directories::chat_local_agent_dir(os) {
+            candidates.push(local_dir.join(&file_name));
+        }
+        candidates.push(directories::chat_global_agent_path(os)?.join(&file_name));
+        candidates
+    };
+
+    let mut in_chain = false;
+    for candidate in candidates {
+        if !os.fs.exists(&candidate) {
+            continue;
+        }
+        let canonical_path = os.fs.canonicalize(&candidate).await?;
+        if chain.iter().any(|(path, _)| *path == canonical_path) {
+            in_chain = true;
+            continue;
+        }
+        return Ok(candidate);
+    }
+
+    if in_chain {
+        let mut names = chain
+            .iter()
+            .map(|(path, _)| path.display().to_string())
+            .collect::<Vec<_>>();
+        names.push(parent.to_string());
+        return Err(AgentConfigError::ExtendsCycle {
+            path: path.to_path_buf(),
+            chain: names.join(" -> "),
+        });
+    }
+
+    Err(AgentConfigError::ExtendsNotFound {
+        path: path.to_path_buf(),
+        parent: parent.to_string(),
+    })
+}
+
+/// Merges `child` into `parent` following the rules described in the module docs.
+fn merge(mut parent: Map<String, Value>, child: Map<String, Value>) -> Map<String, Value> {
+    for (key, value) in child {
+        let inherited = parent.remove(&key);
+        let value = match key.as_str() {
+            "tools" | "allowedTools" | "resources" => merge_lists(inherited, value),
+            "hooks" => merge_maps(inherited, value, merge_lists),
+            "mcpServers" | "toolAliases" => merge_maps(inherited, value, |_, value| value),
+            "toolsSettings" => merge_maps(inherited, value, merge_settings),
+            _ => value,
+        };
+        parent.insert(key, value);
+    }
+
+    parent
+}
+
+/// Appends the entries of `value` that are not inherited yet, and removes the inherited entries
+/// named by `"!entry"`.
+fn merge_lists(inherited: Option<Value>, value: Value) -> Value {
+    let Value::Array(entries) = value else {
+        return value;
+    };
+    let mut merged = match inherited {
+        Some(Value::Array(inherited)) => inherited,
+        _ => Vec::new(),
+    };
+
+    for entry in entries {
+        match entry.as_str().and_then(|entry| entry.strip_prefix('!')) {
+            Some(removed) => merged.retain(|inherited| inherited.as_str() != Some(removed)),
+            None if !merged.contains(&entry) => merged.push(entry),
+            None => {},
+        }
+    }
+
+    Value::Array(merged)
+}
+
+/// Merges each entry of `value` into the inherited entry of the same key with `merge_entry`.
+/// Entries set to `null` remove the inherited entry.
+fn merge_maps(inherited: Option<Value>, value: Value, merge_entry: fn(Option<Value>, Value) -> Value) -> Value {
+    let Value::Object(entries) = value else {
+        return value;
+    };
+    let mut merged = match inherited {
+        Some(Value::Object(inherited)) => inherited,
+        _ => Map::new(),
+    };
+
+    for (key, value) in entries {
+        let inherited = merged.remove(&key);
+        if !value.is_null() {
+            merged.insert(key, merge_entry(inherited, value));
+        }
+    }
+
+    Value::Object(merged)
+}
+
+fn merge_settings(inherited: Option<Value>, value: Value) -> Value {
+    if value.is_object() {
+        merge_maps(inherited, value, merge_settings)
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn write(os: &Os, path: &str, config: Value) {
+        let path = PathBuf::from(path);
+        os.fs.create_dir_all(path.parent().unwrap()).await.unwrap();
+        os.fs.write(&path, config.to_string()).await.unwrap();
+    }
+
+    #[test]
+    fn test_merge() {
+        let parent = json!({
+            "name": "base",
+            "description": "Base agent",
+            "tools": ["fs_read", "execute_bash", "@git"],
+            "allowedTools": ["fs_read"],
+            "mcpServers": {
+                "git": { "command": "git-mcp" },
+                "fetch": { "command": "fetch" }
+            },
+            "toolsSettings": {
+                "execute_bash": { "allowedCommands": ["ls"], "autoAllowReadonly": true },
+                "use_aws": { "allowedServices": ["s3"] }
+            },
+            "hooks": {
+                "agentSpawn": [{ "command": "git status" }]
+            },
+            "model": "base-model"
+        });
+        let child = json!({
+            "name": "child",
+            "extends": "base",
+            "tools": ["!execute_bash", "fs_write", "fs_read"],
+            "mcpServers": {
+                "fetch": null,
+                "git": { "command": "other-git-mcp" }
+            },
+            "toolsSettings": {
+                "execute_bash": { "allowedCommands": ["pwd"] },
+                "use_aws": null
+            },
+            "hooks": {
+                "agentSpawn": [{ "command": "ls" }]
+            },
+            "model": null
+        });
+
+        let (Value::Object(parent), Value::Object(child)) = (parent, child) else {
+            unreachable!()
+        };
+        let merged = Value::Object(merge(parent, child));
+
+        assert_eq!(merged["name"], "child");
+        assert_eq!(merged["description"], "Base agent");
+        assert_eq!(merged["tools"], json!(["fs_read", "@git", "fs_write"]));
+        assert_eq!(merged["allowedTools"], json!(["fs_read"]));
+        assert_eq!(merged["mcpServers"], json!({ "git": { "command": "other-git-mcp" } }));
+        assert_eq!(
+            merged["toolsSettings"],
+            json!({ "execute_bash": { "allowedCommands": ["pwd"], "autoAllowReadonly": true } })
+        );
+        assert_eq!(
+            merged["hooks"],
+            json!({ "agentSpawn": [{ "command": "git status" }, { "command": "ls" }] })
+        );
+        assert_eq!(merged["model"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_chain() {
+        let os = Os::new().await.unwrap();
+        write(
+            &os,
+            "/agents/base.json",
+            json!({ "name": "base", "tools": ["fs_read"], "prompt": "base" }),
+        )
+        .await;
+        write(
+            &os,
+            "/agents/middle.json",
+            json!({ "name": "middle", "extends": "base", "tools": ["fs_write"] }),
+        )
+        .await;
+        write(
+            &os,
+            "/agents/nested/child.json",
+            json!({ "name": "child", "extends": "../middle.json", "tools": ["!fs_read"] }),
+        )
+        .await;
+
+        let resolved = resolve(&os, Path::new("/agents/nested/child.json")).await.unwrap();
+        assert_eq!(
+            resolved,
+            json!({ "name": "child", "tools": ["fs_write"], "prompt": "base" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_errors() {
+        let os = Os::new().await.unwrap();
+        write(&os, "/agents/a.json", json!({ "name": "a", "extends": "b" })).await;
+        write(&os, "/agents/b.json", json!({ "name": "b", "extends": "a" })).await;
+        write(
+            &os,
+            "/agents/orphan.json",
+            json!({ "name": "orphan", "extends": "missing" }),
+        )
+        .await;
+
+        assert!(matches!(
+            resolve(&os, Path::new("/agents/a.json")).await,
+            Err(AgentConfigError::ExtendsCycle { .. })
+        ));
+        assert!(matches!(
+            resolve(&os, Path::new("/agents/orphan.json")).await,
+            Err(AgentConfigError::ExtendsNotFound { .. })
+        ));
+    }
+}
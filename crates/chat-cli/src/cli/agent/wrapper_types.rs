@@ -78,6 +78,175 @@ pub fn tool_settings_schema(generator: &mut SchemaGenerator) -> Schema {
 
     json_schema!({
         "type": "object",
+        "properties": {
+            "execute_bash": {
+                "type": "object",
+                "description": "Settings for the execute_bash tool",
+                "properties": {
+                    "allowedCommands": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Commands that are allowed without prompting. Regexes anchored with \\A and \\z"
+                    },
+                    "deniedCommands": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Commands that are denied. Regexes anchored with \\A and \\z"
+                    },
+                    "commandRules": {
+                        "type": "array",
+                        "description": "Rules for the programs a command runs, checked in every part of the command. The strictest matching rule wins",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "command": { "type": "string", "description": "Name of the program, e.g. git" },
+                                "subcommands": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Only match when the arguments that aren't flags start with one of these"
+                                },
+                                "flags": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Only match when one of these flags is passed"
+                                },
+                                "action": { "type": "string", "enum": ["allow", "ask", "deny"] },
+                                "reason": { "type": "string", "description": "Explanation shown when the rule denies a command" }
+                            },
+                            "required": ["command", "action"]
+                        }
+                    },
+                    "autoAllowReadonly": {
+                        "type": "boolean",
+                        "description": "Whether to allow read-only commands without prompting"
+                    },
+                    "timeoutSeconds": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Kill commands that are still running after this many seconds, along with every process they started. On Unix, these commands run with stdin redirected from /dev/null"
+                    },
+                    "maxOutputBytes": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of bytes of stdout and of stderr returned to the model"
+                    },
+                    "truncationStrategy": {
+                        "type": "string",
+                        "enum": ["head", "tail"],
+                        "description": "Whether the beginning or the end of long output is kept"
+                    },
+                    "env": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Environment variables set for every command"
+                    },
+                    "sandbox": {
+                        "type": "object",
+                        "description": "Run commands in a sandbox on Linux and allow them without confirmation",
+                        "properties": {
+                            "enabled": { "type": "boolean", "default": false },
+                            "writablePaths": {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Paths commands can write to. Everything else is read-only"
+                            },
+                            "allowNetwork": {
+                                "type": "boolean",
+                                "default": false,
+                                "description": "Let commands reach the network. Commands then prompt as usual"
+                            },
+                            "privateTmp": {
+                                "type": "boolean",
+                                "default": true,
+                                "description": "Give every command an empty /tmp of its own"
+                            }
+                        }
+                    }
+                }
+            },
+            "use_aws": {
+                "type": "object",
+                "description": "Settings for the use_aws tool",
+                "properties": {
+                    "allowedServices": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Services that are allowed without prompting"
+                    },
+                    "deniedServices": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Services that are denied"
+                    },
+                    "autoAllowReadonly": {
+                        "type": "boolean",
+                        "description": "Whether to allow read-only operations without prompting"
+                    },
+                    "rules": {
+                        "type": "array",
+                        "description": "Rules for calls matching a service, operation, region, profile and parameter values. Fields accept globs. The strictest matching rule wins",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "service": { "type": "string", "description": "Service name, e.g. s3api" },
+                                "operation": { "type": "string", "description": "Operation name, e.g. put-object" },
+                                "region": { "type": "string" },
+                                "profile": { "type": "string", "description": "Profile the call is made with. Defaults to AWS_PROFILE, or default" },
+                                "parameters": {
+                                    "type": "object",
+                                    "additionalProperties": {
+                                        "oneOf": [
+                                            { "type": "string" },
+                                            { "type": "array", "items": { "type": "string" } }
+                                        ]
+                                    },
+                                    "description": "Parameters that must be passed with a value matching one of the patterns"
+                                },
+                                "action": { "type": "string", "enum": ["allow", "ask", "deny"] },
+                                "reason": { "type": "string", "description": "Explanation shown when the rule denies a call" }
+                            },
+                            "required": ["action"]
+                        }
+                    }
+                }
+            },
+            "git": {
+                "type": "object",
+                "description": "Settings for the git tool",
+                "properties": {
+                    "rules": {
+                        "type": "array",
+                        "description": "Rules for operations matching an operation name and branch. Fields accept globs. The strictest matching rule wins",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "operation": { "type": "string", "description": "Operation name, e.g. commit" },
+                                "branch": { "type": "string", "description": "Branch being checked out, or the current branch for other operations" },
+                                "action": { "type": "string", "enum": ["allow", "ask", "deny"] },
+                                "reason": { "type": "string", "description": "Explanation shown when the rule denies an operation" }
+                            },
+                            "required": ["action"]
+                        }
+                    }
+                }
+            },
+            "delegate": {
+                "type": "object",
+                "description": "Settings for the delegate tool",
+                "properties": {
+                    "allowedAgents": {
+                        "type": "array",
+                        "description": "Agents that tasks can be delegated to without prompting. Accepts globs",
+                        "items": { "type": "string" }
+                    },
+                    "deniedAgents": {
+                        "type": "array",
+                        "description": "Agents that tasks can never be delegated to. Accepts globs",
+                        "items": { "type": "string" }
+                    }
+                }
+            }
+        },
         "additionalProperties": {
             "type": "object",
             "description": "Settings for tools. Refer to our documentations to see how to configure them"
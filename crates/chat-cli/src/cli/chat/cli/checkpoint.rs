@@ -0,0 +1,141 @@
+use clap::Subcommand;
+use crossterm::execute;
+use crossterm::style::{
+    self,
+    Color,
+};
+
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+
+/// Save and restore named snapshots of the conversation
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum CheckpointSubcommand {
+    /// Save the current conversation history, transcript and context files as a checkpoint
+    Save {
+        /// Name of the checkpoint. Saving with an existing name replaces that checkpoint. Defaults
+        /// to checkpoint-<n>
+        name: Option<String>,
+    },
+    /// List the checkpoints saved in this session
+    List,
+    /// Roll the conversation back to a checkpoint
+    Restore {
+        /// Name of the checkpoint to restore
+        name: String,
+    },
+}
+
+impl CheckpointSubcommand {
+    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        match self {
+            Self::Save { name } => {
+                let name =
+                    name.unwrap_or_else(|| format!("checkpoint-{}", session.conversation.checkpoints().len() + 1));
+                let replaced = session.conversation.save_checkpoint(name.clone());
+                let message_count = session
+                    .conversation
+                    .checkpoints()
+                    .last()
+                    .map(|checkpoint| checkpoint.message_count())
+                    .unwrap_or_default();
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n{} checkpoint ", if replaced { "Replaced" } else { "Saved" })),
+                    style::SetForegroundColor(Color::Cyan),
+                    style::Print(&name),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!(" with {} messages.\n\n", message_count)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            Self::List => {
+                if session.conversation.checkpoints().is_empty() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo checkpoints saved. Create one with /checkpoint save <name>\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                } else {
+                    execute!(session.stderr, style::Print("\n"))?;
+                    for checkpoint in session.conversation.checkpoints() {
+                        execute!(
+                            session.stderr,
+                            style::SetForegroundColor(Color::Cyan),
+                            style::Print(format!("{:<24}", checkpoint.name)),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print(format!(" {}", checkpoint.created_at.format("%m/%d %H:%M:%S"))),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!(
+                                "  {} messages, ~{} tokens\n",
+                                checkpoint.message_count(),
+                                checkpoint.token_count()
+                            )),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
+                    execute!(session.stderr, style::Print("\n"))?;
+                }
+            },
+            Self::Restore { name } => {
+                if session.conversation.is_in_tangent_mode() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print("\nExit tangent mode with /tangent before restoring a checkpoint.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        skip_printing_tools: true,
+                    });
+                }
+
+                let Some(checkpoint) = session.conversation.restore_checkpoint(&name) else {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nNo checkpoint named '{name}'. See /checkpoint list\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        skip_printing_tools: true,
+                    });
+                };
+                let created_at = checkpoint.created_at.format("%m/%d %H:%M:%S").to_string();
+
+                // Any tool uses awaiting approval belong to the discarded part of the conversation.
+                session.tool_uses.clear();
+                session.pending_tool_index = None;
+
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print("\nRestored conversation to checkpoint "),
+                    style::SetForegroundColor(Color::Cyan),
+                    style::Print(&name),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(" (saved {created_at}).\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Save { .. } => "save",
+            Self::List => "list",
+            Self::Restore { .. } => "restore",
+        }
+    }
+}
@@ -1,12 +1,20 @@
-use clap::Args;
+use std::collections::VecDeque;
+
+use clap::{
+    Args,
+    ValueEnum,
+};
+use tracing::warn;
 
 use crate::cli::chat::consts::MAX_USER_MESSAGE_SIZE;
+use crate::cli::chat::conversation::HistoryEntry;
 use crate::cli::chat::message::UserMessageContent;
 use crate::cli::chat::{
     ChatError,
     ChatSession,
     ChatState,
 };
+use crate::database::settings::Setting;
 use crate::os::Os;
 
 #[deny(missing_docs)]
@@ -28,8 +36,16 @@ How it works
 • Clears the conversation history to free up space
 • The assistant will reference the summary context in future responses
 
+Strategies
+• summary              Replace the history with a single summary (default)
+• prune-tool-results   Replace the output of older tool uses with a placeholder, keeping
+                       the dialogue. Falls back to a summary once there is nothing to prune
+• rolling-window       Summarize only the oldest half of the history
+• hierarchical         Summarize the history, keeping previous summaries as they are
+
 Compaction will be automatically performed whenever the context window overflows.
-To disable this behavior, run: `q settings chat.disableAutoCompaction true`"
+To disable this behavior, run: `q settings chat.disableAutoCompaction true`
+To change the default strategy, run: `q settings chat.compactionStrategy <strategy>`"
 )]
 /// Arguments for the `/compact` command that summarizes conversation history to free up context
 /// space.
@@ -52,6 +68,9 @@ pub struct CompactArgs {
     /// truncate_large_messages to be set.
     #[arg(long, requires = "truncate_large_messages")]
     max_message_length: Option<usize>,
+    /// How the history is compacted. Defaults to the chat.compactionStrategy setting, or summary.
+    #[arg(long, value_enum)]
+    strategy: Option<CompactionKind>,
 }
 
 impl CompactArgs {
@@ -74,6 +93,7 @@ impl CompactArgs {
                 max_message_length: self.max_message_length.map_or(default.max_message_length, |v| {
                     v.clamp(UserMessageContent::TRUNCATED_SUFFIX.len(), MAX_USER_MESSAGE_SIZE)
                 }),
+                kind: self.strategy.unwrap_or_else(|| CompactionKind::from_settings(os)),
             })
             .await
     }
@@ -88,6 +108,8 @@ pub struct CompactStrategy {
     pub truncate_large_messages: bool,
     /// Maximum allowed size of messages in the conversation history.
     pub max_message_length: usize,
+    /// Which [Compactor] to use.
+    pub kind: CompactionKind,
 }
 
 impl Default for CompactStrategy {
@@ -96,6 +118,169 @@ impl Default for CompactStrategy {
             messages_to_exclude: Default::default(),
             truncate_large_messages: Default::default(),
             max_message_length: MAX_USER_MESSAGE_SIZE,
+            kind: Default::default(),
+        }
+    }
+}
+
+/// The built-in [Compactor] implementations, selectable with `/compact --strategy` or the
+/// `chat.compactionStrategy` setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompactionKind {
+    /// Replace the history with a single summary generated by the model
+    #[default]
+    Summary,
+    /// Replace the output of older tool uses with a placeholder, keeping the dialogue
+    PruneToolResults,
+    /// Summarize only the oldest half of the history
+    RollingWindow,
+    /// Summarize the history, keeping previous summaries as they are
+    Hierarchical,
+}
+
+impl CompactionKind {
+    /// The kind configured with the `chat.compactionStrategy` setting.
+    pub fn from_settings(os: &Os) -> Self {
+        let Some(value) = os.database.settings.get_string(Setting::ChatCompactionStrategy) else {
+            return Self::default();
+        };
+        <Self as ValueEnum>::from_str(&value, true).unwrap_or_else(|_| {
+            warn!(?value, "invalid {}, using the default", Setting::ChatCompactionStrategy);
+            Self::default()
+        })
+    }
+
+    pub fn compactor(self) -> Box<dyn Compactor> {
+        match self {
+            Self::Summary => Box::new(SummaryCompactor),
+            Self::PruneToolResults => Box::new(PruneToolResultsCompactor::default()),
+            Self::RollingWindow => Box::new(RollingWindowCompactor),
+            Self::Hierarchical => Box::new(HierarchicalCompactor),
+        }
+    }
+}
+
+impl std::fmt::Display for CompactionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Decides how the conversation history is compacted.
+///
+/// Compaction happens in two steps: first the oldest [Compactor::entries_to_summarize] history
+/// entries are summarized by the model, then [Compactor::compact] updates the history. Strategies
+/// that do not need a summary skip the model request entirely.
+pub trait Compactor: std::fmt::Debug + Send + Sync {
+    /// Number of the oldest history entries that should be summarized by the model, or [None] if
+    /// the history should be compacted without a summary.
+    fn entries_to_summarize(&self, history: &VecDeque<HistoryEntry>, strategy: &CompactStrategy) -> Option<usize>;
+
+    /// Whether a new summary replaces the previous one. If `false`, the previous summary is left
+    /// out of the summary request and the new summary is appended to it instead.
+    fn replaces_summary(&self) -> bool {
+        true
+    }
+
+    /// Updates the history after the oldest `summarized` entries were summarized, or without a
+    /// summary if `summarized` is [None].
+    fn compact(&self, history: &mut VecDeque<HistoryEntry>, summarized: Option<usize>, _strategy: &CompactStrategy) {
+        if let Some(summarized) = summarized {
+            history.drain(..summarized.min(history.len()));
+        }
+    }
+}
+
+/// Summarizes everything except the excluded messages.
+#[derive(Debug, Clone, Copy)]
+pub struct SummaryCompactor;
+
+impl Compactor for SummaryCompactor {
+    fn entries_to_summarize(&self, history: &VecDeque<HistoryEntry>, strategy: &CompactStrategy) -> Option<usize> {
+        Some(history.len().saturating_sub(strategy.messages_to_exclude))
+    }
+}
+
+/// Like [SummaryCompactor], except that previous summaries are kept verbatim and the new summary
+/// only covers the messages since.
+#[derive(Debug, Clone, Copy)]
+pub struct HierarchicalCompactor;
+
+impl Compactor for HierarchicalCompactor {
+    fn entries_to_summarize(&self, history: &VecDeque<HistoryEntry>, strategy: &CompactStrategy) -> Option<usize> {
+        SummaryCompactor.entries_to_summarize(history, strategy)
+    }
+
+    fn replaces_summary(&self) -> bool {
+        false
+    }
+}
+
+/// Summarizes the oldest half of the history and keeps the rest verbatim.
+#[derive(Debug, Clone, Copy)]
+pub struct RollingWindowCompactor;
+
+impl Compactor for RollingWindowCompactor {
+    fn entries_to_summarize(&self, history: &VecDeque<HistoryEntry>, strategy: &CompactStrategy) -> Option<usize> {
+        let candidates = history.len().saturating_sub(strategy.messages_to_exclude);
+        // The history left after compaction must start with a prompt rather than tool results,
+        // otherwise it would be dropped when the conversation invariants are enforced.
+        Some(
+            (candidates.div_ceil(2)..candidates)
+                .find(|&i| !history[i].user().has_tool_use_results())
+                .unwrap_or(candidates),
+        )
+    }
+}
+
+/// Replaces the output of tool uses outside of the most recent messages with a placeholder,
+/// which usually frees the most context while keeping the dialogue intact. Once there is no tool
+/// output left to prune, the history is summarized instead.
+#[derive(Debug, Clone, Copy)]
+pub struct PruneToolResultsCompactor {
+    /// Number of the most recent history entries whose tool results are kept.
+    pub keep_recent: usize,
+}
+
+impl Default for PruneToolResultsCompactor {
+    fn default() -> Self {
+        Self { keep_recent: 4 }
+    }
+}
+
+impl PruneToolResultsCompactor {
+    fn prunable(&self, history_len: usize, strategy: &CompactStrategy) -> usize {
+        history_len.saturating_sub(self.keep_recent.max(strategy.messages_to_exclude))
+    }
+}
+
+impl Compactor for PruneToolResultsCompactor {
+    fn entries_to_summarize(&self, history: &VecDeque<HistoryEntry>, strategy: &CompactStrategy) -> Option<usize> {
+        let has_prunable = history
+            .iter()
+            .take(self.prunable(history.len(), strategy))
+            .filter_map(|entry| entry.user().tool_use_results())
+            .flatten()
+            .any(|result| !result.is_pruned());
+        if has_prunable {
+            None
+        } else {
+            SummaryCompactor.entries_to_summarize(history, strategy)
+        }
+    }
+
+    fn compact(&self, history: &mut VecDeque<HistoryEntry>, summarized: Option<usize>, strategy: &CompactStrategy) {
+        match summarized {
+            Some(summarized) => SummaryCompactor.compact(history, Some(summarized), strategy),
+            None => {
+                let prunable = self.prunable(history.len(), strategy);
+                for entry in history.iter_mut().take(prunable) {
+                    entry.prune_tool_use_results();
+                }
+            },
         }
     }
 }
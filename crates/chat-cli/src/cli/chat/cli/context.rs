@@ -16,7 +16,10 @@ use crate::cli::chat::context::{
     calc_max_context_files_size,
 };
 use crate::cli::chat::token_counter::TokenCounter;
-use crate::cli::chat::util::drop_matched_context_files;
+use crate::cli::chat::util::{
+    drop_matched_context_files,
+    truncate_safe,
+};
 use crate::cli::chat::{
     ChatError,
     ChatSession,
@@ -176,6 +179,44 @@ impl ContextSubcommand {
                     execute!(session.stderr, style::Print("\n"))?;
                 }
 
+                execute!(
+                    session.stderr,
+                    style::SetAttribute(Attribute::Bold),
+                    style::SetForegroundColor(Color::Magenta),
+                    style::Print("📌 Pinned messages (kept by /compact):\n"),
+                    style::SetAttribute(Attribute::Reset),
+                )?;
+
+                if session.conversation.pins().is_empty() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("    <none>\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                } else {
+                    let tokenizer = session.conversation.tokenizer();
+                    for (i, pin) in session.conversation.pins().iter().enumerate() {
+                        let content = if expand {
+                            pin.content.replace('\n', "\n       ")
+                        } else {
+                            let first_line = pin.content.lines().next().unwrap_or_default();
+                            match truncate_safe(first_line, 80) {
+                                truncated if truncated.len() < pin.content.len() => format!("{truncated}..."),
+                                truncated => truncated.to_string(),
+                            }
+                        };
+                        execute!(
+                            session.stderr,
+                            style::Print(format!("    {}. {} ", i + 1, content)),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!("(~{} tkns)\n", tokenizer.count_tokens(&pin.content))),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                    }
+                    execute!(session.stderr, style::Print("\n"))?;
+                }
+
                 if profile_context_files.is_empty() {
                     execute!(
                         session.stderr,
@@ -50,6 +50,11 @@ static AVAILABLE_EXPERIMENTS: &[Experiment] = &[
         description: "Enables Q to create todo lists that can be viewed and managed using /todos",
         setting_key: Setting::EnabledTodoList,
     },
+    Experiment {
+        name: "Persistent Shell",
+        description: "Keeps the working directory and environment between shell commands run by Q (/shell)",
+        setting_key: Setting::EnabledPersistentShell,
+    },
 ];
 
 #[derive(Debug, PartialEq, Args)]
@@ -0,0 +1,128 @@
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::execute;
+use crossterm::style::{
+    self,
+    Color,
+};
+
+use crate::cli::chat::tools::execute::JobStatus;
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+
+/// Arguments for the `/jobs` command that manages commands started in the background by
+/// `execute_bash`.
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Args)]
+#[command(
+    before_long_help = "Q can start long-running shell commands such as dev servers, watchers or test suites in the
+background and check on their output later. Background jobs are killed when the chat session ends.
+
+Usage
+• /jobs                 List the background jobs of this session
+• /jobs kill <id>       Kill a background job and everything it started"
+)]
+pub struct JobsArgs {
+    #[command(subcommand)]
+    subcommand: Option<JobsSubcommand>,
+}
+
+/// Subcommands for managing background jobs
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum JobsSubcommand {
+    /// List the background jobs of this session
+    List,
+    /// Kill a background job
+    Kill {
+        /// Id of the job as shown by /jobs
+        id: u32,
+    },
+}
+
+impl JobsArgs {
+    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        match self.subcommand.unwrap_or(JobsSubcommand::List) {
+            JobsSubcommand::List => {
+                let jobs = session.background_jobs.list().await;
+                if jobs.is_empty() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo background jobs.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        skip_printing_tools: true,
+                    });
+                }
+
+                execute!(session.stderr, style::Print("\n"))?;
+                for job in jobs {
+                    let status_color = match job.status {
+                        JobStatus::Running => Color::Green,
+                        JobStatus::Exited(_) | JobStatus::Killed => Color::DarkGrey,
+                    };
+                    let pid = job.pid.map(|pid| format!(", pid {pid}")).unwrap_or_default();
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Cyan),
+                        style::Print(format!("{}. ", job.id)),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(&job.command),
+                        style::SetForegroundColor(status_color),
+                        style::Print(format!("\n   {}", job.status)),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(
+                            " ({}s{pid}, {} bytes of output)\n",
+                            job.elapsed.as_secs(),
+                            job.output_len
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(session.stderr, style::Print("\n"))?;
+            },
+            JobsSubcommand::Kill { id } => match session.background_jobs.kill(id).await {
+                Ok(job) => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\nJob {id} {}.\n\n", job.status)),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+                Err(err) => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\n{err}. See /jobs\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn subcommand_name(&self) -> Option<&'static str> {
+        self.subcommand.as_ref().map(|s| s.name())
+    }
+}
+
+impl JobsSubcommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::List => "list",
+            Self::Kill { .. } => "kill",
+        }
+    }
+}
@@ -1,23 +1,30 @@
 pub mod changelog;
+pub mod checkpoint;
 pub mod clear;
 pub mod compact;
 pub mod context;
 pub mod editor;
 pub mod experiment;
 pub mod hooks;
+pub mod jobs;
 pub mod knowledge;
 pub mod mcp;
 pub mod model;
 pub mod persist;
+pub mod pin;
 pub mod profile;
 pub mod prompts;
+pub mod shell;
+pub mod subagents;
 pub mod subscribe;
 pub mod tangent;
 pub mod todos;
 pub mod tools;
+pub mod undo;
 pub mod usage;
 
 use changelog::ChangelogArgs;
+use checkpoint::CheckpointSubcommand;
 use clap::Parser;
 use clear::ClearArgs;
 use compact::CompactArgs;
@@ -25,15 +32,20 @@ use context::ContextSubcommand;
 use editor::EditorArgs;
 use experiment::ExperimentArgs;
 use hooks::HooksArgs;
+use jobs::JobsArgs;
 use knowledge::KnowledgeSubcommand;
 use mcp::McpArgs;
 use model::ModelArgs;
 use persist::PersistSubcommand;
+use pin::PinArgs;
 use profile::AgentSubcommand;
 use prompts::PromptsArgs;
+use shell::ShellArgs;
+use subagents::SubagentsArgs;
 use tangent::TangentArgs;
 use todos::TodoSubcommand;
 use tools::ToolsArgs;
+use undo::UndoArgs;
 
 use crate::cli::chat::cli::subscribe::SubscribeArgs;
 use crate::cli::chat::cli::usage::UsageArgs;
@@ -98,6 +110,19 @@ pub enum SlashCommand {
     /// chat.enableTangentMode true"
     #[command(hide = true)]
     Tangent(TangentArgs),
+    /// Save, list, and restore named snapshots of the conversation
+    #[command(subcommand)]
+    Checkpoint(CheckpointSubcommand),
+    /// Revert file changes made by the fs_write tool
+    Undo(UndoArgs),
+    /// Pin messages so they are kept verbatim when the conversation is compacted
+    Pin(PinArgs),
+    /// Show or reset the persistent shell used to run shell commands
+    Shell(ShellArgs),
+    /// List and kill commands started in the background by Q
+    Jobs(JobsArgs),
+    /// List tasks delegated to other agents and view their transcripts
+    Subagents(SubagentsArgs),
     #[command(flatten)]
     Persist(PersistSubcommand),
     // #[command(flatten)]
@@ -140,7 +165,7 @@ impl SlashCommand {
             Self::Knowledge(subcommand) => subcommand.execute(os, session).await,
             Self::PromptEditor(args) => args.execute(session).await,
             Self::Compact(args) => args.execute(os, session).await,
-            Self::Tools(args) => args.execute(session).await,
+            Self::Tools(args) => args.execute(os, session).await,
             Self::Issue(args) => {
                 if let Err(err) = args.execute(os).await {
                     return Err(ChatError::Custom(err.to_string().into()));
@@ -159,6 +184,12 @@ impl SlashCommand {
             Self::Experiment(args) => args.execute(os, session).await,
             Self::Subscribe(args) => args.execute(os, session).await,
             Self::Tangent(args) => args.execute(os, session).await,
+            Self::Checkpoint(subcommand) => subcommand.execute(session).await,
+            Self::Undo(args) => args.execute(os, session).await,
+            Self::Pin(args) => args.execute(session).await,
+            Self::Shell(args) => args.execute(os, session).await,
+            Self::Jobs(args) => args.execute(session).await,
+            Self::Subagents(args) => args.execute(session).await,
             Self::Persist(subcommand) => subcommand.execute(os, session).await,
             // Self::Root(subcommand) => {
             //     if let Err(err) = subcommand.execute(os, database, telemetry).await {
@@ -173,6 +204,32 @@ impl SlashCommand {
         }
     }
 
+    /// Whether the command reads from the terminal, e.g. to confirm, to select from a list or to
+    /// open an editor.
+    pub fn is_interactive(&self) -> bool {
+        match self {
+            Self::Clear(_)
+            | Self::PromptEditor(_)
+            | Self::Model(_)
+            | Self::Experiment(_)
+            | Self::Subscribe(_)
+            | Self::Undo(_)
+            | Self::Knowledge(KnowledgeSubcommand::Clear) => true,
+            Self::Agent(subcommand) => matches!(
+                subcommand,
+                AgentSubcommand::Create { .. }
+                    | AgentSubcommand::Edit { .. }
+                    | AgentSubcommand::Generate {}
+                    | AgentSubcommand::Swap { name: None }
+            ),
+            Self::Todos(subcommand) => matches!(
+                subcommand,
+                TodoSubcommand::Resume | TodoSubcommand::View | TodoSubcommand::Delete { all: false }
+            ),
+            _ => false,
+        }
+    }
+
     pub fn command_name(&self) -> &'static str {
         match self {
             Self::Quit => "quit",
@@ -194,9 +251,16 @@ impl SlashCommand {
             Self::Experiment(_) => "experiment",
             Self::Subscribe(_) => "subscribe",
             Self::Tangent(_) => "tangent",
+            Self::Checkpoint(_) => "checkpoint",
+            Self::Undo(_) => "undo",
+            Self::Pin(_) => "pin",
+            Self::Shell(_) => "shell",
+            Self::Jobs(_) => "jobs",
+            Self::Subagents(_) => "subagents",
             Self::Persist(sub) => match sub {
                 PersistSubcommand::Save { .. } => "save",
                 PersistSubcommand::Load { .. } => "load",
+                PersistSubcommand::Export { .. } => "export",
             },
             Self::Todos(_) => "todos",
         }
@@ -207,8 +271,13 @@ impl SlashCommand {
             SlashCommand::Agent(sub) => Some(sub.name()),
             SlashCommand::Context(sub) => Some(sub.name()),
             SlashCommand::Knowledge(sub) => Some(sub.name()),
+            SlashCommand::Checkpoint(sub) => Some(sub.name()),
             SlashCommand::Tools(arg) => arg.subcommand_name(),
             SlashCommand::Prompts(arg) => arg.subcommand_name(),
+            SlashCommand::Pin(arg) => arg.subcommand_name(),
+            SlashCommand::Shell(arg) => arg.subcommand_name(),
+            SlashCommand::Jobs(arg) => arg.subcommand_name(),
+            SlashCommand::Subagents(arg) => arg.subcommand_name(),
             _ => None,
         }
     }
@@ -15,6 +15,7 @@ use serde::{
 };
 
 use crate::api_client::Endpoint;
+use crate::cli::chat::token_counter::Tokenizer;
 use crate::cli::chat::{
     ChatError,
     ChatSession,
@@ -32,6 +33,10 @@ pub struct ModelInfo {
     /// Size of the model's context window, in tokens
     #[serde(default = "default_context_window")]
     pub context_window_tokens: usize,
+    /// Tokenizer used to count tokens for this model. Inferred from the model family when unset,
+    /// see [Self::tokenizer]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<Tokenizer>,
 }
 
 impl ModelInfo {
@@ -44,6 +49,7 @@ impl ModelInfo {
             model_id: model.model_id().to_string(),
             model_name: model.model_name().map(|s| s.to_string()),
             context_window_tokens,
+            tokenizer: None,
         }
     }
 
@@ -53,12 +59,31 @@ impl ModelInfo {
             model_id,
             model_name: None,
             context_window_tokens: 200_000,
+            tokenizer: None,
         }
     }
 
     pub fn display_name(&self) -> &str {
         self.model_name.as_deref().unwrap_or(&self.model_id)
     }
+
+    /// The tokenizer used to count tokens for this model.
+    ///
+    /// Claude's tokenizer is not public, so Claude models use `cl100k_base` which is the closest
+    /// bundled BPE vocabulary. Unknown model families fall back to the length heuristic.
+    pub fn tokenizer(&self) -> Tokenizer {
+        if let Some(tokenizer) = self.tokenizer {
+            return tokenizer;
+        }
+        let model_id = self.model_id.to_lowercase();
+        if model_id.contains("claude") {
+            Tokenizer::Cl100kBase
+        } else if model_id.contains("gpt") {
+            Tokenizer::O200kBase
+        } else {
+            Tokenizer::Heuristic
+        }
+    }
 }
 
 /// Command-line arguments for model selection operations
@@ -195,11 +220,13 @@ fn get_fallback_models() -> Vec<ModelInfo> {
             model_name: Some("claude-sonnet-4".to_string()),
             model_id: "claude-sonnet-4".to_string(),
             context_window_tokens: 200_000,
+            tokenizer: None,
         },
         ModelInfo {
             model_name: Some("claude-3.7-sonnet".to_string()),
             model_id: "claude-3.7-sonnet".to_string(),
             context_window_tokens: 200_000,
+            tokenizer: None,
         },
     ]
 }
@@ -221,3 +248,23 @@ pub fn find_model<'a>(models: &'a [ModelInfo], name: &str) -> Option<&'a ModelIn
             || m.model_id.eq_ignore_ascii_case(normalized)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_tokenizer() {
+        let tokenizer = |model_id: &str| ModelInfo::from_id(model_id.to_string()).tokenizer();
+        assert_eq!(tokenizer("CLAUDE_SONNET_4_20250514_V1_0"), Tokenizer::Cl100kBase);
+        assert_eq!(tokenizer("claude-3.7-sonnet"), Tokenizer::Cl100kBase);
+        assert_eq!(tokenizer("OPENAI_GPT_OSS_120B_1_0"), Tokenizer::O200kBase);
+        assert_eq!(tokenizer("some-new-model"), Tokenizer::Heuristic);
+
+        let overridden = ModelInfo {
+            tokenizer: Some(Tokenizer::Heuristic),
+            ..ModelInfo::from_id("claude-sonnet-4".to_string())
+        };
+        assert_eq!(overridden.tokenizer(), Tokenizer::Heuristic);
+    }
+}
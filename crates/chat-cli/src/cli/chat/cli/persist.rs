@@ -7,6 +7,11 @@ use crossterm::style::{
 };
 
 use crate::cli::ConversationState;
+use crate::cli::chat::transcript::{
+    TranscriptFormat,
+    TranscriptOptions,
+    render_transcript,
+};
 use crate::cli::chat::{
     ChatError,
     ChatSession,
@@ -31,6 +36,21 @@ pub enum PersistSubcommand {
         /// Path to the conversation file to load
         path: String,
     },
+    /// Export the conversation as a readable transcript
+    Export {
+        /// Path where the transcript will be written. The extension of the format is appended if
+        /// the path has none
+        path: String,
+        /// Format of the transcript
+        #[arg(long, value_enum, default_value_t)]
+        format: TranscriptFormat,
+        /// Replace the content of tool results with a placeholder
+        #[arg(long)]
+        redact_tool_output: bool,
+        #[arg(short, long)]
+        /// Force overwrite if file already exists
+        force: bool,
+    },
 }
 
 impl PersistSubcommand {
@@ -81,6 +101,43 @@ impl PersistSubcommand {
                     style::SetAttribute(Attribute::Reset)
                 )?;
             },
+            Self::Export {
+                path,
+                format,
+                redact_tool_output,
+                force,
+            } => {
+                let path = match std::path::Path::new(&path).extension() {
+                    Some(_) => path,
+                    None => format!("{path}.{}", format.extension()),
+                };
+                if os.fs.exists(&path) && !force {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!(
+                            "\nFile at {} already exists. To overwrite, use -f or --force\n\n",
+                            &path
+                        )),
+                        style::SetAttribute(Attribute::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        skip_printing_tools: true,
+                    });
+                }
+                let contents = render_transcript(&session.conversation, TranscriptOptions {
+                    format,
+                    redact_tool_output,
+                });
+                tri!(os.fs.write(&path, contents).await, "export to", &path);
+
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n✔ Exported conversation transcript to {}\n\n", &path)),
+                    style::SetAttribute(Attribute::Reset)
+                )?;
+            },
             Self::Load { path } => {
                 // Try the original path first
                 let original_result = os.fs.read_to_string(&path).await;
@@ -0,0 +1,198 @@
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::execute;
+use crossterm::style::{
+    self,
+    Color,
+};
+
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+
+/// Arguments for the `/pin` command that keeps messages verbatim across `/compact`.
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Args)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    before_long_help = "/pin keeps a message verbatim for the rest of the conversation. Pinned messages are sent
+as context with every request and are never folded into the summary created by /compact,
+so exact requirements or API signatures are not lost.
+
+Usage
+• /pin                  Pin your last prompt
+• /pin --index <n>      Pin the prompt at position <n> in the conversation history
+• /pin <text>           Pin arbitrary text
+• /pin list             List the pinned messages
+• /pin remove <n>       Remove the pin numbered <n> in /pin list
+• /pin clear            Remove all pins"
+)]
+pub struct PinArgs {
+    #[command(subcommand)]
+    subcommand: Option<PinSubcommand>,
+    /// Text to pin. Defaults to your last prompt
+    text: Vec<String>,
+    /// Pin the prompt at this position in the conversation history, starting at 0
+    #[arg(long, conflicts_with = "text")]
+    index: Option<usize>,
+}
+
+/// Subcommands for managing pinned messages
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum PinSubcommand {
+    /// List the pinned messages
+    List,
+    /// Remove a pinned message
+    #[command(alias = "rm")]
+    Remove {
+        /// Number of the pin as shown by /pin list
+        number: usize,
+    },
+    /// Remove all pinned messages
+    Clear,
+}
+
+impl PinArgs {
+    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        if let Some(subcommand) = self.subcommand {
+            return subcommand.execute(session).await;
+        }
+
+        let content = if !self.text.is_empty() {
+            Ok(self.text.join(" "))
+        } else if let Some(index) = self.index {
+            match session.conversation.history().get(index) {
+                Some(entry) => entry
+                    .user()
+                    .prompt()
+                    .map(str::to_string)
+                    .ok_or(format!("History entry {index} does not contain a prompt")),
+                None => Err(format!(
+                    "No history entry at index {index}, the history has {} entries",
+                    session.conversation.history().len()
+                )),
+            }
+        } else {
+            session
+                .conversation
+                .history()
+                .iter()
+                .rev()
+                .find_map(|entry| entry.user().prompt())
+                .map(str::to_string)
+                .ok_or("There is no prompt in the conversation history to pin".to_string())
+        };
+
+        match content {
+            Ok(content) => {
+                let tokens = session.conversation.tokenizer().count_tokens(&content);
+                session.conversation.pin(content);
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\nPinned message {}", session.conversation.pins().len())),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(
+                        " (~{tokens} tokens). It will be kept verbatim by /compact.\n\n"
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            Err(err) => {
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\n{err}\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn subcommand_name(&self) -> Option<&'static str> {
+        self.subcommand.as_ref().map(|s| s.name())
+    }
+}
+
+impl PinSubcommand {
+    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        match self {
+            Self::List => {
+                if session.conversation.pins().is_empty() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo pinned messages. Pin your last prompt with /pin\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                } else {
+                    let tokenizer = session.conversation.tokenizer();
+                    execute!(session.stderr, style::Print("\n"))?;
+                    for (i, pin) in session.conversation.pins().iter().enumerate() {
+                        execute!(
+                            session.stderr,
+                            style::SetForegroundColor(Color::Cyan),
+                            style::Print(format!("{}.", i + 1)),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!(
+                                " {} (~{} tokens)\n",
+                                pin.created_at.format("%m/%d %H:%M:%S"),
+                                tokenizer.count_tokens(&pin.content)
+                            )),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print(format!("{}\n\n", pin.content)),
+                        )?;
+                    }
+                }
+            },
+            Self::Remove { number } => match number.checked_sub(1).and_then(|i| session.conversation.unpin(i)) {
+                Some(_) => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\nRemoved pin {number}.\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+                None => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nNo pin numbered {number}. See /pin list\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+            },
+            Self::Clear => {
+                let count = session.conversation.clear_pins();
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\nRemoved {count} pin(s).\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::List => "list",
+            Self::Remove { .. } => "remove",
+            Self::Clear => "clear",
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::execute;
+use crossterm::style::{
+    self,
+    Color,
+};
+
+use crate::cli::chat::tools::execute::PersistentShell;
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+use crate::os::Os;
+
+/// Arguments for the `/shell` command that manages the persistent shell used by `execute_bash`.
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Args)]
+#[command(
+    before_long_help = "When the persistent shell is enabled, Q runs shell commands in a single long-lived shell
+for the whole chat session, so `cd`, exported variables, activated virtualenvs and sourced
+scripts carry over between commands. Enable it with /experiment or
+q settings chat.enablePersistentShell true
+
+Usage
+• /shell                Show the shell's process id and working directory
+• /shell reset          Kill the shell. The next command starts a fresh one"
+)]
+pub struct ShellArgs {
+    #[command(subcommand)]
+    subcommand: Option<ShellSubcommand>,
+}
+
+/// Subcommands for managing the persistent shell
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum ShellSubcommand {
+    /// Show the shell's process id and working directory
+    Status,
+    /// Kill the shell, discarding its working directory and environment
+    Reset,
+}
+
+impl ShellArgs {
+    pub async fn execute(self, os: &Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        if !PersistentShell::is_enabled(os) {
+            execute!(
+                session.stderr,
+                style::SetForegroundColor(Color::Red),
+                style::Print(
+                    "\nThe persistent shell is disabled. Enable it with: q settings chat.enablePersistentShell true\n\n"
+                ),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(ChatState::PromptUser {
+                skip_printing_tools: true,
+            });
+        }
+
+        match self.subcommand.unwrap_or(ShellSubcommand::Status) {
+            ShellSubcommand::Status => match session.persistent_shell.status().await {
+                Some((pid, cwd)) => {
+                    let pid = pid.map_or("unknown".to_string(), |pid| pid.to_string());
+                    let cwd = match cwd {
+                        Some(cwd) => cwd.display().to_string(),
+                        None => "unknown".to_string(),
+                    };
+                    execute!(
+                        session.stderr,
+                        style::Print("\nShell running with pid "),
+                        style::SetForegroundColor(Color::Cyan),
+                        style::Print(pid),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(" in "),
+                        style::SetForegroundColor(Color::Cyan),
+                        style::Print(cwd),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print("\n\n"),
+                    )?;
+                },
+                None => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nThe shell is not running. It starts with the next shell command.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+            },
+            ShellSubcommand::Reset => {
+                let message = if session.persistent_shell.reset().await {
+                    "\nShell reset. The next shell command starts a fresh shell.\n\n"
+                } else {
+                    "\nThe shell is not running. The next shell command starts a fresh shell.\n\n"
+                };
+                execute!(
+                    session.stderr,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(message),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn subcommand_name(&self) -> Option<&'static str> {
+        self.subcommand.as_ref().map(|s| s.name())
+    }
+}
+
+impl ShellSubcommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Reset => "reset",
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::execute;
+use crossterm::style::{
+    self,
+    Color,
+};
+
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+
+/// Arguments for the `/subagents` command that shows the tasks delegated to other agents.
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Args)]
+#[command(
+    before_long_help = "Q can delegate focused tasks to other agents with the delegate tool. Each agent works on its task
+in a conversation of its own, and only its final answer is returned to this conversation.
+
+Usage
+• /subagents                List the tasks delegated in this session
+• /subagents show <id>      Show the transcript of a delegated task"
+)]
+pub struct SubagentsArgs {
+    #[command(subcommand)]
+    subcommand: Option<SubagentsSubcommand>,
+}
+
+/// Subcommands for viewing delegated tasks
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum SubagentsSubcommand {
+    /// List the tasks delegated in this session
+    List,
+    /// Show the transcript of a delegated task
+    Show {
+        /// Id of the task as shown by /subagents
+        id: usize,
+    },
+}
+
+impl SubagentsArgs {
+    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        match self.subcommand.unwrap_or(SubagentsSubcommand::List) {
+            SubagentsSubcommand::List => {
+                if session.subagent_runs.is_empty() {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo tasks were delegated to other agents.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        skip_printing_tools: true,
+                    });
+                }
+
+                execute!(session.stderr, style::Print("\n"))?;
+                for (i, run) in session.subagent_runs.iter().enumerate() {
+                    let task = run.task.lines().next().unwrap_or_default();
+                    let (status_color, status) = match &run.result {
+                        Ok(_) => (Color::Green, "finished".to_string()),
+                        Err(reason) => (Color::Red, format!("stopped: {reason}")),
+                    };
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Cyan),
+                        style::Print(format!("{}. ", i + 1)),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(format!("{}{}: {task}", "  ".repeat(run.depth - 1), run.agent)),
+                        style::SetForegroundColor(status_color),
+                        style::Print(format!("\n   {status}")),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(" ({:.1}s)\n", run.elapsed.as_secs_f64())),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(session.stderr, style::Print("\n"))?;
+            },
+            SubagentsSubcommand::Show { id } => match id.checked_sub(1).and_then(|i| session.subagent_runs.get(i)) {
+                Some(run) => {
+                    execute!(
+                        session.stderr,
+                        style::Print("\n"),
+                        style::Print(&run.transcript),
+                        style::Print("\n"),
+                    )?;
+                },
+                None => {
+                    execute!(
+                        session.stderr,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nNo delegated task with id {id}. See /subagents\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                },
+            },
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+
+    pub fn subcommand_name(&self) -> Option<&'static str> {
+        self.subcommand.as_ref().map(|s| s.name())
+    }
+}
+
+impl SubagentsSubcommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::List => "list",
+            Self::Show { .. } => "show",
+        }
+    }
+}
@@ -33,6 +33,7 @@ use crate::cli::chat::{
     ChatState,
     TRUST_ALL_TEXT,
 };
+use crate::os::Os;
 use crate::util::consts::MCP_SERVER_TOOL_DELIMITER;
 
 /// Command-line arguments for managing tools in the chat session
@@ -44,9 +45,9 @@ pub struct ToolsArgs {
 }
 
 impl ToolsArgs {
-    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+    pub async fn execute(self, os: &Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
         if let Some(subcommand) = self.subcommand {
-            return subcommand.execute(session).await;
+            return subcommand.execute(os, session).await;
         }
 
         // No subcommand - print the current tools and their permissions.
@@ -225,7 +226,7 @@ pub enum ToolsSubcommand {
 }
 
 impl ToolsSubcommand {
-    pub async fn execute(self, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+    pub async fn execute(self, os: &Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
         // Here we need to obtain the list of host tool names
         let existing_custom_tools = session
             .conversation
@@ -376,14 +377,9 @@ impl ToolsSubcommand {
 
                 let active_agent_path = session.conversation.agents.get_active().and_then(|a| a.path.clone());
                 if let Some(path) = active_agent_path {
-                    let result = async {
-                        let content = tokio::fs::read(&path).await?;
-                        let orig_agent = serde_json::from_slice::<Agent>(&content)?;
-                        // since all we're doing here is swapping the tool list, it's okay if we
-                        // don't thaw it here
-                        Ok::<Agent, Box<dyn std::error::Error>>(orig_agent)
-                    }
-                    .await;
+                    // since all we're doing here is swapping the tool list, it's okay if we don't
+                    // thaw it here
+                    let result = Agent::read_resolved(os, &path).await;
 
                     if let (Ok(orig_agent), Some(active_agent)) = (result, session.conversation.agents.get_active_mut())
                     {
@@ -0,0 +1,152 @@
+use clap::Args;
+use crossterm::style::{
+    self,
+    Color,
+    Stylize,
+};
+use crossterm::{
+    cursor,
+    execute,
+    queue,
+};
+
+use crate::cli::chat::tools::format_path;
+use crate::cli::chat::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+use crate::os::Os;
+
+/// Arguments for the undo command that reverts files written by `fs_write`.
+#[deny(missing_docs)]
+#[derive(Debug, PartialEq, Args)]
+pub struct UndoArgs {
+    /// Revert the changes made in this turn and every turn after it. Defaults to the last turn
+    /// that modified files
+    #[arg(long)]
+    pub turn: Option<usize>,
+}
+
+impl UndoArgs {
+    pub async fn execute(self, os: &Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        let Some(turn) = self.turn.or(session.file_snapshots.latest_turn()) else {
+            execute!(
+                session.stderr,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("\nNo file changes to undo.\n\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(ChatState::PromptUser {
+                skip_printing_tools: true,
+            });
+        };
+
+        let snapshots = session.file_snapshots.snapshots_since(turn);
+        if snapshots.is_empty() {
+            execute!(
+                session.stderr,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("\nNo file changes were made in or after turn {turn}.\n\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(ChatState::PromptUser {
+                skip_printing_tools: true,
+            });
+        }
+
+        let cwd = os.env.current_dir()?;
+        let turns = session
+            .file_snapshots
+            .turns()
+            .iter()
+            .filter(|t| t.turn >= turn)
+            .map(|t| t.turn.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        execute!(
+            session.stderr,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("\nReverting file changes from turn(s) {turns}:\n\n")),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+
+        for snapshot in &snapshots {
+            let current = match os.fs.exists(&snapshot.path) {
+                true => os.fs.read(&snapshot.path).await?,
+                false => Vec::new(),
+            };
+            let action = match (&snapshot.content, os.fs.exists(&snapshot.path)) {
+                (None, true) => "Deleting: ",
+                (Some(_), false) => "Recreating: ",
+                _ => "Restoring: ",
+            };
+            queue!(
+                session.stderr,
+                style::Print(action),
+                style::SetForegroundColor(Color::Green),
+                style::Print(format_path(&cwd, &snapshot.path)),
+                style::SetForegroundColor(Color::Reset),
+                style::Print("\n"),
+            )?;
+
+            let current = String::from_utf8_lossy(&current);
+            let previous = String::from_utf8_lossy(snapshot.content.as_deref().unwrap_or_default());
+            let diff = similar::TextDiff::from_lines(current.as_ref(), previous.as_ref());
+            for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+                queue!(session.stderr, style::Print(format!("{}\n", hunk.header()).cyan()))?;
+                for change in hunk.iter_changes() {
+                    let line = format!("{}{}", change.tag(), change.value());
+                    let line = if line.ends_with('\n') { line } else { format!("{line}\n") };
+                    match change.tag() {
+                        similar::ChangeTag::Delete => queue!(session.stderr, style::Print(line.red()))?,
+                        similar::ChangeTag::Insert => queue!(session.stderr, style::Print(line.green()))?,
+                        similar::ChangeTag::Equal => queue!(session.stderr, style::Print(line.dark_grey()))?,
+                    }
+                }
+            }
+            queue!(session.stderr, style::Print("\n"))?;
+        }
+
+        execute!(
+            session.stderr,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("Revert these changes? "),
+            style::Print("["),
+            style::SetForegroundColor(Color::Green),
+            style::Print("y"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("/"),
+            style::SetForegroundColor(Color::Green),
+            style::Print("n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("]:\n\n"),
+            style::SetForegroundColor(Color::Reset),
+            cursor::Show,
+        )?;
+
+        // Setting `exit_on_single_ctrl_c` for better ux: exit the confirmation dialog rather than the CLI
+        let user_input = match session.read_user_input("> ".yellow().to_string().as_str(), true) {
+            Some(input) => input,
+            None => "".to_string(),
+        };
+
+        if ["y", "Y"].contains(&user_input.as_str()) {
+            let restored = session
+                .file_snapshots
+                .restore(os, turn)
+                .await
+                .map_err(|e| ChatError::Custom(format!("Failed to revert file changes: {e}").into()))?;
+            execute!(
+                session.stderr,
+                style::SetForegroundColor(Color::Green),
+                style::Print(format!("\nReverted {} file(s).\n\n", restored.len())),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+        }
+
+        Ok(ChatState::PromptUser {
+            skip_printing_tools: true,
+        })
+    }
+}
@@ -1,4 +1,10 @@
-use clap::Args;
+use std::collections::VecDeque;
+use std::io::Write;
+
+use clap::{
+    Args,
+    ValueEnum,
+};
 use crossterm::style::{
     Attribute,
     Color,
@@ -8,12 +14,24 @@ use crossterm::{
     queue,
     style,
 };
+use serde::Serialize;
 
 use super::model::context_window_tokens;
+use crate::cli::chat::conversation::{
+    BackendConversationState,
+    ContextUsageSample,
+    ConversationSize,
+};
+use crate::cli::chat::message::AssistantToolUse;
+use crate::cli::chat::output_format::{
+    ChatOutputFormat,
+    OutputEvent,
+};
 use crate::cli::chat::token_counter::{
-    CharCount,
     TokenCount,
+    TokenCounted,
 };
+use crate::cli::chat::util::truncate_safe;
 use crate::cli::chat::{
     ChatError,
     ChatSession,
@@ -28,10 +46,91 @@ use crate::os::Os;
 /// assistant responses, and user prompts) within the current chat session's context window.
 #[deny(missing_docs)]
 #[derive(Debug, PartialEq, Args)]
-pub struct UsageArgs;
+pub struct UsageArgs {
+    /// Show the tokens used by each context file, MCP server, tool result and turn
+    #[arg(long, short)]
+    detailed: bool,
+    /// Column to sort the detailed breakdown by
+    #[arg(long, value_enum, default_value_t)]
+    sort: UsageSort,
+    /// Maximum number of rows shown in the detailed breakdown
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+    /// Show the context size at the end of each turn of this session
+    #[arg(long)]
+    timeline: bool,
+    /// Print the usage, the detailed breakdown and the timeline as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Columns of the detailed breakdown that can be sorted by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UsageSort {
+    /// Largest first
+    #[default]
+    Tokens,
+    Name,
+    Kind,
+}
+
+/// What a row in the detailed breakdown refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum UsageItemKind {
+    ContextFile,
+    Summary,
+    Pin,
+    ToolSpecs,
+    ToolResult,
+    Turn,
+}
+
+impl std::fmt::Display for UsageItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::ContextFile => "Context file",
+            Self::Summary => "Summary",
+            Self::Pin => "Pin",
+            Self::ToolSpecs => "Tool specs",
+            Self::ToolResult => "Tool result",
+            Self::Turn => "Turn",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageItem {
+    kind: UsageItemKind,
+    name: String,
+    tokens: TokenCount,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageReport<'a> {
+    context_window: usize,
+    total: TokenCount,
+    #[serde(flatten)]
+    size: ConversationSize,
+    tools: TokenCount,
+    items: Vec<UsageItem>,
+    timeline: &'a VecDeque<ContextUsageSample>,
+}
 
 impl UsageArgs {
     pub async fn execute(self, os: &Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        let context_files = match session.conversation.context_manager.as_ref() {
+            Some(context_manager) => context_manager
+                .collect_context_files_with_limit(os)
+                .await
+                .map(|(files, _)| files)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let summary = session.conversation.latest_summary().map(str::to_string);
+
         let state = session
             .conversation
             .backend_conversation_state(os, true, &mut session.stderr)
@@ -51,28 +150,63 @@ impl UsageArgs {
         }
 
         let data = state.calculate_conversation_size();
-        let tool_specs_json: String = state
-            .tools
-            .values()
-            .filter_map(|s| serde_json::to_string(s).ok())
-            .collect::<Vec<String>>()
-            .join("");
-        let context_token_count: TokenCount = data.context_messages.into();
-        let assistant_token_count: TokenCount = data.assistant_messages.into();
-        let user_token_count: TokenCount = data.user_messages.into();
-        let tools_char_count: CharCount = tool_specs_json.len().into(); // usize → CharCount
-        let tools_token_count: TokenCount = tools_char_count.into(); // CharCount → TokenCount
-        let total_token_used: TokenCount =
-            (data.context_messages + data.user_messages + data.assistant_messages + tools_char_count).into();
+        let tools_token_count = state.tool_specs_token_count();
+        let mut items = if self.detailed || self.json {
+            usage_items(&state, &context_files, summary.as_deref())
+        } else {
+            Vec::new()
+        };
+        match self.sort {
+            UsageSort::Tokens => items.sort_by(|a, b| b.tokens.cmp(&a.tokens)),
+            UsageSort::Name => items.sort_by(|a, b| a.name.cmp(&b.name)),
+            UsageSort::Kind => items.sort_by(|a, b| a.kind.cmp(&b.kind).then(b.tokens.cmp(&a.tokens))),
+        }
+
+        let context_window_size = context_window_tokens(session.conversation.model_info.as_ref());
+        if self.json {
+            let report = UsageReport {
+                context_window: context_window_size,
+                total: data.total() + tools_token_count,
+                size: data,
+                tools: tools_token_count,
+                items,
+                timeline: session.conversation.usage_timeline(),
+            };
+            let report = serde_json::to_value(&report).map_err(|err| ChatError::Custom(err.to_string().into()))?;
+            match session.output_events.format() {
+                ChatOutputFormat::Text => {
+                    let json = serde_json::to_string_pretty(&report)
+                        .map_err(|err| ChatError::Custom(err.to_string().into()))?;
+                    writeln!(session.stdout, "{json}")?;
+                },
+                // Stdout only holds events in the other formats, e.g. JSON-RPC messages.
+                _ => session
+                    .output_events
+                    .emit(&mut session.stdout, OutputEvent::CommandOutput {
+                        command: "usage".to_string(),
+                        output: report,
+                    })?,
+            }
+            return Ok(ChatState::PromptUser {
+                skip_printing_tools: true,
+            });
+        }
+
+        let context_token_count = data.context_messages;
+        let pinned_token_count = data.pinned_messages;
+        let assistant_token_count = data.assistant_messages;
+        let user_token_count = data.user_messages;
+        let total_token_used: TokenCount = data.total() + tools_token_count;
         let window_width = session.terminal_width();
         // set a max width for the progress bar for better aesthetic
         let progress_bar_width = std::cmp::min(window_width, 80);
 
-        let context_window_size = context_window_tokens(session.conversation.model_info.as_ref());
         let context_width =
             ((context_token_count.value() as f64 / context_window_size as f64) * progress_bar_width as f64) as usize;
         let assistant_width =
             ((assistant_token_count.value() as f64 / context_window_size as f64) * progress_bar_width as f64) as usize;
+        let pinned_width =
+            ((pinned_token_count.value() as f64 / context_window_size as f64) * progress_bar_width as f64) as usize;
         let tools_width =
             ((tools_token_count.value() as f64 / context_window_size as f64) * progress_bar_width as f64) as usize;
         let user_width =
@@ -80,11 +214,12 @@ impl UsageArgs {
 
         let left_over_width = progress_bar_width
             - std::cmp::min(
-                context_width + assistant_width + user_width + tools_width,
+                context_width + pinned_width + assistant_width + user_width + tools_width,
                 progress_bar_width,
             );
 
-        let is_overflow = (context_width + assistant_width + user_width + tools_width) > progress_bar_width;
+        let is_overflow =
+            (context_width + pinned_width + assistant_width + user_width + tools_width) > progress_bar_width;
 
         if is_overflow {
             queue!(
@@ -121,6 +256,14 @@ impl UsageArgs {
                     0
                 })),
                 style::Print("█".repeat(context_width)),
+                // Pinned messages
+                style::SetForegroundColor(Color::DarkYellow),
+                style::Print("|".repeat(if pinned_width == 0 && *pinned_token_count > 0 {
+                    1
+                } else {
+                    0
+                })),
+                style::Print("█".repeat(pinned_width)),
                 // Tools
                 style::SetForegroundColor(Color::DarkRed),
                 style::Print("|".repeat(if tools_width == 0 && *tools_token_count > 0 {
@@ -164,6 +307,14 @@ impl UsageArgs {
                 context_token_count,
                 (context_token_count.value() as f32 / context_window_size as f32) * 100.0
             )),
+            style::SetForegroundColor(Color::DarkYellow),
+            style::Print("█ Pinned:     "),
+            style::SetForegroundColor(Color::Reset),
+            style::Print(format!(
+                "~{} tokens ({:.2}%)\n",
+                pinned_token_count,
+                (pinned_token_count.value() as f32 / context_window_size as f32) * 100.0
+            )),
             style::SetForegroundColor(Color::DarkRed),
             style::Print("█ Tools:    "),
             style::SetForegroundColor(Color::Reset),
@@ -190,6 +341,13 @@ impl UsageArgs {
             )),
         )?;
 
+        if self.detailed {
+            print_items(session, &items, self.limit, context_window_size)?;
+        }
+        if self.timeline {
+            print_timeline(session, context_window_size)?;
+        }
+
         queue!(
             session.stderr,
             style::SetAttribute(Attribute::Bold),
@@ -210,7 +368,12 @@ impl UsageArgs {
             style::SetForegroundColor(Color::DarkGreen),
             style::Print("/context show"),
             style::SetForegroundColor(Color::DarkGrey),
-            style::Print(" to see tokens per context file\n\n"),
+            style::Print(" to see tokens per context file\n"),
+            style::Print("Run "),
+            style::SetForegroundColor(Color::DarkGreen),
+            style::Print("/usage --detailed --timeline"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(" to see what uses the most tokens and how usage grew per turn\n\n"),
             style::SetForegroundColor(Color::Reset),
         )?;
 
@@ -219,3 +382,286 @@ impl UsageArgs {
         })
     }
 }
+
+/// Breaks the context down into the individual context files, pins, MCP servers, tool results and
+/// turns.
+fn usage_items(
+    state: &BackendConversationState<'_>,
+    context_files: &[(String, String)],
+    summary: Option<&str>,
+) -> Vec<UsageItem> {
+    let mut items = Vec::new();
+    let tokenizer = state.tokenizer;
+
+    for (path, content) in context_files {
+        items.push(UsageItem {
+            kind: UsageItemKind::ContextFile,
+            name: path.clone(),
+            tokens: tokenizer.count_tokens(content),
+        });
+    }
+    if let Some(summary) = summary {
+        items.push(UsageItem {
+            kind: UsageItemKind::Summary,
+            name: "Conversation summary".to_string(),
+            tokens: tokenizer.count_tokens(summary),
+        });
+    }
+    for (i, pin) in state.pins.iter().enumerate() {
+        items.push(UsageItem {
+            kind: UsageItemKind::Pin,
+            name: format!("{}. {}", i + 1, first_line(&pin.content)),
+            tokens: tokenizer.count_tokens(&pin.content),
+        });
+    }
+    for (origin, tools) in state.tools {
+        items.push(UsageItem {
+            kind: UsageItemKind::ToolSpecs,
+            name: format!("{origin} ({} tools)", tools.len()),
+            tokens: state.tool_origin_token_count(tools),
+        });
+    }
+
+    // Turns start with a prompt and include every tool use made on its behalf.
+    let mut turn: Option<UsageItem> = None;
+    let mut turn_number = 0;
+    let mut previous_tool_uses: Option<&[AssistantToolUse]> = None;
+    for entry in state.history.clone() {
+        if !entry.user().has_tool_use_results() {
+            turn_number += 1;
+            items.extend(turn.take());
+            turn = Some(UsageItem {
+                kind: UsageItemKind::Turn,
+                name: format!(
+                    "{turn_number}. {}",
+                    first_line(entry.user().prompt().unwrap_or_default())
+                ),
+                tokens: TokenCount::from(0),
+            });
+        }
+        if let Some(turn) = turn.as_mut() {
+            turn.tokens = turn.tokens + entry.user().token_count(tokenizer) + entry.assistant().token_count(tokenizer);
+        }
+
+        for result in entry.user().tool_use_results().unwrap_or_default() {
+            let tool_name = previous_tool_uses
+                .and_then(|tool_uses| tool_uses.iter().find(|t| t.id == result.tool_use_id))
+                .map_or("unknown tool", |t| t.name.as_str());
+            items.push(UsageItem {
+                kind: UsageItemKind::ToolResult,
+                name: format!("{tool_name} in turn {turn_number} ({})", result.tool_use_id),
+                tokens: std::slice::from_ref(result).token_count(tokenizer),
+            });
+        }
+        previous_tool_uses = entry.assistant().tool_uses();
+    }
+    items.extend(turn);
+
+    items
+}
+
+fn print_items(
+    session: &mut ChatSession,
+    items: &[UsageItem],
+    limit: usize,
+    context_window_size: usize,
+) -> Result<(), ChatError> {
+    const KIND_WIDTH: usize = 14;
+    const TOKENS_WIDTH: usize = 10;
+    const PERCENT_WIDTH: usize = 8;
+    let name_width = session
+        .terminal_width()
+        .min(120)
+        .saturating_sub(KIND_WIDTH + TOKENS_WIDTH + PERCENT_WIDTH + 3)
+        .max(20);
+
+    queue!(
+        session.stderr,
+        style::SetAttribute(Attribute::Bold),
+        style::Print(format!(
+            "{:<KIND_WIDTH$} {:<name_width$} {:>TOKENS_WIDTH$} {:>PERCENT_WIDTH$}\n",
+            "Kind", "Name", "Tokens", "Window"
+        )),
+        style::SetAttribute(Attribute::Reset),
+    )?;
+    for item in items.iter().take(limit) {
+        let name = match truncate_safe(&item.name, name_width) {
+            truncated if truncated.len() < item.name.len() => {
+                format!("{}...", truncate_safe(truncated, name_width - 3))
+            },
+            truncated => truncated.to_string(),
+        };
+        queue!(
+            session.stderr,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("{:<KIND_WIDTH$} ", item.kind.to_string())),
+            style::SetForegroundColor(Color::Reset),
+            style::Print(format!("{name:<name_width$} ")),
+            style::Print(format!(
+                "{:>TOKENS_WIDTH$} {:>PERCENT_WIDTH$}\n",
+                format!("~{}", item.tokens),
+                format!(
+                    "{:.2}%",
+                    (item.tokens.value() as f32 / context_window_size as f32) * 100.0
+                )
+            )),
+        )?;
+    }
+    if items.len() > limit {
+        queue!(
+            session.stderr,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("({} more rows, see --limit)\n", items.len() - limit)),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+    execute!(session.stderr, style::Print("\n"))?;
+    Ok(())
+}
+
+fn print_timeline(session: &mut ChatSession, context_window_size: usize) -> Result<(), ChatError> {
+    const BAR_WIDTH: usize = 30;
+    let timeline = session.conversation.usage_timeline();
+    if timeline.is_empty() {
+        execute!(
+            session.stderr,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("No turns recorded in this session yet.\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        return Ok(());
+    }
+
+    queue!(
+        session.stderr,
+        style::SetAttribute(Attribute::Bold),
+        style::Print(format!(
+            "{:>4}  {:<8}  {:>8}  {:>10}  {:>8}\n",
+            "Turn", "Time", "Messages", "Tokens", "Window"
+        )),
+        style::SetAttribute(Attribute::Reset),
+    )?;
+    for (i, sample) in timeline.iter().enumerate() {
+        let ratio = sample.total().value() as f64 / context_window_size as f64;
+        let bar_width = ((ratio * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        queue!(
+            session.stderr,
+            style::Print(format!(
+                "{:>4}  {:<8}  {:>8}  {:>10}  {:>8}  ",
+                i + 1,
+                sample.timestamp.format("%H:%M:%S"),
+                sample.history_len,
+                format!("~{}", sample.total()),
+                format!("{:.2}%", ratio * 100.0)
+            )),
+            style::SetForegroundColor(if ratio > 0.8 { Color::DarkRed } else { Color::DarkCyan }),
+            style::Print("█".repeat(bar_width)),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("█".repeat(BAR_WIDTH - bar_width)),
+            style::SetForegroundColor(Color::Reset),
+            style::Print("\n"),
+        )?;
+    }
+    execute!(session.stderr, style::Print("\n"))?;
+    Ok(())
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::agent::Agents;
+    use crate::cli::chat::ToolResultStatus;
+    use crate::cli::chat::conversation::ConversationState;
+    use crate::cli::chat::message::{
+        AssistantMessage,
+        ToolUseResult,
+        ToolUseResultBlock,
+    };
+    use crate::cli::chat::tool_manager::ToolManager;
+
+    #[tokio::test]
+    async fn test_usage_items_and_timeline() {
+        let mut os = Os::new().await.unwrap();
+        let mut tool_manager = ToolManager::default();
+        let mut conversation = ConversationState::new(
+            "fake_conv_id",
+            Agents::default(),
+            tool_manager.load_tools(&mut os, &mut vec![]).await.unwrap(),
+            tool_manager,
+            None,
+            &os,
+            false,
+        )
+        .await;
+
+        for i in 0..2 {
+            conversation.set_next_user_message(format!("prompt {i}\ndetails")).await;
+            conversation
+                .as_sendable_conversation_state(&os, &mut vec![], false)
+                .await
+                .unwrap();
+            conversation.push_assistant_message(
+                &mut os,
+                AssistantMessage::new_tool_use(None, String::new(), vec![AssistantToolUse {
+                    id: format!("tool_{i}"),
+                    name: "fs_read".to_string(),
+                    ..Default::default()
+                }]),
+                None,
+            );
+            conversation.add_tool_results(vec![ToolUseResult {
+                tool_use_id: format!("tool_{i}"),
+                content: vec![ToolUseResultBlock::Text("output ".repeat(100 * (i + 1)))],
+                status: ToolResultStatus::Success,
+            }]);
+            conversation
+                .as_sendable_conversation_state(&os, &mut vec![], false)
+                .await
+                .unwrap();
+            conversation.push_assistant_message(
+                &mut os,
+                AssistantMessage::new_response(None, format!("response {i}")),
+                None,
+            );
+        }
+        conversation.pin("keep this".to_string());
+
+        // Tool use requests update the sample of the turn they belong to.
+        let timeline = conversation.usage_timeline();
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].history_len, 1);
+        assert_eq!(timeline[1].history_len, 3);
+        assert!(timeline[1].total() > timeline[0].total());
+
+        let state = conversation
+            .backend_conversation_state(&os, false, &mut vec![])
+            .await
+            .unwrap();
+        let items = usage_items(&state, &[("AGENTS.md".to_string(), "be nice".to_string())], None);
+        let names = |kind| {
+            items
+                .iter()
+                .filter(|item| item.kind == kind)
+                .map(|item| item.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(UsageItemKind::ContextFile), vec!["AGENTS.md"]);
+        assert_eq!(names(UsageItemKind::Pin), vec!["1. keep this"]);
+        assert_eq!(names(UsageItemKind::Turn), vec!["1. prompt 0", "2. prompt 1"]);
+        assert_eq!(names(UsageItemKind::ToolResult), vec![
+            "fs_read in turn 1 (tool_0)",
+            "fs_read in turn 2 (tool_1)"
+        ]);
+        assert!(!names(UsageItemKind::ToolSpecs).is_empty());
+
+        let turns = items.iter().filter(|item| item.kind == UsageItemKind::Turn);
+        let results = items.iter().filter(|item| item.kind == UsageItemKind::ToolResult);
+        for (turn, result) in turns.zip(results) {
+            assert!(turn.tokens >= result.tokens);
+        }
+    }
+}
@@ -443,6 +443,7 @@ mod tests {
                 model_id: "CLAUDE_SONNET_4_20250514_V1_0".to_string(),
                 model_name: Some("Claude".to_string()),
                 context_window_tokens: 200_000,
+                tokenizer: None,
             })),
             150_000
         );
@@ -451,6 +452,7 @@ mod tests {
                 model_id: "OPENAI_GPT_OSS_120B_1_0".to_string(),
                 model_name: Some("GPT".to_string()),
                 context_window_tokens: 128_000,
+                tokenizer: None,
             })),
             96_000
         );
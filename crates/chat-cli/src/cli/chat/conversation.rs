@@ -6,7 +6,10 @@ use std::collections::{
 use std::io::Write;
 use std::sync::atomic::Ordering;
 
-use chrono::Local;
+use chrono::{
+    DateTime,
+    Local,
+};
 use crossterm::style::Color;
 use crossterm::{
     execute,
@@ -28,7 +31,10 @@ use tracing::{
     warn,
 };
 
-use super::cli::compact::CompactStrategy;
+use super::cli::compact::{
+    CompactStrategy,
+    Compactor,
+};
 use super::cli::hooks::HookOutput;
 use super::cli::model::context_window_tokens;
 use super::consts::{
@@ -36,6 +42,7 @@ use super::consts::{
     MAX_CONVERSATION_STATE_HISTORY_LEN,
 };
 use super::context::{
+    ContextFilePath,
     ContextManager,
     calc_max_context_files_size,
 };
@@ -49,16 +56,22 @@ use super::parser::RequestMetadata;
 use super::token_counter::{
     CharCount,
     CharCounter,
-    TokenCounter,
+    TokenCount,
+    TokenCounted,
+    Tokenizer,
 };
 use super::tool_manager::ToolManager;
+use super::tools::delegate::Delegate;
 use super::tools::{
     InputSchema,
     QueuedTool,
     ToolOrigin,
     ToolSpec,
 };
-use super::util::serde_value_to_document;
+use super::util::{
+    serde_value_to_document,
+    truncate_safe,
+};
 use crate::api_client::model::{
     ChatMessage,
     ConversationState as FigConversationState,
@@ -92,6 +105,21 @@ pub struct HistoryEntry {
     request_metadata: Option<RequestMetadata>,
 }
 
+impl HistoryEntry {
+    pub fn user(&self) -> &UserMessage {
+        &self.user
+    }
+
+    pub fn assistant(&self) -> &AssistantMessage {
+        &self.assistant
+    }
+
+    /// See [UserMessage::prune_tool_use_results].
+    pub fn prune_tool_use_results(&mut self) {
+        self.user.prune_tool_use_results();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct McpServerInfo {
     pub name: String,
@@ -143,6 +171,48 @@ pub struct ConversationState {
     /// Tangent mode checkpoint - stores main conversation when in tangent mode
     #[serde(default, skip_serializing_if = "Option::is_none")]
     tangent_state: Option<ConversationCheckpoint>,
+    /// Checkpoints created with `/checkpoint save`, in order of creation. These only live as long
+    /// as the chat session.
+    #[serde(skip)]
+    checkpoints: Vec<NamedCheckpoint>,
+    /// Messages pinned with `/pin`. These are sent verbatim as context with every request and are
+    /// never folded into the summary created by `/compact`.
+    #[serde(default)]
+    pins: Vec<PinnedMessage>,
+    /// Size of the context sent at the end of each user turn, oldest first.
+    #[serde(default)]
+    usage_timeline: VecDeque<ContextUsageSample>,
+    /// Whether the conversation is saved as a session of the current directory after each turn.
+    /// Conversations of sub-agents and of `q agent run` are not, so that they can't be resumed in
+    /// place of those of the user.
+    #[serde(skip, default = "default_true")]
+    persisted: bool,
+}
+
+/// The context window utilization of a single user turn, as shown by `/usage --timeline`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextUsageSample {
+    /// Time of the last request sent during the turn.
+    pub timestamp: DateTime<Local>,
+    /// Number of user and assistant message pairs in the history.
+    pub history_len: usize,
+    #[serde(flatten)]
+    pub size: ConversationSize,
+    pub tools: TokenCount,
+}
+
+impl ContextUsageSample {
+    pub fn total(&self) -> TokenCount {
+        self.size.total() + self.tools
+    }
+}
+
+/// A message pinned to the conversation with `/pin`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedMessage {
+    pub content: String,
+    pub created_at: DateTime<Local>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -155,16 +225,54 @@ struct ConversationCheckpoint {
     main_transcript: VecDeque<String>,
     /// Main conversation summary
     main_latest_summary: Option<(String, RequestMetadata)>,
+    /// Main conversation pinned messages
+    #[serde(default)]
+    main_pins: Vec<PinnedMessage>,
     /// Timestamp when tangent mode was entered (milliseconds since epoch)
     #[serde(default = "time::OffsetDateTime::now_utc")]
     tangent_start_time: time::OffsetDateTime,
 }
 
+/// A snapshot of the conversation saved under a user provided name.
+#[derive(Debug, Clone)]
+pub struct NamedCheckpoint {
+    pub name: String,
+    pub created_at: DateTime<Local>,
+    checkpoint: ConversationCheckpoint,
+    /// Context file paths at the time the checkpoint was created.
+    context_paths: Option<Vec<ContextFilePath>>,
+}
+
+impl NamedCheckpoint {
+    /// Number of messages in the checkpoint, counting the user and the assistant message of each
+    /// entry of the history.
+    pub fn message_count(&self) -> usize {
+        self.checkpoint.main_history.len() * 2
+    }
+
+    /// Estimated size of the checkpointed history, including the summary if one exists.
+    pub fn token_count(&self) -> TokenCount {
+        let history_chars = self
+            .checkpoint
+            .main_history
+            .iter()
+            .fold(0, |acc, HistoryEntry { user, assistant, .. }| {
+                acc + *user.char_count() + *assistant.char_count()
+            });
+        let summary_chars = self
+            .checkpoint
+            .main_latest_summary
+            .as_ref()
+            .map_or(0, |(summary, _)| summary.len());
+        CharCount::from(history_chars + summary_chars).into()
+    }
+}
+
 impl ConversationState {
     pub async fn new(
         conversation_id: &str,
         agents: Agents,
-        tool_config: HashMap<String, ToolSpec>,
+        mut tool_config: HashMap<String, ToolSpec>,
         tool_manager: ToolManager,
         current_model_id: Option<String>,
         os: &Os,
@@ -188,6 +296,10 @@ impl ConversationState {
             None
         };
 
+        if let Some(spec) = tool_config.get_mut("delegate") {
+            spec.description = Delegate::describe_agents(&spec.description, &agents);
+        }
+
         Self {
             conversation_id: conversation_id.to_string(),
             next_message: None,
@@ -205,6 +317,10 @@ impl ConversationState {
             file_line_tracker: HashMap::new(),
             mcp_enabled,
             tangent_state: None,
+            checkpoints: Vec::new(),
+            pins: Vec::new(),
+            usage_timeline: VecDeque::new(),
+            persisted: true,
         }
     }
 
@@ -216,11 +332,53 @@ impl ConversationState {
         &self.history
     }
 
-    /// Clears the conversation history and summary.
+    /// A short, single line title for the conversation: the first prompt in the history, falling
+    /// back to the summary if the history was compacted away.
+    pub fn title(&self) -> Option<String> {
+        const MAX_TITLE_BYTES: usize = 80;
+        self.history
+            .iter()
+            .find_map(|entry| entry.user.prompt())
+            .or(self.latest_summary())
+            .and_then(|s| s.lines().map(str::trim).find(|l| !l.is_empty()))
+            .map(|line| match truncate_safe(line, MAX_TITLE_BYTES) {
+                truncated if truncated.len() < line.len() => format!("{truncated}..."),
+                truncated => truncated.to_string(),
+            })
+    }
+
+    /// Clears the conversation history, summary and pinned messages.
     pub fn clear(&mut self) {
         self.next_message = None;
         self.history.clear();
         self.latest_summary = None;
+        self.pins.clear();
+    }
+
+    pub fn pins(&self) -> &[PinnedMessage] {
+        &self.pins
+    }
+
+    pub fn usage_timeline(&self) -> &VecDeque<ContextUsageSample> {
+        &self.usage_timeline
+    }
+
+    /// Pins `content` to the conversation so that it survives compaction.
+    pub fn pin(&mut self, content: String) {
+        self.pins.push(PinnedMessage {
+            content,
+            created_at: Local::now(),
+        });
+    }
+
+    /// Removes the pin at `index`, returning it if it exists.
+    pub fn unpin(&mut self, index: usize) -> Option<PinnedMessage> {
+        (index < self.pins.len()).then(|| self.pins.remove(index))
+    }
+
+    /// Removes every pin, returning how many were removed.
+    pub fn clear_pins(&mut self) -> usize {
+        std::mem::take(&mut self.pins).len()
     }
 
     /// Check if currently in tangent mode
@@ -235,6 +393,7 @@ impl ConversationState {
             main_next_message: self.next_message.clone(),
             main_transcript: self.transcript.clone(),
             main_latest_summary: self.latest_summary.clone(),
+            main_pins: self.pins.clone(),
             tangent_start_time: time::OffsetDateTime::now_utc(),
         }
     }
@@ -245,9 +404,15 @@ impl ConversationState {
         self.next_message = checkpoint.main_next_message;
         self.transcript = checkpoint.main_transcript;
         self.latest_summary = checkpoint.main_latest_summary;
+        self.pins = checkpoint.main_pins;
         self.valid_history_range = (0, self.history.len());
     }
 
+    /// Sets whether the conversation is saved as a session of the current directory.
+    pub fn set_persisted(&mut self, persisted: bool) {
+        self.persisted = persisted;
+    }
+
     /// Enter tangent mode - creates checkpoint of current state
     pub fn enter_tangent_mode(&mut self) {
         if self.tangent_state.is_none() {
@@ -291,6 +456,48 @@ impl ConversationState {
         }
     }
 
+    pub fn checkpoints(&self) -> &[NamedCheckpoint] {
+        &self.checkpoints
+    }
+
+    /// Saves a snapshot of the current history, transcript and context files under `name`,
+    /// replacing any existing checkpoint with the same name.
+    ///
+    /// Returns `true` if an existing checkpoint was replaced.
+    pub fn save_checkpoint(&mut self, name: String) -> bool {
+        let existing = self.checkpoints.iter().position(|c| c.name == name);
+        if let Some(i) = existing {
+            self.checkpoints.remove(i);
+        }
+        self.checkpoints.push(NamedCheckpoint {
+            name,
+            created_at: Local::now(),
+            checkpoint: self.create_checkpoint(),
+            context_paths: self.context_manager.as_ref().map(|cm| cm.paths.clone()),
+        });
+        existing.is_some()
+    }
+
+    /// Restores the conversation to the checkpoint named `name`. Checkpoints are kept after being
+    /// restored, so the same checkpoint can be restored multiple times.
+    ///
+    /// Returns the restored checkpoint, or [None] if no checkpoint exists with the given name.
+    pub fn restore_checkpoint(&mut self, name: &str) -> Option<&NamedCheckpoint> {
+        let index = self.checkpoints.iter().position(|c| c.name == name)?;
+        let NamedCheckpoint {
+            checkpoint,
+            context_paths,
+            ..
+        } = self.checkpoints[index].clone();
+        self.restore_from_checkpoint(checkpoint);
+        if let (Some(cm), Some(paths)) = (self.context_manager.as_mut(), context_paths) {
+            cm.paths = paths;
+        }
+        // Cached context is no longer valid since the context files may have changed.
+        self.context_message_length = None;
+        Some(&self.checkpoints[index])
+    }
+
     /// Appends a collection prompts into history and returns the last message in the collection.
     /// It asserts that the collection ends with a prompt that assumes the role of user.
     pub fn append_prompts(&mut self, mut prompts: VecDeque<PromptMessage>) -> Option<String> {
@@ -401,8 +608,8 @@ impl ConversationState {
             request_metadata,
         });
 
-        if let Ok(cwd) = std::env::current_dir() {
-            os.database.set_conversation_by_path(cwd, self).ok();
+        if let (true, Ok(cwd)) = (self.persisted, std::env::current_dir()) {
+            os.database.set_session(cwd, self).ok();
         }
     }
 
@@ -500,7 +707,14 @@ impl ConversationState {
         self.history.drain(self.valid_history_range.1..);
         self.history.drain(..self.valid_history_range.0);
 
+        let is_new_turn = self.next_message.as_ref().is_some_and(|m| !m.has_tool_use_results());
         let context = self.backend_conversation_state(os, run_perprompt_hooks, stderr).await?;
+        let usage = ContextUsageSample {
+            timestamp: Local::now(),
+            history_len: context.history.len(),
+            size: context.calculate_conversation_size(),
+            tools: context.tool_specs_token_count(),
+        };
         if !context.dropped_context_files.is_empty() {
             execute!(
                 stderr,
@@ -515,9 +729,24 @@ impl ConversationState {
             .ok();
         }
 
-        Ok(context
+        let state = context
             .into_fig_conversation_state()
-            .expect("unable to construct conversation state"))
+            .expect("unable to construct conversation state");
+        self.record_usage(usage, is_new_turn);
+        Ok(state)
+    }
+
+    /// Adds `usage` to the timeline, replacing the sample of the current turn unless this is the
+    /// first request of a new turn.
+    fn record_usage(&mut self, usage: ContextUsageSample, is_new_turn: bool) {
+        const MAX_USAGE_TIMELINE_LEN: usize = 500;
+        if !is_new_turn {
+            self.usage_timeline.pop_back();
+        }
+        if self.usage_timeline.len() >= MAX_USAGE_TIMELINE_LEN {
+            self.usage_timeline.pop_front();
+        }
+        self.usage_timeline.push_back(usage);
     }
 
     pub async fn update_state(&mut self, force_update: bool) {
@@ -532,9 +761,13 @@ impl ConversationState {
             .schema
             .values()
             .fold(HashMap::<ToolOrigin, Vec<Tool>>::new(), |mut acc, v| {
+                let description = match v.name.as_str() {
+                    "delegate" => Delegate::describe_agents(&v.description, &self.agents),
+                    _ => v.description.clone(),
+                };
                 let tool = Tool::ToolSpecification(ToolSpecification {
                     name: v.name.clone(),
-                    description: v.description.clone(),
+                    description,
                     input_schema: v.input_schema.clone().into(),
                 });
                 acc.entry(v.tool_origin.clone())
@@ -589,6 +822,8 @@ impl ConversationState {
             dropped_context_files,
             tools: &self.tools,
             model_id: self.model_info.as_ref().map(|m| m.model_id.as_str()),
+            tokenizer: self.tokenizer(),
+            pins: &self.pins,
         })
     }
 
@@ -602,6 +837,7 @@ impl ConversationState {
         os: &Os,
         custom_prompt: Option<impl AsRef<str>>,
         strategy: CompactStrategy,
+        compactor: &dyn Compactor,
     ) -> Result<FigConversationState, ChatError> {
         let mut summary_content = match custom_prompt {
             Some(custom_prompt) => {
@@ -651,7 +887,7 @@ impl ConversationState {
                         FILTER OUT CHAT CONVENTIONS (greetings, offers to help, etc).".to_string()
             },
         };
-        if let Some((summary, _)) = &self.latest_summary {
+        if let (true, Some((summary, _))) = (compactor.replaces_summary(), &self.latest_summary) {
             summary_content.push_str("\n\n");
             summary_content.push_str(CONTEXT_ENTRY_START_HEADER);
             summary_content.push_str("This summary contains ALL relevant information from our previous conversation including tool uses, results, code analysis, and file operations. YOU MUST be sure to include this information when creating your summarization document.\n\n");
@@ -660,13 +896,16 @@ impl ConversationState {
             summary_content.push('\n');
             summary_content.push_str(CONTEXT_ENTRY_END_HEADER);
         }
+        if !self.pins.is_empty() {
+            summary_content.push_str("\n\nThe messages pinned by the user are kept verbatim outside of the summary, so they do not need to be repeated in it.");
+        }
 
+        let to_summarize = self.entries_to_summarize(&strategy, compactor).unwrap_or_default();
         let conv_state = self.backend_conversation_state(os, false, &mut vec![]).await?;
         let mut summary_message = Some(UserMessage::new_prompt(summary_content.clone(), None));
 
         // Create the history according to the passed compact strategy.
-        let mut history = conv_state.history.cloned().collect::<VecDeque<_>>();
-        history.drain((history.len().saturating_sub(strategy.messages_to_exclude))..);
+        let mut history = conv_state.history.take(to_summarize).cloned().collect::<VecDeque<_>>();
         if strategy.truncate_large_messages {
             for HistoryEntry { user, .. } in &mut history {
                 user.truncate_safe(strategy.max_message_length);
@@ -697,19 +936,38 @@ impl ConversationState {
         })
     }
 
-    /// `strategy` - The [CompactStrategy] used for the corresponding
-    /// [ConversationState::create_summary_request].
+    /// Pinned messages are kept as is, so they are still sent verbatim alongside the summary.
+    ///
+    /// `strategy` and `compactor` - The [CompactStrategy] and [Compactor] used for the
+    /// corresponding [ConversationState::create_summary_request].
     pub fn replace_history_with_summary(
         &mut self,
         summary: String,
         strategy: CompactStrategy,
+        compactor: &dyn Compactor,
         request_metadata: RequestMetadata,
     ) {
-        self.history
-            .drain(..(self.history.len().saturating_sub(strategy.messages_to_exclude)));
+        let summarized = self.entries_to_summarize(&strategy, compactor).unwrap_or_default();
+        compactor.compact(&mut self.history, Some(summarized), &strategy);
+        let summary = match self.latest_summary.take() {
+            Some((previous, _)) if !compactor.replaces_summary() => format!("{previous}\n\n{summary}"),
+            _ => summary,
+        };
         self.latest_summary = Some((summary, request_metadata));
     }
 
+    /// Number of the oldest history entries that `compactor` summarizes, or [None] if it compacts
+    /// the history without a summary request.
+    pub fn entries_to_summarize(&self, strategy: &CompactStrategy, compactor: &dyn Compactor) -> Option<usize> {
+        compactor.entries_to_summarize(&self.history, strategy)
+    }
+
+    /// Compacts the history without a summary, for compactors where
+    /// [ConversationState::entries_to_summarize] is [None].
+    pub fn compact_history_without_summary(&mut self, strategy: &CompactStrategy, compactor: &dyn Compactor) {
+        compactor.compact(&mut self.history, None, strategy);
+    }
+
     pub async fn create_agent_generation_request(
         &mut self,
         agent_name: &str,
@@ -787,6 +1045,10 @@ Return only the JSON configuration, no additional text.",
             context_content.push_str(CONTEXT_ENTRY_END_HEADER);
         }
 
+        if let Some(pins) = format_pins(&self.pins) {
+            context_content.push_str(&pins);
+        }
+
         // Add context files if available
         if let Some(context_manager) = self.context_manager.as_mut() {
             match context_manager.collect_context_files_with_limit(os).await {
@@ -839,20 +1101,26 @@ Return only the JSON configuration, no additional text.",
         self.context_message_length
     }
 
-    /// Calculate the total character count in the conversation
-    pub async fn calculate_char_count(&mut self, os: &Os) -> Result<CharCount, ChatError> {
+    /// Calculate the total token count in the conversation
+    pub async fn calculate_token_count(&mut self, os: &Os) -> Result<TokenCount, ChatError> {
         Ok(self
             .backend_conversation_state(os, false, &mut vec![])
             .await?
-            .char_count())
+            .calculate_conversation_size()
+            .total())
+    }
+
+    /// The tokenizer of the current model.
+    pub fn tokenizer(&self) -> Tokenizer {
+        self.model_info.as_ref().map(ModelInfo::tokenizer).unwrap_or_default()
     }
 
     /// Get the current token warning level
     pub async fn get_token_warning_level(&mut self, os: &Os) -> Result<TokenWarningLevel, ChatError> {
-        let total_chars = self.calculate_char_count(os).await?;
-        let max_chars = TokenCounter::token_to_chars(context_window_tokens(self.model_info.as_ref()));
+        let total_tokens = self.calculate_token_count(os).await?;
+        let max_tokens = context_window_tokens(self.model_info.as_ref());
 
-        Ok(if *total_chars >= max_chars {
+        Ok(if *total_tokens >= max_tokens {
             TokenWarningLevel::Critical
         } else {
             TokenWarningLevel::None
@@ -938,6 +1206,10 @@ pub struct BackendConversationStateImpl<'a, T, U> {
     pub dropped_context_files: Vec<(String, String)>,
     pub tools: &'a HashMap<ToolOrigin, Vec<Tool>>,
     pub model_id: Option<&'a str>,
+    /// Tokenizer of the model, used to measure the context window utilization.
+    pub tokenizer: Tokenizer,
+    /// Messages pinned by the user. These are included in `context_messages`.
+    pub pins: &'a [PinnedMessage],
 }
 
 impl BackendConversationStateImpl<'_, std::collections::vec_deque::Iter<'_, HistoryEntry>, Option<Vec<HistoryEntry>>> {
@@ -956,44 +1228,69 @@ impl BackendConversationStateImpl<'_, std::collections::vec_deque::Iter<'_, Hist
         })
     }
 
+    /// Tokens used by the specifications of the tools available to the model.
+    pub fn tool_specs_token_count(&self) -> TokenCount {
+        self.tools
+            .values()
+            .map(|tools| self.tool_origin_token_count(tools))
+            .sum()
+    }
+
+    /// Tokens used by the given tool specifications.
+    pub fn tool_origin_token_count(&self, tools: &[Tool]) -> TokenCount {
+        serde_json::to_string(tools).map_or(TokenCount::from(0), |json| self.tokenizer.count_tokens(&json))
+    }
+
     pub fn calculate_conversation_size(&self) -> ConversationSize {
-        let mut user_chars = 0;
-        let mut assistant_chars = 0;
-        let mut context_chars = 0;
+        let mut user_tokens = TokenCount::from(0);
+        let mut assistant_tokens = TokenCount::from(0);
 
-        // Count the chars used by the messages in the history.
+        // Count the tokens used by the messages in the history.
         // this clone is cheap
         let history = self.history.clone();
         for HistoryEntry { user, assistant, .. } in history {
-            user_chars += *user.char_count();
-            assistant_chars += *assistant.char_count();
+            user_tokens = user_tokens + user.token_count(self.tokenizer);
+            assistant_tokens = assistant_tokens + assistant.token_count(self.tokenizer);
         }
 
-        // Add any chars from context messages, if available.
-        context_chars += self
+        // Add any tokens from context messages, if available.
+        let context_tokens: TokenCount = self
             .context_messages
-            .as_ref()
-            .map(|v| {
-                v.iter().fold(0, |acc, HistoryEntry { user, assistant, .. }| {
-                    acc + *user.char_count() + *assistant.char_count()
-                })
+            .iter()
+            .flatten()
+            .map(|HistoryEntry { user, assistant, .. }| {
+                user.token_count(self.tokenizer) + assistant.token_count(self.tokenizer)
             })
-            .unwrap_or_default();
+            .sum();
+
+        // Pins are sent as part of the context messages, but are accounted for separately.
+        let pinned_tokens = format_pins(self.pins).map_or(TokenCount::from(0), |pins| {
+            self.tokenizer.count_tokens(&pins).min(context_tokens)
+        });
 
         ConversationSize {
-            context_messages: context_chars.into(),
-            user_messages: user_chars.into(),
-            assistant_messages: assistant_chars.into(),
+            context_messages: TokenCount::from(context_tokens.value() - pinned_tokens.value()),
+            pinned_messages: pinned_tokens,
+            user_messages: user_tokens,
+            assistant_messages: assistant_tokens,
         }
     }
 }
 
 /// Reflects a detailed accounting of the context window utilization for a given conversation.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ConversationSize {
-    pub context_messages: CharCount,
-    pub user_messages: CharCount,
-    pub assistant_messages: CharCount,
+    pub context_messages: TokenCount,
+    pub pinned_messages: TokenCount,
+    pub user_messages: TokenCount,
+    pub assistant_messages: TokenCount,
+}
+
+impl ConversationSize {
+    pub fn total(&self) -> TokenCount {
+        self.context_messages + self.pinned_messages + self.user_messages + self.assistant_messages
+    }
 }
 
 /// Converts a list of user/assistant message pairs into a flattened list of ChatMessage.
@@ -1025,6 +1322,25 @@ impl From<InputSchema> for ToolInputSchema {
     }
 }
 
+/// Formats pinned messages into a context block.
+///
+/// # Returns
+/// [Option::None] if there are no pins.
+fn format_pins(pins: &[PinnedMessage]) -> Option<String> {
+    if pins.is_empty() {
+        return None;
+    }
+
+    let mut context_content = String::new();
+    context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+    context_content.push_str("The user pinned the following messages for the entire conversation. YOU MUST follow any requirements, constraints and signatures they contain exactly as written.\n\n");
+    for (i, pin) in pins.iter().enumerate() {
+        context_content.push_str(&format!("[Pin {}]\n{}\n\n", i + 1, pin.content));
+    }
+    context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+    Some(context_content)
+}
+
 /// Formats hook output to be used within context blocks (e.g., in context messages or in new user
 /// prompts).
 ///
@@ -1205,7 +1521,16 @@ fn default_true() -> bool {
 }
 #[cfg(test)]
 mod tests {
-    use super::super::message::AssistantToolUse;
+    use super::super::cli::compact::{
+        HierarchicalCompactor,
+        PruneToolResultsCompactor,
+        RollingWindowCompactor,
+        SummaryCompactor,
+    };
+    use super::super::message::{
+        AssistantToolUse,
+        ToolUseResultBlock,
+    };
     use super::*;
     use crate::api_client::model::{
         AssistantResponseMessage,
@@ -1308,6 +1633,44 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_conversation_sessions_are_saved_per_id() {
+        let mut os = Os::new().await.unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        let mut tool_manager = ToolManager::default();
+        let tool_config = tool_manager.load_tools(&mut os, &mut vec![]).await.unwrap();
+
+        for (id, prompt) in [("session_a", "first prompt\nmore details"), ("session_b", "second prompt")] {
+            let mut conversation = ConversationState::new(
+                id,
+                Agents::default(),
+                tool_config.clone(),
+                tool_manager.clone(),
+                None,
+                &os,
+                false,
+            )
+            .await;
+            conversation.set_next_user_message(prompt.to_string()).await;
+            conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "ok".to_string()), None);
+            conversation.set_next_user_message("follow up".to_string()).await;
+            conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "ok".to_string()), None);
+        }
+
+        let sessions = os.database.list_sessions(Some(&cwd)).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let session_a = sessions.iter().find(|s| s.id == "session_a").unwrap();
+        assert_eq!(session_a.title.as_deref(), Some("first prompt"));
+
+        let resumed = os.database.get_session("session_b").unwrap().unwrap();
+        assert_eq!(resumed.history().len(), 2);
+        assert_eq!(os.database.find_session("session_").unwrap(), None, "prefix is ambiguous");
+
+        assert!(os.database.delete_session("session_a").unwrap());
+        assert_eq!(os.database.list_sessions(Some(&cwd)).unwrap().len(), 1);
+        assert_eq!(os.database.prune_sessions(i64::MAX).unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn test_conversation_state_history_handling_truncation() {
         let mut os = Os::new().await.unwrap();
@@ -1556,6 +1919,204 @@ mod tests {
         assert!(!conversation.is_in_tangent_mode());
     }
 
+    #[tokio::test]
+    async fn test_checkpoints() {
+        let mut os = Os::new().await.unwrap();
+        let agents = Agents::default();
+        let mut tool_manager = ToolManager::default();
+        let mut conversation = ConversationState::new(
+            "fake_conv_id",
+            agents,
+            tool_manager.load_tools(&mut os, &mut vec![]).await.unwrap(),
+            tool_manager,
+            None,
+            &os,
+            false, // mcp_enabled
+        )
+        .await;
+
+        conversation.set_next_user_message("first".to_string()).await;
+        conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "one".to_string()), None);
+        assert!(!conversation.save_checkpoint("a".to_string()));
+
+        conversation.set_next_user_message("second".to_string()).await;
+        conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "two ".repeat(100)), None);
+        assert!(!conversation.save_checkpoint("b".to_string()));
+
+        conversation.set_next_user_message("third".to_string()).await;
+        conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "three".to_string()), None);
+        assert_eq!(conversation.history.len(), 3);
+
+        let names = conversation
+            .checkpoints()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(conversation.checkpoints()[1].message_count(), 4);
+        assert!(*conversation.checkpoints()[1].token_count() > 0);
+
+        // Restoring an older checkpoint keeps the newer ones around.
+        assert!(conversation.restore_checkpoint("missing").is_none());
+        assert_eq!(conversation.restore_checkpoint("a").unwrap().name, "a");
+        assert_eq!(conversation.history.len(), 1);
+        assert_eq!(conversation.transcript.len(), 1);
+        assert_eq!(conversation.valid_history_range, (0, 1));
+        assert_eq!(conversation.checkpoints().len(), 2);
+
+        conversation.restore_checkpoint("b").unwrap();
+        assert_eq!(conversation.history.len(), 2);
+
+        // Saving with an existing name replaces the checkpoint.
+        conversation.restore_checkpoint("a").unwrap();
+        assert!(conversation.save_checkpoint("b".to_string()));
+        assert_eq!(conversation.checkpoints().len(), 2);
+        assert_eq!(conversation.checkpoints()[1].message_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pins_survive_compaction() {
+        let mut os = Os::new().await.unwrap();
+        let agents = Agents::default();
+        let mut tool_manager = ToolManager::default();
+        let mut conversation = ConversationState::new(
+            "fake_conv_id",
+            agents,
+            tool_manager.load_tools(&mut os, &mut vec![]).await.unwrap(),
+            tool_manager,
+            None,
+            &os,
+            false, // mcp_enabled
+        )
+        .await;
+
+        let requirement = "fn parse(input: &str) -> Result<Ast, ParseError>";
+        conversation.set_next_user_message(requirement.to_string()).await;
+        conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "ok".to_string()), None);
+        conversation.pin(requirement.to_string());
+        conversation.pin("always use tabs".to_string());
+
+        conversation.replace_history_with_summary(
+            "summary".to_string(),
+            CompactStrategy::default(),
+            &SummaryCompactor,
+            RequestMetadata::default(),
+        );
+        assert!(conversation.history.is_empty());
+        assert_eq!(conversation.pins().len(), 2);
+
+        conversation.set_next_user_message("next".to_string()).await;
+        let state = conversation
+            .backend_conversation_state(&os, false, &mut vec![])
+            .await
+            .unwrap();
+        let context = state.context_messages.as_ref().unwrap()[0].user.prompt().unwrap();
+        assert!(context.contains("SUMMARY CONTENT:\nsummary"));
+        assert!(context.contains(&format!("[Pin 1]\n{requirement}\n")));
+        assert!(context.contains("[Pin 2]\nalways use tabs\n"));
+        assert!(*state.calculate_conversation_size().pinned_messages > 0);
+
+        assert_eq!(conversation.unpin(0).unwrap().content, requirement);
+        assert!(conversation.unpin(1).is_none());
+        assert_eq!(conversation.clear_pins(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compactors() {
+        let mut os = Os::new().await.unwrap();
+        let agents = Agents::default();
+        let mut tool_manager = ToolManager::default();
+        let mut conversation = ConversationState::new(
+            "fake_conv_id",
+            agents,
+            tool_manager.load_tools(&mut os, &mut vec![]).await.unwrap(),
+            tool_manager,
+            None,
+            &os,
+            false, // mcp_enabled
+        )
+        .await;
+
+        // Each turn adds two entries: the prompt with a tool use, and the tool result with the
+        // response.
+        for i in 0..4 {
+            conversation.set_next_user_message(format!("prompt {i}")).await;
+            conversation.push_assistant_message(
+                &mut os,
+                AssistantMessage::new_tool_use(None, String::new(), vec![AssistantToolUse {
+                    id: format!("tool_{i}"),
+                    name: "fs_read".to_string(),
+                    ..Default::default()
+                }]),
+                None,
+            );
+            conversation.add_tool_results(vec![ToolUseResult {
+                tool_use_id: format!("tool_{i}"),
+                content: vec![ToolUseResultBlock::Text("output ".repeat(100))],
+                status: ToolResultStatus::Success,
+            }]);
+            conversation.push_assistant_message(
+                &mut os,
+                AssistantMessage::new_response(None, format!("response {i}")),
+                None,
+            );
+        }
+        assert_eq!(conversation.history.len(), 8);
+        let strategy = CompactStrategy::default();
+        let is_pruned = |entry: &HistoryEntry| entry.user.tool_use_results().unwrap()[0].is_pruned();
+
+        // Pruning only touches tool results outside of the most recent entries, and falls back to
+        // a summary once there is nothing left to prune.
+        let prune = PruneToolResultsCompactor::default();
+        assert_eq!(conversation.entries_to_summarize(&strategy, &prune), None);
+        conversation.compact_history_without_summary(&strategy, &prune);
+        assert_eq!(conversation.history.len(), 8);
+        assert!(is_pruned(&conversation.history[1]));
+        assert!(is_pruned(&conversation.history[3]));
+        assert!(!is_pruned(&conversation.history[5]));
+        assert!(!is_pruned(&conversation.history[7]));
+        assert_eq!(conversation.entries_to_summarize(&strategy, &prune), Some(8));
+
+        // The rolling window keeps the newest half, starting at a prompt.
+        conversation.replace_history_with_summary(
+            "first".to_string(),
+            strategy,
+            &RollingWindowCompactor,
+            RequestMetadata::default(),
+        );
+        assert_eq!(conversation.history.len(), 4);
+        assert_eq!(conversation.history[0].user.prompt(), Some("prompt 2"));
+        assert_eq!(conversation.latest_summary(), Some("first"));
+
+        // Hierarchical compaction keeps the previous summary.
+        conversation.replace_history_with_summary(
+            "second".to_string(),
+            strategy,
+            &HierarchicalCompactor,
+            RequestMetadata::default(),
+        );
+        assert!(conversation.history.is_empty());
+        assert_eq!(conversation.latest_summary(), Some("first\n\nsecond"));
+
+        // Any other implementation can be swapped in.
+        #[derive(Debug)]
+        struct KeepSummaryOnly;
+        impl Compactor for KeepSummaryOnly {
+            fn entries_to_summarize(&self, _: &VecDeque<HistoryEntry>, _: &CompactStrategy) -> Option<usize> {
+                None
+            }
+
+            fn compact(&self, history: &mut VecDeque<HistoryEntry>, _: Option<usize>, _: &CompactStrategy) {
+                history.clear();
+            }
+        }
+        conversation.set_next_user_message("prompt".to_string()).await;
+        conversation.push_assistant_message(&mut os, AssistantMessage::new_response(None, "ok".to_string()), None);
+        conversation.compact_history_without_summary(&strategy, &KeepSummaryOnly);
+        assert!(conversation.history.is_empty());
+        assert_eq!(conversation.latest_summary(), Some("first\n\nsecond"));
+    }
+
     #[tokio::test]
     async fn test_tangent_mode_duration() {
         let mut os = Os::new().await.unwrap();
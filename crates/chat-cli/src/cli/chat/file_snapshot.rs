@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use eyre::Result;
+
+use crate::os::Os;
+
+/// Contents of a file before it was first modified by `fs_write` within a turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// [None] if the file did not exist, ie. it was created by `fs_write`.
+    pub content: Option<Vec<u8>>,
+}
+
+/// Files modified by `fs_write` during a single user turn.
+#[derive(Debug, Clone)]
+pub struct TurnSnapshot {
+    /// 1-indexed number of the user turn within the chat session.
+    pub turn: usize,
+    pub files: Vec<FileSnapshot>,
+}
+
+/// Keeps the pre-image of every file written to by `fs_write`, grouped by user turn, so that the
+/// changes can be reverted with `/undo`. Snapshots only live as long as the chat session.
+#[derive(Debug, Default)]
+pub struct FileSnapshotTracker {
+    current_turn: usize,
+    /// Turns with at least one snapshot, in ascending order.
+    turns: Vec<TurnSnapshot>,
+}
+
+impl FileSnapshotTracker {
+    /// Marks the start of a new user turn.
+    pub fn start_turn(&mut self) {
+        self.current_turn += 1;
+    }
+
+    pub fn turns(&self) -> &[TurnSnapshot] {
+        &self.turns
+    }
+
+    /// Snapshots the current contents of `path`, unless it was already snapshotted during the
+    /// current turn.
+    pub async fn record(&mut self, os: &Os, path: PathBuf) -> Result<()> {
+        if self.turns.last().is_none_or(|t| t.turn != self.current_turn) {
+            self.turns.push(TurnSnapshot {
+                turn: self.current_turn,
+                files: Vec::new(),
+            });
+        }
+        let Some(turn) = self.turns.last_mut() else {
+            return Ok(());
+        };
+        if turn.files.iter().any(|f| f.path == path) {
+            return Ok(());
+        }
+
+        let content = if os.fs.exists(&path) {
+            Some(os.fs.read(&path).await?)
+        } else {
+            None
+        };
+        turn.files.push(FileSnapshot { path, content });
+
+        Ok(())
+    }
+
+    /// Adds snapshots taken by another tracker, e.g. the one of a sub-agent, to the current turn.
+    /// Files that were already snapshotted during the current turn keep their earlier snapshot.
+    pub fn merge(&mut self, snapshots: Vec<FileSnapshot>) {
+        if snapshots.is_empty() {
+            return;
+        }
+        if self.turns.last().is_none_or(|t| t.turn != self.current_turn) {
+            self.turns.push(TurnSnapshot {
+                turn: self.current_turn,
+                files: Vec::new(),
+            });
+        }
+        let Some(turn) = self.turns.last_mut() else {
+            return;
+        };
+        for snapshot in snapshots {
+            if !turn.files.iter().any(|f| f.path == snapshot.path) {
+                turn.files.push(snapshot);
+            }
+        }
+    }
+
+    /// The turn that `/undo` reverts when no turn is given.
+    pub fn latest_turn(&self) -> Option<usize> {
+        self.turns.last().map(|t| t.turn)
+    }
+
+    /// Returns the contents each file had before `turn`, for every file modified during `turn` or
+    /// any turn after it.
+    pub fn snapshots_since(&self, turn: usize) -> Vec<FileSnapshot> {
+        let mut seen = HashSet::new();
+        self.turns
+            .iter()
+            .filter(|t| t.turn >= turn)
+            .flat_map(|t| t.files.iter())
+            .filter(|f| seen.insert(f.path.clone()))
+            .cloned()
+            .collect()
+    }
+
+    /// Reverts every file modified during `turn` and the turns after it, deleting files that were
+    /// created. The reverted turns are no longer tracked afterwards.
+    ///
+    /// Returns the restored snapshots.
+    pub async fn restore(&mut self, os: &Os, turn: usize) -> Result<Vec<FileSnapshot>> {
+        let snapshots = self.snapshots_since(turn);
+        for snapshot in &snapshots {
+            match &snapshot.content {
+                Some(content) => {
+                    if let Some(parent) = snapshot.path.parent() {
+                        os.fs.create_dir_all(parent).await?;
+                    }
+                    os.fs.write(&snapshot.path, content).await?;
+                },
+                None if os.fs.exists(&snapshot.path) => os.fs.remove_file(&snapshot.path).await?,
+                None => (),
+            }
+        }
+        self.turns.retain(|t| t.turn < turn);
+
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restore_reverts_turns() {
+        let os = Os::new().await.unwrap();
+        let existing = PathBuf::from("/existing.txt");
+        let created = PathBuf::from("/created.txt");
+        os.fs.write(&existing, "original").await.unwrap();
+
+        let mut tracker = FileSnapshotTracker::default();
+        assert!(tracker.latest_turn().is_none());
+
+        // Turn 1 modifies the existing file twice, only the first pre-image should be kept.
+        tracker.start_turn();
+        tracker.record(&os, existing.clone()).await.unwrap();
+        os.fs.write(&existing, "turn 1").await.unwrap();
+        tracker.record(&os, existing.clone()).await.unwrap();
+        os.fs.write(&existing, "turn 1 again").await.unwrap();
+
+        // Turn 2 doesn't write anything.
+        tracker.start_turn();
+
+        // Turn 3 creates a new file and modifies the existing one.
+        tracker.start_turn();
+        tracker.record(&os, created.clone()).await.unwrap();
+        os.fs.write(&created, "new").await.unwrap();
+        tracker.record(&os, existing.clone()).await.unwrap();
+        os.fs.write(&existing, "turn 3").await.unwrap();
+
+        assert_eq!(tracker.turns().iter().map(|t| t.turn).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(tracker.latest_turn(), Some(3));
+        assert_eq!(tracker.snapshots_since(1).len(), 2);
+
+        let restored = tracker.restore(&os, 3).await.unwrap();
+        assert_eq!(restored.len(), 2);
+        assert!(!os.fs.exists(&created));
+        assert_eq!(os.fs.read_to_string(&existing).await.unwrap(), "turn 1 again");
+        assert_eq!(tracker.latest_turn(), Some(1));
+
+        tracker.restore(&os, 1).await.unwrap();
+        assert_eq!(os.fs.read_to_string(&existing).await.unwrap(), "original");
+        assert!(tracker.turns().is_empty());
+    }
+}
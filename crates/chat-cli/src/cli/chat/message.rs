@@ -263,6 +263,17 @@ impl UserMessage {
         self.content.truncate_safe(max_bytes);
     }
 
+    /// Replaces the content of each tool use result with a short placeholder.
+    pub fn prune_tool_use_results(&mut self) {
+        if let UserMessageContent::CancelledToolUses { tool_use_results, .. }
+        | UserMessageContent::ToolUseResults { tool_use_results } = &mut self.content
+        {
+            for result in tool_use_results {
+                result.content = vec![ToolUseResultBlock::Text(ToolUseResult::PRUNED_CONTENT.to_string())];
+            }
+        }
+    }
+
     pub fn replace_content_with_tool_use_results(&mut self) {
         if let Some(tool_results) = self.tool_use_results() {
             let tool_content: Vec<String> = tool_results
@@ -339,6 +350,16 @@ pub struct ToolUseResult {
     pub status: ToolResultStatus,
 }
 
+impl ToolUseResult {
+    /// Placeholder for tool output removed by [UserMessage::prune_tool_use_results].
+    pub const PRUNED_CONTENT: &str = "[Tool output removed to save context space]";
+
+    /// Whether the content was replaced by [UserMessage::prune_tool_use_results].
+    pub fn is_pruned(&self) -> bool {
+        matches!(self.content.as_slice(), [ToolUseResultBlock::Text(text)] if text == Self::PRUNED_CONTENT)
+    }
+}
+
 impl From<ToolResult> for ToolUseResult {
     fn from(value: ToolResult) -> Self {
         Self {
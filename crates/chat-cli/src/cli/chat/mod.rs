@@ -2,20 +2,26 @@ pub mod cli;
 mod consts;
 pub mod context;
 mod conversation;
+mod file_snapshot;
 mod input_source;
 mod message;
 mod parse;
 use std::path::MAIN_SEPARATOR;
 mod line_tracker;
+pub mod output_format;
 mod parser;
 mod prompt;
 mod prompt_parser;
+pub mod run;
+pub mod serve;
 pub mod server_messenger;
+pub mod sessions;
 #[cfg(unix)]
 mod skim_integration;
 mod token_counter;
 pub mod tool_manager;
 pub mod tools;
+mod transcript;
 pub mod util;
 use std::borrow::Cow;
 use std::collections::{
@@ -39,15 +45,20 @@ use clap::{
     Args,
     CommandFactory,
     Parser,
+    Subcommand,
     ValueEnum,
 };
-use cli::compact::CompactStrategy;
+use cli::compact::{
+    CompactStrategy,
+    CompactionKind,
+};
 use cli::hooks::ToolContext;
 use cli::model::{
     find_model,
     get_available_models,
     select_model,
 };
+use consts::MAX_CONCURRENT_TOOL_USES;
 pub use conversation::ConversationState;
 use conversation::TokenWarningLevel;
 use crossterm::style::{
@@ -68,6 +79,8 @@ use eyre::{
     bail,
     eyre,
 };
+use file_snapshot::FileSnapshotTracker;
+use futures::StreamExt;
 use input_source::InputSource;
 use message::{
     AssistantMessage,
@@ -75,6 +88,12 @@ use message::{
     ToolUseResult,
     ToolUseResultBlock,
 };
+use output_format::{
+    ChatOutputFormat,
+    ExitReason,
+    OutputEvent,
+    OutputEventWriter,
+};
 use parse::{
     ParseState,
     interpret_markdown,
@@ -86,6 +105,11 @@ use parser::{
 };
 use regex::Regex;
 use rmcp::model::PromptMessage;
+use run::{
+    RunExitReason,
+    RunTracker,
+};
+use serve::StdioServer;
 use spinners::{
     Spinner,
     Spinners,
@@ -98,14 +122,25 @@ use tokio::sync::{
     Mutex,
     broadcast,
 };
+use tokio::task::AbortHandle;
 use tool_manager::{
     PromptQuery,
     PromptQueryResult,
     ToolManager,
     ToolManagerBuilder,
 };
+use tools::delegate::{
+    Delegate,
+    MAX_DELEGATION_DEPTH,
+    SubagentRun,
+};
+use tools::execute::{
+    BackgroundJobs,
+    PersistentShell,
+};
 use tools::gh_issue::GhIssueContext;
 use tools::{
+    InvokeOutput,
     NATIVE_TOOLS,
     OutputKind,
     QueuedTool,
@@ -119,6 +154,10 @@ use tracing::{
     trace,
     warn,
 };
+use transcript::{
+    TranscriptOptions,
+    render_transcript,
+};
 use util::images::RichImageBlock;
 use util::ui::draw_box;
 use util::{
@@ -128,6 +167,7 @@ use util::{
 use winnow::Partial;
 use winnow::stream::Offset;
 
+use super::agent::params::parse_param;
 use super::agent::{
     Agent,
     DEFAULT_AGENT_NAME,
@@ -149,6 +189,10 @@ use crate::cli::chat::cli::prompts::{
     PromptsSubcommand,
 };
 use crate::cli::chat::message::UserMessage;
+use crate::cli::chat::sessions::{
+    SessionsSubcommand,
+    select_session,
+};
 use crate::cli::chat::util::sanitize_unicode_tags;
 use crate::database::settings::Setting;
 use crate::os::Os;
@@ -166,6 +210,7 @@ use crate::telemetry::{
     get_error_reason,
 };
 use crate::util::{
+    CLI_BINARY_NAME,
     MCP_SERVER_TOOL_DELIMITER,
     directories,
     ui,
@@ -207,9 +252,19 @@ pub struct ChatArgs {
     /// Resumes the previous conversation from this directory.
     #[arg(short, long)]
     pub resume: bool,
+    /// Resumes the saved session with this id, or with an id starting with it
+    #[arg(long, value_name = "ID", conflicts_with = "resume")]
+    pub resume_id: Option<String>,
+    /// Interactively pick one of the sessions saved for this directory to resume
+    #[arg(long, conflicts_with_all = ["resume", "resume_id"])]
+    pub resume_picker: bool,
     /// Context profile to use
     #[arg(long = "agent", alias = "profile")]
     pub agent: Option<String>,
+    /// Parameter for the agent, validated against its inputSchema. Can be given multiple times.
+    /// Example: '--param path=src'
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    pub params: Vec<(String, String)>,
     /// Current model to use
     #[arg(long = "model")]
     pub model: Option<String>,
@@ -223,15 +278,58 @@ pub struct ChatArgs {
     /// Whether the command should run without expecting user input
     #[arg(long, alias = "non-interactive")]
     pub no_interactive: bool,
-    /// The first question to ask
+    /// The first question to ask. A question that is only the word "sessions" must be given after
+    /// -- to not run the sessions subcommand
     pub input: Option<String>,
     /// Control line wrapping behavior (default: auto-detect)
     #[arg(short = 'w', long, value_enum)]
     pub wrap: Option<WrapMode>,
+    /// Output format for non-interactive sessions (default: text)
+    #[arg(long, value_enum, requires = "no_interactive")]
+    pub output_format: Option<ChatOutputFormat>,
+    /// Run as a JSON-RPC server over stdin and stdout for editor and IDE integrations, instead of
+    /// reading prompts from the terminal
+    #[arg(long, conflicts_with_all = ["input", "no_interactive", "resume_picker"])]
+    pub serve_stdio: bool,
+    #[command(subcommand)]
+    pub subcommand: Option<ChatSubcommand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum ChatSubcommand {
+    /// Manage the chat sessions saved for each directory
+    #[command(subcommand)]
+    Sessions(SessionsSubcommand),
 }
 
 impl ChatArgs {
     pub async fn execute(mut self, os: &mut Os) -> Result<ExitCode> {
+        if let Some(ChatSubcommand::Sessions(subcommand)) = self.subcommand {
+            return subcommand.execute(os, &mut std::io::stdout()).await;
+        }
+
+        let previous_conversation = if self.resume_picker {
+            if self.no_interactive {
+                bail!("--resume-picker cannot be used in non-interactive mode");
+            }
+            match select_session(os)? {
+                Some(conversation) => Some(conversation),
+                None => return Ok(ExitCode::SUCCESS),
+            }
+        } else if let Some(id) = &self.resume_id {
+            match os.database.get_session(id)? {
+                Some(conversation) => Some(conversation),
+                None => bail!("No session found with id '{id}'. See {CLI_BINARY_NAME} chat sessions list"),
+            }
+        } else if self.resume {
+            std::env::current_dir()
+                .ok()
+                .and_then(|cwd| os.database.get_latest_session_by_path(cwd).ok())
+                .flatten()
+        } else {
+            None
+        };
+
         let mut input = self.input;
 
         if self.no_interactive && input.is_none() {
@@ -275,6 +373,7 @@ impl ChatArgs {
             )?;
         }
 
+        let interactive = !self.no_interactive && !self.serve_stdio;
         let conversation_id = uuid::Uuid::new_v4().to_string();
         info!(?conversation_id, "Generated new conversation id");
 
@@ -288,7 +387,7 @@ impl ChatArgs {
         };
 
         let agents = {
-            let skip_migration = self.no_interactive;
+            let skip_migration = !interactive;
             let (mut agents, md) =
                 Agents::load(os, self.agent.as_deref(), skip_migration, &mut stderr, mcp_enabled).await;
             agents.trust_all_tools = self.trust_all_tools;
@@ -311,7 +410,7 @@ impl ChatArgs {
                     .get_active()
                     .is_some_and(|a| !a.mcp_servers.mcp_servers.is_empty())
             {
-                if !self.no_interactive && !os.database.settings.get_bool(Setting::McpLoadedBefore).unwrap_or(false) {
+                if interactive && !os.database.settings.get_bool(Setting::McpLoadedBefore).unwrap_or(false) {
                     execute!(
                         stderr,
                         style::Print(
@@ -350,6 +449,10 @@ impl ChatArgs {
                 }
             }
 
+            if let Some(agent) = agents.get_active_mut() {
+                agent.apply_param_args(std::mem::take(&mut self.params), interactive)?;
+            }
+
             agents
         };
 
@@ -411,11 +514,11 @@ impl ChatArgs {
             .prompt_query_result_receiver(prompt_response_receiver.resubscribe())
             .conversation_id(&conversation_id)
             .agent(agents.get_active().cloned().unwrap_or_default())
-            .build(os, Box::new(std::io::stderr()), !self.no_interactive)
+            .build(os, Box::new(std::io::stderr()), interactive)
             .await?;
         let tool_config = tool_manager.load_tools(os, &mut stderr).await?;
 
-        ChatSession::new(
+        let mut session = ChatSession::new(
             os,
             stdout,
             stderr,
@@ -423,19 +526,22 @@ impl ChatArgs {
             agents,
             input,
             InputSource::new(os, prompt_request_sender, prompt_response_receiver)?,
-            self.resume,
+            previous_conversation,
             || terminal::window_size().map(|s| s.columns.into()).ok(),
             tool_manager,
             model_id,
             tool_config,
-            !self.no_interactive,
+            interactive,
             mcp_enabled,
             self.wrap,
+            self.output_format,
         )
-        .await?
-        .spawn(os)
-        .await
-        .map(|_| ExitCode::SUCCESS)
+        .await?;
+        if self.serve_stdio {
+            session = session.with_stdio_server(StdioServer::stdin());
+        }
+
+        session.spawn(os).await.map(|_| ExitCode::SUCCESS)
     }
 }
 
@@ -645,6 +751,35 @@ pub struct ChatSession {
     inner: Option<ChatState>,
     ctrlc_rx: broadcast::Receiver<()>,
     wrap: Option<WrapMode>,
+    /// Writes machine-readable events to stdout, depending on the selected [ChatOutputFormat].
+    output_events: OutputEventWriter,
+    /// Pre-images of files written by `fs_write`, used by `/undo`.
+    file_snapshots: FileSnapshotTracker,
+    /// Reads requests instead of [Self::input_source] when running with `--serve-stdio`.
+    stdio_server: Option<StdioServer>,
+    /// Shell that `execute_bash` runs commands in when the persistent shell is enabled.
+    persistent_shell: PersistentShell,
+    /// Commands started in the background by `execute_bash`, killed when the session ends.
+    background_jobs: BackgroundJobs,
+    /// Aborts the task listening for sigints once the session is dropped.
+    ctrlc_task: AbortHandle,
+    /// How deep this session is nested as a sub-agent, 0 for the main conversation.
+    subagent_depth: usize,
+    /// The sub-agent working on a task given through the `delegate` tool.
+    subagent: Option<Subagent>,
+    /// Finished runs of sub-agents, shown by `/subagents`.
+    subagent_runs: Vec<SubagentRun>,
+    /// Set for sessions of `q agent run` and their sub-agents, which reject tool uses that need
+    /// approval instead of prompting for it.
+    run: Option<RunTracker>,
+}
+
+/// A sub-agent working on a task given through the `delegate` tool, see
+/// [ChatSession::run_subagent].
+struct Subagent {
+    session: Box<ChatSession>,
+    delegate: Delegate,
+    start: Instant,
 }
 
 impl ChatSession {
@@ -657,7 +792,7 @@ impl ChatSession {
         mut agents: Agents,
         mut input: Option<String>,
         input_source: InputSource,
-        resume_conversation: bool,
+        previous_conversation: Option<ConversationState>,
         terminal_width_provider: fn() -> Option<usize>,
         tool_manager: ToolManager,
         model_id: Option<String>,
@@ -665,20 +800,14 @@ impl ChatSession {
         interactive: bool,
         mcp_enabled: bool,
         wrap: Option<WrapMode>,
+        output_format: Option<ChatOutputFormat>,
     ) -> Result<Self> {
-        // Reload prior conversation
-        let mut existing_conversation = false;
-        let previous_conversation = std::env::current_dir()
-            .ok()
-            .and_then(|cwd| os.database.get_conversation_by_path(cwd).ok())
-            .flatten();
-
         // Only restore conversations where there were actual messages.
         // Prevents edge case where user clears conversation then exits without chatting.
-        let conversation = match resume_conversation
-            && previous_conversation
-                .as_ref()
-                .is_some_and(|cs| !cs.history().is_empty())
+        let mut existing_conversation = false;
+        let conversation = match previous_conversation
+            .as_ref()
+            .is_some_and(|cs| !cs.history().is_empty())
         {
             true => {
                 let mut cs = previous_conversation.unwrap();
@@ -721,7 +850,7 @@ impl ChatSession {
 
         // Spawn a task for listening and broadcasting sigints.
         let (ctrlc_tx, ctrlc_rx) = tokio::sync::broadcast::channel(4);
-        tokio::spawn(async move {
+        let ctrlc_task = tokio::spawn(async move {
             loop {
                 match ctrl_c().await {
                     Ok(_) => {
@@ -734,7 +863,8 @@ impl ChatSession {
                     },
                 }
             }
-        });
+        })
+        .abort_handle();
 
         Ok(Self {
             stdout,
@@ -757,16 +887,46 @@ impl ChatSession {
             inner: Some(ChatState::default()),
             ctrlc_rx,
             wrap,
+            output_events: OutputEventWriter::new(output_format.unwrap_or_default()),
+            file_snapshots: FileSnapshotTracker::default(),
+            stdio_server: None,
+            persistent_shell: PersistentShell::default(),
+            background_jobs: BackgroundJobs::default(),
+            ctrlc_task,
+            subagent_depth: 0,
+            subagent: None,
+            subagent_runs: Vec::new(),
+            run: None,
         })
     }
 
+    /// Serves the session over JSON-RPC instead of prompting on the terminal. See [serve].
+    pub fn with_stdio_server(mut self, server: StdioServer) -> Self {
+        self.output_events = OutputEventWriter::new(ChatOutputFormat::JsonRpc);
+        self.stdio_server = Some(server);
+        self
+    }
+
     pub async fn next(&mut self, os: &mut Os) -> Result<(), ChatError> {
         // Update conversation state with new tool information
         self.conversation.update_state(false).await;
 
         let mut ctrl_c_stream = self.ctrlc_rx.resubscribe();
         let result = match self.inner.take().expect("state must always be Some") {
+            ChatState::PromptUser { .. } if self.stdio_server.is_some() => {
+                let mut server = self.stdio_server.take().expect("checked above");
+                let result = server.handle_requests(os, self).await;
+                self.stdio_server = Some(server);
+                result
+            },
             ChatState::PromptUser { skip_printing_tools } => {
+                // Sub-agents only prompt the user to approve tool uses, and end once the model
+                // responds without using tools.
+                if self.subagent_depth > 0 && self.pending_tool_index.is_none() {
+                    self.inner = Some(ChatState::Exit);
+                    return Ok(());
+                }
+
                 match (self.interactive, self.tool_uses.is_empty()) {
                     (false, true) => {
                         self.inner = Some(ChatState::Exit);
@@ -844,7 +1004,13 @@ impl ChatSession {
 
         // We encountered an error. Handle it.
         error!(?err, "An error occurred processing the current state");
+        // A running sub-agent is stopped along with the current state.
+        self.finish_subagent(Some(err.to_string()));
         let (reason, reason_desc) = get_error_reason(&err);
+        self.output_events.emit(&mut self.stdout, OutputEvent::Error {
+            reason: reason.clone(),
+            message: err.to_string(),
+        })?;
         self.send_error_telemetry(os, reason, Some(reason_desc), err.status_code())
             .await;
 
@@ -936,12 +1102,13 @@ impl ChatSession {
                                 } else {
                                     Default::default()
                                 },
+                                kind: CompactionKind::from_settings(os),
                                 ..Default::default()
                             },
                         });
 
                         execute!(
-                            self.stdout,
+                            &mut self.display_output(),
                             style::SetForegroundColor(Color::Yellow),
                             style::Print("The context window has overflowed, summarizing the history..."),
                             style::SetAttribute(Attribute::Reset),
@@ -1147,6 +1314,8 @@ impl ChatSession {
 
 impl Drop for ChatSession {
     fn drop(&mut self) {
+        self.ctrlc_task.abort();
+
         if let Some(spinner) = &mut self.spinner {
             spinner.stop();
         }
@@ -1327,9 +1496,19 @@ impl ChatSession {
         }
 
         while !matches!(self.inner, Some(ChatState::Exit)) {
-            self.next(os).await?;
+            if let Err(err) = self.next(os).await {
+                let reason = match err {
+                    ChatError::NonInteractiveToolApproval => ExitReason::ToolApprovalRequired,
+                    _ => ExitReason::Error,
+                };
+                self.output_events.finish(&mut self.stdout, reason)?;
+                return Err(err.into());
+            }
         }
 
+        let reason = self.output_events.implied_exit_reason();
+        self.output_events.finish(&mut self.stdout, reason)?;
+
         Ok(())
     }
 
@@ -1400,6 +1579,42 @@ impl ChatSession {
             });
         }
 
+        let compactor = strategy.kind.compactor();
+        if self
+            .conversation
+            .entries_to_summarize(&strategy, compactor.as_ref())
+            .is_none()
+        {
+            let tokens_before = self.conversation.calculate_token_count(os).await?;
+            self.conversation
+                .compact_history_without_summary(&strategy, compactor.as_ref());
+            let tokens_after = self.conversation.calculate_token_count(os).await?;
+            execute!(
+                self.stderr,
+                style::SetForegroundColor(Color::Green),
+                style::Print("\n✔ Conversation history has been compacted successfully!\n\n"),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!(
+                    "• Freed ~{} tokens with the {} strategy\n\n",
+                    tokens_before.value().saturating_sub(tokens_after.value()),
+                    strategy.kind
+                )),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+
+            return if self.conversation.next_user_message().is_some() {
+                Ok(ChatState::HandleResponseStream(
+                    self.conversation
+                        .as_sendable_conversation_state(os, &mut self.stderr, false)
+                        .await?,
+                ))
+            } else {
+                Ok(ChatState::PromptUser {
+                    skip_printing_tools: true,
+                })
+            };
+        }
+
         if strategy.truncate_large_messages {
             info!("truncating large messages");
             execute!(
@@ -1415,7 +1630,7 @@ impl ChatSession {
 
         let summary_state = self
             .conversation
-            .create_summary_request(os, custom_prompt.as_ref(), strategy)
+            .create_summary_request(os, custom_prompt.as_ref(), strategy, compactor.as_ref())
             .await?;
 
         if self.interactive {
@@ -1464,6 +1679,7 @@ impl ChatSession {
                                     truncate_large_messages: true,
                                     max_message_length: 25_000,
                                     messages_to_exclude: 0,
+                                    ..strategy
                                 },
                             });
                         }
@@ -1548,7 +1764,7 @@ impl ChatSession {
         }
 
         self.conversation
-            .replace_history_with_summary(summary.clone(), strategy, request_metadata);
+            .replace_history_with_summary(summary.clone(), strategy, compactor.as_ref(), request_metadata);
 
         // If a next message is set, then retry the request.
         let should_retry = self.conversation.next_user_message().is_some();
@@ -1573,6 +1789,15 @@ impl ChatSession {
                     style::Print(format!("• Custom prompt applied: {}\n", custom_prompt))
                 )?;
             }
+            if !self.conversation.pins().is_empty() {
+                execute!(
+                    output,
+                    style::Print(format!(
+                        "• Pinned messages kept verbatim: {}\n",
+                        self.conversation.pins().len()
+                    ))
+                )?;
+            }
             animate_output(&mut self.stderr, &output)?;
 
             // Display the summary if the show_summary flag is set
@@ -2141,24 +2366,42 @@ impl ChatSession {
                 self.conversation.set_next_user_message(user_input).await;
             }
 
-            self.reset_user_turn();
+            self.send_user_turn(os).await
+        }
+    }
 
-            let conv_state = self
-                .conversation
-                .as_sendable_conversation_state(os, &mut self.stderr, true)
-                .await?;
-            self.send_tool_use_telemetry(os).await;
+    /// Sends `task` to the model as the next user message. Unlike [Self::handle_input], the text
+    /// is never run as a slash command, shell command or prompt reference, since it does not come
+    /// from the user.
+    async fn handle_task(&mut self, os: &mut Os, task: &str) -> Result<ChatState, ChatError> {
+        queue!(self.stderr, style::Print('\n'))?;
+        self.tool_use_status = ToolUseStatus::Idle;
+        self.conversation
+            .set_next_user_message(sanitize_unicode_tags(task))
+            .await;
+        self.send_user_turn(os).await
+    }
 
-            queue!(self.stderr, style::SetForegroundColor(Color::Magenta))?;
-            queue!(self.stderr, style::SetForegroundColor(Color::Reset))?;
-            queue!(self.stderr, cursor::Hide)?;
+    /// Starts a user turn with the next user message of the conversation.
+    async fn send_user_turn(&mut self, os: &mut Os) -> Result<ChatState, ChatError> {
+        self.reset_user_turn();
+        self.file_snapshots.start_turn();
 
-            if self.interactive {
-                self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_owned()));
-            }
+        let conv_state = self
+            .conversation
+            .as_sendable_conversation_state(os, &mut self.stderr, true)
+            .await?;
+        self.send_tool_use_telemetry(os).await;
 
-            Ok(ChatState::HandleResponseStream(conv_state))
+        queue!(self.stderr, style::SetForegroundColor(Color::Magenta))?;
+        queue!(self.stderr, style::SetForegroundColor(Color::Reset))?;
+        queue!(self.stderr, cursor::Hide)?;
+
+        if self.interactive {
+            self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_owned()));
         }
+
+        Ok(ChatState::HandleResponseStream(conv_state))
     }
 
     async fn tool_use_execute(&mut self, os: &mut Os) -> Result<ChatState, ChatError> {
@@ -2207,6 +2450,11 @@ impl ChatSession {
                     || self.conversation.agents.trust_all_tools;
 
             if let Some(match_set) = denied_match_set {
+                self.output_events.emit(&mut self.stdout, OutputEvent::ToolDenied {
+                    tool_use_id: tool.id.clone(),
+                    name: tool.name.clone(),
+                    reason: format!("Denied by the rules: {}", match_set.join(", ")),
+                })?;
                 let formatted_set = match_set.into_iter().fold(String::new(), |mut acc, rule| {
                     acc.push_str(&format!("\n  - {rule}"));
                     acc
@@ -2249,6 +2497,7 @@ impl ChatSession {
 
             if allowed {
                 tool.accepted = true;
+                tool.auto_approved = true;
                 self.tool_use_telemetry_events
                     .entry(tool.id.clone())
                     .and_modify(|ev| ev.is_trusted = true);
@@ -2257,175 +2506,89 @@ impl ChatSession {
 
             self.pending_tool_index = Some(i);
 
+            if let Some(run) = &self.run {
+                let tool = &self.tool_uses[i];
+                let reason = "Requires an approval that the agent's permissions do not give".to_string();
+                self.output_events.emit(&mut self.stdout, OutputEvent::ToolDenied {
+                    tool_use_id: tool.id.clone(),
+                    name: tool.name.clone(),
+                    reason: reason.clone(),
+                })?;
+                execute!(
+                    self.stderr,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nTool {} is rejected: {reason}\n", tool.name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+
+                if !run.continues_on_denied_tools() {
+                    run.stop(RunExitReason::ApprovalRequired);
+                    return Ok(ChatState::Exit);
+                }
+                let task = format!(
+                    "Tool use with {} was rejected because it requires an approval and no user is available to give it. Continue without it.",
+                    tool.name
+                );
+                return self.handle_task(os, &task).await;
+            }
+
             return Ok(ChatState::PromptUser {
                 skip_printing_tools: false,
             });
         }
 
         // All tools are allowed now
-        // Execute the requested tools.
+        // Execute the requested tools. Consecutive tools that were approved by the agent's
+        // permissions and are read-only are executed concurrently, anything else is executed on
+        // its own and in order.
         let mut tool_results = vec![];
         let mut image_blocks: Vec<RichImageBlock> = Vec::new();
 
-        for tool in &self.tool_uses {
-            let tool_start = std::time::Instant::now();
-            let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
-            tool_telemetry = tool_telemetry.and_modify(|ev| {
-                ev.is_accepted = true;
-            });
-
-            // Extract AWS service name and operation name if available
-            if let Some(additional_info) = tool.tool.get_additional_info() {
-                if let Some(aws_service_name) = additional_info.get("aws_service_name").and_then(|v| v.as_str()) {
-                    tool_telemetry =
-                        tool_telemetry.and_modify(|ev| ev.aws_service_name = Some(aws_service_name.to_string()));
-                }
-                if let Some(aws_operation_name) = additional_info.get("aws_operation_name").and_then(|v| v.as_str()) {
-                    tool_telemetry =
-                        tool_telemetry.and_modify(|ev| ev.aws_operation_name = Some(aws_operation_name.to_string()));
-                }
-            }
-
-            let invoke_result = tool
-                .tool
-                .invoke(
-                    os,
-                    &mut self.stdout,
-                    &mut self.conversation.file_line_tracker,
-                    self.conversation.agents.get_active(),
-                )
-                .await;
-
-            if self.spinner.is_some() {
-                queue!(
-                    self.stderr,
-                    terminal::Clear(terminal::ClearType::CurrentLine),
-                    cursor::MoveToColumn(0),
-                    cursor::Show
-                )?;
-            }
-            execute!(self.stdout, style::Print("\n"))?;
-
-            let tool_end_time = Instant::now();
-            let tool_time = tool_end_time.duration_since(tool_start);
-            tool_telemetry = tool_telemetry.and_modify(|ev| {
-                ev.execution_duration = Some(tool_time);
-                ev.turn_duration = self.tool_turn_start_time.map(|t| tool_end_time.duration_since(t));
-            });
-            if let Tool::Custom(ct) = &tool.tool {
-                tool_telemetry = tool_telemetry.and_modify(|ev| {
-                    ev.is_custom_tool = true;
-                    // legacy fields previously implemented for only MCP tools
-                    ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
-                    ev.input_token_size = Some(ct.get_input_token_size());
-                });
+        let mut i = 0;
+        while i < self.tool_uses.len() {
+            let batch_len = self.tool_uses[i..]
+                .iter()
+                .take_while(|tool| tool.auto_approved && tool.tool.is_read_only())
+                .count();
+            if batch_len > 1 {
+                let results = self
+                    .execute_tools_concurrently(os, i..i + batch_len, &mut image_blocks)
+                    .await?;
+                tool_results.extend(results);
+                i += batch_len;
+                continue;
             }
-            let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
-            match invoke_result {
-                Ok(result) => {
-                    match result.output {
-                        OutputKind::Text(ref text) => {
-                            debug!("Output is Text: {}", text);
-                        },
-                        OutputKind::Json(ref json) => {
-                            debug!("Output is JSON: {}", json);
-                        },
-                        OutputKind::Images(ref image) => {
-                            image_blocks.extend(image.clone());
-                        },
-                        OutputKind::Mixed { ref text, ref images } => {
-                            debug!("Output is Mixed: text = {:?}, images = {}", text, images.len());
-                            image_blocks.extend(images.clone());
-                        },
-                    }
-
-                    debug!("tool result output: {:#?}", result);
-                    execute!(
-                        self.stdout,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
-                        style::SetForegroundColor(Color::Green),
-                        style::SetAttribute(Attribute::Bold),
-                        style::Print(format!(" ● Completed in {}s", tool_time)),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print("\n\n"),
-                    )?;
-
-                    tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
-                    if let Tool::Custom(_) = &tool.tool {
-                        tool_telemetry
-                            .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(&result.as_str())));
-                    }
-
-                    // Send telemetry for agent contribution
-                    if let Tool::FsWrite(w) = &tool.tool {
-                        let sanitized_path_str = w.path(os).to_string_lossy().to_string();
-                        let conversation_id = self.conversation.conversation_id().to_string();
-                        let message_id = self.conversation.message_id().map(|s| s.to_string());
-                        if let Some(tracker) = self.conversation.file_line_tracker.get_mut(&sanitized_path_str) {
-                            let lines_by_agent = tracker.lines_by_agent();
-                            let lines_by_user = tracker.lines_by_user();
-
-                            os.telemetry
-                                .send_agent_contribution_metric(
-                                    &os.database,
-                                    conversation_id,
-                                    message_id,
-                                    Some(tool.id.clone()),   // Already a String
-                                    Some(tool.name.clone()), // Already a String
-                                    Some(lines_by_agent),
-                                    Some(lines_by_user),
-                                )
-                                .await
-                                .ok();
 
-                            tracker.prev_fswrite_lines = tracker.after_fswrite_lines;
-                        }
+            let tool = &self.tool_uses[i];
+            let tool_start = std::time::Instant::now();
+            if let Tool::FsWrite(w) = &tool.tool {
+                for path in w.paths(os) {
+                    if let Err(err) = self.file_snapshots.record(os, path).await {
+                        warn!(?err, "failed to snapshot file before fs_write");
                     }
+                }
+            }
 
-                    tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id.clone(),
-                        content: vec![result.into()],
-                        status: ToolResultStatus::Success,
-                    });
-                },
-                Err(err) => {
-                    error!(?err, "An error occurred processing the tool");
-                    execute!(
-                        self.stderr,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
-                        style::SetAttribute(Attribute::Bold),
-                        style::SetForegroundColor(Color::Red),
-                        style::Print(format!(" ● Execution failed after {}s:\n", tool_time)),
-                        style::SetAttribute(Attribute::Reset),
-                        style::SetForegroundColor(Color::Red),
-                        style::Print(&err),
-                        style::SetAttribute(Attribute::Reset),
-                        style::Print("\n\n"),
-                    )?;
+            let invoke_result = if let Tool::Delegate(delegate) = &tool.tool {
+                let delegate = delegate.clone();
+                Box::pin(self.run_subagent(os, &delegate)).await
+            } else {
+                let mut display_output = self.output_events.display_output(&mut self.stdout, &mut self.stderr);
+                tool.tool
+                    .invoke(
+                        os,
+                        &mut display_output,
+                        &mut self.conversation.file_line_tracker,
+                        self.conversation.agents.get_active(),
+                    )
+                    .await
+            };
 
-                    tool_telemetry.and_modify(|ev| {
-                        ev.is_success = Some(false);
-                        ev.reason_desc = Some(err.to_string());
-                    });
-                    tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id.clone(),
-                        content: vec![ToolUseResultBlock::Text(format!(
-                            "An error occurred processing the tool: \n{}",
-                            &err
-                        ))],
-                        status: ToolResultStatus::Error,
-                    });
-                    if let ToolUseStatus::Idle = self.tool_use_status {
-                        self.tool_use_status = ToolUseStatus::RetryInProgress(
-                            self.conversation
-                                .message_id()
-                                .map_or("No utterance id found".to_string(), |v| v.to_string()),
-                        );
-                    }
-                },
-            }
+            let result = self
+                .handle_tool_result(os, i, invoke_result, tool_start, &mut image_blocks)
+                .await?;
+            tool_results.push(result);
+            i += 1;
         }
 
         // Run PostToolUse hooks for all executed tools after we have the tool_results
@@ -2496,81 +2659,539 @@ impl ChatSession {
         ));
     }
 
-    /// Sends a [crate::api_client::ApiClient::send_message] request to the backend and consumes
-    /// the response stream.
+    /// Works on the task of `delegate` with another agent in a conversation of its own, returning
+    /// the final answer of the agent.
     ///
-    /// In order to handle sigints while also keeping track of metadata about how the
-    /// response stream was handled, we need an extra parameter:
-    /// * `request_metadata_lock` - Updated with the [RequestMetadata] once it has been received
-    ///   (either though a successful request, or on an error).
-    async fn handle_response(
-        &mut self,
-        os: &mut Os,
-        state: crate::api_client::model::ConversationState,
-        request_metadata_lock: Arc<Mutex<Option<RequestMetadata>>>,
-    ) -> Result<ChatState, ChatError> {
-        let mut rx = self.send_message(os, state, request_metadata_lock, None).await?;
-
-        let request_id = rx.request_id().map(String::from);
+    /// The sub-agent takes over the input of this session while it runs, so that the user is
+    /// prompted to approve its tool uses according to the permissions of that agent. It ends once
+    /// the model responds without using tools.
+    async fn run_subagent(&mut self, os: &mut Os, delegate: &Delegate) -> Result<InvokeOutput> {
+        if self.subagent_depth >= MAX_DELEGATION_DEPTH {
+            bail!("Sub-agents cannot be nested more than {MAX_DELEGATION_DEPTH} levels deep");
+        }
 
-        let mut buf = String::new();
-        let mut offset = 0;
-        let mut ended = false;
-        let terminal_width = match self.wrap {
-            Some(WrapMode::Never) => None,
-            Some(WrapMode::Always) => Some(self.terminal_width()),
-            Some(WrapMode::Auto) | None => {
-                if std::io::stdout().is_terminal() {
-                    Some(self.terminal_width())
-                } else {
-                    None
-                }
-            },
+        let mut agents = self.conversation.agents.clone();
+        if agents.switch(&delegate.agent).is_err() {
+            let mut names = agents.agents.keys().cloned().collect::<Vec<_>>();
+            names.sort();
+            bail!(
+                "There is no agent named {}, the available agents are: {}",
+                delegate.agent,
+                names.join(", ")
+            );
+        }
+        let Some(agent) = agents.get_active_mut() else {
+            bail!("There is no agent named {}", delegate.agent);
         };
+        // The context of the task holds the parameters of agents that declare an input schema.
+        if agent.input_schema.is_some() {
+            let params = match &delegate.context {
+                Some(serde_json::Value::Object(context)) => context.clone(),
+                _ => serde_json::Map::new(),
+            };
+            agent.apply_params(params)?;
+        }
+        let agent = agent.clone();
 
-        let mut state = ParseState::new(
-            terminal_width,
-            os.database.settings.get_bool(Setting::ChatDisableMarkdownRendering),
-        );
-        let mut response_prefix_printed = false;
-
-        let mut tool_uses = Vec::new();
-        let mut tool_name_being_recvd: Option<String> = None;
-
-        if self.spinner.is_some() {
-            drop(self.spinner.take());
-            queue!(
-                self.stderr,
-                style::SetForegroundColor(Color::Reset),
-                cursor::MoveToColumn(0),
-                cursor::Show,
-                terminal::Clear(terminal::ClearType::CurrentLine),
-            )?;
+        let mut model_id = self.conversation.model_info.as_ref().map(|model| model.model_id.clone());
+        if let Some(agent_model) = &agent.model {
+            let (models, _default_model) = get_available_models(os).await?;
+            match find_model(&models, agent_model) {
+                Some(model) => model_id = Some(model.model_id.clone()),
+                None => warn!(agent_model, "model of the sub-agent is not available, using the current model"),
+            }
         }
 
-        loop {
-            match rx.recv().await {
-                Some(Ok(msg_event)) => {
-                    trace!("Consumed: {:?}", msg_event);
-                    match msg_event {
-                        parser::ResponseEvent::ToolUseStart { name } => {
-                            // We need to flush the buffer here, otherwise text will not be
-                            // printed while we are receiving tool use events.
-                            buf.push('\n');
-                            tool_name_being_recvd = Some(name);
-                        },
-                        parser::ResponseEvent::AssistantText(text) => {
-                            // Add Q response prefix before the first assistant text.
-                            if !response_prefix_printed && !text.trim().is_empty() {
-                                queue!(
-                                    self.stdout,
-                                    style::SetForegroundColor(Color::Green),
-                                    style::Print("> "),
-                                    style::SetForegroundColor(Color::Reset)
-                                )?;
-                                response_prefix_printed = true;
-                            }
-                            buf.push_str(&text);
+        // Approvals cannot be prompted for while stdin is used for JSON-RPC requests.
+        let interactive = self.interactive && self.output_events.format() != ChatOutputFormat::JsonRpc;
+        let conversation_id = uuid::Uuid::new_v4().to_string();
+        // The input of this session keeps querying the prompts of the main conversation.
+        let (prompt_request_sender, prompt_request_receiver) = tokio::sync::broadcast::channel::<PromptQuery>(5);
+        let (prompt_response_sender, prompt_response_receiver) =
+            tokio::sync::broadcast::channel::<PromptQueryResult>(5);
+        let mut tool_manager = ToolManagerBuilder::default()
+            .prompt_query_result_sender(prompt_response_sender)
+            .prompt_query_receiver(prompt_request_receiver)
+            .prompt_query_sender(prompt_request_sender)
+            .prompt_query_result_receiver(prompt_response_receiver)
+            .conversation_id(&conversation_id)
+            .agent(agent)
+            .build(os, Box::new(std::io::stderr()), interactive)
+            .await?;
+        let tool_config = tool_manager.load_tools(os, &mut self.stderr).await?;
+
+        // The events of sub-agents are never written, stdout is reserved for the events of the main
+        // conversation.
+        let output_format = match self.output_events.format().is_json() {
+            true => ChatOutputFormat::Json,
+            false => ChatOutputFormat::Text,
+        };
+        let mut session = ChatSession::new(
+            os,
+            std::io::stdout(),
+            std::io::stderr(),
+            &conversation_id,
+            agents,
+            None,
+            InputSource::new_mock(vec![]),
+            None,
+            self.terminal_width_provider,
+            tool_manager,
+            model_id,
+            tool_config,
+            interactive,
+            self.conversation.mcp_enabled,
+            self.wrap,
+            Some(output_format),
+        )
+        .await?;
+        session.subagent_depth = self.subagent_depth + 1;
+        session.run = self.run.as_ref().map(RunTracker::for_subagent);
+        session.conversation.set_persisted(false);
+
+        execute!(
+            self.stderr,
+            style::SetForegroundColor(Color::Cyan),
+            style::Print(format!("\n▸ Agent {} is working on the task\n\n", delegate.agent)),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        // The task is written by the model, so it must not run as a slash or shell command.
+        let first_state = session.handle_task(os, &delegate.prompt()).await?;
+        session.inner = Some(first_state);
+        session.input_source = std::mem::replace(&mut self.input_source, InputSource::new_mock(vec![]));
+
+        let start = Instant::now();
+        self.subagent = Some(Subagent {
+            session: Box::new(session),
+            delegate: delegate.clone(),
+            start,
+        });
+        let mut error = None;
+        while let Some(subagent) = self.subagent.as_mut() {
+            if let Some(reason) = subagent.session.check_run() {
+                error = Some(reason.to_string());
+                break;
+            }
+            if matches!(subagent.session.inner, Some(ChatState::Exit)) {
+                break;
+            }
+            if let Err(err) = Box::pin(subagent.session.next(os)).await {
+                error = Some(err.to_string());
+                break;
+            }
+        }
+        let result = self
+            .finish_subagent(error)
+            .unwrap_or_else(|| Err("The agent did not run".to_string()));
+
+        execute!(
+            self.stderr,
+            style::SetForegroundColor(Color::Cyan),
+            style::Print(format!(
+                "\n▸ Agent {} {} after {:.1}s, see /subagents for its transcript\n",
+                delegate.agent,
+                if result.is_ok() { "finished" } else { "stopped" },
+                start.elapsed().as_secs_f64()
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+
+        match result {
+            Ok(answer) => Ok(InvokeOutput {
+                output: OutputKind::Text(answer),
+            }),
+            Err(reason) => bail!("Agent {} did not finish the task: {reason}", delegate.agent),
+        }
+    }
+
+    /// Ends the running sub-agent, taking back the input of this session and recording the run for
+    /// `/subagents`. Returns the final answer of the sub-agent, or why it did not give one.
+    fn finish_subagent(&mut self, error: Option<String>) -> Option<Result<String, String>> {
+        let Subagent {
+            mut session,
+            delegate,
+            start,
+        } = self.subagent.take()?;
+        // The sub-agent may have been stopped while running a sub-agent of its own.
+        session.finish_subagent(error.clone());
+        self.input_source = std::mem::replace(&mut session.input_source, InputSource::new_mock(vec![]));
+        // Files written by the sub-agent are reverted with the turn that delegated the task.
+        self.file_snapshots.merge(session.file_snapshots.snapshots_since(0));
+
+        let output_events = &session.output_events;
+        let result = match (error, &session.inner) {
+            (Some(error), _) => Err(error),
+            (None, Some(ChatState::Exit)) => match (output_events.implied_exit_reason(), output_events.last_response()) {
+                (ExitReason::EndTurn, Some(answer)) => Ok(answer.to_string()),
+                (ExitReason::EndTurn, None) => Err("The agent ended without responding".to_string()),
+                _ => Err(output_events
+                    .last_error_message()
+                    .unwrap_or("The agent ended after an error")
+                    .to_string()),
+            },
+            (None, _) => Err("The agent was stopped".to_string()),
+        };
+
+        self.subagent_runs.push(SubagentRun {
+            agent: delegate.agent,
+            task: delegate.task,
+            depth: self.subagent_depth + 1,
+            elapsed: start.elapsed(),
+            result: result.clone(),
+            transcript: render_transcript(&session.conversation, TranscriptOptions::default()),
+        });
+        // Runs of its own sub-agents are listed after the run that started them.
+        self.subagent_runs.append(&mut session.subagent_runs);
+        Some(result)
+    }
+
+    /// Executes the read-only tools at `range` within [Self::tool_uses] concurrently, with at most
+    /// [MAX_CONCURRENT_TOOL_USES] running at a time.
+    ///
+    /// Each tool is announced when it starts, labelled `[k/N]` with the position of the tool among
+    /// the N tools. Its output is buffered and displayed under the same label once it completes.
+    /// The results are returned in the same order as the tools. Tools are polled as part of the
+    /// current task so that they are cancelled once the future is dropped, eg. on Ctrl-C.
+    async fn execute_tools_concurrently(
+        &mut self,
+        os: &Os,
+        range: std::ops::Range<usize>,
+        image_blocks: &mut Vec<RichImageBlock>,
+    ) -> Result<Vec<ToolUseResult>, ChatError> {
+        let (first, total) = (range.start, range.len());
+        let agent = self.conversation.agents.get_active().cloned();
+        let agent = agent.as_ref();
+        let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+        let invocations = range
+            .map(|i| {
+                let tool = self.tool_uses[i].tool.clone();
+                let started_tx = started_tx.clone();
+                async move {
+                    started_tx.send(i).ok();
+                    let tool_start = Instant::now();
+                    let mut output = Vec::new();
+                    // Read-only tools do not write to files, so nothing is tracked.
+                    let mut line_tracker = HashMap::new();
+                    let result = tool.invoke(os, &mut output, &mut line_tracker, agent).await;
+                    (i, result, tool_start, output)
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut invocations = futures::stream::iter(invocations).buffer_unordered(MAX_CONCURRENT_TOOL_USES);
+
+        let label = |tool_uses: &[QueuedTool], i: usize| {
+            format!("[{}/{}] {}", i - first + 1, total, tool_uses[i].tool.display_name())
+        };
+
+        let mut results = Vec::with_capacity(total);
+        loop {
+            let mut started = Vec::new();
+            let completed = tokio::select! {
+                biased;
+                Some(i) = started_rx.recv() => {
+                    started.push(i);
+                    None
+                },
+                invocation = invocations.next() => match invocation {
+                    Some(invocation) => Some(invocation),
+                    None => break,
+                },
+            };
+            // Tools that complete as soon as they are polled are announced before their output.
+            while let Ok(i) = started_rx.try_recv() {
+                started.push(i);
+            }
+            for i in started {
+                let label = label(&self.tool_uses, i);
+                let mut display_output = self.display_output();
+                queue!(
+                    &mut display_output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!("{label} running\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                display_output.flush()?;
+            }
+            let Some((i, invoke_result, tool_start, output)) = completed else {
+                continue;
+            };
+
+            let label = label(&self.tool_uses, i);
+            let mut display_output = self.display_output();
+            queue!(
+                &mut display_output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("{label}\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            display_output.write_all(&output)?;
+
+            let result = self
+                .handle_tool_result(os, i, invoke_result, tool_start, image_blocks)
+                .await?;
+            results.push((i, result));
+        }
+
+        results.sort_by_key(|(i, _)| *i);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    /// Displays the result of invoking the tool at `index` within [Self::tool_uses] and records
+    /// its telemetry, returning the result to send back to the model.
+    async fn handle_tool_result(
+        &mut self,
+        os: &Os,
+        index: usize,
+        invoke_result: Result<InvokeOutput>,
+        tool_start: Instant,
+        image_blocks: &mut Vec<RichImageBlock>,
+    ) -> Result<ToolUseResult, ChatError> {
+        let tool = &self.tool_uses[index];
+        let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
+        tool_telemetry = tool_telemetry.and_modify(|ev| {
+            ev.is_accepted = true;
+        });
+
+        // Extract AWS service name and operation name if available
+        if let Some(additional_info) = tool.tool.get_additional_info() {
+            if let Some(aws_service_name) = additional_info.get("aws_service_name").and_then(|v| v.as_str()) {
+                tool_telemetry =
+                    tool_telemetry.and_modify(|ev| ev.aws_service_name = Some(aws_service_name.to_string()));
+            }
+            if let Some(aws_operation_name) = additional_info.get("aws_operation_name").and_then(|v| v.as_str()) {
+                tool_telemetry =
+                    tool_telemetry.and_modify(|ev| ev.aws_operation_name = Some(aws_operation_name.to_string()));
+            }
+        }
+
+        if self.spinner.is_some() {
+            queue!(
+                self.stderr,
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                cursor::MoveToColumn(0),
+                cursor::Show
+            )?;
+        }
+        let mut display_output = self.output_events.display_output(&mut self.stdout, &mut self.stderr);
+        execute!(&mut display_output, style::Print("\n"))?;
+
+        let tool_end_time = Instant::now();
+        let tool_time = tool_end_time.duration_since(tool_start);
+        tool_telemetry = tool_telemetry.and_modify(|ev| {
+            ev.execution_duration = Some(tool_time);
+            ev.turn_duration = self.tool_turn_start_time.map(|t| tool_end_time.duration_since(t));
+        });
+        if let Tool::Custom(ct) = &tool.tool {
+            tool_telemetry = tool_telemetry.and_modify(|ev| {
+                ev.is_custom_tool = true;
+                // legacy fields previously implemented for only MCP tools
+                ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
+                ev.input_token_size = Some(ct.get_input_token_size());
+            });
+        }
+        let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
+        let result = match invoke_result {
+            Ok(result) => {
+                match result.output {
+                    OutputKind::Text(ref text) => {
+                        debug!("Output is Text: {}", text);
+                    },
+                    OutputKind::Json(ref json) => {
+                        debug!("Output is JSON: {}", json);
+                    },
+                    OutputKind::Images(ref image) => {
+                        image_blocks.extend(image.clone());
+                    },
+                    OutputKind::Mixed { ref text, ref images } => {
+                        debug!("Output is Mixed: text = {:?}, images = {}", text, images.len());
+                        image_blocks.extend(images.clone());
+                    },
+                }
+
+                debug!("tool result output: {:#?}", result);
+                execute!(
+                    &mut display_output,
+                    style::Print(CONTINUATION_LINE),
+                    style::Print("\n"),
+                    style::SetForegroundColor(Color::Green),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print(format!(" ● Completed in {}s", tool_time)),
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print("\n\n"),
+                )?;
+
+                tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
+                if let Tool::Custom(_) = &tool.tool {
+                    tool_telemetry
+                        .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(&result.as_str())));
+                }
+
+                // Send telemetry for agent contribution
+                if let Tool::FsWrite(w) = &tool.tool {
+                    for path in w.paths(os) {
+                        let sanitized_path_str = path.to_string_lossy().to_string();
+                        let conversation_id = self.conversation.conversation_id().to_string();
+                        let message_id = self.conversation.message_id().map(|s| s.to_string());
+                        if let Some(tracker) = self.conversation.file_line_tracker.get_mut(&sanitized_path_str) {
+                            let lines_by_agent = tracker.lines_by_agent();
+                            let lines_by_user = tracker.lines_by_user();
+
+                            os.telemetry
+                                .send_agent_contribution_metric(
+                                    &os.database,
+                                    conversation_id,
+                                    message_id,
+                                    Some(tool.id.clone()),   // Already a String
+                                    Some(tool.name.clone()), // Already a String
+                                    Some(lines_by_agent),
+                                    Some(lines_by_user),
+                                )
+                                .await
+                                .ok();
+
+                            tracker.prev_fswrite_lines = tracker.after_fswrite_lines;
+                        }
+                    }
+                }
+
+                let content: Vec<ToolUseResultBlock> = vec![result.into()];
+                self.output_events.emit(&mut self.stdout, OutputEvent::ToolResult {
+                    tool_use_id: tool.id.clone(),
+                    name: tool.name.clone(),
+                    status: ToolResultStatus::Success,
+                    content: content.clone(),
+                })?;
+                ToolUseResult {
+                    tool_use_id: tool.id.clone(),
+                    content,
+                    status: ToolResultStatus::Success,
+                }
+            },
+            Err(err) => {
+                error!(?err, "An error occurred processing the tool");
+                execute!(
+                    self.stderr,
+                    style::Print(CONTINUATION_LINE),
+                    style::Print("\n"),
+                    style::SetAttribute(Attribute::Bold),
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!(" ● Execution failed after {}s:\n", tool_time)),
+                    style::SetAttribute(Attribute::Reset),
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(&err),
+                    style::SetAttribute(Attribute::Reset),
+                    style::Print("\n\n"),
+                )?;
+
+                tool_telemetry.and_modify(|ev| {
+                    ev.is_success = Some(false);
+                    ev.reason_desc = Some(err.to_string());
+                });
+                let content = vec![ToolUseResultBlock::Text(format!(
+                    "An error occurred processing the tool: \n{}",
+                    &err
+                ))];
+                self.output_events.emit(&mut self.stdout, OutputEvent::ToolResult {
+                    tool_use_id: tool.id.clone(),
+                    name: tool.name.clone(),
+                    status: ToolResultStatus::Error,
+                    content: content.clone(),
+                })?;
+                if let ToolUseStatus::Idle = self.tool_use_status {
+                    self.tool_use_status = ToolUseStatus::RetryInProgress(
+                        self.conversation
+                            .message_id()
+                            .map_or("No utterance id found".to_string(), |v| v.to_string()),
+                    );
+                }
+                ToolUseResult {
+                    tool_use_id: tool.id.clone(),
+                    content,
+                    status: ToolResultStatus::Error,
+                }
+            },
+        };
+
+        Ok(result)
+    }
+
+    /// Sends a [crate::api_client::ApiClient::send_message] request to the backend and consumes
+    /// the response stream.
+    ///
+    /// In order to handle sigints while also keeping track of metadata about how the
+    /// response stream was handled, we need an extra parameter:
+    /// * `request_metadata_lock` - Updated with the [RequestMetadata] once it has been received
+    ///   (either though a successful request, or on an error).
+    async fn handle_response(
+        &mut self,
+        os: &mut Os,
+        state: crate::api_client::model::ConversationState,
+        request_metadata_lock: Arc<Mutex<Option<RequestMetadata>>>,
+    ) -> Result<ChatState, ChatError> {
+        let mut rx = self.send_message(os, state, request_metadata_lock, None).await?;
+
+        let request_id = rx.request_id().map(String::from);
+
+        let mut buf = String::new();
+        let mut offset = 0;
+        let mut ended = false;
+        let terminal_width = match self.wrap {
+            Some(WrapMode::Never) => None,
+            Some(WrapMode::Always) => Some(self.terminal_width()),
+            Some(WrapMode::Auto) | None => {
+                if std::io::stdout().is_terminal() {
+                    Some(self.terminal_width())
+                } else {
+                    None
+                }
+            },
+        };
+
+        let mut state = ParseState::new(
+            terminal_width,
+            os.database.settings.get_bool(Setting::ChatDisableMarkdownRendering),
+        );
+        let mut response_prefix_printed = false;
+
+        let mut tool_uses = Vec::new();
+        let mut tool_name_being_recvd: Option<String> = None;
+
+        if self.spinner.is_some() {
+            drop(self.spinner.take());
+            queue!(
+                self.stderr,
+                style::SetForegroundColor(Color::Reset),
+                cursor::MoveToColumn(0),
+                cursor::Show,
+                terminal::Clear(terminal::ClearType::CurrentLine),
+            )?;
+        }
+
+        loop {
+            match rx.recv().await {
+                Some(Ok(msg_event)) => {
+                    trace!("Consumed: {:?}", msg_event);
+                    // Also records the last response in text mode, which sub-agents answer with.
+                    self.output_events.emit(&mut self.stdout, (&msg_event).into())?;
+                    match msg_event {
+                        parser::ResponseEvent::ToolUseStart { name } => {
+                            // We need to flush the buffer here, otherwise text will not be
+                            // printed while we are receiving tool use events.
+                            buf.push('\n');
+                            tool_name_being_recvd = Some(name);
+                        },
+                        parser::ResponseEvent::AssistantText(text) => {
+                            // Add Q response prefix before the first assistant text.
+                            if !response_prefix_printed && !text.trim().is_empty() {
+                                queue!(
+                                    &mut self.display_output(),
+                                    style::SetForegroundColor(Color::Green),
+                                    style::Print("> "),
+                                    style::SetForegroundColor(Color::Reset)
+                                )?;
+                                response_prefix_printed = true;
+                            }
+                            buf.push_str(&text);
                         },
                         parser::ResponseEvent::ToolUse(tool_use) => {
                             if self.spinner.is_some() {
@@ -2730,10 +3351,10 @@ impl ChatSession {
             // Print the response for normal cases
             loop {
                 let input = Partial::new(&buf[offset..]);
-                match interpret_markdown(input, &mut self.stdout, &mut state) {
+                match interpret_markdown(input, self.display_output(), &mut state) {
                     Ok(parsed) => {
                         offset += parsed.offset_from(&input);
-                        self.stdout.flush()?;
+                        self.display_output().flush()?;
                         state.newline = state.set_newline;
                         state.set_newline = false;
                     },
@@ -2768,11 +3389,11 @@ impl ChatSession {
                 }
 
                 queue!(self.stderr, style::ResetColor, style::SetAttribute(Attribute::Reset))?;
-                execute!(self.stdout, style::Print("\n"))?;
+                execute!(&mut self.display_output(), style::Print("\n"))?;
 
                 for (i, citation) in &state.citations {
                     queue!(
-                        self.stdout,
+                        &mut self.display_output(),
                         style::Print("\n"),
                         style::SetForegroundColor(Color::Blue),
                         style::Print(format!("[^{i}]: ")),
@@ -2824,7 +3445,7 @@ impl ChatSession {
             match self.conversation.tool_manager.get_tool_from_tool_use(tool_use).await {
                 Ok(mut tool) => {
                     // Apply non-Q-generated context to tools
-                    self.contextualize_tool(&mut tool);
+                    self.contextualize_tool(os, &mut tool);
 
                     match tool.validate(os).await {
                         Ok(()) => {
@@ -2834,6 +3455,7 @@ impl ChatSession {
                                 name: tool_use_name,
                                 tool,
                                 accepted: false,
+                                auto_approved: false,
                                 tool_input,
                             });
                         },
@@ -3010,7 +3632,7 @@ impl ChatSession {
     // We cannot attach this any other way because Tools are constructed by deserializing
     // output from Amazon Q.
     // TODO: Is there a better way?
-    fn contextualize_tool(&self, tool: &mut Tool) {
+    fn contextualize_tool(&self, os: &Os, tool: &mut Tool) {
         if let Tool::GhIssue(gh_issue) = tool {
             let allowed_tools = self
                 .conversation
@@ -3028,13 +3650,23 @@ impl ChatSession {
                 tool_permissions: allowed_tools,
             });
         }
+        if let (Tool::FsRead(fs_read), Some(agent)) = (&mut *tool, self.conversation.agents.get_active()) {
+            fs_read.set_denied_paths(agent);
+        }
+        if let Tool::ExecuteCommand(execute_command) = tool {
+            execute_command.jobs = Some(self.background_jobs.clone());
+            if PersistentShell::is_enabled(os) {
+                execute_command.shell = Some(self.persistent_shell.clone());
+            }
+        }
     }
 
     async fn print_tool_description(&mut self, os: &Os, tool_index: usize, trusted: bool) -> Result<(), ChatError> {
         let tool_use = &self.tool_uses[tool_index];
+        let mut output = self.output_events.display_output(&mut self.stdout, &mut self.stderr);
 
         queue!(
-            self.stdout,
+            &mut output,
             style::SetForegroundColor(Color::Magenta),
             style::Print(format!(
                 "🛠️  Using tool: {}{}",
@@ -3045,7 +3677,7 @@ impl ChatSession {
         )?;
         if let Tool::Custom(ref tool) = tool_use.tool {
             queue!(
-                self.stdout,
+                &mut output,
                 style::SetForegroundColor(Color::Reset),
                 style::Print(" from mcp server "),
                 style::SetForegroundColor(Color::Magenta),
@@ -3055,7 +3687,7 @@ impl ChatSession {
         }
 
         execute!(
-            self.stdout,
+            &mut output,
             style::Print("\n"),
             style::Print(CONTINUATION_LINE),
             style::Print("\n"),
@@ -3064,7 +3696,7 @@ impl ChatSession {
 
         tool_use
             .tool
-            .queue_description(os, &mut self.stdout)
+            .queue_description(os, &mut output)
             .await
             .map_err(|e| ChatError::Custom(format!("failed to print tool, `{}`: {}", tool_use.name, e).into()))?;
 
@@ -3126,6 +3758,12 @@ impl ChatSession {
         (self.terminal_width_provider)().unwrap_or(80)
     }
 
+    /// Writer for human-readable output that is normally written to stdout. When stdout is
+    /// reserved for JSON events, stderr is used instead.
+    fn display_output(&mut self) -> &mut dyn Write {
+        self.output_events.display_output(&mut self.stdout, &mut self.stderr)
+    }
+
     fn all_tools_trusted(&self) -> bool {
         self.conversation.agents.trust_all_tools
     }
@@ -3511,7 +4149,7 @@ mod tests {
                 "y".to_string(),
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3519,6 +4157,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -3529,6 +4168,339 @@ mod tests {
         assert_eq!(os.fs.read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
     }
 
+    #[tokio::test]
+    async fn test_flow_delegate() {
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(serde_json::json!([
+            [
+                "I'll have the writer do that",
+                {
+                    "tool_use_id": "1",
+                    "name": "delegate",
+                    "args": {
+                        "agent": "writer",
+                        "task": "Create /file.txt",
+                    }
+                }
+            ],
+            [
+                "Creating the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Created /file.txt",
+            ],
+            [
+                "The writer created the file",
+            ],
+        ]));
+
+        let mut agents = get_test_agents(&os).await;
+        agents.agents.insert("writer".to_string(), Agent {
+            name: "writer".to_string(),
+            use_legacy_mcp_json: false,
+            ..Default::default()
+        });
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut session = ChatSession::new(
+            &mut os,
+            std::io::stdout(),
+            std::io::stderr(),
+            "fake_conv_id",
+            agents,
+            None,
+            InputSource::new_mock(vec![
+                "delegate creating a file".to_string(),
+                // Approves the delegate tool, then the fs_write tool of the sub-agent.
+                "y".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            None,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            true,
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        session.spawn(&mut os).await.unwrap();
+
+        assert_eq!(os.fs.read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        assert_eq!(session.subagent_runs.len(), 1);
+        let run = &session.subagent_runs[0];
+        assert_eq!(run.agent, "writer");
+        assert_eq!(run.depth, 1);
+        assert_eq!(run.result, Ok("Created /file.txt".to_string()));
+        assert!(run.transcript.contains("Create /file.txt"));
+
+        // Only the main conversation is saved as a session that can be resumed.
+        let sessions = os.database.list_sessions(None).unwrap();
+        let ids = sessions.iter().map(|session| session.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["fake_conv_id"]);
+    }
+
+    #[test]
+    fn test_flow_delegate_nested() {
+        // Polling a chat session from within the sub-agent of another takes more than the default
+        // stack of test threads in debug builds.
+        let test = || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let mut os = Os::new().await.unwrap();
+                    os.client.set_mock_output(serde_json::json!([
+                        [
+                            "I'll have the planner do that",
+                            {
+                                "tool_use_id": "1",
+                                "name": "delegate",
+                                "args": {
+                                    "agent": "planner",
+                                    "task": "Plan the release",
+                                }
+                            }
+                        ],
+                        [
+                            "I'll have the writer write the notes",
+                            {
+                                "tool_use_id": "1",
+                                "name": "delegate",
+                                "args": {
+                                    "agent": "writer",
+                                    "task": "Write the release notes",
+                                }
+                            }
+                        ],
+                        [
+                            "The notes are written",
+                        ],
+                        [
+                            "The release is planned",
+                        ],
+                        [
+                            "The planner planned the release",
+                        ],
+                    ]));
+
+                    let mut agents = get_test_agents(&os).await;
+                    for name in ["planner", "writer"] {
+                        agents.agents.insert(name.to_string(), Agent {
+                            name: name.to_string(),
+                            use_legacy_mcp_json: false,
+                            ..Default::default()
+                        });
+                    }
+                    let tool_manager = ToolManager::default();
+                    let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+                        .expect("Tools failed to load");
+                    let mut session = ChatSession::new(
+                        &mut os,
+                        std::io::stdout(),
+                        std::io::stderr(),
+                        "fake_conv_id",
+                        agents,
+                        None,
+                        InputSource::new_mock(vec![
+                            "plan the release".to_string(),
+                            // Approves the delegate tool, then the delegate tool of the planner.
+                            "y".to_string(),
+                            "y".to_string(),
+                            "exit".to_string(),
+                        ]),
+                        None,
+                        || Some(80),
+                        tool_manager,
+                        None,
+                        tool_config,
+                        true,
+                        false,
+                        None,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                    session.spawn(&mut os).await.unwrap();
+
+                    let runs = session
+                        .subagent_runs
+                        .iter()
+                        .map(|run| (run.agent.as_str(), run.depth, run.result.clone()))
+                        .collect::<Vec<_>>();
+                    assert_eq!(runs, vec![
+                        ("planner", 1, Ok("The release is planned".to_string())),
+                        ("writer", 2, Ok("The notes are written".to_string())),
+                    ]);
+    
+                });
+        };
+        std::thread::Builder::new()
+            .stack_size(16 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flow_delegate_task_is_not_a_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ran");
+        let task = format!("!touch {}", marker.display());
+
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(serde_json::json!([
+            [
+                "I'll have the writer do that",
+                {
+                    "tool_use_id": "1",
+                    "name": "delegate",
+                    "args": { "agent": "writer", "task": task }
+                }
+            ],
+            ["I won't run that"],
+            ["The writer declined"],
+        ]));
+
+        let mut agents = get_test_agents(&os).await;
+        agents.agents.insert("writer".to_string(), Agent {
+            name: "writer".to_string(),
+            use_legacy_mcp_json: false,
+            ..Default::default()
+        });
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut session = ChatSession::new(
+            &mut os,
+            std::io::stdout(),
+            std::io::stderr(),
+            "fake_conv_id",
+            agents,
+            None,
+            InputSource::new_mock(vec![
+                "delegate a task".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            None,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            true,
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        session.spawn(&mut os).await.unwrap();
+
+        assert!(!marker.exists());
+        let run = &session.subagent_runs[0];
+        assert_eq!(run.result, Ok("I won't run that".to_string()));
+        assert!(run.transcript.contains(&task));
+    }
+
+    #[tokio::test]
+    async fn test_flow_serve_stdio() {
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Hope that looks good to you!",
+            ],
+        ]));
+
+        let agents = get_test_agents(&os).await;
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let request = |id: u32, method: &str, params: serde_json::Value| {
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            })
+        };
+        let mut session = ChatSession::new(
+            &mut os,
+            std::io::stdout(),
+            std::io::stderr(),
+            "fake_conv_id",
+            agents,
+            None,
+            InputSource::new_mock(vec![]),
+            None,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            false,
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        .with_stdio_server(StdioServer::new_mock(vec![
+            // Tool approval is rejected while no tool use is pending.
+            request(1, "approveTool", serde_json::json!({ "decision": "allow" })),
+            request(2, "prompt", serde_json::json!({ "text": "create a new file" })),
+            request(3, "getState", serde_json::Value::Null),
+            // Prompts are rejected while the tool use awaits approval, rather than answering it.
+            request(4, "prompt", serde_json::json!({ "text": "t" })),
+            request(5, "approveTool", serde_json::json!({ "decision": "allow" })),
+            request(6, "command", serde_json::json!({ "command": "/pin" })),
+            request(7, "shutdown", serde_json::Value::Null),
+            request(8, "prompt", serde_json::json!({ "text": "never read" })),
+        ]));
+        session.spawn(&mut os).await.unwrap();
+
+        assert_eq!(os.fs.read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        assert_eq!(session.conversation.history().len(), 2);
+        assert_eq!(session.conversation.pins().len(), 1);
+        assert_eq!(session.conversation.pins()[0].content, "create a new file");
+        assert!(
+            !session
+                .conversation
+                .agents
+                .get_active()
+                .unwrap()
+                .allowed_tools
+                .contains("fs_write")
+        );
+    }
+
     #[tokio::test]
     async fn test_flow_tool_permissions() {
         let mut os = Os::new().await.unwrap();
@@ -3654,7 +4626,7 @@ mod tests {
                 "n".to_string(),             // cancel
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3662,6 +4634,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -3752,7 +4725,7 @@ mod tests {
                 "y".to_string(),
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3760,6 +4733,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -3773,6 +4747,67 @@ mod tests {
         assert_eq!(os.fs.read_to_string("/file4.txt").await.unwrap(), "Hello, world!\n");
     }
 
+    #[tokio::test]
+    async fn test_execute_tools_concurrently() {
+        let mut os = Os::new().await.unwrap();
+        let agents = get_test_agents(&os).await;
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut session = ChatSession::new(
+            &mut os,
+            std::io::stdout(),
+            std::io::stderr(),
+            "fake_conv_id",
+            agents,
+            None,
+            InputSource::new_mock(vec![]),
+            None,
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            false,
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let mut tool_uses = Vec::new();
+        for i in 0..10 {
+            let path = format!("/file{i}.txt");
+            os.fs.write(&path, format!("content {i}")).await.unwrap();
+            let tool_input = serde_json::json!({ "operations": [{ "mode": "Line", "path": path }] });
+            tool_uses.push(QueuedTool {
+                id: i.to_string(),
+                name: "fs_read".to_string(),
+                accepted: true,
+                auto_approved: true,
+                tool: Tool::FsRead(serde_json::from_value(tool_input.clone()).unwrap()),
+                tool_input,
+            });
+        }
+        assert!(tool_uses.iter().all(|t| t.tool.is_read_only()));
+        session.tool_uses = tool_uses;
+
+        let mut image_blocks = Vec::new();
+        let results = session
+            .execute_tools_concurrently(&os, 0..10, &mut image_blocks)
+            .await
+            .unwrap();
+
+        // Results are returned in the order of the tool uses.
+        assert_eq!(results.len(), 10);
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.tool_use_id, i.to_string());
+            assert!(matches!(result.status, ToolResultStatus::Success));
+            assert!(
+                matches!(&result.content[0], ToolUseResultBlock::Text(text) if text.contains(&format!("content {i}")))
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_flow_tools_trust_all() {
         // let _ = tracing_subscriber::fmt::try_init();
@@ -3828,7 +4863,7 @@ mod tests {
                 "create a new file".to_string(),
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3836,6 +4871,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -3880,7 +4916,7 @@ mod tests {
             agents,
             None,
             InputSource::new_mock(vec!["/subscribe".to_string(), "y".to_string(), "/quit".to_string()]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3888,6 +4924,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -3985,7 +5022,7 @@ mod tests {
                 "y".to_string(), // Accept tool execution
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -3993,6 +5030,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
@@ -4116,7 +5154,7 @@ mod tests {
                 "read /sensitive.txt".to_string(),
                 "exit".to_string(),
             ]),
-            false,
+            None,
             || Some(80),
             tool_manager,
             None,
@@ -4124,6 +5162,7 @@ mod tests {
             true,
             false,
             None,
+            None,
         )
         .await
         .unwrap()
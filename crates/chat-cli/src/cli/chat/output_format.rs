@@ -0,0 +1,322 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::message::{
+    AssistantMessage,
+    AssistantToolUse,
+    ToolUseResultBlock,
+};
+use super::parser::{
+    RequestMetadata,
+    ResponseEvent,
+};
+use super::serve::write_notification;
+use crate::api_client::model::ToolResultStatus;
+
+/// Controls how the chat session writes its output to stdout.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChatOutputFormat {
+    /// Rendered markdown meant to be read by humans (default)
+    #[default]
+    Text,
+    /// A single JSON document containing every event, written once the session ends
+    Json,
+    /// Newline-delimited JSON events, written as they occur
+    StreamJson,
+    /// JSON-RPC `event` notifications, used by `--serve-stdio`
+    #[value(skip)]
+    JsonRpc,
+}
+
+impl ChatOutputFormat {
+    /// Whether stdout is reserved for JSON events.
+    pub fn is_json(&self) -> bool {
+        !matches!(self, Self::Text)
+    }
+}
+
+/// Reason for the chat session ending, reported as the final event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExitReason {
+    /// The model finished responding and there is no more input to handle.
+    EndTurn,
+    /// A tool use required approval, which cannot be given without an interactive user.
+    ToolApprovalRequired,
+    /// The session was interrupted with a sigint.
+    Interrupted,
+    /// The session ended after encountering an error.
+    Error,
+}
+
+/// An event written to stdout when running with a JSON [ChatOutputFormat].
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum OutputEvent {
+    /// See [ResponseEvent::AssistantText].
+    AssistantText { text: String },
+    /// See [ResponseEvent::ToolUseStart].
+    ToolUseStart { name: String },
+    /// See [ResponseEvent::ToolUse].
+    ToolUse { tool_use: AssistantToolUse },
+    /// See [ResponseEvent::EndStream].
+    EndStream {
+        message: AssistantMessage,
+        request_metadata: RequestMetadata,
+    },
+    /// The result of invoking a tool requested by the model.
+    ToolResult {
+        tool_use_id: String,
+        name: String,
+        status: ToolResultStatus,
+        content: Vec<ToolUseResultBlock>,
+    },
+    /// A tool requested by the model that was not run, because the agent's permissions deny it or
+    /// it needed an approval that could not be given.
+    ToolDenied {
+        tool_use_id: String,
+        name: String,
+        reason: String,
+    },
+    /// Structured output of a slash command, e.g. `/usage --json`.
+    CommandOutput { command: String, output: serde_json::Value },
+    /// An error encountered while handling the current state.
+    Error { reason: String, message: String },
+    /// Always the last event written.
+    Exit { reason: ExitReason },
+}
+
+impl From<&ResponseEvent> for OutputEvent {
+    fn from(value: &ResponseEvent) -> Self {
+        match value {
+            ResponseEvent::AssistantText(text) => Self::AssistantText { text: text.clone() },
+            ResponseEvent::ToolUseStart { name } => Self::ToolUseStart { name: name.clone() },
+            ResponseEvent::ToolUse(tool_use) => Self::ToolUse {
+                tool_use: tool_use.clone(),
+            },
+            ResponseEvent::EndStream {
+                message,
+                request_metadata,
+            } => Self::EndStream {
+                message: message.clone(),
+                request_metadata: request_metadata.clone(),
+            },
+        }
+    }
+}
+
+/// The document written for [ChatOutputFormat::Json].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonOutput<'a> {
+    /// Content of the last assistant message, if any.
+    response: Option<&'a str>,
+    exit_reason: ExitReason,
+    events: &'a [OutputEvent],
+}
+
+/// Writes [OutputEvent]s according to the selected [ChatOutputFormat].
+#[derive(Debug, Default)]
+pub struct OutputEventWriter {
+    format: ChatOutputFormat,
+    /// Events held until [Self::finish] for [ChatOutputFormat::Json].
+    buffered: Vec<OutputEvent>,
+    /// Content of the last assistant message received.
+    last_response: Option<String>,
+    /// Reason code of the last error, cleared once the model responds successfully.
+    last_error_reason: Option<String>,
+    /// Message of the last error, cleared along with [Self::last_error_reason].
+    last_error_message: Option<String>,
+    finished: bool,
+}
+
+impl OutputEventWriter {
+    pub fn new(format: ChatOutputFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+
+    pub fn format(&self) -> ChatOutputFormat {
+        self.format
+    }
+
+    /// Writer for human-readable output: `stdout`, unless it is reserved for
+    /// [ChatOutputFormat::Json] events, in which case `stderr`.
+    pub fn display_output<'a>(&self, stdout: &'a mut dyn Write, stderr: &'a mut dyn Write) -> &'a mut dyn Write {
+        match self.format.is_json() {
+            true => stderr,
+            false => stdout,
+        }
+    }
+
+    /// Content of the last assistant message received.
+    pub fn last_response(&self) -> Option<&str> {
+        self.last_response.as_deref()
+    }
+
+    /// Events held until [Self::finish] for [ChatOutputFormat::Json].
+    pub fn events(&self) -> &[OutputEvent] {
+        &self.buffered
+    }
+
+    /// Message of the last error, unless the model responded successfully since.
+    pub fn last_error_message(&self) -> Option<&str> {
+        self.last_error_message.as_deref()
+    }
+
+    /// Writes `event` to `output`, or buffers it for [ChatOutputFormat::Json]. Does nothing for
+    /// [ChatOutputFormat::Text].
+    pub fn emit(&mut self, output: &mut impl Write, event: OutputEvent) -> std::io::Result<()> {
+        match &event {
+            OutputEvent::EndStream { message, .. } => {
+                self.last_response = Some(message.content().to_string());
+                self.last_error_reason = None;
+                self.last_error_message = None;
+            },
+            OutputEvent::Error { reason, message } => {
+                self.last_error_reason = Some(reason.clone());
+                self.last_error_message = Some(message.clone());
+            },
+            _ => (),
+        }
+
+        match self.format {
+            ChatOutputFormat::Text => Ok(()),
+            ChatOutputFormat::StreamJson => {
+                serde_json::to_writer(&mut *output, &event)?;
+                writeln!(output)?;
+                output.flush()
+            },
+            ChatOutputFormat::JsonRpc => write_notification(output, "event", &event),
+            ChatOutputFormat::Json => {
+                self.buffered.push(event);
+                Ok(())
+            },
+        }
+    }
+
+    /// Writes the final [OutputEvent::Exit] event, flushing all buffered events for
+    /// [ChatOutputFormat::Json]. Subsequent calls do nothing.
+    pub fn finish(&mut self, output: &mut impl Write, reason: ExitReason) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.emit(output, OutputEvent::Exit { reason })?;
+        if self.format == ChatOutputFormat::Json {
+            serde_json::to_writer(&mut *output, &JsonOutput {
+                response: self.last_response.as_deref(),
+                exit_reason: reason,
+                events: &self.buffered,
+            })?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// The reason to report when the session ends normally, based on whether an error was
+    /// encountered since the last model response.
+    pub fn implied_exit_reason(&self) -> ExitReason {
+        match self.last_error_reason.as_deref() {
+            Some("Interrupted") => ExitReason::Interrupted,
+            Some(_) => ExitReason::Error,
+            None => ExitReason::EndTurn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn end_stream(content: &str) -> OutputEvent {
+        OutputEvent::EndStream {
+            message: AssistantMessage::new_response(None, content.to_string()),
+            request_metadata: RequestMetadata::default(),
+        }
+    }
+
+    fn lines(output: &[u8]) -> Vec<serde_json::Value> {
+        String::from_utf8_lossy(output)
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_json_writes_each_event() {
+        let mut output = Vec::new();
+        let mut writer = OutputEventWriter::new(ChatOutputFormat::StreamJson);
+        writer
+            .emit(&mut output, OutputEvent::AssistantText {
+                text: "hello".to_string(),
+            })
+            .unwrap();
+        writer.emit(&mut output, end_stream("hello")).unwrap();
+        writer.finish(&mut output, writer.implied_exit_reason()).unwrap();
+        // Finishing twice should not write another exit event.
+        writer.finish(&mut output, ExitReason::Error).unwrap();
+
+        let events = lines(&output);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["type"], "assistantText");
+        assert_eq!(events[0]["text"], "hello");
+        assert_eq!(events[1]["type"], "endStream");
+        assert!(events[1]["requestMetadata"].is_object());
+        assert_eq!(events[2]["type"], "exit");
+        assert_eq!(events[2]["reason"], "endTurn");
+    }
+
+    #[test]
+    fn test_json_writes_single_document() {
+        let mut output = Vec::new();
+        let mut writer = OutputEventWriter::new(ChatOutputFormat::Json);
+        writer.emit(&mut output, end_stream("first")).unwrap();
+        assert!(output.is_empty());
+        writer
+            .emit(&mut output, OutputEvent::Error {
+                reason: "Interrupted".to_string(),
+                message: "interrupted".to_string(),
+            })
+            .unwrap();
+        assert_eq!(writer.implied_exit_reason(), ExitReason::Interrupted);
+        writer.finish(&mut output, writer.implied_exit_reason()).unwrap();
+
+        let documents = lines(&output);
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["response"], "first");
+        assert_eq!(documents[0]["exitReason"], "interrupted");
+        assert_eq!(documents[0]["events"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_json_rpc_writes_notifications() {
+        let mut output = Vec::new();
+        let mut writer = OutputEventWriter::new(ChatOutputFormat::JsonRpc);
+        writer.emit(&mut output, end_stream("hello")).unwrap();
+        writer.finish(&mut output, ExitReason::EndTurn).unwrap();
+
+        let notifications = lines(&output);
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0]["jsonrpc"], "2.0");
+        assert_eq!(notifications[0]["method"], "event");
+        assert_eq!(notifications[0]["params"]["type"], "endStream");
+        assert_eq!(notifications[1]["params"]["type"], "exit");
+    }
+
+    #[test]
+    fn test_text_writes_nothing() {
+        let mut output = Vec::new();
+        let mut writer = OutputEventWriter::new(ChatOutputFormat::Text);
+        writer.emit(&mut output, end_stream("hello")).unwrap();
+        writer.finish(&mut output, ExitReason::EndTurn).unwrap();
+        assert!(output.is_empty());
+    }
+}
@@ -92,9 +92,25 @@ pub const COMMANDS: &[&str] = &[
     "/compact",
     "/compact help",
     "/usage",
+    "/checkpoint",
+    "/checkpoint save",
+    "/checkpoint list",
+    "/checkpoint restore",
+    "/undo",
+    "/pin",
+    "/pin list",
+    "/pin remove",
+    "/pin clear",
+    "/shell",
+    "/shell reset",
+    "/jobs",
+    "/jobs kill",
+    "/subagents",
+    "/subagents show",
     "/changelog",
     "/save",
     "/load",
+    "/export",
     "/subscribe",
     "/todos",
     "/todos resume",
@@ -0,0 +1,706 @@
+//! One-shot agent runs started with `q agent run`.
+//!
+//! A run sends a single task to an agent and lets the conversation go on without a user until the
+//! model responds without using tools, or until one of the limits of its [RunBudget] is reached.
+//! Sub-agents started with the `delegate` tool count towards the same limits. Tool uses that the
+//! permissions of the agent don't allow are rejected instead of prompting for an approval, which
+//! ends the run unless it continues without them. The outcome of the run is written as a
+//! [RunResult] JSON document.
+
+use std::io::{
+    IsTerminal,
+    Read,
+    Write,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use clap::Args;
+use crossterm::terminal;
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Serialize;
+
+use super::cli::model::{
+    find_model,
+    get_available_models,
+};
+use super::input_source::InputSource;
+use super::output_format::{
+    ChatOutputFormat,
+    ExitReason,
+    OutputEvent,
+};
+use super::token_counter::TokenCounted;
+use super::tool_manager::{
+    PromptQuery,
+    PromptQueryResult,
+    ToolManagerBuilder,
+};
+use super::{
+    ChatSession,
+    ChatState,
+};
+use crate::api_client::model::ToolResultStatus;
+use crate::cli::agent::Agents;
+use crate::cli::agent::params::parse_param;
+use crate::database::settings::Setting;
+use crate::os::Os;
+
+/// Runs an agent on a task without user interaction and writes the result as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct RunArgs {
+    /// Name of the agent to run
+    pub name: String,
+    /// The task for the agent. Read from --input-file or stdin when not given
+    #[arg(conflicts_with = "input_file")]
+    pub input: Option<String>,
+    /// File to read the task from
+    #[arg(long, value_name = "PATH")]
+    pub input_file: Option<PathBuf>,
+    /// Parameter for the agent, validated against its inputSchema. Can be given multiple times.
+    /// Example: '--param pr=123'
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    pub params: Vec<(String, String)>,
+    /// Model to use instead of the model of the agent
+    #[arg(long)]
+    pub model: Option<String>,
+    /// File to write the result to. The result is written to stdout if not given
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// Stop once the model has responded this many times
+    #[arg(long, value_name = "TURNS")]
+    pub max_turns: Option<usize>,
+    /// Stop once this many tokens were sent to and received from the model
+    #[arg(long, value_name = "TOKENS")]
+    pub max_tokens: Option<usize>,
+    /// Stop once the run has taken this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+    /// Reject tool uses that need an approval and tell the model to continue without them,
+    /// instead of ending the run
+    #[arg(long)]
+    pub continue_on_denied_tools: bool,
+}
+
+impl RunArgs {
+    pub async fn execute(self, os: &mut Os) -> Result<ExitCode> {
+        let input = match (self.input, &self.input_file) {
+            (Some(input), _) => input,
+            (None, Some(path)) => os.fs.read_to_string(path).await?,
+            (None, None) if !std::io::stdin().is_terminal() => {
+                let mut buffer = String::new();
+                std::io::stdin().read_to_string(&mut buffer)?;
+                buffer
+            },
+            (None, None) => String::new(),
+        };
+        if input.trim().is_empty() {
+            bail!("The task must be given as an argument, with --input-file or through stdin");
+        }
+
+        let mut stderr = std::io::stderr();
+        let mcp_enabled = match os.client.is_mcp_enabled().await {
+            Ok(enabled) => enabled,
+            Err(err) => {
+                tracing::warn!(?err, "Failed to check MCP configuration, defaulting to enabled");
+                true
+            },
+        };
+        let (mut agents, _) = Agents::load(os, Some(&self.name), true, &mut stderr, mcp_enabled).await;
+        if agents.switch(&self.name).is_err() {
+            bail!("There is no agent named {}", self.name);
+        }
+        let Some(agent) = agents.get_active_mut() else {
+            bail!("There is no agent named {}", self.name);
+        };
+        agent.apply_param_args(self.params, false)?;
+        let agent = agent.clone();
+
+        let (models, default_model) = get_available_models(os).await?;
+        let model_id = match self.model.as_ref().or(agent.model.as_ref()) {
+            Some(requested) => match find_model(&models, requested) {
+                Some(model) => model.model_id.clone(),
+                None => bail!("Model '{requested}' is not available"),
+            },
+            None => os
+                .database
+                .settings
+                .get_string(Setting::ChatDefaultModel)
+                .and_then(|saved| find_model(&models, &saved))
+                .unwrap_or(&default_model)
+                .model_id
+                .clone(),
+        };
+
+        let conversation_id = uuid::Uuid::new_v4().to_string();
+        let (prompt_request_sender, prompt_request_receiver) = tokio::sync::broadcast::channel::<PromptQuery>(5);
+        let (prompt_response_sender, prompt_response_receiver) =
+            tokio::sync::broadcast::channel::<PromptQueryResult>(5);
+        let mut tool_manager = ToolManagerBuilder::default()
+            .prompt_query_result_sender(prompt_response_sender)
+            .prompt_query_receiver(prompt_request_receiver)
+            .prompt_query_sender(prompt_request_sender)
+            .prompt_query_result_receiver(prompt_response_receiver)
+            .conversation_id(&conversation_id)
+            .agent(agent)
+            .build(os, Box::new(std::io::stderr()), false)
+            .await?;
+        let tool_config = tool_manager.load_tools(os, &mut stderr).await?;
+
+        // Events are never written, stdout is reserved for the result. Everything else is displayed
+        // on stderr.
+        let mut session = ChatSession::new(
+            os,
+            std::io::stdout(),
+            stderr,
+            &conversation_id,
+            agents,
+            None,
+            InputSource::new_mock(vec![]),
+            None,
+            || terminal::window_size().map(|s| s.columns.into()).ok(),
+            tool_manager,
+            Some(model_id),
+            tool_config,
+            false,
+            mcp_enabled,
+            None,
+            Some(ChatOutputFormat::Json),
+        )
+        .await?;
+
+        let budget = RunBudget {
+            max_turns: self.max_turns,
+            max_tokens: self.max_tokens,
+            timeout: self.timeout.map(Duration::from_secs),
+        };
+        session.run = Some(RunTracker::new(budget, self.continue_on_denied_tools));
+        let result = session.run_to_completion(os, input).await;
+
+        let json = serde_json::to_string_pretty(&result)?;
+        match &self.output {
+            Some(path) => os.fs.write(path, format!("{json}\n")).await?,
+            None => writeln!(std::io::stdout(), "{json}")?,
+        }
+
+        Ok(match result.exit_reason {
+            RunExitReason::Completed => ExitCode::SUCCESS,
+            _ => ExitCode::FAILURE,
+        })
+    }
+}
+
+/// Limits of a run. Limits that are [None] are not enforced.
+#[derive(Debug, Clone, Default)]
+pub struct RunBudget {
+    /// Number of requests sent to the model.
+    pub max_turns: Option<usize>,
+    /// Tokens of the requests sent to the model and of its responses, counted like `/usage` does.
+    pub max_tokens: Option<usize>,
+    /// Wall-clock time of the whole run.
+    pub timeout: Option<Duration>,
+}
+
+/// Why a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunExitReason {
+    /// The model responded without using tools.
+    Completed,
+    /// The model would have been sent more than [RunBudget::max_turns] requests.
+    MaxTurns,
+    /// [RunBudget::max_tokens] was used up.
+    MaxTokens,
+    /// [RunBudget::timeout] elapsed.
+    Timeout,
+    /// A tool use needed an approval that the permissions of the agent do not give.
+    ApprovalRequired,
+    /// The run was interrupted with a sigint.
+    Interrupted,
+    /// The run ended after encountering an error.
+    Error,
+}
+
+impl std::fmt::Display for RunExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Completed => "The run completed",
+            Self::MaxTurns => "The run reached its maximum number of turns",
+            Self::MaxTokens => "The run used up its tokens",
+            Self::Timeout => "The run timed out",
+            Self::ApprovalRequired => "A tool use needed an approval, which is not available during the run",
+            Self::Interrupted => "The run was interrupted",
+            Self::Error => "The run ended after an error",
+        })
+    }
+}
+
+/// Tracks a run across the session of `q agent run` and the sub-agents it starts, which all count
+/// towards the same [RunBudget].
+#[derive(Debug, Clone)]
+pub struct RunTracker {
+    budget: RunBudget,
+    continue_on_denied_tools: bool,
+    usage: Arc<Mutex<RunUsage>>,
+    /// Number of output events of the session that were counted.
+    seen_events: usize,
+}
+
+#[derive(Debug, Default)]
+struct RunUsage {
+    turns: usize,
+    tokens: usize,
+    /// Set once the run must end, e.g. because a sub-agent needed an approval.
+    stop: Option<RunExitReason>,
+}
+
+impl RunTracker {
+    pub fn new(budget: RunBudget, continue_on_denied_tools: bool) -> Self {
+        Self {
+            budget,
+            continue_on_denied_tools,
+            usage: Arc::default(),
+            seen_events: 0,
+        }
+    }
+
+    /// The tracker of a sub-agent started by the session, sharing the usage of the run.
+    pub fn for_subagent(&self) -> Self {
+        Self {
+            seen_events: 0,
+            ..self.clone()
+        }
+    }
+
+    pub fn continues_on_denied_tools(&self) -> bool {
+        self.continue_on_denied_tools
+    }
+
+    /// Ends the run, which happens once the session or the sessions it runs in check the run.
+    pub fn stop(&self, reason: RunExitReason) {
+        self.usage.lock().unwrap().stop.get_or_insert(reason);
+    }
+}
+
+/// What happened to a tool use requested by the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolCallStatus {
+    Succeeded,
+    Failed,
+    /// The tool was not run because the permissions of the agent do not allow it.
+    Denied,
+    /// The tool was not run, eg. because another tool requested along with it was denied or the
+    /// run ended first.
+    NotRun,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+    pub status: ToolCallStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// A file written by `fs_write` during the run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub change: FileChangeKind,
+}
+
+/// The document written once a run ends.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunResult {
+    pub agent: String,
+    pub exit_reason: RunExitReason,
+    /// Content of the last response of the model.
+    pub response: Option<String>,
+    /// Message of the error that ended the run, if any.
+    pub error: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub files_changed: Vec<FileChange>,
+    /// Number of requests sent to the model.
+    pub turns: usize,
+    /// Tokens of the requests sent to the model and of its responses.
+    pub tokens: usize,
+    pub duration_secs: f64,
+}
+
+impl ChatSession {
+    /// Counts the usage of the session towards its run before it handles its next state. Returns
+    /// why the run must end before that state is handled, if it must.
+    ///
+    /// Requests are counted before they are sent, the request about to be sent is already part of
+    /// the usage timeline.
+    pub(super) fn check_run(&mut self) -> Option<RunExitReason> {
+        let tracker = self.run.as_mut()?;
+        let mut usage = tracker.usage.lock().unwrap();
+        if let Some(reason) = usage.stop {
+            return Some(reason);
+        }
+
+        let tokenizer = self.conversation.tokenizer();
+        for event in &self.output_events.events()[tracker.seen_events..] {
+            if let OutputEvent::EndStream { message, .. } = event {
+                usage.tokens += message.token_count(tokenizer).value();
+            }
+        }
+        tracker.seen_events = self.output_events.events().len();
+
+        if let Some(ChatState::HandleResponseStream(_)) = &self.inner {
+            let budget = &tracker.budget;
+            let reason = if budget.max_turns.is_some_and(|max| usage.turns >= max) {
+                RunExitReason::MaxTurns
+            } else if budget.max_tokens.is_some_and(|max| usage.tokens >= max) {
+                RunExitReason::MaxTokens
+            } else {
+                usage.turns += 1;
+                usage.tokens += self
+                    .conversation
+                    .usage_timeline()
+                    .back()
+                    .map_or(0, |usage| usage.total().value());
+                return None;
+            };
+            usage.stop = Some(reason);
+            return Some(reason);
+        }
+        None
+    }
+
+    /// Sends `input` and handles the states of the session until it exits or the budget of
+    /// [Self::run] is used up.
+    ///
+    /// Must be run with [ChatOutputFormat::Json], since the result is built from the buffered
+    /// events. The conversation is not saved as a session.
+    async fn run_to_completion(&mut self, os: &mut Os, input: String) -> RunResult {
+        let start = Instant::now();
+        self.conversation.set_persisted(false);
+        let tracker = self
+            .run
+            .get_or_insert_with(|| RunTracker::new(RunBudget::default(), false));
+        let usage = Arc::clone(&tracker.usage);
+        let deadline = tracker
+            .budget
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut error = None;
+
+        let exit_reason = 'run: {
+            // The task is sent to the model as is, it does not run as a slash or shell command.
+            match self.handle_task(os, &input).await {
+                Ok(state) => self.inner = Some(state),
+                Err(err) => {
+                    error = Some(err.to_string());
+                    break 'run RunExitReason::Error;
+                },
+            }
+
+            loop {
+                if let Some(reason) = self.check_run() {
+                    break reason;
+                }
+                if let Some(ChatState::Exit) = &self.inner {
+                    break match self.output_events.implied_exit_reason() {
+                        ExitReason::EndTurn => RunExitReason::Completed,
+                        ExitReason::Interrupted => RunExitReason::Interrupted,
+                        _ => {
+                            error = self.output_events.last_error_message().map(str::to_string);
+                            RunExitReason::Error
+                        },
+                    };
+                }
+
+                let result = match deadline {
+                    Some(deadline) => match tokio::time::timeout_at(deadline, self.next(os)).await {
+                        Ok(result) => result,
+                        Err(_elapsed) => break RunExitReason::Timeout,
+                    },
+                    None => self.next(os).await,
+                };
+                if let Err(err) = result {
+                    error = Some(err.to_string());
+                    break RunExitReason::Error;
+                }
+            }
+        };
+
+        let (turns, tokens) = {
+            let usage = usage.lock().unwrap();
+            (usage.turns, usage.tokens)
+        };
+        let mut files_changed = Vec::new();
+        for snapshot in self.file_snapshots.snapshots_since(0) {
+            let change = match snapshot.content {
+                None => FileChangeKind::Created,
+                Some(_) if !os.fs.exists(&snapshot.path) => FileChangeKind::Deleted,
+                Some(_) => FileChangeKind::Modified,
+            };
+            files_changed.push(FileChange {
+                path: snapshot.path,
+                change,
+            });
+        }
+
+        RunResult {
+            agent: self
+                .conversation
+                .agents
+                .get_active()
+                .map(|agent| agent.name.clone())
+                .unwrap_or_default(),
+            exit_reason,
+            response: self.output_events.last_response().map(str::to_string),
+            error,
+            tool_calls: tool_calls(self.output_events.events()),
+            files_changed,
+            turns,
+            tokens,
+            duration_secs: start.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// The tool uses requested by the model, in order, along with what happened to them.
+fn tool_calls(events: &[OutputEvent]) -> Vec<ToolCall> {
+    let mut calls = Vec::<ToolCall>::new();
+    for event in events {
+        let (id, status) = match event {
+            OutputEvent::ToolUse { tool_use } => {
+                calls.push(ToolCall {
+                    id: tool_use.id.clone(),
+                    name: tool_use.name.clone(),
+                    input: tool_use.args.clone(),
+                    status: ToolCallStatus::NotRun,
+                });
+                continue;
+            },
+            OutputEvent::ToolResult {
+                tool_use_id, status, ..
+            } => (tool_use_id, match status {
+                ToolResultStatus::Success => ToolCallStatus::Succeeded,
+                ToolResultStatus::Error => ToolCallStatus::Failed,
+            }),
+            OutputEvent::ToolDenied { tool_use_id, .. } => (tool_use_id, ToolCallStatus::Denied),
+            _ => continue,
+        };
+        if let Some(call) = calls.iter_mut().rev().find(|call| call.id == *id) {
+            call.status = status;
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::cli::agent::Agent;
+    use crate::cli::chat::tool_manager::ToolManager;
+    use crate::cli::chat::tools::ToolSpec;
+
+    /// Runs the first of `agents`, which can delegate tasks to the others.
+    async fn run(os: &mut Os, agents: &[Agent], tracker: RunTracker) -> RunResult {
+        let mut all_agents = Agents::default();
+        for agent in agents {
+            all_agents.agents.insert(agent.name.clone(), agent.clone());
+        }
+        all_agents.switch(&agents[0].name).unwrap();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut session = ChatSession::new(
+            os,
+            std::io::stdout(),
+            std::io::stderr(),
+            "fake_conv_id",
+            all_agents,
+            None,
+            InputSource::new_mock(vec![]),
+            None,
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            false,
+            false,
+            None,
+            Some(ChatOutputFormat::Json),
+        )
+        .await
+        .unwrap();
+        session.run = Some(tracker);
+        session.run_to_completion(os, "create a file".to_string()).await
+    }
+
+    fn agent(name: &str, allowed_tools: &[&str]) -> Agent {
+        Agent {
+            name: name.to_string(),
+            allowed_tools: allowed_tools.iter().map(|tool| (*tool).to_string()).collect(),
+            use_legacy_mcp_json: false,
+            ..Default::default()
+        }
+    }
+
+    /// Mocked responses that create a file, then end with `answer`.
+    fn create_file_then(answer: &str) -> serde_json::Value {
+        serde_json::json!([
+            [
+                "Creating the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [answer],
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_unapproved_tools() {
+        let mut os = Os::new().await.unwrap();
+        os.client
+            .set_mock_output(create_file_then("I am not allowed to create the file"));
+
+        let result = run(
+            &mut os,
+            &[agent("reviewer", &[])],
+            RunTracker::new(RunBudget::default(), false),
+        )
+        .await;
+        assert_eq!(result.exit_reason, RunExitReason::ApprovalRequired);
+        assert_eq!(result.response.as_deref(), Some("Creating the file"));
+        assert_eq!(result.turns, 1);
+        assert_eq!(result.tool_calls[0].status, ToolCallStatus::Denied);
+        assert!(!os.fs.exists("/file.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_run_continues_on_denied_tools() {
+        let mut os = Os::new().await.unwrap();
+        os.client
+            .set_mock_output(create_file_then("I am not allowed to create the file"));
+
+        let result = run(
+            &mut os,
+            &[agent("reviewer", &[])],
+            RunTracker::new(RunBudget::default(), true),
+        )
+        .await;
+        assert_eq!(result.exit_reason, RunExitReason::Completed);
+        assert_eq!(result.response.as_deref(), Some("I am not allowed to create the file"));
+        assert_eq!(result.turns, 2);
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].name, "fs_write");
+        assert_eq!(result.tool_calls[0].status, ToolCallStatus::Denied);
+        assert!(result.files_changed.is_empty());
+        assert!(!os.fs.exists("/file.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_run_max_turns() {
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(create_file_then("Created the file"));
+        let budget = RunBudget {
+            max_turns: Some(1),
+            ..Default::default()
+        };
+
+        let result = run(
+            &mut os,
+            &[agent("writer", &["fs_write"])],
+            RunTracker::new(budget, false),
+        )
+        .await;
+        assert_eq!(result.exit_reason, RunExitReason::MaxTurns);
+        assert_eq!(result.response.as_deref(), Some("Creating the file"));
+        assert_eq!(result.turns, 1);
+        assert!(result.tokens > 0);
+        assert_eq!(result.tool_calls[0].status, ToolCallStatus::Succeeded);
+        assert_eq!(result.files_changed.len(), 1);
+        assert_eq!(result.files_changed[0].change, FileChangeKind::Created);
+        assert_eq!(os.fs.read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_counts_subagents() {
+        let responses = serde_json::json!([
+            [
+                "The writer will do it",
+                {
+                    "tool_use_id": "1",
+                    "name": "delegate",
+                    "args": { "agent": "writer", "task": "create a file" }
+                }
+            ],
+            [
+                "Creating the file",
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            ["Created the file"],
+            ["The writer created the file"],
+        ]);
+        let agents = [agent("lead", &["delegate"]), agent("writer", &["fs_write"])];
+
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(responses.clone());
+        let result = run(&mut os, &agents, RunTracker::new(RunBudget::default(), false)).await;
+        assert_eq!(result.exit_reason, RunExitReason::Completed);
+        assert_eq!(result.response.as_deref(), Some("The writer created the file"));
+        assert_eq!(result.turns, 4);
+        assert_eq!(result.files_changed.len(), 1);
+        assert_eq!(result.files_changed[0].change, FileChangeKind::Created);
+        assert!(result.files_changed[0].path.ends_with("file.txt"));
+        // Neither the run nor its sub-agents are saved as sessions.
+        assert!(os.database.list_sessions(None).unwrap().is_empty());
+
+        // The budget is used up by the second request of the sub-agent, which ends the whole run.
+        let mut os = Os::new().await.unwrap();
+        os.client.set_mock_output(responses);
+        let budget = RunBudget {
+            max_turns: Some(2),
+            ..Default::default()
+        };
+        let result = run(&mut os, &agents, RunTracker::new(budget, false)).await;
+        assert_eq!(result.exit_reason, RunExitReason::MaxTurns);
+        assert_eq!(result.turns, 2);
+        assert_eq!(result.files_changed.len(), 1);
+    }
+}
@@ -0,0 +1,544 @@
+//! A JSON-RPC 2.0 frontend for the chat session, started with `q chat --serve-stdio`.
+//!
+//! Requests are read from stdin and responses are written to stdout, one JSON message per line.
+//! Requests are handled whenever the session waits for input, i.e. before the first prompt, after
+//! each turn, and when a tool use requires approval. Requests sent while the model is responding
+//! are queued until the turn ends.
+//!
+//! Methods:
+//! - `prompt {"text"}`: send a prompt to the model
+//! - `command {"command"}`: run a slash command, e.g. `/compact`. Its human-readable output is
+//!   written to stderr. Commands that read from the terminal, e.g. `/clear` or `/model`, are
+//!   rejected
+//! - `approveTool {"decision": "allow" | "deny" | "trust"}`: answer a `toolApprovalRequest`
+//! - `getState`: return the conversation, context and pending approval state
+//! - `shutdown`: end the session
+//!
+//! Notifications sent by the server:
+//! - `event`: an [OutputEvent], e.g. streamed assistant text, tool uses and tool results
+//! - `toolApprovalRequest`: a tool use is waiting for `approveTool`
+//! - `ready`: the session finished handling the last request and waits for the next one
+
+use std::io::Write;
+
+use clap::Parser;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use tokio::io::{
+    AsyncBufRead,
+    AsyncBufReadExt,
+};
+
+use super::conversation::ConversationSize;
+#[cfg(doc)]
+use super::output_format::OutputEvent;
+use super::token_counter::TokenCount;
+use super::tools::QueuedTool;
+use super::{
+    ChatError,
+    ChatSession,
+    ChatState,
+};
+use crate::cli::chat::cli::SlashCommand;
+use crate::cli::chat::cli::model::context_window_tokens;
+use crate::os::Os;
+
+const JSONRPC_VERSION: &str = "2.0";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Writes a JSON-RPC notification to `output`.
+pub fn write_notification(output: &mut impl Write, method: &str, params: &impl Serialize) -> std::io::Result<()> {
+    write_message(
+        output,
+        &serde_json::json!({
+            "jsonrpc": JSONRPC_VERSION,
+            "method": method,
+            "params": params,
+        }),
+    )
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *output, message)?;
+    writeln!(output)?;
+    output.flush()
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRequest {
+    jsonrpc: Option<String>,
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A request read by [StdioServer::next_request].
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    /// [None] for notifications, which are not answered.
+    pub id: Option<Value>,
+    pub method: Method,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    Prompt { text: String },
+    Command { command: String },
+    ApproveTool { decision: ToolDecision },
+    GetState,
+    Shutdown,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptParams {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandParams {
+    command: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApproveToolParams {
+    decision: ToolDecision,
+}
+
+/// The answer to a `toolApprovalRequest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolDecision {
+    /// Run the tool once.
+    Allow,
+    /// Do not run the tool. The model is asked to clarify the expected action.
+    Deny,
+    /// Run the tool and trust it for the rest of the session.
+    Trust,
+}
+
+impl ToolDecision {
+    /// The input a user would type in the terminal for this decision.
+    fn as_input(self) -> &'static str {
+        match self {
+            Self::Allow => "y",
+            Self::Deny => "n",
+            Self::Trust => "t",
+        }
+    }
+}
+
+/// Parameters of the `toolApprovalRequest` notification.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolApprovalRequest {
+    tool_use_id: String,
+    name: String,
+    input: Value,
+}
+
+impl From<&QueuedTool> for ToolApprovalRequest {
+    fn from(value: &QueuedTool) -> Self {
+        Self {
+            tool_use_id: value.id.clone(),
+            name: value.name.clone(),
+            input: value.tool_input.clone(),
+        }
+    }
+}
+
+/// The result of `getState`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionState {
+    conversation_id: String,
+    agent: Option<String>,
+    model: Option<String>,
+    history_length: usize,
+    pins: usize,
+    context_files: Vec<ContextFileState>,
+    context_window: usize,
+    #[serde(flatten)]
+    size: ConversationSize,
+    tools: TokenCount,
+    pending_tool_approval: Option<ToolApprovalRequest>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContextFileState {
+    path: String,
+    tokens: TokenCount,
+}
+
+/// Reads JSON-RPC requests for a [ChatSession] running with `--serve-stdio`.
+pub struct StdioServer {
+    input: Box<dyn AsyncBufRead + Unpin + Send>,
+}
+
+impl std::fmt::Debug for StdioServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StdioServer").finish_non_exhaustive()
+    }
+}
+
+impl StdioServer {
+    /// Reads requests from stdin.
+    pub fn stdin() -> Self {
+        Self {
+            input: Box::new(tokio::io::BufReader::new(tokio::io::stdin())),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn new_mock(requests: Vec<Value>) -> Self {
+        let lines = requests.iter().map(|r| format!("{r}\n")).collect::<String>();
+        Self {
+            input: Box::new(std::io::Cursor::new(lines.into_bytes())),
+        }
+    }
+
+    /// Reads the next valid request, answering malformed ones with an error. Returns [None] once
+    /// the input is closed.
+    pub async fn next_request(&mut self, output: &mut impl Write) -> std::io::Result<Option<Request>> {
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let raw = match serde_json::from_str::<RawRequest>(&line) {
+                Ok(raw) => raw,
+                Err(err) => {
+                    let code = match serde_json::from_str::<Value>(&line) {
+                        Ok(_) => INVALID_REQUEST,
+                        Err(_) => PARSE_ERROR,
+                    };
+                    respond_error(output, Some(Value::Null), code, &err.to_string())?;
+                    continue;
+                },
+            };
+            if raw.jsonrpc.as_deref() != Some(JSONRPC_VERSION) {
+                respond_error(output, raw.id, INVALID_REQUEST, "jsonrpc must be \"2.0\"")?;
+                continue;
+            }
+
+            let method = match raw.method.as_str() {
+                "prompt" => serde_json::from_value::<PromptParams>(raw.params).map(|p| Method::Prompt { text: p.text }),
+                "command" => {
+                    serde_json::from_value::<CommandParams>(raw.params).map(|p| Method::Command { command: p.command })
+                },
+                "approveTool" => serde_json::from_value::<ApproveToolParams>(raw.params)
+                    .map(|p| Method::ApproveTool { decision: p.decision }),
+                "getState" => Ok(Method::GetState),
+                "shutdown" => Ok(Method::Shutdown),
+                other => {
+                    respond_error(output, raw.id, METHOD_NOT_FOUND, &format!("Unknown method '{other}'"))?;
+                    continue;
+                },
+            };
+
+            match method {
+                Ok(method) => return Ok(Some(Request { id: raw.id, method })),
+                Err(err) => respond_error(output, raw.id, INVALID_PARAMS, &err.to_string())?,
+            }
+        }
+    }
+
+    /// Handles requests until one of them produces input for the session, sending a
+    /// `toolApprovalRequest` or `ready` notification first.
+    pub async fn handle_requests(&mut self, os: &mut Os, session: &mut ChatSession) -> Result<ChatState, ChatError> {
+        let pending_tool_approval = session
+            .pending_tool_index
+            .and_then(|i| session.tool_uses.get(i))
+            .map(ToolApprovalRequest::from);
+        match &pending_tool_approval {
+            Some(request) => write_notification(&mut session.stdout, "toolApprovalRequest", request)?,
+            None => write_notification(&mut session.stdout, "ready", &serde_json::json!({}))?,
+        }
+
+        loop {
+            let Some(Request { id, method }) = self.next_request(&mut session.stdout).await? else {
+                return Ok(ChatState::Exit);
+            };
+
+            let input = match method {
+                Method::Prompt { text } => {
+                    // The session reads the answer to a tool approval as input, so a prompt would
+                    // be taken as one.
+                    if pending_tool_approval.is_some() {
+                        respond_error(
+                            &mut session.stdout,
+                            id,
+                            INVALID_REQUEST,
+                            "A tool use is awaiting approval. Use the approveTool method to answer it",
+                        )?;
+                        continue;
+                    }
+                    if text.trim_start().starts_with(['/', '!']) {
+                        respond_error(
+                            &mut session.stdout,
+                            id,
+                            INVALID_PARAMS,
+                            "Prompts cannot start with '/' or '!'. Use the command method to run slash commands",
+                        )?;
+                        continue;
+                    }
+                    text
+                },
+                Method::Command { command } => {
+                    if !command.trim_start().starts_with('/') {
+                        respond_error(&mut session.stdout, id, INVALID_PARAMS, "Commands must start with '/'")?;
+                        continue;
+                    }
+                    if is_interactive_command(&command) {
+                        respond_error(
+                            &mut session.stdout,
+                            id,
+                            INVALID_PARAMS,
+                            "This command reads from the terminal and cannot be run with --serve-stdio",
+                        )?;
+                        continue;
+                    }
+                    command
+                },
+                Method::ApproveTool { decision } => {
+                    if pending_tool_approval.is_none() {
+                        respond_error(
+                            &mut session.stdout,
+                            id,
+                            INVALID_REQUEST,
+                            "No tool use is awaiting approval",
+                        )?;
+                        continue;
+                    }
+                    decision.as_input().to_string()
+                },
+                Method::GetState => {
+                    let state = session_state(os, session, pending_tool_approval.clone()).await?;
+                    respond(&mut session.stdout, id, &state)?;
+                    continue;
+                },
+                Method::Shutdown => {
+                    respond(&mut session.stdout, id, &Value::Null)?;
+                    return Ok(ChatState::Exit);
+                },
+            };
+
+            respond(&mut session.stdout, id, &Value::Null)?;
+            session.conversation.append_user_transcript(&input);
+            return Ok(ChatState::HandleInput { input });
+        }
+    }
+}
+
+/// Whether `command` parses to a slash command that reads from the terminal, which would read the
+/// requests sent to the server instead. Commands that don't parse are left to the session, which
+/// prints their usage.
+fn is_interactive_command(command: &str) -> bool {
+    let Some(mut args) = command.trim().strip_prefix('/').and_then(shlex::split) else {
+        return false;
+    };
+    args.insert(0, "slash_command".to_owned());
+    SlashCommand::try_parse_from(args).is_ok_and(|command| command.is_interactive())
+}
+
+fn respond(output: &mut impl Write, id: Option<Value>, result: &impl Serialize) -> std::io::Result<()> {
+    let Some(id) = id else {
+        return Ok(());
+    };
+    write_message(
+        output,
+        &serde_json::json!({
+            "jsonrpc": JSONRPC_VERSION,
+            "id": id,
+            "result": result,
+        }),
+    )
+}
+
+fn respond_error(output: &mut impl Write, id: Option<Value>, code: i64, message: &str) -> std::io::Result<()> {
+    let Some(id) = id else {
+        return Ok(());
+    };
+    write_message(
+        output,
+        &serde_json::json!({
+            "jsonrpc": JSONRPC_VERSION,
+            "id": id,
+            "error": {
+                "code": code,
+                "message": message,
+            },
+        }),
+    )
+}
+
+async fn session_state(
+    os: &Os,
+    session: &mut ChatSession,
+    pending_tool_approval: Option<ToolApprovalRequest>,
+) -> Result<SessionState, ChatError> {
+    let tokenizer = session.conversation.tokenizer();
+    let context_files = match session.conversation.context_manager.as_ref() {
+        Some(context_manager) => context_manager
+            .collect_context_files_with_limit(os)
+            .await
+            .map(|(files, _)| files)
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let state = session
+        .conversation
+        .backend_conversation_state(os, true, &mut session.stderr)
+        .await?;
+    let size = state.calculate_conversation_size();
+    let tools = state.tool_specs_token_count();
+
+    Ok(SessionState {
+        conversation_id: session.conversation.conversation_id().to_string(),
+        agent: session.conversation.agents.get_active().map(|a| a.name.clone()),
+        model: session.conversation.model_info.as_ref().map(|m| m.model_id.clone()),
+        history_length: session.conversation.history().len(),
+        pins: session.conversation.pins().len(),
+        context_files: context_files
+            .into_iter()
+            .map(|(path, content)| ContextFileState {
+                tokens: tokenizer.count_tokens(&content),
+                path,
+            })
+            .collect(),
+        context_window: context_window_tokens(session.conversation.model_info.as_ref()),
+        size,
+        tools,
+        pending_tool_approval,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn responses(output: &[u8]) -> Vec<Value> {
+        String::from_utf8_lossy(output)
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_next_request() {
+        let mut server = StdioServer {
+            input: Box::new(std::io::Cursor::new(
+                [
+                    "not json",
+                    r#"{"jsonrpc":"2.0","id":1,"method":"unknown"}"#,
+                    r#"{"jsonrpc":"2.0","id":2,"method":"prompt","params":{}}"#,
+                    r#"{"jsonrpc":"1.0","id":3,"method":"getState"}"#,
+                    "",
+                    r#"{"jsonrpc":"2.0","id":4,"method":"prompt","params":{"text":"hello"}}"#,
+                    r#"{"jsonrpc":"2.0","method":"approveTool","params":{"decision":"trust"}}"#,
+                    r#"{"jsonrpc":"2.0","id":"a","method":"getState"}"#,
+                ]
+                .join("\n")
+                .into_bytes(),
+            )),
+        };
+        let mut output = Vec::new();
+
+        assert_eq!(
+            server.next_request(&mut output).await.unwrap(),
+            Some(Request {
+                id: Some(json!(4)),
+                method: Method::Prompt {
+                    text: "hello".to_string()
+                },
+            })
+        );
+        let errors = responses(&output);
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(errors[0]["id"], Value::Null);
+        assert_eq!(errors[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(errors[2]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(errors[2]["id"], 2);
+        assert_eq!(errors[3]["error"]["code"], INVALID_REQUEST);
+
+        output.clear();
+        assert_eq!(
+            server.next_request(&mut output).await.unwrap(),
+            Some(Request {
+                id: None,
+                method: Method::ApproveTool {
+                    decision: ToolDecision::Trust
+                },
+            })
+        );
+        assert_eq!(
+            server.next_request(&mut output).await.unwrap(),
+            Some(Request {
+                id: Some(json!("a")),
+                method: Method::GetState,
+            })
+        );
+        assert_eq!(server.next_request(&mut output).await.unwrap(), None);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_is_interactive_command() {
+        for command in [
+            "/clear",
+            "/model",
+            "/agent swap",
+            "/todos view",
+            "/editor",
+            "/knowledge clear",
+        ] {
+            assert!(is_interactive_command(command), "{command}");
+        }
+        for command in [
+            "/compact",
+            "/agent swap coder",
+            "/todos delete --all",
+            "/usage",
+            "/unknown",
+            "/'",
+        ] {
+            assert!(!is_interactive_command(command), "{command}");
+        }
+    }
+
+    #[test]
+    fn test_responses_skip_notifications() {
+        let mut output = Vec::new();
+        respond(&mut output, None, &Value::Null).unwrap();
+        respond_error(&mut output, None, INVALID_PARAMS, "ignored").unwrap();
+        assert!(output.is_empty());
+
+        respond(&mut output, Some(json!(1)), &json!({ "ok": true })).unwrap();
+        write_notification(&mut output, "ready", &json!({})).unwrap();
+        let messages = responses(&output);
+        assert_eq!(
+            messages[0],
+            json!({ "jsonrpc": "2.0", "id": 1, "result": { "ok": true } })
+        );
+        assert_eq!(
+            messages[1],
+            json!({ "jsonrpc": "2.0", "method": "ready", "params": {} })
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::Write;
+use std::process::ExitCode;
+
+use chrono::{
+    DateTime,
+    Local,
+};
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    bail,
+};
+
+use super::conversation::ConversationState;
+#[cfg(unix)]
+use super::skim_integration::launch_skim_selector;
+use crate::database::SessionInfo;
+use crate::os::Os;
+
+/// Manage the chat sessions saved for each directory
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum SessionsSubcommand {
+    /// List saved sessions, most recently updated first
+    List(ListArgs),
+    /// Show the metadata and transcript of a session
+    Show {
+        /// Id of the session. A unique prefix of the id is also accepted
+        id: String,
+    },
+    /// Delete a session
+    #[command(alias = "rm")]
+    Delete {
+        /// Id of the session. A unique prefix of the id is also accepted
+        id: String,
+    },
+    /// Delete sessions that have not been updated for a number of days
+    Prune {
+        /// Delete sessions last updated more than this many days ago
+        #[arg(long, default_value_t = 30)]
+        older_than_days: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ListArgs {
+    /// List sessions from every directory instead of only the current one
+    #[arg(long, short)]
+    pub all: bool,
+}
+
+impl SessionsSubcommand {
+    pub async fn execute(self, os: &mut Os, output: &mut impl Write) -> Result<ExitCode> {
+        match self {
+            Self::List(ListArgs { all }) => {
+                let cwd = os.env.current_dir()?;
+                let sessions = os.database.list_sessions((!all).then_some(cwd.as_path()))?;
+                if sessions.is_empty() {
+                    writeln!(output, "No saved sessions found.")?;
+                } else {
+                    for session in &sessions {
+                        writeln!(output, "{}", format_session(session, all))?;
+                    }
+                }
+            },
+            Self::Show { id } => {
+                let Some(info) = os.database.find_session(&id)? else {
+                    bail!("No session found with id '{id}'");
+                };
+                writeln!(output, "{}      {}", "Id:".bold(), info.id)?;
+                writeln!(output, "{}    {}", "Path:".bold(), info.path)?;
+                writeln!(output, "{}   {}", "Title:".bold(), info.title.as_deref().unwrap_or("-"))?;
+                writeln!(output, "{}   {}", "Agent:".bold(), info.agent.as_deref().unwrap_or("-"))?;
+                writeln!(output, "{}   {}", "Model:".bold(), info.model.as_deref().unwrap_or("-"))?;
+                writeln!(output, "{} {}", "Created:".bold(), format_timestamp(info.created_at))?;
+                writeln!(output, "{} {}", "Updated:".bold(), format_timestamp(info.updated_at))?;
+                if let Some(conversation) = os.database.get_session(&info.id)? {
+                    writeln!(output)?;
+                    for line in &conversation.transcript {
+                        writeln!(output, "{line}")?;
+                    }
+                }
+            },
+            Self::Delete { id } => {
+                let Some(info) = os.database.find_session(&id)? else {
+                    bail!("No session found with id '{id}'");
+                };
+                os.database.delete_session(&info.id)?;
+                writeln!(output, "Deleted session {}", info.id)?;
+            },
+            Self::Prune { older_than_days } => {
+                let before = chrono::Utc::now().timestamp() - i64::from(older_than_days) * 24 * 60 * 60;
+                let count = os.database.prune_sessions(before)?;
+                writeln!(output, "Deleted {count} session(s)")?;
+            },
+        }
+
+        output.flush()?;
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Opens a fuzzy picker over the sessions saved for the current directory and returns the
+/// selected conversation, or [None] if the selection was cancelled.
+pub fn select_session(os: &Os) -> Result<Option<ConversationState>> {
+    let cwd = os.env.current_dir()?;
+    let sessions = os.database.list_sessions(Some(&cwd))?;
+    if sessions.is_empty() {
+        bail!("No saved sessions found for {}", cwd.display());
+    }
+
+    let items = sessions.iter().map(|s| format_session(s, false)).collect::<Vec<_>>();
+    let Some(index) = pick(&items)? else {
+        return Ok(None);
+    };
+
+    Ok(os.database.get_session(&sessions[index].id)?)
+}
+
+#[cfg(unix)]
+fn pick(items: &[String]) -> Result<Option<usize>> {
+    let selected = launch_skim_selector(items, "Select a session to resume: ", false)?;
+    Ok(selected
+        .as_ref()
+        .and_then(|selected| selected.first())
+        .and_then(|s| items.iter().position(|item| item == s)))
+}
+
+#[cfg(not(unix))]
+fn pick(items: &[String]) -> Result<Option<usize>> {
+    Ok(dialoguer::FuzzySelect::with_theme(&crate::util::dialoguer_theme())
+        .with_prompt("Select a session to resume")
+        .items(items)
+        .report(false)
+        .interact_opt()?)
+}
+
+fn format_session(session: &SessionInfo, with_path: bool) -> String {
+    let mut line = format!(
+        "{}  {}  {}",
+        session.id,
+        format_timestamp(session.updated_at),
+        session.title.as_deref().unwrap_or("(untitled)")
+    );
+    if with_path {
+        line.push_str(&format!("  [{}]", session.path));
+    }
+    line
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
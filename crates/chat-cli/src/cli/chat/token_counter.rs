@@ -1,4 +1,24 @@
+use std::collections::HashMap;
+use std::hash::{
+    DefaultHasher,
+    Hash,
+    Hasher,
+};
 use std::ops::Deref;
+use std::sync::{
+    LazyLock,
+    Mutex,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tiktoken_rs::{
+    CoreBPE,
+    cl100k_base_singleton,
+    o200k_base_singleton,
+};
 
 use super::message::{
     AssistantMessage,
@@ -7,10 +27,6 @@ use super::message::{
     UserMessage,
     UserMessageContent,
 };
-use crate::cli::chat::conversation::{
-    BackendConversationState,
-    ConversationSize,
-};
 
 #[derive(Debug, Clone, Copy)]
 pub struct CharCount(usize);
@@ -43,7 +59,7 @@ impl std::ops::Add for CharCount {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct TokenCount(usize);
 
 impl TokenCount {
@@ -60,6 +76,26 @@ impl Deref for TokenCount {
     }
 }
 
+impl From<usize> for TokenCount {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl std::ops::Add for TokenCount {
+    type Output = TokenCount;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.value() + rhs.value())
+    }
+}
+
+impl std::iter::Sum for TokenCount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self(0), |acc, v| acc + v)
+    }
+}
+
 impl From<CharCount> for TokenCount {
     fn from(value: CharCount) -> Self {
         Self(TokenCounter::count_tokens_char_count(value.value()))
@@ -72,6 +108,65 @@ impl std::fmt::Display for TokenCount {
     }
 }
 
+/// The tokenizer used to count the tokens of a model's context window, see
+/// [ModelInfo::tokenizer](super::cli::model::ModelInfo::tokenizer).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tokenizer {
+    /// Estimates tokens from the content length, see [TokenCounter::count_tokens].
+    #[default]
+    Heuristic,
+    /// The bundled `cl100k_base` BPE vocabulary.
+    Cl100kBase,
+    /// The bundled `o200k_base` BPE vocabulary.
+    O200kBase,
+}
+
+/// Texts shorter than this are tokenized on every call instead of being cached.
+const MIN_CACHED_TEXT_LEN: usize = 512;
+
+/// Upper bound on the number of cached token counts; the cache is cleared once exceeded.
+const MAX_CACHED_TOKEN_COUNTS: usize = 8192;
+
+/// Token counts of previously tokenized texts keyed by the tokenizer and a hash of the text, so
+/// that recounting the unchanged history of a long conversation doesn't tokenize it again.
+static TOKEN_COUNT_CACHE: LazyLock<Mutex<HashMap<(Tokenizer, u64), usize>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+impl Tokenizer {
+    /// Counts the tokens in `text`.
+    pub fn count_tokens(&self, text: &str) -> TokenCount {
+        let bpe = match self {
+            Self::Heuristic => return TokenCounter::count_tokens(text).into(),
+            Self::Cl100kBase => cl100k_base_singleton(),
+            Self::O200kBase => o200k_base_singleton(),
+        };
+        if text.len() < MIN_CACHED_TEXT_LEN {
+            return Self::encode_len(bpe, text).into();
+        }
+
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let key = (*self, hasher.finish());
+        if let Some(count) = TOKEN_COUNT_CACHE.lock().ok().and_then(|cache| cache.get(&key).copied()) {
+            return count.into();
+        }
+
+        let count = Self::encode_len(bpe, text);
+        if let Ok(mut cache) = TOKEN_COUNT_CACHE.lock() {
+            if cache.len() >= MAX_CACHED_TOKEN_COUNTS {
+                cache.clear();
+            }
+            cache.insert(key, count);
+        }
+        count.into()
+    }
+
+    fn encode_len(bpe: &CoreBPE, text: &str) -> usize {
+        bpe.encode_ordinary(text).len()
+    }
+}
+
 pub struct TokenCounter;
 
 impl TokenCounter {
@@ -88,10 +183,6 @@ impl TokenCounter {
     fn count_tokens_char_count(count: usize) -> usize {
         (count / Self::TOKEN_TO_CHAR_RATIO + 5) / 10 * 10
     }
-
-    pub const fn token_to_chars(token: usize) -> usize {
-        token * Self::TOKEN_TO_CHAR_RATIO
-    }
 }
 
 /// A trait for types that represent some number of characters (aka bytes). For use in calculating
@@ -103,18 +194,6 @@ pub trait CharCounter {
     fn char_count(&self) -> CharCount;
 }
 
-impl CharCounter for BackendConversationState<'_> {
-    fn char_count(&self) -> CharCount {
-        self.calculate_conversation_size().char_count()
-    }
-}
-
-impl CharCounter for ConversationSize {
-    fn char_count(&self) -> CharCount {
-        self.user_messages + self.assistant_messages + self.context_messages
-    }
-}
-
 impl CharCounter for UserMessage {
     fn char_count(&self) -> CharCount {
         let mut total_chars = 0;
@@ -167,6 +246,68 @@ impl CharCounter for &[ToolUseResult] {
     }
 }
 
+/// A trait for types whose size can be measured in tokens with a [Tokenizer]. For use in
+/// calculating context window size utilization.
+pub trait TokenCounted {
+    /// Returns the number of tokens contained within this type.
+    fn token_count(&self, tokenizer: Tokenizer) -> TokenCount;
+}
+
+impl TokenCounted for UserMessage {
+    fn token_count(&self, tokenizer: Tokenizer) -> TokenCount {
+        if tokenizer == Tokenizer::Heuristic {
+            return self.char_count().into();
+        }
+        let mut total = tokenizer.count_tokens(self.additional_context());
+        match self.content() {
+            UserMessageContent::Prompt { prompt } => {
+                total = total + tokenizer.count_tokens(prompt);
+            },
+            UserMessageContent::CancelledToolUses {
+                prompt,
+                tool_use_results,
+            } => {
+                total = total + tokenizer.count_tokens(prompt.as_deref().unwrap_or_default());
+                total = total + tool_use_results.as_slice().token_count(tokenizer);
+            },
+            UserMessageContent::ToolUseResults { tool_use_results } => {
+                total = total + tool_use_results.as_slice().token_count(tokenizer);
+            },
+        }
+        total
+    }
+}
+
+impl TokenCounted for AssistantMessage {
+    fn token_count(&self, tokenizer: Tokenizer) -> TokenCount {
+        if tokenizer == Tokenizer::Heuristic {
+            return self.char_count().into();
+        }
+        let tool_uses = self
+            .tool_uses()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| tokenizer.count_tokens(&v.args.to_string()))
+            .sum::<TokenCount>();
+        tokenizer.count_tokens(self.content()) + tool_uses
+    }
+}
+
+impl TokenCounted for &[ToolUseResult] {
+    fn token_count(&self, tokenizer: Tokenizer) -> TokenCount {
+        if tokenizer == Tokenizer::Heuristic {
+            return self.char_count().into();
+        }
+        self.iter()
+            .flat_map(|v| &v.content)
+            .map(|v| match v {
+                ToolUseResultBlock::Json(v) => tokenizer.count_tokens(&v.to_string()),
+                ToolUseResultBlock::Text(s) => tokenizer.count_tokens(s),
+            })
+            .sum()
+    }
+}
+
 fn calculate_value_char_count(document: &serde_json::Value) -> usize {
     match document {
         serde_json::Value::Null => 1,
@@ -190,6 +331,25 @@ mod tests {
         assert_eq!(count, (text.len() / 3 + 5) / 10 * 10);
     }
 
+    #[test]
+    fn test_tokenizer_count_tokens() {
+        let text = "fn main() { println!(\"Hello, world!\"); }";
+        assert_eq!(
+            Tokenizer::Heuristic.count_tokens(text).value(),
+            TokenCounter::count_tokens(text)
+        );
+        assert_eq!(Tokenizer::Cl100kBase.count_tokens("").value(), 0);
+        assert_eq!(Tokenizer::Cl100kBase.count_tokens("hello world").value(), 2);
+        assert_eq!(Tokenizer::O200kBase.count_tokens("hello world").value(), 2);
+
+        // Non-English text uses far more tokens than the length heuristic suggests.
+        let japanese = "吾輩は猫である。名前はまだ無い。".repeat(40);
+        let counted = Tokenizer::Cl100kBase.count_tokens(&japanese);
+        assert!(counted.value() > TokenCounter::count_tokens(&japanese));
+        // Long texts are served from the cache on the second call.
+        assert_eq!(Tokenizer::Cl100kBase.count_tokens(&japanese), counted);
+    }
+
     #[test]
     fn test_calculate_value_char_count() {
         // Test simple types
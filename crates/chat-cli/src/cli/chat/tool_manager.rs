@@ -71,10 +71,12 @@ use crate::cli::chat::server_messenger::{
     UpdateEventMessage,
 };
 use crate::cli::chat::tools::custom_tool::CustomTool;
+use crate::cli::chat::tools::delegate::Delegate;
 use crate::cli::chat::tools::execute::ExecuteCommand;
 use crate::cli::chat::tools::fs_read::FsRead;
 use crate::cli::chat::tools::fs_write::FsWrite;
 use crate::cli::chat::tools::gh_issue::GhIssue;
+use crate::cli::chat::tools::git::Git;
 use crate::cli::chat::tools::introspect::Introspect;
 use crate::cli::chat::tools::knowledge::Knowledge;
 use crate::cli::chat::tools::thinking::Thinking;
@@ -753,6 +755,7 @@ impl ToolManager {
                     },
                         "required": ["command"]})),
                     tool_origin: ToolOrigin::Native,
+                    read_only: false,
                 });
             }
 
@@ -867,6 +870,8 @@ impl ToolManager {
                 Tool::ExecuteCommand(serde_json::from_value::<ExecuteCommand>(value.args).map_err(map_err)?)
             },
             "use_aws" => Tool::UseAws(serde_json::from_value::<UseAws>(value.args).map_err(map_err)?),
+            "git" => Tool::Git(serde_json::from_value::<Git>(value.args).map_err(map_err)?),
+            "delegate" => Tool::Delegate(serde_json::from_value::<Delegate>(value.args).map_err(map_err)?),
             "report_issue" => Tool::GhIssue(serde_json::from_value::<GhIssue>(value.args).map_err(map_err)?),
             "introspect" => Tool::Introspect(serde_json::from_value::<Introspect>(value.args).map_err(map_err)?),
             "thinking" => Tool::Thinking(serde_json::from_value::<Thinking>(value.args).map_err(map_err)?),
@@ -913,6 +918,7 @@ impl ToolManager {
                     server_name: server_name.to_owned(),
                     client: running_service.clone(),
                     params: value.args.as_object().cloned(),
+                    read_only: self.schema.get(name).is_some_and(|spec| spec.read_only),
                 })
             },
         })
@@ -1454,6 +1460,7 @@ fn spawn_orchestrator_task(
                                     description: v.description.as_ref().map(|d| d.to_string()).unwrap_or_default(),
                                     input_schema: crate::cli::chat::tools::InputSchema(v.schema_as_json_value()),
                                     tool_origin: ToolOrigin::Native,
+                                    read_only: v.annotations.as_ref().and_then(|a| a.read_only_hint).unwrap_or(false),
                                 })
                                 .filter(|spec| tool_filter.should_include(&spec.name))
                                 .collect::<Vec<_>>();
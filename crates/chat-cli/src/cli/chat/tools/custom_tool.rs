@@ -93,6 +93,8 @@ pub struct CustomTool {
     /// Optional parameters to pass to the tool when invoking the method.
     /// Structured as a JSON value to accommodate various parameter types and structures.
     pub params: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Whether the server annotated the tool as read-only. See [super::Tool::is_read_only].
+    pub read_only: bool,
 }
 
 impl CustomTool {
@@ -0,0 +1,248 @@
+use std::io::Write;
+use std::time::Duration;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::cli::agent::{
+    Agent,
+    Agents,
+    PermissionEvalResult,
+};
+use crate::util::pattern_matching::{
+    matches_any_pattern,
+    matches_pattern,
+};
+
+/// How many levels of sub-agents can be nested below the main conversation.
+pub const MAX_DELEGATION_DEPTH: usize = 2;
+
+/// Hands a task to another configured agent, which works on it in a conversation of its own and
+/// returns its final answer. The sub-agent itself is run by the chat session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Delegate {
+    /// Name of the agent to run.
+    pub agent: String,
+    /// The task, sent as the first prompt of the sub-agent.
+    pub task: String,
+    /// Additional context, appended to the task. For agents that declare an `inputSchema`, it
+    /// also holds their parameters, which are checked by [Agent::apply_params] when the sub-agent
+    /// is started.
+    pub context: Option<serde_json::Value>,
+}
+
+impl Delegate {
+    pub async fn validate(&mut self) -> Result<()> {
+        if self.agent.trim().is_empty() {
+            bail!("The name of the agent to delegate to must not be empty");
+        }
+        if self.task.trim().is_empty() {
+            bail!("The task to delegate must not be empty");
+        }
+        Ok(())
+    }
+
+    pub fn queue_description(&self, output: &mut impl Write) -> Result<()> {
+        queue!(
+            output,
+            style::Print("Delegating a task to the agent "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.agent),
+            style::ResetColor,
+            style::Print(":\n\n"),
+            style::Print(&self.task),
+            style::Print("\n"),
+        )?;
+        if let Some(context) = &self.context {
+            queue!(
+                output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("\nContext: {context}\n")),
+                style::ResetColor,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The first prompt of the sub-agent.
+    pub fn prompt(&self) -> String {
+        match &self.context {
+            Some(context) => format!(
+                "{}\n\nContext:\n{}",
+                self.task,
+                serde_json::to_string_pretty(context).unwrap_or_else(|_err| context.to_string())
+            ),
+            None => self.task.clone(),
+        }
+    }
+
+    pub fn eval_perm(&self, agent: &Agent) -> PermissionEvalResult {
+        #[derive(Debug, Default, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Settings {
+            #[serde(default)]
+            allowed_agents: Vec<String>,
+            #[serde(default)]
+            denied_agents: Vec<String>,
+        }
+
+        let is_in_allowlist = matches_any_pattern(&agent.allowed_tools, "delegate");
+        let settings = match agent.tools_settings.get("delegate") {
+            Some(settings) => match serde_json::from_value::<Settings>(settings.clone()) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    error!("Failed to deserialize tool settings for delegate: {:?}", e);
+                    return PermissionEvalResult::Ask;
+                },
+            },
+            None => Settings::default(),
+        };
+
+        let denied_by = settings
+            .denied_agents
+            .iter()
+            .filter(|pattern| matches_pattern(pattern, &self.agent))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !denied_by.is_empty() {
+            return PermissionEvalResult::Deny(denied_by);
+        }
+        let is_allowed_agent = settings
+            .allowed_agents
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &self.agent));
+        if is_in_allowlist || is_allowed_agent {
+            return PermissionEvalResult::Allow;
+        }
+        PermissionEvalResult::Ask
+    }
+
+    /// Appends the agents that tasks can be delegated to to the description of the tool, so that
+    /// the model knows which agents exist and what they are for.
+    pub fn describe_agents(description: &str, agents: &Agents) -> String {
+        let mut names = agents
+            .agents
+            .keys()
+            .filter(|name| **name != agents.active_idx)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return format!("{description}\n\nThere are currently no other agents to delegate to.");
+        }
+        names.sort();
+
+        let mut description = format!("{description}\n\nAvailable agents:");
+        for name in names {
+            description.push_str(&format!("\n- {name}"));
+            let Some(agent) = agents.agents.get(name) else {
+                continue;
+            };
+            if let Some(agent_description) = &agent.description {
+                description.push_str(&format!(": {agent_description}"));
+            }
+            if let Some(input_schema) = &agent.input_schema {
+                description.push_str(&format!(
+                    "\n  Pass its parameters as the context, following: {input_schema}"
+                ));
+            }
+        }
+        description
+    }
+}
+
+/// A finished run of a sub-agent, kept so that it can be looked at with `/subagents`.
+#[derive(Debug, Clone)]
+pub struct SubagentRun {
+    pub agent: String,
+    pub task: String,
+    /// How deep the run was nested, 1 for agents run by the main conversation.
+    pub depth: usize,
+    pub elapsed: Duration,
+    /// The final answer of the agent, or why it did not give one.
+    pub result: Result<String, String>,
+    /// Markdown transcript of the conversation of the sub-agent.
+    pub transcript: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::agent::ToolSettingTarget;
+
+    fn delegate(agent: &str) -> Delegate {
+        serde_json::from_value(json!({ "agent": agent, "task": "audit the files" })).unwrap()
+    }
+
+    #[test]
+    fn test_eval_perm() {
+        let mut agent = Agent {
+            name: "main".to_string(),
+            tools_settings: HashMap::from([(
+                ToolSettingTarget("delegate".to_string()),
+                json!({ "allowedAgents": ["review*"], "deniedAgents": ["deploy"] }),
+            )]),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            delegate("reviewer").eval_perm(&agent),
+            PermissionEvalResult::Allow
+        ));
+        assert!(matches!(
+            delegate("writer").eval_perm(&agent),
+            PermissionEvalResult::Ask
+        ));
+        assert!(matches!(
+            delegate("deploy").eval_perm(&agent),
+            PermissionEvalResult::Deny(_)
+        ));
+
+        agent.allowed_tools.insert("delegate".to_string());
+        assert!(matches!(
+            delegate("writer").eval_perm(&agent),
+            PermissionEvalResult::Allow
+        ));
+        assert!(matches!(
+            delegate("deploy").eval_perm(&agent),
+            PermissionEvalResult::Deny(_)
+        ));
+    }
+
+    #[test]
+    fn test_describe_agents() {
+        let agent = |name: &str, description: Option<&str>| Agent {
+            name: name.to_string(),
+            description: description.map(str::to_string),
+            ..Default::default()
+        };
+        let agents = Agents {
+            agents: HashMap::from([
+                ("main".to_string(), agent("main", None)),
+                ("reviewer".to_string(), agent("reviewer", Some("Reviews code"))),
+                ("writer".to_string(), Agent {
+                    input_schema: Some(serde_json::json!({ "type": "object" })),
+                    ..agent("writer", None)
+                }),
+            ]),
+            active_idx: "main".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Delegate::describe_agents("Delegates tasks.", &agents),
+            "Delegates tasks.\n\nAvailable agents:\n- reviewer: Reviews code\n- writer\n  Pass its parameters as the context, following: {\"type\":\"object\"}"
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::{
+    ExitStatus,
+    Stdio,
+};
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use eyre::{
+    Context as EyreContext,
+    Result,
+    bail,
+    eyre,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWriteExt,
+};
+use tokio::process::{
+    Child,
+    ChildStdin,
+};
+use tokio::sync::Mutex;
+
+use super::{
+    CommandOptions,
+    env_vars_with_user_agent,
+};
+use crate::os::Os;
+
+/// Number of bytes of output kept per job. Older output is discarded.
+const MAX_JOB_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Process groups of the running jobs of every chat session. Destructors don't run when `q` is
+/// terminated by a signal, so these are killed by [watch_termination_signals] instead.
+#[cfg(unix)]
+static RUNNING_JOB_GROUPS: std::sync::Mutex<std::collections::BTreeSet<u32>> =
+    std::sync::Mutex::new(std::collections::BTreeSet::new());
+
+/// Commands started in the background by `execute_bash` during a chat session.
+///
+/// Jobs are numbered from 1 and keep their combined stdout and stderr so that it can be read
+/// incrementally. Every job that is still running is killed when the last handle is dropped, or on
+/// Unix when `q` is terminated by SIGTERM or SIGHUP.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundJobs(Arc<Mutex<JobsState>>);
+
+#[derive(Debug, Default)]
+struct JobsState {
+    last_id: u32,
+    jobs: BTreeMap<u32, Job>,
+}
+
+#[derive(Debug)]
+struct Job {
+    command: String,
+    started_at: Instant,
+    child: Child,
+    /// Id of [Self::child], which is also the id of the process group of the job on Unix.
+    pid: Option<u32>,
+    stdin: Option<ChildStdin>,
+    output: Arc<std::sync::Mutex<JobOutput>>,
+    /// Offset up to which the output has been returned by [BackgroundJobs::output].
+    read_offset: usize,
+    status: JobStatus,
+}
+
+#[derive(Debug, Default)]
+struct JobOutput {
+    bytes: Vec<u8>,
+    /// Number of bytes discarded from the start of [Self::bytes].
+    discarded: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Exited(Option<i32>),
+    Killed,
+}
+
+/// A snapshot of a background job.
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub id: u32,
+    pub pid: Option<u32>,
+    pub command: String,
+    pub status: JobStatus,
+    pub elapsed: Duration,
+    /// Total number of bytes the job has written to stdout and stderr.
+    pub output_len: usize,
+}
+
+/// A range of the output of a background job.
+#[derive(Debug)]
+pub struct JobOutputChunk {
+    pub status: JobStatus,
+    pub output: String,
+    /// Offset of the start of [Self::output].
+    pub offset: usize,
+    /// Offset to continue reading from.
+    pub next_offset: usize,
+    /// Number of bytes between the requested offset and [Self::offset] that were discarded
+    /// because the job produced more than [MAX_JOB_OUTPUT_BYTES] since.
+    pub skipped: usize,
+}
+
+impl BackgroundJobs {
+    /// Starts `command` in a new shell without waiting for it to exit.
+    pub async fn start(
+        &self,
+        os: &Os,
+        command: &str,
+        cwd: Option<PathBuf>,
+        options: &CommandOptions,
+    ) -> Result<JobInfo> {
+        #[cfg(windows)]
+        let mut cmd = {
+            let mut cmd = tokio::process::Command::new("cmd");
+            cmd.arg("/C");
+            cmd
+        };
+        #[cfg(not(windows))]
+        let mut cmd = tokio::process::Command::new(std::env::var("AMAZON_Q_CHAT_SHELL").unwrap_or("bash".to_string()));
+        #[cfg(not(windows))]
+        cmd.arg("-c");
+
+        cmd.arg(command)
+            .envs(env_vars_with_user_agent(os))
+            .envs(&options.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        // Keep Ctrl+C in the terminal away from the job and allow killing everything it started.
+        #[cfg(unix)]
+        cmd.process_group(0);
+        if let Some(sandbox) = &options.sandbox {
+            super::sandbox::apply(os, sandbox, cmd.as_std_mut())?;
+        }
+
+        let mut child = cmd
+            .spawn()
+            .wrap_err_with(|| format!("Unable to spawn command '{command}'"))?;
+        let pid = child.id();
+        #[cfg(unix)]
+        if let (Some(pid), Ok(mut groups)) = (pid, RUNNING_JOB_GROUPS.lock()) {
+            groups.insert(pid);
+            watch_termination_signals();
+        }
+
+        let output = Arc::new(std::sync::Mutex::new(JobOutput::default()));
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(collect_output(stdout, output.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(collect_output(stderr, output.clone()));
+        }
+
+        let mut state = self.0.lock().await;
+        state.last_id += 1;
+        let id = state.last_id;
+        let job = state.jobs.entry(id).or_insert(Job {
+            command: command.to_string(),
+            started_at: Instant::now(),
+            stdin: child.stdin.take(),
+            child,
+            pid,
+            output,
+            read_offset: 0,
+            status: JobStatus::Running,
+        });
+
+        Ok(job.info(id))
+    }
+
+    /// Returns up to `max_bytes` of the output of job `id`, starting at `offset` or where the
+    /// previous call left off.
+    pub async fn output(&self, id: u32, offset: Option<usize>, max_bytes: usize) -> Result<JobOutputChunk> {
+        let mut state = self.0.lock().await;
+        let job = state.get_mut(id)?;
+        let status = job.poll_status();
+
+        let output = job
+            .output
+            .lock()
+            .map_err(|err| eyre!("Output of job {id} is unavailable: {err}"))?;
+        let requested = offset.unwrap_or(job.read_offset);
+        let start = requested.clamp(output.discarded, output.len());
+        let mut end = output.len().min(start + max_bytes);
+        let mut bytes = &output.bytes[start - output.discarded..end - output.discarded];
+        // Don't split a multi-byte character at the end, it is returned by the next call instead.
+        if end < output.len() {
+            if let Err(err) = std::str::from_utf8(bytes) {
+                if err.error_len().is_none() && err.valid_up_to() > 0 {
+                    bytes = &bytes[..err.valid_up_to()];
+                    end = start + bytes.len();
+                }
+            }
+        }
+        let chunk = JobOutputChunk {
+            status,
+            output: String::from_utf8_lossy(bytes).into_owned(),
+            offset: start,
+            next_offset: end,
+            skipped: start.saturating_sub(requested),
+        };
+        drop(output);
+
+        job.read_offset = end;
+        Ok(chunk)
+    }
+
+    pub async fn info(&self, id: u32) -> Result<JobInfo> {
+        let mut state = self.0.lock().await;
+        let job = state.get_mut(id)?;
+        job.poll_status();
+        Ok(job.info(id))
+    }
+
+    pub async fn list(&self) -> Vec<JobInfo> {
+        let mut state = self.0.lock().await;
+        state
+            .jobs
+            .iter_mut()
+            .map(|(id, job)| {
+                job.poll_status();
+                job.info(*id)
+            })
+            .collect()
+    }
+
+    /// Writes `input` to the stdin of job `id`.
+    pub async fn write_stdin(&self, id: u32, input: &str) -> Result<()> {
+        let mut state = self.0.lock().await;
+        let job = state.get_mut(id)?;
+        if job.poll_status() != JobStatus::Running {
+            bail!("Job {id} is no longer running");
+        }
+        let Some(stdin) = job.stdin.as_mut() else {
+            bail!("Job {id} does not accept input");
+        };
+        stdin
+            .write_all(input.as_bytes())
+            .await
+            .wrap_err_with(|| format!("Unable to write to the stdin of job {id}"))?;
+        stdin
+            .flush()
+            .await
+            .wrap_err_with(|| format!("Unable to write to the stdin of job {id}"))?;
+        Ok(())
+    }
+
+    /// Kills job `id` along with any processes it started.
+    pub async fn kill(&self, id: u32) -> Result<JobInfo> {
+        let mut state = self.0.lock().await;
+        let job = state.get_mut(id)?;
+        if job.poll_status() == JobStatus::Running {
+            job.kill();
+            job.child
+                .wait()
+                .await
+                .wrap_err_with(|| format!("Unable to kill job {id}"))?;
+            job.status = JobStatus::Killed;
+        }
+        Ok(job.info(id))
+    }
+}
+
+impl JobsState {
+    fn get_mut(&mut self, id: u32) -> Result<&mut Job> {
+        self.jobs
+            .get_mut(&id)
+            .ok_or_else(|| eyre!("No background job with id {id}"))
+    }
+}
+
+impl Job {
+    fn poll_status(&mut self) -> JobStatus {
+        if self.status == JobStatus::Running {
+            if let Ok(Some(exit_status)) = self.child.try_wait() {
+                self.status = JobStatus::Exited(exit_code(exit_status));
+                self.untrack();
+            }
+        }
+        self.status
+    }
+
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        super::kill_process_group(self.pid);
+        let _ = self.child.start_kill();
+        self.untrack();
+    }
+
+    fn untrack(&self) {
+        #[cfg(unix)]
+        if let (Some(pid), Ok(mut groups)) = (self.pid, RUNNING_JOB_GROUPS.lock()) {
+            groups.remove(&pid);
+        }
+    }
+
+    fn info(&self, id: u32) -> JobInfo {
+        JobInfo {
+            id,
+            pid: self.child.id(),
+            command: self.command.clone(),
+            status: self.status,
+            elapsed: self.started_at.elapsed(),
+            output_len: self.output.lock().map(|output| output.len()).unwrap_or_default(),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if self.poll_status() == JobStatus::Running {
+            self.kill();
+        }
+    }
+}
+
+impl JobOutput {
+    fn len(&self) -> usize {
+        self.discarded + self.bytes.len()
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+        if self.bytes.len() > MAX_JOB_OUTPUT_BYTES {
+            let excess = self.bytes.len() - MAX_JOB_OUTPUT_BYTES;
+            self.bytes.drain(..excess);
+            self.discarded += excess;
+        }
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Running => f.write_str("running"),
+            JobStatus::Exited(Some(code)) => write!(f, "exited with status {code}"),
+            JobStatus::Exited(None) => f.write_str("exited"),
+            JobStatus::Killed => f.write_str("killed"),
+        }
+    }
+}
+
+fn exit_code(exit_status: ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        exit_status.code().or(exit_status.signal().map(|signal| 128 + signal))
+    }
+    #[cfg(not(unix))]
+    exit_status.code()
+}
+
+/// Kills the process groups in [RUNNING_JOB_GROUPS] and exits once `q` receives SIGTERM or SIGHUP,
+/// since jobs are in their own process groups and would otherwise keep running.
+#[cfg(unix)]
+fn watch_termination_signals() {
+    static WATCHING: std::sync::Once = std::sync::Once::new();
+    WATCHING.call_once(|| {
+        tokio::spawn(async {
+            use tokio::signal::unix::{
+                SignalKind,
+                signal,
+            };
+
+            let (mut terminate, mut hangup) = match (signal(SignalKind::terminate()), signal(SignalKind::hangup())) {
+                (Ok(terminate), Ok(hangup)) => (terminate, hangup),
+                (Err(err), _) | (_, Err(err)) => {
+                    tracing::error!(%err, "Unable to watch for termination signals, background jobs may outlive q");
+                    return;
+                },
+            };
+            let signal = tokio::select! {
+                _ = terminate.recv() => SignalKind::terminate(),
+                _ = hangup.recv() => SignalKind::hangup(),
+            };
+            if let Ok(groups) = RUNNING_JOB_GROUPS.lock() {
+                for pid in groups.iter() {
+                    super::kill_process_group(Some(*pid));
+                }
+            }
+            #[allow(clippy::exit)]
+            std::process::exit(128 + signal.as_raw_value());
+        });
+    });
+}
+
+async fn collect_output(mut reader: impl AsyncRead + Unpin, output: Arc<std::sync::Mutex<JobOutput>>) {
+    let mut buf = [0; 8192];
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        match output.lock() {
+            Ok(mut output) => output.push(&buf[..n]),
+            Err(_) => break,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    async fn wait_for_output(jobs: &BackgroundJobs, id: u32, expected: &str) -> String {
+        let mut output = String::new();
+        for _ in 0..100 {
+            output.push_str(&jobs.output(id, None, 1000).await.unwrap().output);
+            if output.contains(expected) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        output
+    }
+
+    #[tokio::test]
+    async fn test_background_jobs() {
+        let os = Os::new().await.unwrap();
+        let jobs = BackgroundJobs::default();
+
+        let job = jobs
+            .start(
+                &os,
+                "echo ready; read line; echo \"got $line\" >&2",
+                None,
+                &CommandOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(job.id, 1);
+        assert_eq!(job.status, JobStatus::Running);
+        assert_eq!(wait_for_output(&jobs, 1, "ready").await, "ready\n");
+
+        jobs.write_stdin(1, "input\n").await.unwrap();
+        assert_eq!(wait_for_output(&jobs, 1, "got input").await, "got input\n");
+        for _ in 0..100 {
+            if jobs.info(1).await.unwrap().status != JobStatus::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(jobs.info(1).await.unwrap().status, JobStatus::Exited(Some(0)));
+
+        // Reading from an explicit offset
+        let chunk = jobs.output(1, Some(2), 3).await.unwrap();
+        assert_eq!(chunk.output, "ady");
+        assert_eq!(chunk.next_offset, 5);
+        assert!(jobs.write_stdin(1, "more\n").await.is_err());
+
+        let job = jobs
+            .start(&os, "sleep 30", None, &CommandOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(job.id, 2);
+        let pid = job.pid.unwrap();
+        assert!(RUNNING_JOB_GROUPS.lock().unwrap().contains(&pid));
+        assert_eq!(jobs.kill(2).await.unwrap().status, JobStatus::Killed);
+        assert!(!RUNNING_JOB_GROUPS.lock().unwrap().contains(&pid));
+        assert_eq!(jobs.list().await.len(), 2);
+        assert!(jobs.info(3).await.is_err());
+    }
+
+    #[test]
+    fn test_job_output_discards_old_output() {
+        let mut output = JobOutput::default();
+        output.push(&vec![b'a'; MAX_JOB_OUTPUT_BYTES]);
+        output.push(b"bc");
+        assert_eq!(output.len(), MAX_JOB_OUTPUT_BYTES + 2);
+        assert_eq!(output.discarded, 2);
+        assert_eq!(output.bytes.last(), Some(&b'c'));
+    }
+}
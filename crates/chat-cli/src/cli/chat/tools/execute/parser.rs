@@ -0,0 +1,903 @@
+//! Parser for the part of the shell language needed to check commands before running them.
+//!
+//! Covers lists, pipelines, subshells, groups, redirections including here-documents, quoting,
+//! and parameter, arithmetic, command and process substitutions. Compound commands such as `if`
+//! or `for` and function definitions are rejected, so that commands using them are never
+//! considered safe.
+
+use eyre::{
+    Result,
+    bail,
+};
+
+/// Pipelines separated by `;`, `&`, `&&`, `||` or newlines.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub pipelines: Vec<Pipeline>,
+}
+
+/// Commands separated by `|` or `|&`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `( ... )`
+    Subshell {
+        body: Script,
+        redirections: Vec<Redirection>,
+    },
+    /// `{ ...; }`
+    Group {
+        body: Script,
+        redirections: Vec<Redirection>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimpleCommand {
+    /// `NAME=value` words before the command name.
+    pub assignments: Vec<Word>,
+    /// The command name followed by its arguments.
+    pub words: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word {
+    /// Text of the word after quote removal, with expansions left as written.
+    pub text: String,
+    /// Whether the word contains expansions, so that its value is only known when the command
+    /// runs.
+    pub dynamic: bool,
+    /// Scripts run by the command and process substitutions in the word.
+    pub substitutions: Vec<Script>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`
+    Input,
+    /// `>` and `>|`
+    Output,
+    /// `>>`
+    Append,
+    /// `<>`
+    ReadWrite,
+    /// `&>`
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+    /// `>&`
+    DupOutput,
+    /// `<&`
+    DupInput,
+    /// `<<` and `<<-`
+    HereDoc,
+    /// `<<<`
+    HereString,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirection {
+    pub fd: Option<u32>,
+    pub op: RedirectOp,
+    /// The file or file descriptor, or the content of here-documents and here-strings.
+    pub target: Word,
+}
+
+impl Redirection {
+    /// The file that the redirection writes to, if any.
+    pub fn written_file(&self) -> Option<&Word> {
+        match self.op {
+            RedirectOp::Output
+            | RedirectOp::Append
+            | RedirectOp::ReadWrite
+            | RedirectOp::OutputAll
+            | RedirectOp::AppendAll => Some(&self.target),
+            // `>&2` and `>&-` duplicate or close a file descriptor, while `>&file` is `&>file`.
+            RedirectOp::DupOutput => {
+                let text = &self.target.text;
+                let is_fd = text == "-" || (!text.is_empty() && text.chars().all(|c| c.is_ascii_digit()));
+                (self.target.dynamic || !is_fd).then_some(&self.target)
+            },
+            RedirectOp::Input | RedirectOp::DupInput | RedirectOp::HereDoc | RedirectOp::HereString => None,
+        }
+    }
+}
+
+/// Reserved words that start compound commands, which aren't supported.
+const COMPOUND_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "select", "function",
+    "coproc", "[[", "}",
+];
+
+pub fn parse(input: &str) -> Result<Script> {
+    let mut parser = Parser::new(input);
+    parser.script(Terminator::Eof)
+}
+
+impl Script {
+    /// The names of the commands run by the script, including the ones in substitutions, in the
+    /// order they are written.
+    pub fn programs(&self) -> Vec<&Word> {
+        let mut programs = Vec::new();
+        self.collect_programs(&mut programs);
+        programs
+    }
+
+    fn collect_programs<'a>(&'a self, programs: &mut Vec<&'a Word>) {
+        let substitutions = |words: &mut dyn Iterator<Item = &'a Word>, programs: &mut Vec<&'a Word>| {
+            for script in words.flat_map(|word| &word.substitutions) {
+                script.collect_programs(programs);
+            }
+        };
+        for command in self.pipelines.iter().flat_map(|pipeline| &pipeline.commands) {
+            let redirections = match command {
+                Command::Simple(command) => {
+                    programs.extend(command.words.first());
+                    substitutions(&mut command.assignments.iter().chain(&command.words), programs);
+                    &command.redirections
+                },
+                Command::Subshell { body, redirections } | Command::Group { body, redirections } => {
+                    body.collect_programs(programs);
+                    redirections
+                },
+            };
+            substitutions(
+                &mut redirections.iter().map(|redirection| &redirection.target),
+                programs,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Terminator {
+    Eof,
+    /// `)` of subshells and command and process substitutions
+    Paren,
+    /// `}` of groups
+    Brace,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// End of the here-document bodies following the current line.
+    heredoc_end: Option<usize>,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+            heredoc_end: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    /// Whether `word` is at the current position as a word of its own.
+    fn at_reserved_word(&self, word: &str) -> bool {
+        self.starts_with(word)
+            && matches!(
+                self.peek_at(word.chars().count()),
+                None | Some(' ' | '\t' | '\n' | ';' | '&' | '|' | '(' | ')' | '<' | '>')
+            )
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_blanks();
+        if self.peek() != Some(c) {
+            bail!("expected `{c}`");
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Skips blanks, line continuations and comments.
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t') => self.pos += 1,
+                Some('\\') if self.peek_at(1) == Some('\n') => self.pos += 2,
+                Some('#') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                },
+                _ => break,
+            }
+        }
+    }
+
+    /// Consumes a newline along with the here-document bodies following it.
+    fn newline(&mut self) {
+        self.pos += 1;
+        if let Some(end) = self.heredoc_end.take() {
+            self.pos = self.pos.max(end);
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        loop {
+            self.skip_blanks();
+            if self.peek() != Some('\n') {
+                break;
+            }
+            self.newline();
+        }
+    }
+
+    fn script(&mut self, end: Terminator) -> Result<Script> {
+        let mut pipelines = Vec::new();
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                Some('\n') => {
+                    self.newline();
+                    continue;
+                },
+                None if end == Terminator::Eof => break,
+                None => bail!("unexpected end of the command"),
+                Some(')') if end == Terminator::Paren => break,
+                _ if end == Terminator::Brace && self.at_reserved_word("}") => break,
+                _ => {},
+            }
+
+            loop {
+                pipelines.push(self.pipeline()?);
+                self.skip_blanks();
+                if !(self.starts_with("&&") || self.starts_with("||")) {
+                    break;
+                }
+                self.pos += 2;
+                self.skip_newlines();
+            }
+
+            match self.peek() {
+                Some(';') if self.peek_at(1) != Some(';') => self.pos += 1,
+                Some('&') => self.pos += 1,
+                Some('\n') => self.newline(),
+                None => {},
+                Some(')') if end == Terminator::Paren => {},
+                Some(c) => bail!("unexpected `{c}`"),
+            }
+        }
+        Ok(Script { pipelines })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline> {
+        self.skip_blanks();
+        // Negation doesn't change what runs.
+        if self.at_reserved_word("!") {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.command()?];
+        loop {
+            self.skip_blanks();
+            if self.peek() != Some('|') || self.peek_at(1) == Some('|') {
+                break;
+            }
+            self.pos += 1;
+            if self.peek() == Some('&') {
+                self.pos += 1;
+            }
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { commands })
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        self.skip_blanks();
+        if self.peek() == Some('(') {
+            if self.peek_at(1) == Some('(') {
+                bail!("arithmetic commands are not supported");
+            }
+            self.pos += 1;
+            let body = self.script(Terminator::Paren)?;
+            self.expect(')')?;
+            let redirections = self.redirections()?;
+            return Ok(Command::Subshell { body, redirections });
+        }
+        if self.at_reserved_word("{") {
+            self.pos += 1;
+            let body = self.script(Terminator::Brace)?;
+            self.pos += 1;
+            let redirections = self.redirections()?;
+            return Ok(Command::Group { body, redirections });
+        }
+        if let Some(keyword) = COMPOUND_KEYWORDS.iter().find(|keyword| self.at_reserved_word(keyword)) {
+            bail!("`{keyword}` is not supported");
+        }
+        self.simple_command().map(Command::Simple)
+    }
+
+    fn redirections(&mut self) -> Result<Vec<Redirection>> {
+        let mut redirections = Vec::new();
+        loop {
+            self.skip_blanks();
+            match self.redirection()? {
+                Some(redirection) => redirections.push(redirection),
+                None => break,
+            }
+        }
+        Ok(redirections)
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand> {
+        let mut command = SimpleCommand::default();
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None | Some('\n' | ';' | '|' | ')') => break,
+                Some('&') if self.peek_at(1) != Some('>') => break,
+                Some('(') => bail!("unexpected `(`"),
+                _ => {},
+            }
+            if let Some(redirection) = self.redirection()? {
+                command.redirections.push(redirection);
+                continue;
+            }
+            let is_assignment = command.words.is_empty() && self.at_assignment();
+            let word = self.word()?;
+            if is_assignment {
+                command.assignments.push(word);
+            } else {
+                command.words.push(word);
+            }
+        }
+
+        if command.words.is_empty() && command.assignments.is_empty() && command.redirections.is_empty() {
+            match self.peek() {
+                Some(c) => bail!("unexpected `{c}`"),
+                None => bail!("unexpected end of the command"),
+            }
+        }
+        Ok(command)
+    }
+
+    /// Whether the word at the current position starts with `NAME=` or `NAME+=`.
+    fn at_assignment(&self) -> bool {
+        if !matches!(self.peek(), Some(c) if c == '_' || c.is_ascii_alphabetic()) {
+            return false;
+        }
+        let mut i = 1;
+        while matches!(self.peek_at(i), Some(c) if c == '_' || c.is_ascii_alphanumeric()) {
+            i += 1;
+        }
+        self.peek_at(i) == Some('=') || (self.peek_at(i) == Some('+') && self.peek_at(i + 1) == Some('='))
+    }
+
+    fn redirection(&mut self) -> Result<Option<Redirection>> {
+        let start = self.pos;
+        let digits = (0..)
+            .take_while(|i| matches!(self.peek_at(*i), Some(c) if c.is_ascii_digit()))
+            .count();
+        let mut fd = None;
+        if digits > 0 {
+            if !matches!(self.peek_at(digits), Some('<' | '>')) {
+                return Ok(None);
+            }
+            fd = self.chars[self.pos..self.pos + digits]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .ok();
+            self.pos += digits;
+        }
+
+        const OPERATORS: &[(&str, RedirectOp)] = &[
+            ("&>>", RedirectOp::AppendAll),
+            ("&>", RedirectOp::OutputAll),
+            ("<<<", RedirectOp::HereString),
+            ("<<", RedirectOp::HereDoc),
+            ("<>", RedirectOp::ReadWrite),
+            ("<&", RedirectOp::DupInput),
+            ("<", RedirectOp::Input),
+            (">>", RedirectOp::Append),
+            (">|", RedirectOp::Output),
+            (">&", RedirectOp::DupOutput),
+            (">", RedirectOp::Output),
+        ];
+        // `<(` and `>(` start process substitutions.
+        let operator = OPERATORS
+            .iter()
+            .find(|(operator, _)| self.starts_with(operator))
+            .filter(|(operator, _)| !(operator.len() == 1 && self.peek_at(1) == Some('(')));
+        let Some((operator, op)) = operator else {
+            self.pos = start;
+            return Ok(None);
+        };
+        self.pos += operator.len();
+
+        let strip_tabs = *op == RedirectOp::HereDoc && self.peek() == Some('-');
+        if strip_tabs {
+            self.pos += 1;
+        }
+        self.skip_blanks();
+        if matches!(self.peek(), None | Some('\n' | ';' | '&' | '|' | '(' | ')' | '<' | '>')) {
+            bail!("expected a file name after `{operator}`");
+        }
+        let word_start = self.pos;
+        let mut target = self.word()?;
+        if *op == RedirectOp::HereDoc {
+            let quoted = self.chars[word_start..self.pos]
+                .iter()
+                .any(|c| matches!(c, '\'' | '"' | '\\'));
+            target = self.heredoc_body(&target.text, quoted, strip_tabs)?;
+        }
+
+        Ok(Some(Redirection { fd, op: *op, target }))
+    }
+
+    /// Reads the body of a here-document from the lines following the current one.
+    fn heredoc_body(&mut self, delimiter: &str, quoted: bool, strip_tabs: bool) -> Result<Word> {
+        let mut pos = match self.heredoc_end {
+            Some(end) => end,
+            None => self.chars[self.pos..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map_or(self.chars.len(), |i| self.pos + i + 1),
+        };
+        let mut body = String::new();
+        while pos < self.chars.len() {
+            let line_end = self.chars[pos..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map_or(self.chars.len(), |i| pos + i);
+            let line = self.chars[pos..line_end].iter().collect::<String>();
+            pos = (line_end + 1).min(self.chars.len());
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                &line
+            };
+            if line == delimiter {
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+        self.heredoc_end = Some(pos);
+
+        if quoted {
+            return Ok(Word {
+                text: body,
+                ..Default::default()
+            });
+        }
+        let mut word = Word::default();
+        Parser::new(&body).double_quoted(&mut word, None)?;
+        Ok(word)
+    }
+
+    fn word(&mut self) -> Result<Word> {
+        let mut word = Word::default();
+        let start = self.pos;
+        let mut brace = false;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' | ';' | '&' | '|' | ')' => break,
+                '(' => bail!("unexpected `(`"),
+                '<' | '>' if self.peek_at(1) == Some('(') => {
+                    self.pos += 2;
+                    let script = self.script(Terminator::Paren)?;
+                    self.expect(')')?;
+                    word.text.extend(&self.chars[start..self.pos]);
+                    word.dynamic = true;
+                    word.substitutions.push(script);
+                },
+                '<' | '>' => break,
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n') => self.pos += 1,
+                        Some(c) => {
+                            word.text.push(c);
+                            self.pos += 1;
+                        },
+                        None => {},
+                    }
+                },
+                '\'' => {
+                    self.pos += 1;
+                    let text = self.until('\'')?;
+                    word.text.push_str(&text);
+                },
+                '"' => {
+                    self.pos += 1;
+                    self.double_quoted(&mut word, Some('"'))?;
+                },
+                '$' => self.dollar(&mut word, false)?,
+                '`' => self.backtick(&mut word)?,
+                c => {
+                    brace |= c == '{';
+                    word.text.push(c);
+                    self.pos += 1;
+                },
+            }
+        }
+        // Brace expansion turns one word into several.
+        if brace && (word.text.contains(',') || word.text.contains("..")) {
+            word.dynamic = true;
+        }
+        Ok(word)
+    }
+
+    /// Reads up to the next `end`, which is consumed.
+    fn until(&mut self, end: char) -> Result<String> {
+        let Some(len) = self.chars[self.pos..].iter().position(|c| *c == end) else {
+            bail!("missing closing `{end}`");
+        };
+        let text = self.chars[self.pos..self.pos + len].iter().collect();
+        self.pos += len + 1;
+        Ok(text)
+    }
+
+    /// Reads the content of double quotes, or of a here-document when `end` is `None`.
+    fn double_quoted(&mut self, word: &mut Word, end: Option<char>) -> Result<()> {
+        loop {
+            let Some(c) = self.peek() else {
+                if end.is_some() {
+                    bail!("missing closing `\"`");
+                }
+                return Ok(());
+            };
+            if Some(c) == end {
+                self.pos += 1;
+                return Ok(());
+            }
+            match c {
+                '\\' => match self.peek_at(1) {
+                    Some('\n') => self.pos += 2,
+                    Some(next @ ('$' | '`' | '"' | '\\')) => {
+                        word.text.push(next);
+                        self.pos += 2;
+                    },
+                    _ => {
+                        word.text.push('\\');
+                        self.pos += 1;
+                    },
+                },
+                '$' => self.dollar(word, true)?,
+                '`' => self.backtick(word)?,
+                c => {
+                    word.text.push(c);
+                    self.pos += 1;
+                },
+            }
+        }
+    }
+
+    /// Reads an expansion starting with `$`.
+    fn dollar(&mut self, word: &mut Word, in_quotes: bool) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+        // Substitutions nested in the expansion, whose text is already part of the expansion.
+        let mut inner = Word::default();
+        match self.peek() {
+            Some('(') if self.peek_at(1) == Some('(') => {
+                self.pos += 2;
+                self.arithmetic(&mut inner)?;
+            },
+            Some('(') => {
+                self.pos += 1;
+                let script = self.script(Terminator::Paren)?;
+                self.expect(')')?;
+                inner.substitutions.push(script);
+            },
+            Some('{') => {
+                self.pos += 1;
+                self.braced_parameter(&mut inner)?;
+            },
+            Some('\'') if !in_quotes => {
+                self.pos += 1;
+                return self.ansi_c_quoted(word);
+            },
+            Some('"') if !in_quotes => {
+                self.pos += 1;
+                return self.double_quoted(word, Some('"'));
+            },
+            Some(c) if c.is_ascii_digit() => self.pos += 1,
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+                while matches!(self.peek(), Some(c) if c == '_' || c.is_ascii_alphanumeric()) {
+                    self.pos += 1;
+                }
+            },
+            Some('@' | '*' | '#' | '?' | '-' | '$' | '!') => self.pos += 1,
+            _ => {
+                word.text.push('$');
+                return Ok(());
+            },
+        }
+        word.text.extend(&self.chars[start..self.pos]);
+        word.dynamic = true;
+        word.substitutions.append(&mut inner.substitutions);
+        Ok(())
+    }
+
+    /// Reads the rest of `$((...))`.
+    fn arithmetic(&mut self, inner: &mut Word) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => bail!("missing closing `))`"),
+                Some(')') if depth == 0 => {
+                    if self.peek_at(1) != Some(')') {
+                        bail!("missing closing `))`");
+                    }
+                    self.pos += 2;
+                    return Ok(());
+                },
+                Some(')') => {
+                    depth -= 1;
+                    self.pos += 1;
+                },
+                Some('(') => {
+                    depth += 1;
+                    self.pos += 1;
+                },
+                Some('$') => self.dollar(inner, true)?,
+                Some('`') => self.backtick(inner)?,
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Reads the rest of `${...}`.
+    fn braced_parameter(&mut self, inner: &mut Word) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => bail!("missing closing `}}`"),
+                Some('}') if depth == 0 => {
+                    self.pos += 1;
+                    return Ok(());
+                },
+                Some('}') => {
+                    depth -= 1;
+                    self.pos += 1;
+                },
+                Some('{') => {
+                    depth += 1;
+                    self.pos += 1;
+                },
+                Some('\\') => self.pos += 2,
+                Some('\'') => {
+                    self.pos += 1;
+                    self.until('\'')?;
+                },
+                Some('"') => {
+                    self.pos += 1;
+                    self.double_quoted(inner, Some('"'))?;
+                },
+                Some('$') => self.dollar(inner, true)?,
+                Some('`') => self.backtick(inner)?,
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Reads a command substitution in backquotes.
+    fn backtick(&mut self, word: &mut Word) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut content = String::new();
+        loop {
+            match self.peek() {
+                None => bail!("missing closing '`'"),
+                Some('`') => {
+                    self.pos += 1;
+                    break;
+                },
+                Some('\\') if matches!(self.peek_at(1), Some('`' | '$' | '\\')) => {
+                    content.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                },
+                Some(c) => {
+                    content.push(c);
+                    self.pos += 1;
+                },
+            }
+        }
+        word.text.extend(&self.chars[start..self.pos]);
+        word.dynamic = true;
+        word.substitutions.push(parse(&content)?);
+        Ok(())
+    }
+
+    /// Reads the rest of `$'...'`. Escapes that aren't decoded make the word dynamic.
+    fn ansi_c_quoted(&mut self, word: &mut Word) -> Result<()> {
+        loop {
+            match self.peek() {
+                None => bail!("missing closing `'`"),
+                Some('\'') => {
+                    self.pos += 1;
+                    return Ok(());
+                },
+                Some('\\') => {
+                    self.pos += 1;
+                    let Some(c) = self.peek() else { continue };
+                    self.pos += 1;
+                    let decoded = match c {
+                        'n' => Some('\n'),
+                        't' => Some('\t'),
+                        'r' => Some('\r'),
+                        'a' => Some('\x07'),
+                        'b' => Some('\x08'),
+                        'e' | 'E' => Some('\x1b'),
+                        'f' => Some('\x0c'),
+                        'v' => Some('\x0b'),
+                        '\\' | '\'' | '"' | '?' => Some(c),
+                        'x' => self.escaped_number(16, 2),
+                        'u' => self.escaped_number(16, 4),
+                        'U' => self.escaped_number(16, 8),
+                        '0'..='7' => {
+                            self.pos -= 1;
+                            self.escaped_number(8, 3)
+                        },
+                        _ => None,
+                    };
+                    match decoded {
+                        Some(c) => word.text.push(c),
+                        None => word.dynamic = true,
+                    }
+                },
+                Some(c) => {
+                    word.text.push(c);
+                    self.pos += 1;
+                },
+            }
+        }
+    }
+
+    fn escaped_number(&mut self, radix: u32, max_digits: usize) -> Option<char> {
+        let digits = (0..max_digits)
+            .take_while(|i| matches!(self.peek_at(*i), Some(c) if c.is_digit(radix)))
+            .count();
+        let number = self.chars[self.pos..self.pos + digits].iter().collect::<String>();
+        self.pos += digits;
+        u32::from_str_radix(&number, radix).ok().and_then(char::from_u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(command) => command,
+            _ => panic!("expected a simple command, got {command:?}"),
+        }
+    }
+
+    fn words(command: &SimpleCommand) -> Vec<&str> {
+        command.words.iter().map(|word| word.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_lists_and_pipelines() {
+        let script = parse("git log --oneline | head -n 5 && echo 'a b' \"c $HOME\"; ls &\npwd").unwrap();
+        assert_eq!(script.pipelines.len(), 4);
+        let pipeline = &script.pipelines[0].commands;
+        assert_eq!(words(simple(&pipeline[0])), ["git", "log", "--oneline"]);
+        assert_eq!(words(simple(&pipeline[1])), ["head", "-n", "5"]);
+
+        let echo = simple(&script.pipelines[1].commands[0]);
+        assert_eq!(words(echo), ["echo", "a b", "c $HOME"]);
+        assert!(!echo.words[1].dynamic);
+        assert!(echo.words[2].dynamic);
+        assert_eq!(words(simple(&script.pipelines[3].commands[0])), ["pwd"]);
+    }
+
+    #[test]
+    fn test_parse_substitutions() {
+        let script = parse("echo $(rm -rf x) `touch y` <(cat z) ${a:-$(whoami)} $((1 + $(id -u)))").unwrap();
+        let echo = simple(&script.pipelines[0].commands[0]);
+        assert!(echo.words[1..].iter().all(|word| word.dynamic));
+        let substituted = echo
+            .words
+            .iter()
+            .flat_map(|word| &word.substitutions)
+            .map(|script| words(simple(&script.pipelines[0].commands[0]))[0])
+            .collect::<Vec<_>>();
+        assert_eq!(substituted, ["rm", "touch", "cat", "whoami", "id"]);
+    }
+
+    #[test]
+    fn test_parse_redirections() {
+        let script = parse("FOO=1 cmd 2>&1 >out <in 2>>log &>/dev/null >&file").unwrap();
+        let cmd = simple(&script.pipelines[0].commands[0]);
+        assert_eq!(cmd.assignments[0].text, "FOO=1");
+        assert_eq!(words(cmd), ["cmd"]);
+        let written = cmd
+            .redirections
+            .iter()
+            .filter_map(|redirection| redirection.written_file())
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(written, ["out", "log", "/dev/null", "file"]);
+        assert_eq!(cmd.redirections[0].fd, Some(2));
+
+        let script = parse("(cd dir && make) > build.log; { ls; } 2>/dev/null").unwrap();
+        assert!(
+            matches!(&script.pipelines[0].commands[0], Command::Subshell { body, redirections }
+            if body.pipelines.len() == 2 && redirections.len() == 1)
+        );
+        assert!(matches!(&script.pipelines[1].commands[0], Command::Group { .. }));
+    }
+
+    #[test]
+    fn test_parse_heredoc() {
+        let script = parse("cat <<EOF > out\nhello $(whoami)\nEOF\ncat <<'EOF'\n$(rm x)\nEOF\nls").unwrap();
+        assert_eq!(script.pipelines.len(), 3);
+        let first = simple(&script.pipelines[0].commands[0]);
+        assert_eq!(first.redirections[0].op, RedirectOp::HereDoc);
+        assert_eq!(first.redirections[0].target.substitutions.len(), 1);
+        assert_eq!(first.redirections[1].written_file().unwrap().text, "out");
+        let second = simple(&script.pipelines[1].commands[0]);
+        assert_eq!(second.redirections[0].target.text, "$(rm x)\n");
+        assert!(second.redirections[0].target.substitutions.is_empty());
+        assert_eq!(words(simple(&script.pipelines[2].commands[0])), ["ls"]);
+    }
+
+    #[test]
+    fn test_parse_quoting() {
+        let script = parse(r#"find . -${t}exec $'-\x64elete' a\ b {a,b} "x"'y'z"#).unwrap();
+        let find = simple(&script.pipelines[0].commands[0]);
+        assert!(find.words[2].dynamic);
+        assert_eq!(find.words[3].text, "-delete");
+        assert!(!find.words[3].dynamic);
+        assert_eq!(find.words[4].text, "a b");
+        assert!(find.words[5].dynamic);
+        assert_eq!(find.words[6].text, "xyz");
+    }
+
+    #[test]
+    fn test_programs() {
+        let script =
+            parse("FOO=$(date) git log | (grep -v x > \"$(mktemp)\"; { \"$EDITOR\" a; }) | diff <(sort b) -").unwrap();
+        let programs = script
+            .programs()
+            .into_iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(programs, vec![
+            "git", "date", "grep", "mktemp", "$EDITOR", "diff", "sort"
+        ]);
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        for command in [
+            "if true; then rm x; fi",
+            "for f in *; do rm $f; done",
+            "f() { rm x; }",
+            "echo 'unterminated",
+            "echo $(ls",
+            "ls |",
+            "ls &&",
+            "(( x = 1 ))",
+        ] {
+            assert!(parse(command).is_err(), "{command}");
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Decides whether a shell command can run without confirmation from its syntax tree, using the
+//! `commandRules` of the agent and the built-in rules for read-only commands.
+
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+use tracing::debug;
+
+use super::READONLY_COMMANDS;
+use super::parser::{
+    self,
+    Command,
+    RedirectOp,
+    Redirection,
+    Script,
+    SimpleCommand,
+    Word,
+};
+
+/// A rule in the `commandRules` of `execute_bash`, matching invocations of a program.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandRule {
+    /// Name of the program, e.g. `git`.
+    pub command: String,
+    /// Only match when the arguments that aren't flags start with one of these, e.g. `push` or
+    /// `stash drop`.
+    #[serde(default)]
+    pub subcommands: Vec<String>,
+    /// Only match when one of these flags is passed. Short flags also match when combined with
+    /// others, e.g. `-f` matches `-rf`.
+    #[serde(default)]
+    pub flags: Vec<String>,
+    pub action: RuleAction,
+    /// Explanation shown to the user when the rule denies a command.
+    pub reason: Option<String>,
+}
+
+/// What happens to commands matching a rule. When several rules match, the strictest wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Ask,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Ask,
+    /// Denied, with the rules responsible.
+    Deny(Vec<String>),
+}
+
+/// Built-in rules applied with `autoAllowReadonly`.
+///
+/// Tools such as git and cargo are left to the `commandRules` of agents: even their read-only
+/// subcommands run programs configured by the repository, e.g. `core.fsmonitor` on `git status`
+/// or `build.rustc` on `cargo metadata`.
+static READONLY_RULES: LazyLock<Vec<CommandRule>> = LazyLock::new(|| {
+    use RuleAction::{
+        Allow,
+        Ask,
+    };
+
+    let mut rules = READONLY_COMMANDS
+        .iter()
+        .map(|command| CommandRule::new(command, &[], &[], Allow))
+        .collect::<Vec<_>>();
+    rules.extend([
+        // Flags that make read-only commands write files or run other commands.
+        CommandRule::new(
+            "find",
+            &[],
+            &[
+                "-exec", "-execdir", "-ok", "-okdir", "-delete", "-fls", "-fprint", "-fprint0", "-fprintf",
+            ],
+            Ask,
+        ),
+        CommandRule::new("grep", &[], &["-P", "--perl-regexp"], Ask),
+        CommandRule::new("rg", &[], &["--pre"], Ask),
+        CommandRule::new("tree", &[], &["-o"], Ask),
+        CommandRule::new("date", &[], &["-s", "--set"], Ask),
+    ]);
+    rules
+});
+
+/// Commands that run their arguments as another command, which deny rules look through.
+const WRAPPERS: &[&str] = &["command", "builtin", "exec", "nohup", "time", "sudo", "env", "xargs"];
+
+impl CommandRule {
+    fn new(command: &str, subcommands: &[&str], flags: &[&str], action: RuleAction) -> Self {
+        Self {
+            command: command.to_string(),
+            subcommands: subcommands.iter().map(|s| (*s).to_string()).collect(),
+            flags: flags.iter().map(|s| (*s).to_string()).collect(),
+            action,
+            reason: None,
+        }
+    }
+
+    fn matches(&self, name: &str, args: &[Word]) -> bool {
+        if self.command != name {
+            return false;
+        }
+        if !self.subcommands.is_empty() {
+            let positional = args
+                .iter()
+                .map(|arg| arg.text.as_str())
+                .filter(|arg| !arg.starts_with('-'))
+                .collect::<Vec<_>>();
+            let matches_subcommand = self.subcommands.iter().any(|subcommand| {
+                let parts = subcommand.split_whitespace().collect::<Vec<_>>();
+                positional.starts_with(&parts)
+            });
+            if !matches_subcommand {
+                return false;
+            }
+        }
+        self.flags.is_empty()
+            || args
+                .iter()
+                .any(|arg| self.flags.iter().any(|flag| flag_matches(flag, &arg.text)))
+    }
+
+    /// The rule as shown to the user, e.g. `git push: Pushing is reviewed by a human`.
+    fn description(&self) -> String {
+        let mut description = self.command.clone();
+        for part in [&self.subcommands, &self.flags] {
+            if !part.is_empty() {
+                description.push(' ');
+                description.push_str(&part.join("|"));
+            }
+        }
+        match &self.reason {
+            Some(reason) => format!("{description}: {reason}"),
+            None => description,
+        }
+    }
+}
+
+fn flag_matches(flag: &str, arg: &str) -> bool {
+    if arg == flag {
+        return true;
+    }
+    // `--flag=value`, and abbreviations such as `--fl` that GNU programs accept for long options
+    // as long as they are unambiguous.
+    if flag.starts_with("--") {
+        let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+        return name.len() > 2 && name.starts_with("--") && flag.starts_with(name);
+    }
+    // `-x` combined with other short flags or its value, e.g. `-rf` or `-ofile`
+    match (flag.strip_prefix('-'), arg.strip_prefix('-')) {
+        (Some(short), Some(combined)) if short.len() == 1 && !combined.starts_with('-') => combined.contains(short),
+        _ => false,
+    }
+}
+
+/// Classifies `command` according to `rules`, and the built-in rules for read-only commands if
+/// `allow_read_only` is set. Commands that can't be parsed always need confirmation.
+pub fn classify(command: &str, rules: &[CommandRule], allow_read_only: bool) -> Decision {
+    let script = match parser::parse(command) {
+        Ok(script) => script,
+        Err(err) => {
+            debug!(%err, command, "Unable to parse the command");
+            return Decision::Ask;
+        },
+    };
+
+    let mut classifier = Classifier {
+        rules,
+        builtin_rules: if allow_read_only { &READONLY_RULES } else { &[] },
+        ask: false,
+        denied: Vec::new(),
+    };
+    classifier.script(&script);
+    if !classifier.denied.is_empty() {
+        Decision::Deny(classifier.denied)
+    } else if classifier.ask {
+        Decision::Ask
+    } else {
+        Decision::Allow
+    }
+}
+
+struct Classifier<'a> {
+    rules: &'a [CommandRule],
+    builtin_rules: &'a [CommandRule],
+    ask: bool,
+    denied: Vec<String>,
+}
+
+impl Classifier<'_> {
+    fn script(&mut self, script: &Script) {
+        for command in script.pipelines.iter().flat_map(|pipeline| &pipeline.commands) {
+            match command {
+                Command::Simple(command) => self.simple_command(command),
+                Command::Subshell { body, redirections } | Command::Group { body, redirections } => {
+                    self.script(body);
+                    self.redirections(redirections);
+                },
+            }
+        }
+    }
+
+    /// Commands whose arguments are only known when they run can't be checked.
+    fn word(&mut self, word: &Word) {
+        self.ask |= word.dynamic;
+        for script in &word.substitutions {
+            self.script(script);
+        }
+    }
+
+    fn redirections(&mut self, redirections: &[Redirection]) {
+        for redirection in redirections {
+            match redirection.op {
+                // Only the commands in the input matter.
+                RedirectOp::HereDoc | RedirectOp::HereString => {
+                    for script in &redirection.target.substitutions {
+                        self.script(script);
+                    }
+                },
+                _ => self.word(&redirection.target),
+            }
+            if let Some(file) = redirection.written_file() {
+                self.ask |= file.text != "/dev/null";
+            }
+        }
+    }
+
+    fn simple_command(&mut self, command: &SimpleCommand) {
+        // Variables such as `PATH` or `LD_PRELOAD` change what runs.
+        self.ask |= !command.assignments.is_empty();
+        for word in command.assignments.iter().chain(&command.words) {
+            self.word(word);
+        }
+        self.redirections(&command.redirections);
+
+        let rules = self.rules.iter().chain(self.builtin_rules);
+        let mut action = None;
+        // Rules that restrict commands also match programs given by path or through wrappers.
+        if let Some((name, args)) = unwrap_command(&command.words).split_first() {
+            let name = name.text.rsplit('/').next().unwrap_or_default();
+            for rule in rules.clone().filter(|rule| rule.action != RuleAction::Allow) {
+                if rule.matches(name, args) {
+                    action = action.max(Some(rule.action));
+                    if rule.action == RuleAction::Deny {
+                        self.denied.push(rule.description());
+                    }
+                }
+            }
+        }
+        if action.is_some() {
+            self.ask = true;
+            return;
+        }
+
+        let Some((name, args)) = command.words.split_first() else {
+            return;
+        };
+        let allowed = rules
+            .filter(|rule| rule.action == RuleAction::Allow)
+            .any(|rule| !name.dynamic && rule.matches(&name.text, args));
+        self.ask |= !allowed;
+    }
+}
+
+/// Skips wrappers such as `sudo` or `env`, along with their flags and variable assignments.
+fn unwrap_command(words: &[Word]) -> &[Word] {
+    let mut words = words;
+    while let Some((first, rest)) = words.split_first() {
+        if !WRAPPERS.contains(&first.text.rsplit('/').next().unwrap_or_default()) {
+            break;
+        }
+        words = rest;
+        while let Some((first, rest)) = words.split_first() {
+            if !(first.text.starts_with('-') || first.text.contains('=')) {
+                break;
+            }
+            words = rest;
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<CommandRule> {
+        serde_json::from_value(serde_json::json!([
+            { "command": "git", "subcommands": ["status", "diff", "log", "stash list"], "action": "allow" },
+            { "command": "git", "subcommands": ["push"], "action": "deny", "reason": "Pushing is done by a human" },
+            { "command": "rm", "flags": ["-r", "--recursive"], "action": "deny" },
+            { "command": "cargo", "subcommands": ["publish"], "action": "ask" },
+            { "command": "cargo", "action": "allow" },
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_classify_with_rules() {
+        let rules = rules();
+        for (command, expected) in [
+            ("git status", Decision::Allow),
+            ("git log --oneline | cargo metadata", Decision::Allow),
+            ("git stash list", Decision::Allow),
+            ("git stash drop", Decision::Ask),
+            ("git commit -m 'x'", Decision::Ask),
+            ("cargo publish --dry-run", Decision::Ask),
+            ("cargo build > build.log", Decision::Ask),
+            ("cargo build 2>&1 >/dev/null", Decision::Allow),
+            ("git status; $CMD", Decision::Ask),
+            (
+                "git push origin main",
+                Decision::Deny(vec!["git push: Pushing is done by a human".to_string()]),
+            ),
+            (
+                "cargo build && sudo /usr/bin/git push",
+                Decision::Deny(vec!["git push: Pushing is done by a human".to_string()]),
+            ),
+            (
+                "echo $(rm -rf /)",
+                Decision::Deny(vec!["rm -r|--recursive".to_string()]),
+            ),
+            (
+                "(cd x && env FOO=1 rm --recursive y)",
+                Decision::Deny(vec!["rm -r|--recursive".to_string()]),
+            ),
+            ("rm -f y", Decision::Ask),
+            ("./git status", Decision::Ask),
+            ("if true; then git status; fi", Decision::Ask),
+        ] {
+            assert_eq!(classify(command, &rules, false), expected, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_classify_read_only() {
+        for (command, expected) in [
+            ("ls *.rs | wc -l", Decision::Allow),
+            ("grep -rn foo src 2>/dev/null", Decision::Allow),
+            ("cat <<EOF\nhello\nEOF", Decision::Allow),
+            ("cat <<EOF\n$(touch x)\nEOF", Decision::Ask),
+            ("git status", Decision::Ask),
+            ("git log --oneline | head", Decision::Ask),
+            ("cargo metadata --format-version 1", Decision::Ask),
+            ("sort -uo out.txt in.txt", Decision::Ask),
+            ("find . -name '*.rs' -delete", Decision::Ask),
+            ("find . $'-\\x64elete'", Decision::Ask),
+            ("git push", Decision::Ask),
+            ("PATH=/tmp ls", Decision::Ask),
+        ] {
+            assert_eq!(classify(command, &[], true), expected, "{command}");
+        }
+        assert_eq!(classify("ls", &[], false), Decision::Ask);
+    }
+}
@@ -0,0 +1,699 @@
+//! Sandbox for the commands run by `execute_bash`, configured with the `sandbox` tool setting.
+//!
+//! On Linux, sandboxed commands run in their own user and mount namespaces, plus a network
+//! namespace without any interface but loopback when the network is denied. Landlock makes the
+//! whole filesystem read-only except for the writable paths and the private `/tmp`, and a seccomp
+//! filter keeps commands from creating Unix sockets, through which they could reach services such
+//! as Docker or the D-Bus session bus that run outside of the sandbox.
+
+use std::sync::OnceLock;
+
+use eyre::Result;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::cli::chat::tools::sanitize_path_tool_arg;
+use crate::os::Os;
+
+/// `sandbox` in the `toolsSettings` of `execute_bash`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxSettings {
+    /// Run commands in the sandbox, and allow them without asking for confirmation when the
+    /// sandbox fully contains them.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Paths that commands can write to, relative to the current directory. Everything else is
+    /// read-only.
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// Let commands reach the network. Commands then prompt as usual, since they could send the
+    /// files they read.
+    #[serde(default)]
+    pub allow_network: bool,
+    /// Give every command an empty `/tmp` of its own, discarded when it exits.
+    #[serde(default = "default_private_tmp")]
+    pub private_tmp: bool,
+}
+
+impl Default for SandboxSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            writable_paths: Vec::new(),
+            allow_network: false,
+            private_tmp: default_private_tmp(),
+        }
+    }
+}
+
+fn default_private_tmp() -> bool {
+    true
+}
+
+/// Devices that commands commonly write to, which stay writable in the sandbox.
+const WRITABLE_DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+/// Why commands can't be sandboxed on this system, or `None` if they can.
+pub fn unsupported_reason() -> Option<&'static str> {
+    static REASON: OnceLock<Option<String>> = OnceLock::new();
+    REASON.get_or_init(check_support).as_deref()
+}
+
+/// Whether commands in the sandbox are contained well enough to run without confirmation. Before
+/// ABI 6, Landlock doesn't keep them from signalling processes outside of the sandbox, such as q
+/// itself.
+pub fn is_contained() -> bool {
+    #[cfg(target_os = "linux")]
+    return unsupported_reason().is_none() && linux::landlock_abi() >= 6;
+    #[cfg(not(target_os = "linux"))]
+    return false;
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_support() -> Option<String> {
+    Some("the sandbox is only available on Linux".to_string())
+}
+
+/// Runs a trivial command in the strictest sandbox, since user namespaces and Landlock can be
+/// disabled in many ways.
+#[cfg(target_os = "linux")]
+fn check_support() -> Option<String> {
+    let mut cmd = std::process::Command::new("/bin/sh");
+    cmd.args(["-c", "exit 0"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    let settings = SandboxSettings {
+        enabled: true,
+        ..Default::default()
+    };
+    if let Err(err) = linux::sandbox_command(&settings, &[], &mut cmd) {
+        return Some(err.to_string());
+    }
+    match cmd.status() {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("a test command in the sandbox failed with {status}")),
+        Err(err) => Some(format!("failed to set up the sandbox: {err}")),
+    }
+}
+
+/// Makes `cmd` run in the sandbox described by `settings`.
+pub fn apply(os: &Os, settings: &SandboxSettings, cmd: &mut std::process::Command) -> Result<()> {
+    let cwd = os.env.current_dir()?;
+    let mut paths = Vec::new();
+    for path in &settings.writable_paths {
+        let path = cwd.join(sanitize_path_tool_arg(os, path));
+        match path.canonicalize() {
+            Ok(path) => paths.push(path),
+            Err(err) => warn!(?path, %err, "Skipping writable path of the sandbox"),
+        }
+    }
+    sandbox_command(settings, &paths, cmd)
+}
+
+#[cfg(target_os = "linux")]
+use linux::sandbox_command;
+
+#[cfg(not(target_os = "linux"))]
+fn sandbox_command(
+    _settings: &SandboxSettings,
+    _paths: &[std::path::PathBuf],
+    _cmd: &mut std::process::Command,
+) -> Result<()> {
+    eyre::bail!("the sandbox is only available on Linux")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    Filesystem,
+    Network,
+}
+
+/// An error printed by a command that may have failed because of the sandbox. Commands don't tell
+/// why they were denied access, so this is only a guess based on their error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxViolation {
+    pub kind: ViolationKind,
+    /// The line of stderr with the error.
+    pub message: String,
+}
+
+const NETWORK_ERRORS: &[&str] = &[
+    "Network is unreachable",
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Name or service not known",
+    "No address associated with hostname",
+    "getaddrinfo",
+];
+
+const FILESYSTEM_ERRORS: &[&str] = &["Permission denied", "Operation not permitted", "Read-only file system"];
+
+impl SandboxViolation {
+    /// Looks for an error that the sandbox may have caused in the stderr of a command that failed.
+    pub fn detect(settings: &SandboxSettings, stderr: &str) -> Option<Self> {
+        let find = |patterns: &[&str]| {
+            stderr
+                .lines()
+                .find(|line| patterns.iter().any(|pattern| line.contains(pattern)))
+                .map(|line| line.trim().to_string())
+        };
+        if !settings.allow_network {
+            if let Some(message) = find(NETWORK_ERRORS) {
+                return Some(Self {
+                    kind: ViolationKind::Network,
+                    message,
+                });
+            }
+        }
+        find(FILESYSTEM_ERRORS).map(|message| Self {
+            kind: ViolationKind::Filesystem,
+            message,
+        })
+    }
+
+    pub fn to_json(&self, settings: &SandboxSettings) -> serde_json::Value {
+        let (kind, hint) = match self.kind {
+            ViolationKind::Filesystem => {
+                let mut writable = settings.writable_paths.clone();
+                if settings.private_tmp {
+                    writable.push("/tmp".to_string());
+                }
+                let writable = if writable.is_empty() {
+                    "nowhere".to_string()
+                } else {
+                    writable.join(", ")
+                };
+                (
+                    "filesystem",
+                    format!(
+                        "Commands in the sandbox can only write to: {writable}. Write somewhere else, or ask the \
+                         user to add the path to sandbox.writablePaths in the execute_bash settings of the agent."
+                    ),
+                )
+            },
+            ViolationKind::Network => (
+                "network",
+                "Commands in the sandbox have no network access. Ask the user to set sandbox.allowNetwork in the \
+                 execute_bash settings of the agent, or to run the command themselves."
+                    .to_string(),
+            ),
+        };
+        serde_json::json!({
+            "kind": kind,
+            "message": self.message,
+            "hint": hint,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::{
+        CStr,
+        CString,
+    };
+    use std::io;
+    use std::os::fd::{
+        AsRawFd,
+        FromRawFd,
+        OwnedFd,
+    };
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::{
+        Path,
+        PathBuf,
+    };
+
+    use eyre::{
+        Result,
+        bail,
+    };
+
+    use super::{
+        SandboxSettings,
+        WRITABLE_DEVICES,
+    };
+
+    const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// ABI 2
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// ABI 3
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    /// ABI 6
+    const SCOPE_ABSTRACT_UNIX_SOCKET: u64 = 1 << 0;
+    const SCOPE_SIGNAL: u64 = 1 << 1;
+
+    /// `AUDIT_ARCH_*` of the architecture, which the seccomp filter checks since system call
+    /// numbers differ between architectures.
+    const AUDIT_ARCH: Option<u32> = if cfg!(target_arch = "x86_64") {
+        Some(0xc000_003e)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(0xc000_00b7)
+    } else {
+        None
+    };
+    /// Set in the numbers of x32 system calls, which are also made with the x86-64 architecture.
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// `struct landlock_ruleset_attr`
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+        handled_access_net: u64,
+        scoped: u64,
+    }
+
+    /// `struct landlock_path_beneath_attr`
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// Everything done in the child between `fork` and `exec`, which must not allocate.
+    struct ChildSetup {
+        ruleset: OwnedFd,
+        /// Access to grant beneath the private `/tmp`.
+        write_access: u64,
+        namespaces: libc::c_int,
+        uid_map: CString,
+        gid_map: CString,
+        private_tmp: bool,
+        deny_network: bool,
+        seccomp_filter: Vec<libc::sock_filter>,
+    }
+
+    /// The version of the Landlock ABI supported by the kernel, or a negative number if Landlock
+    /// is not enabled.
+    pub fn landlock_abi() -> i64 {
+        unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                CREATE_RULESET_VERSION,
+            )
+        }
+    }
+
+    /// A seccomp filter that fails `socket(AF_UNIX, ...)` with `EPERM`, along with every system
+    /// call made for another architecture.
+    fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+        use libc::{
+            BPF_ABS,
+            BPF_JEQ,
+            BPF_JGE,
+            BPF_JMP,
+            BPF_K,
+            BPF_LD,
+            BPF_RET,
+            BPF_W,
+            sock_filter,
+        };
+
+        // Offsets in `struct seccomp_data`, reading the lower half of the first argument.
+        const NR: u32 = 0;
+        const ARCH: u32 = 4;
+        const ARG0: u32 = 16;
+        let load = |k| sock_filter {
+            code: (BPF_LD | BPF_W | BPF_ABS) as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |op: u32, k, jt, jf| sock_filter {
+            code: (BPF_JMP | op | BPF_K) as u16,
+            jt,
+            jf,
+            k,
+        };
+        let ret = |k| sock_filter {
+            code: (BPF_RET | BPF_K) as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        vec![
+            load(ARCH),
+            jump(BPF_JEQ, arch, 0, 6),
+            load(NR),
+            jump(BPF_JGE, X32_SYSCALL_BIT, 4, 0),
+            jump(BPF_JEQ, libc::SYS_socket as u32, 0, 2),
+            load(ARG0),
+            jump(BPF_JEQ, libc::AF_UNIX as u32, 1, 0),
+            ret(libc::SECCOMP_RET_ALLOW),
+            ret(libc::SECCOMP_RET_ERRNO | libc::EPERM as u32),
+        ]
+    }
+
+    pub fn sandbox_command(
+        settings: &SandboxSettings,
+        writable_paths: &[PathBuf],
+        cmd: &mut std::process::Command,
+    ) -> Result<()> {
+        let Some(arch) = AUDIT_ARCH else {
+            bail!("the sandbox is not available on this architecture");
+        };
+        let abi = landlock_abi();
+        if abi < 1 {
+            bail!("Landlock is not enabled in this kernel");
+        }
+
+        let mut write_access = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            write_access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            write_access |= ACCESS_FS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            // Reading and executing are never handled, so they stay allowed everywhere.
+            handled_access_fs: write_access,
+            handled_access_net: 0,
+            // Keep commands from signalling or connecting to processes outside of the sandbox.
+            scoped: if abi >= 6 {
+                SCOPE_ABSTRACT_UNIX_SOCKET | SCOPE_SIGNAL
+            } else {
+                0
+            },
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            bail!("failed to create a Landlock ruleset: {}", io::Error::last_os_error());
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let devices = WRITABLE_DEVICES.iter().map(Path::new).filter(|path| path.exists());
+        for path in writable_paths.iter().map(PathBuf::as_path).chain(devices) {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            let is_dir = path.is_dir();
+            let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                bail!("failed to open {}: {}", path.display(), io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            // Only access rights that apply to files can be granted on a file.
+            let allowed_access = if is_dir {
+                write_access
+            } else {
+                write_access & (ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE)
+            };
+            add_rule(&ruleset, allowed_access, fd.as_raw_fd())
+                .map_err(|err| eyre::eyre!("failed to make {} writable: {err}", path.display()))?;
+        }
+
+        let mut namespaces = 0;
+        if settings.private_tmp {
+            namespaces |= libc::CLONE_NEWUSER | libc::CLONE_NEWNS;
+        }
+        if !settings.allow_network {
+            namespaces |= libc::CLONE_NEWUSER | libc::CLONE_NEWNET;
+        }
+        let uid = nix::unistd::getuid();
+        let gid = nix::unistd::getgid();
+        let setup = ChildSetup {
+            ruleset,
+            write_access,
+            namespaces,
+            uid_map: CString::new(format!("{uid} {uid} 1\n"))?,
+            gid_map: CString::new(format!("{gid} {gid} 1\n"))?,
+            private_tmp: settings.private_tmp,
+            deny_network: !settings.allow_network,
+            seccomp_filter: seccomp_filter(arch),
+        };
+        // SAFETY: `ChildSetup::run` only makes system calls on data prepared before the fork.
+        unsafe {
+            cmd.pre_exec(move || setup.run());
+        }
+        Ok(())
+    }
+
+    fn add_rule(ruleset: &OwnedFd, allowed_access: u64, parent_fd: libc::c_int) -> io::Result<()> {
+        let attr = PathBeneathAttr {
+            allowed_access,
+            parent_fd,
+        };
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    impl ChildSetup {
+        fn run(&self) -> io::Result<()> {
+            if self.namespaces != 0 {
+                check(unsafe { libc::unshare(self.namespaces) })?;
+                // Map the user to itself so that files keep their owner.
+                match write_file(c"/proc/self/setgroups", b"deny") {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {},
+                }
+                write_file(c"/proc/self/uid_map", self.uid_map.as_bytes())?;
+                write_file(c"/proc/self/gid_map", self.gid_map.as_bytes())?;
+            }
+
+            if self.private_tmp {
+                check(unsafe {
+                    libc::mount(
+                        std::ptr::null(),
+                        c"/".as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_REC | libc::MS_PRIVATE,
+                        std::ptr::null(),
+                    )
+                })?;
+                check(unsafe {
+                    libc::mount(
+                        c"tmpfs".as_ptr(),
+                        c"/tmp".as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NODEV,
+                        c"mode=1777".as_ptr().cast(),
+                    )
+                })?;
+                let fd = check(unsafe { libc::open(c"/tmp".as_ptr(), libc::O_PATH | libc::O_CLOEXEC) })?;
+                let res = add_rule(&self.ruleset, self.write_access, fd);
+                unsafe { libc::close(fd) };
+                res?;
+            }
+
+            if self.deny_network {
+                loopback_up();
+            }
+
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            let res = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, self.ruleset.as_raw_fd(), 0) };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let filter = libc::sock_fprog {
+                len: self.seccomp_filter.len() as libc::c_ushort,
+                filter: self.seccomp_filter.as_ptr().cast_mut(),
+            };
+            check(unsafe {
+                libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &filter as *const libc::sock_fprog,
+                )
+            })?;
+            Ok(())
+        }
+    }
+
+    fn check(res: libc::c_int) -> io::Result<libc::c_int> {
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(res)
+    }
+
+    fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        let fd = check(unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) })?;
+        let res = unsafe { libc::write(fd, content.as_ptr().cast(), content.len()) };
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        if res < 0 {
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Brings up the loopback interface of a new network namespace so that commands can still
+    /// talk to servers they start themselves.
+    fn loopback_up() {
+        unsafe {
+            let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+            if fd < 0 {
+                return;
+            }
+            let mut ifr: libc::ifreq = std::mem::zeroed();
+            for (dst, src) in ifr.ifr_name.iter_mut().zip(b"lo") {
+                *dst = *src as libc::c_char;
+            }
+            if libc::ioctl(fd, libc::SIOCGIFFLAGS as _, &mut ifr) == 0 {
+                ifr.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+                libc::ioctl(fd, libc::SIOCSIFFLAGS as _, &ifr);
+            }
+            libc::close(fd);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::*;
+
+    fn run(settings: &SandboxSettings, writable_paths: &[PathBuf], script: &str) -> std::process::Output {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.args(["-c", script]);
+        sandbox_command(settings, writable_paths, &mut cmd).unwrap();
+        cmd.output().unwrap()
+    }
+
+    #[test]
+    fn test_sandbox() {
+        // User namespaces and Landlock are often unavailable in containers.
+        if unsupported_reason().is_some() {
+            return;
+        }
+        let writable = tempfile::tempdir().unwrap();
+        let read_only = tempfile::tempdir().unwrap();
+        let writable_path = writable.path().canonicalize().unwrap();
+        // The temporary directories would be hidden by the private /tmp.
+        let settings = SandboxSettings {
+            enabled: true,
+            private_tmp: false,
+            ..Default::default()
+        };
+
+        let output = run(
+            &settings,
+            &[writable_path.clone()],
+            &format!(
+                "echo hi > {0}/file && mkdir {0}/dir && echo ok > /dev/null",
+                writable_path.display()
+            ),
+        );
+        assert!(output.status.success(), "{output:?}");
+        assert!(writable_path.join("file").exists());
+
+        let output = run(
+            &settings,
+            &[writable_path.clone()],
+            &format!("echo hi > {}/file", read_only.path().display()),
+        );
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let violation = SandboxViolation::detect(&settings, &stderr).unwrap();
+        assert_eq!(violation.kind, ViolationKind::Filesystem);
+        assert!(!read_only.path().join("file").exists());
+
+        // The private /tmp is writable and not shared with the host.
+        let marker = format!("q-sandbox-test-{}", std::process::id());
+        let settings = SandboxSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        let output = run(&settings, &[], &format!("touch /tmp/{marker} && ls /tmp"));
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), marker);
+        assert!(!std::path::Path::new("/tmp").join(&marker).exists());
+
+        // Only the loopback interface exists without network access.
+        let output = run(&settings, &[], "cat /proc/net/dev");
+        let interfaces = String::from_utf8_lossy(&output.stdout);
+        assert!(interfaces.contains("lo:"));
+        assert_eq!(interfaces.lines().count(), 3, "{interfaces}");
+
+        // Unix sockets can't be created, while pipes still work.
+        let output = run(&settings, &[], "echo piped | cat");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "piped\n");
+        if std::path::Path::new("/usr/bin/python3").exists() {
+            let output = run(
+                &settings,
+                &[],
+                "/usr/bin/python3 -c 'import socket; socket.socket(socket.AF_UNIX)'",
+            );
+            assert!(!output.status.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("Operation not permitted"));
+            let output = run(
+                &settings,
+                &[],
+                "/usr/bin/python3 -c 'import socket; socket.socket(socket.AF_INET)'",
+            );
+            assert!(output.status.success(), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn test_detect_violation() {
+        let settings = SandboxSettings::default();
+        let violation = SandboxViolation::detect(
+            &settings,
+            "curl: (6) Could not resolve host: example.com\nsome other line",
+        )
+        .unwrap();
+        assert_eq!(violation.kind, ViolationKind::Network);
+        assert_eq!(violation.message, "curl: (6) Could not resolve host: example.com");
+
+        let allow_network = SandboxSettings {
+            allow_network: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            SandboxViolation::detect(&allow_network, "curl: (6) Could not resolve host: example.com"),
+            None
+        );
+        assert_eq!(
+            SandboxViolation::detect(&settings, "touch: cannot touch '/etc/x': Permission denied")
+                .unwrap()
+                .kind,
+            ViolationKind::Filesystem
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use eyre::{
+    Context as EyreContext,
+    Result,
+    eyre,
+};
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncWriteExt,
+    BufReader,
+    Lines,
+};
+use tokio::process::{
+    Child,
+    ChildStderr,
+    ChildStdin,
+    ChildStdout,
+};
+use tokio::select;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{
+    error,
+    warn,
+};
+
+use super::{
+    CommandOptions,
+    CommandResult,
+    OutputBuffer,
+    TIMEOUT_EXIT_STATUS,
+    env_vars_with_user_agent,
+};
+use crate::database::settings::Setting;
+use crate::os::Os;
+
+/// A long-lived shell shared by every `execute_bash` tool use of a chat session, so that the
+/// working directory, exported variables and sourced scripts persist between commands.
+///
+/// The shell process is started on first use and restarted in the last known working directory
+/// if it exits or a command is interrupted. Cloning returns a handle to the same shell.
+#[derive(Debug, Clone, Default)]
+pub struct PersistentShell(Arc<Mutex<ShellState>>);
+
+#[derive(Debug, Default)]
+struct ShellState {
+    process: Option<ShellProcess>,
+    /// Working directory reported after the last completed command.
+    cwd: Option<PathBuf>,
+    /// Whether a shell was started since the last reset.
+    started: bool,
+}
+
+/// Result of a command run in a [`PersistentShell`].
+pub struct ShellCommandResult {
+    pub result: CommandResult,
+    /// Working directory of the shell after the command completed.
+    pub cwd: PathBuf,
+    /// Whether the shell had to be restarted before running the command, losing its environment.
+    pub restarted: bool,
+}
+
+impl PersistentShell {
+    pub fn is_enabled(os: &Os) -> bool {
+        !cfg!(windows)
+            && os
+                .database
+                .settings
+                .get_bool(Setting::EnabledPersistentShell)
+                .unwrap_or(false)
+    }
+
+    /// Runs `command` in the shell, streaming its output to `updates`.
+    ///
+    /// The shell is killed along with everything it started if the command times out.
+    pub async fn run<W: Write>(
+        &self,
+        os: &Os,
+        command: &str,
+        options: &CommandOptions,
+        updates: &mut W,
+    ) -> Result<ShellCommandResult> {
+        let mut state = self.0.lock().await;
+
+        // A command still marked as running was cancelled part way through, so its remaining
+        // output would be read as the output of this one.
+        if state.process.as_ref().is_some_and(|process| process.running) {
+            state.process = None;
+        }
+
+        let restarted = state.process.is_none() && state.started;
+        if state.process.is_none() {
+            state.process = Some(ShellProcess::spawn(os, state.cwd.clone())?);
+            state.started = true;
+        }
+        let Some(process) = state.process.as_mut() else {
+            return Err(eyre!("Shell process is not running"));
+        };
+
+        let output = process.run(command, options, updates).await?;
+
+        if let Some(cwd) = output.cwd {
+            state.cwd = Some(cwd);
+        } else {
+            // The shell exited before reporting back, e.g. because the command ran `exit`, or has
+            // to be killed because the command timed out.
+            state.process = None;
+        }
+
+        let cwd = match &state.cwd {
+            Some(cwd) => cwd.clone(),
+            None => os.env.current_dir()?,
+        };
+
+        Ok(ShellCommandResult {
+            result: output.result,
+            cwd,
+            restarted,
+        })
+    }
+
+    /// Kills the shell, discarding its working directory and environment. The next command
+    /// starts a fresh shell in the directory `q chat` was launched from.
+    ///
+    /// Returns whether a shell was running.
+    pub async fn reset(&self) -> bool {
+        let mut state = self.0.lock().await;
+        state.cwd = None;
+        state.started = false;
+        state.process.take().is_some()
+    }
+
+    /// Returns the working directory reported after the last completed command.
+    pub async fn cwd(&self) -> Option<PathBuf> {
+        self.0.lock().await.cwd.clone()
+    }
+
+    /// Returns the process id and working directory of the shell, if it is running.
+    pub async fn status(&self) -> Option<(Option<u32>, Option<PathBuf>)> {
+        let state = self.0.lock().await;
+        state
+            .process
+            .as_ref()
+            .map(|process| (process.child.id(), state.cwd.clone()))
+    }
+}
+
+#[derive(Debug)]
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    stderr: Lines<BufReader<ChildStderr>>,
+    /// Set while a command is running. Still being set when the next command starts means the
+    /// previous one was cancelled before its sentinel was read.
+    running: bool,
+}
+
+struct RawOutput {
+    result: CommandResult,
+    /// `None` if the shell exited or timed out instead of printing the sentinel.
+    cwd: Option<PathBuf>,
+}
+
+impl ShellProcess {
+    fn spawn(os: &Os, cwd: Option<PathBuf>) -> Result<Self> {
+        let shell = std::env::var("AMAZON_Q_CHAT_SHELL").unwrap_or("bash".to_string());
+
+        let mut command = tokio::process::Command::new(&shell);
+        command
+            .envs(env_vars_with_user_agent(os))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = cwd.filter(|cwd| cwd.is_dir()) {
+            command.current_dir(cwd);
+        }
+        // Run in a separate process group so that Ctrl+C in the terminal does not kill the
+        // shell, and so that the whole group can be killed when the shell is dropped.
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command
+            .spawn()
+            .wrap_err_with(|| format!("Unable to spawn shell '{shell}'"))?;
+
+        let (Some(stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(eyre!("Unable to access the standard streams of shell '{shell}'"));
+        };
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            stderr: BufReader::new(stderr).lines(),
+            running: false,
+        })
+    }
+
+    async fn run<W: Write>(&mut self, command: &str, options: &CommandOptions, updates: &mut W) -> Result<RawOutput> {
+        let sentinel = format!("__Q_SHELL_{}__", uuid::Uuid::new_v4().simple());
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+
+        // The command is passed through a quoted heredoc so it is not expanded before `eval`, and
+        // reads from /dev/null so it cannot consume the rest of the script. The sentinel may end up
+        // on the same line as output that has no trailing newline.
+        let script = format!(
+            "{}IFS= read -r -d '' __q_command <<'{sentinel}'\n{command}\n{sentinel}\neval \"$__q_command\" < /dev/null\nprintf '%s %s %s\\n' '{sentinel}' \"$?\" \"$PWD\"\nprintf '%s\\n' '{sentinel}' >&2\n",
+            export_script(&options.env)
+        );
+
+        self.running = true;
+        self.stdin
+            .write_all(script.as_bytes())
+            .await
+            .wrap_err("Unable to write to the shell")?;
+        self.stdin.flush().await.wrap_err("Unable to write to the shell")?;
+
+        let mut stdout_buf = OutputBuffer::new(options);
+        let mut stderr_buf = OutputBuffer::new(options);
+        let mut status = None;
+        let mut timed_out = false;
+
+        let mut stdout_done = false;
+        let mut stderr_done = false;
+        loop {
+            select! {
+                biased;
+                line = self.stdout.next_line(), if !stdout_done => match line {
+                    Ok(Some(line)) => match line.split_once(&sentinel) {
+                        Some((prefix, rest)) => {
+                            if !prefix.is_empty() {
+                                push_line(updates, &mut stdout_buf, prefix.to_string())?;
+                            }
+                            status = Some(rest.trim_start().to_string());
+                            stdout_done = true;
+                        },
+                        None => push_line(updates, &mut stdout_buf, line)?,
+                    },
+                    Ok(None) => stdout_done = true,
+                    Err(err) => error!(%err, "Failed to read stdout of shell"),
+                },
+                line = self.stderr.next_line(), if !stderr_done => match line {
+                    Ok(Some(line)) => match line.split_once(&sentinel) {
+                        Some((prefix, _)) => {
+                            if !prefix.is_empty() {
+                                push_line(updates, &mut stderr_buf, prefix.to_string())?;
+                            }
+                            stderr_done = true;
+                        },
+                        None => push_line(updates, &mut stderr_buf, line)?,
+                    },
+                    Ok(None) => stderr_done = true,
+                    Err(err) => error!(%err, "Failed to read stderr of shell"),
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() && !(stdout_done && stderr_done) => {
+                    timed_out = true;
+                    break;
+                },
+                else => break,
+            }
+        }
+        updates.flush()?;
+
+        let (exit_status, cwd) = match status.as_deref().and_then(|status| status.split_once(' ')) {
+            Some((code, cwd)) => (code.parse().ok(), Some(PathBuf::from(cwd))),
+            // The shell is killed when dropped by the caller.
+            None if timed_out => (Some(TIMEOUT_EXIT_STATUS), None),
+            None => {
+                let exit_status = self.child.wait().await.wrap_err("No exit status for the shell")?;
+                (exit_status.code(), None)
+            },
+        };
+        self.running = false;
+
+        Ok(RawOutput {
+            result: CommandResult {
+                exit_status,
+                stdout: stdout_buf.finish(),
+                stderr: stderr_buf.finish(),
+                timed_out,
+            },
+            cwd,
+        })
+    }
+}
+
+impl Drop for ShellProcess {
+    fn drop(&mut self) {
+        // Also kill anything the shell left running in the background.
+        #[cfg(unix)]
+        super::kill_process_group(self.child.id());
+        let _ = self.child.start_kill();
+    }
+}
+
+fn push_line(updates: &mut impl Write, buf: &mut OutputBuffer, line: String) -> Result<()> {
+    writeln!(updates, "{line}")?;
+    buf.push(line);
+    Ok(())
+}
+
+/// Exports `env` in the shell, so that the variables are also set if the shell was started with a
+/// different agent.
+fn export_script(env: &HashMap<String, String>) -> String {
+    let mut script = String::new();
+    for (name, value) in env {
+        let is_valid_name = !name.starts_with(|c: char| c.is_ascii_digit())
+            && !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match shlex::try_quote(value) {
+            Ok(value) if is_valid_name => script.push_str(&format!("export {name}={value}\n")),
+            _ => warn!(%name, "Skipping environment variable that can't be exported in the persistent shell"),
+        }
+    }
+    script
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_persistent_shell() {
+        let os = Os::new().await.unwrap();
+        let shell = PersistentShell::default();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let mut updates = Vec::new();
+        let options = CommandOptions::default();
+
+        let output = shell
+            .run(
+                &os,
+                &format!("cd '{}' && export Q_TEST_VAR=hello", dir.display()),
+                &options,
+                &mut updates,
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.result.exit_status, Some(0));
+        assert_eq!(output.cwd, dir);
+        assert!(!output.restarted);
+
+        // Output without a trailing newline, multi-line commands and stderr
+        let output = shell
+            .run(
+                &os,
+                "printf \"$Q_TEST_VAR\"\necho err >&2\nfalse",
+                &options,
+                &mut updates,
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.result.exit_status, Some(1));
+        assert_eq!(output.result.stdout, "hello");
+        assert_eq!(output.result.stderr, "err");
+        assert_eq!(output.cwd, dir);
+
+        // Exiting the shell restarts it in the same directory without the environment
+        let output = shell.run(&os, "exit 3", &options, &mut updates).await.unwrap();
+        assert_eq!(output.result.exit_status, Some(3));
+        let output = shell
+            .run(&os, "echo \"[$Q_TEST_VAR]\"", &options, &mut updates)
+            .await
+            .unwrap();
+        assert!(output.restarted);
+        assert_eq!(output.result.stdout, "[]");
+        assert_eq!(output.cwd, dir);
+
+        // Resetting also discards the working directory
+        assert!(shell.reset().await);
+        assert!(shell.status().await.is_none());
+        let output = shell.run(&os, "pwd", &options, &mut updates).await.unwrap();
+        assert!(!output.restarted);
+        assert_ne!(output.cwd, dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_timeout_and_env() {
+        let os = Os::new().await.unwrap();
+        let shell = PersistentShell::default();
+        let mut updates = Vec::new();
+        let options = CommandOptions {
+            timeout: Some(std::time::Duration::from_millis(500)),
+            env: HashMap::from([("Q_TEST_ENV".to_string(), "a 'b'".to_string())]),
+            ..Default::default()
+        };
+
+        let output = shell
+            .run(&os, "echo \"$Q_TEST_ENV\"; sleep 30", &options, &mut updates)
+            .await
+            .unwrap();
+        assert!(output.result.timed_out);
+        assert_eq!(output.result.exit_status, Some(TIMEOUT_EXIT_STATUS));
+        assert_eq!(output.result.stdout, "a 'b'");
+        assert!(shell.status().await.is_none());
+
+        let output = shell.run(&os, "echo done", &options, &mut updates).await.unwrap();
+        assert!(output.restarted);
+        assert!(!output.result.timed_out);
+        assert_eq!(output.result.stdout, "done");
+    }
+}
@@ -1,4 +1,3 @@
-use std::collections::VecDeque;
 use std::io::Write;
 use std::process::Stdio;
 
@@ -8,26 +7,31 @@ use eyre::{
 };
 use tokio::io::AsyncBufReadExt;
 use tokio::select;
+use tokio::time::Instant;
 use tracing::error;
 
 use super::{
+    CommandOptions,
     CommandResult,
+    OutputBuffer,
+    TIMEOUT_EXIT_STATUS,
     env_vars_with_user_agent,
     format_output,
+    kill_process_group,
 };
 use crate::os::Os;
 
 /// Run a bash command on Unix systems.
 /// # Arguments
 /// * `command` - The command to run
-/// * `max_result_size` - max size of output streams, truncating if required
+/// * `options` - timeout, environment and limits on the size of the output streams
 /// * `updates` - output stream to push informational messages about the progress
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     os: &Os,
     command: &str,
-    max_result_size: usize,
+    options: &CommandOptions,
     mut updates: Option<W>,
 ) -> Result<CommandResult> {
     let shell = std::env::var("AMAZON_Q_CHAT_SHELL").unwrap_or("bash".to_string());
@@ -36,19 +40,32 @@ pub async fn run_command<W: Write>(
     let env_vars = env_vars_with_user_agent(os);
 
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
-    let mut child = tokio::process::Command::new(shell)
-        .arg("-c")
+    let mut cmd = tokio::process::Command::new(shell);
+    cmd.arg("-c")
         .arg(command)
         .envs(env_vars)
+        .envs(&options.env)
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+        .stderr(Stdio::piped());
+    // A command that can time out runs in its own process group so that everything it started
+    // can be killed with it. Processes outside of the foreground group can't read the terminal.
+    if options.timeout.is_some() {
+        cmd.process_group(0).stdin(Stdio::null()).kill_on_drop(true);
+    }
+    if let Some(sandbox) = &options.sandbox {
+        super::sandbox::apply(os, sandbox, cmd.as_std_mut())?;
+    }
+    let mut child = cmd
         .spawn()
         .wrap_err_with(|| format!("Unable to spawn command '{}'", command))?;
+    let pid = child.id();
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
 
     let stdout_final: String;
     let stderr_final: String;
     let exit_status;
+    let mut timed_out = false;
 
     // Buffered output vs all-at-once
     if let Some(u) = updates.as_mut() {
@@ -60,9 +77,8 @@ pub async fn run_command<W: Write>(
         let stderr = tokio::io::BufReader::new(stderr);
         let mut stderr = stderr.lines();
 
-        const LINE_COUNT: usize = 1024;
-        let mut stdout_buf = VecDeque::with_capacity(LINE_COUNT);
-        let mut stderr_buf = VecDeque::with_capacity(LINE_COUNT);
+        let mut stdout_buf = OutputBuffer::new(options);
+        let mut stderr_buf = OutputBuffer::new(options);
 
         let mut stdout_done = false;
         let mut stderr_done = false;
@@ -72,10 +88,7 @@ pub async fn run_command<W: Write>(
                 line = stdout.next_line(), if !stdout_done => match line {
                     Ok(Some(line)) => {
                         writeln!(u, "{line}")?;
-                        if stdout_buf.len() >= LINE_COUNT {
-                            stdout_buf.pop_front();
-                        }
-                        stdout_buf.push_back(line);
+                        stdout_buf.push(line);
                     },
                     Ok(None) => stdout_done = true,
                     Err(err) => error!(%err, "Failed to read stdout of child process"),
@@ -83,10 +96,7 @@ pub async fn run_command<W: Write>(
                 line = stderr.next_line(), if !stderr_done => match line {
                     Ok(Some(line)) => {
                         writeln!(u, "{line}")?;
-                        if stderr_buf.len() >= LINE_COUNT {
-                            stderr_buf.pop_front();
-                        }
-                        stderr_buf.push_back(line);
+                        stderr_buf.push(line);
                     },
                     Ok(None) => stderr_done = true,
                     Err(err) => error!(%err, "Failed to read stderr of child process"),
@@ -94,43 +104,98 @@ pub async fn run_command<W: Write>(
                 exit_status = child.wait() => {
                     break exit_status;
                 },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    timed_out = true;
+                    kill_process_group(pid);
+                    break child.wait().await;
+                },
             };
         }
-        .wrap_err_with(|| format!("No exit status for '{}'", command))?;
+        .wrap_err_with(|| format!("No exit status for '{}'", command))?
+        .code();
 
         u.flush()?;
 
-        stdout_final = stdout_buf.into_iter().collect::<Vec<_>>().join("\n");
-        stderr_final = stderr_buf.into_iter().collect::<Vec<_>>().join("\n");
+        stdout_final = stdout_buf.finish();
+        stderr_final = stderr_buf.finish();
     } else {
         // Take output all at once since we are not reporting anything in real time
         //
         // NOTE: If we don't split this logic, then any writes to stdout while calling
         // this function concurrently may cause the piped child output to be ignored
 
-        let output = child
-            .wait_with_output()
-            .await
-            .wrap_err_with(|| format!("No exit status for '{}'", command))?;
-
-        exit_status = output.status;
-        stdout_final = String::from_utf8_lossy(&output.stdout).to_string();
-        stderr_final = String::from_utf8_lossy(&output.stderr).to_string();
+        let (status, stdout, stderr) = super::wait_with_output(&mut child, deadline).await;
+        exit_status = match status {
+            Some(status) => status
+                .wrap_err_with(|| format!("No exit status for '{}'", command))?
+                .code(),
+            None => {
+                timed_out = true;
+                kill_process_group(pid);
+                let _ = child.wait().await;
+                None
+            },
+        };
+        stdout_final = format_output(
+            &String::from_utf8_lossy(&stdout),
+            options.max_output_bytes,
+            options.truncation_strategy,
+        );
+        stderr_final = format_output(
+            &String::from_utf8_lossy(&stderr),
+            options.max_output_bytes,
+            options.truncation_strategy,
+        );
     }
 
     Ok(CommandResult {
-        exit_status: exit_status.code(),
-        stdout: format_output(&stdout_final, max_result_size),
-        stderr: format_output(&stderr_final, max_result_size),
+        exit_status: if timed_out {
+            Some(TIMEOUT_EXIT_STATUS)
+        } else {
+            exit_status
+        },
+        stdout: stdout_final,
+        stderr: stderr_final,
+        timed_out,
     })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use super::*;
     use crate::cli::chat::tools::OutputKind;
     use crate::cli::chat::tools::execute::ExecuteCommand;
     use crate::os::Os;
 
+    #[tokio::test]
+    async fn test_run_command_timeout() {
+        let os = Os::new().await.unwrap();
+        let options = CommandOptions {
+            timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let result = run_command(&os, "echo started; sleep 30 & sleep 30", &options, Some(Vec::new()))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(result.timed_out);
+        assert_eq!(result.exit_status, Some(TIMEOUT_EXIT_STATUS));
+        assert_eq!(result.stdout, "started");
+
+        let result = run_command(&os, "echo started; sleep 30", &options, None::<Vec<u8>>)
+            .await
+            .unwrap();
+        assert!(result.timed_out);
+        assert_eq!(result.stdout, "started\n");
+
+        let result = run_command(&os, "echo done", &options, None::<Vec<u8>>).await.unwrap();
+        assert!(!result.timed_out);
+        assert_eq!(result.stdout, "done\n");
+    }
+
     #[ignore = "todo: fix failing on musl for some reason"]
     #[tokio::test]
     async fn test_execute_bash_tool() {
@@ -143,7 +208,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
 
@@ -161,7 +226,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
 
@@ -179,7 +244,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
         if let OutputKind::Json(json) = out.output {
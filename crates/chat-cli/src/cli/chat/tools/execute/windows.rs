@@ -1,4 +1,3 @@
-use std::collections::VecDeque;
 use std::io::Write;
 use std::process::Stdio;
 
@@ -7,11 +6,16 @@ use eyre::{
     Result,
 };
 use tokio::io::AsyncBufReadExt;
+use tokio::process::Child;
 use tokio::select;
+use tokio::time::Instant;
 use tracing::error;
 
 use super::{
+    CommandOptions,
     CommandResult,
+    OutputBuffer,
+    TIMEOUT_EXIT_STATUS,
     env_vars_with_user_agent,
     format_output,
 };
@@ -20,14 +24,14 @@ use crate::os::Os;
 /// Run a command on Windows using cmd.exe.
 /// # Arguments
 /// * `command` - The command to run
-/// * `max_result_size` - max size of output streams, truncating if required
+/// * `options` - timeout, environment and limits on the size of the output streams
 /// * `updates` - output stream to push informational messages about the progress
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     os: &Os,
     command: &str,
-    max_result_size: usize,
+    options: &CommandOptions,
     mut updates: Option<W>,
 ) -> Result<CommandResult> {
     // Set up environment variables with user agent metadata for CloudTrail tracking
@@ -38,15 +42,19 @@ pub async fn run_command<W: Write>(
         .arg("/C")
         .arg(command)
         .envs(env_vars)
+        .envs(&options.env)
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .spawn()
         .wrap_err_with(|| format!("Unable to spawn command '{}'", command))?;
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
 
     let stdout_final: String;
     let stderr_final: String;
     let exit_status;
+    let mut timed_out = false;
 
     // Buffered output vs all-at-once
     if let Some(u) = updates.as_mut() {
@@ -58,9 +66,8 @@ pub async fn run_command<W: Write>(
         let stderr = tokio::io::BufReader::new(stderr);
         let mut stderr = stderr.lines();
 
-        const LINE_COUNT: usize = 1024;
-        let mut stdout_buf = VecDeque::with_capacity(LINE_COUNT);
-        let mut stderr_buf = VecDeque::with_capacity(LINE_COUNT);
+        let mut stdout_buf = OutputBuffer::new(options);
+        let mut stderr_buf = OutputBuffer::new(options);
 
         let mut stdout_done = false;
         let mut stderr_done = false;
@@ -70,10 +77,7 @@ pub async fn run_command<W: Write>(
                 line = stdout.next_line(), if !stdout_done => match line {
                     Ok(Some(line)) => {
                         writeln!(u, "{line}")?;
-                        if stdout_buf.len() >= LINE_COUNT {
-                            stdout_buf.pop_front();
-                        }
-                        stdout_buf.push_back(line);
+                        stdout_buf.push(line);
                     },
                     Ok(None) => stdout_done = true,
                     Err(err) => error!(%err, "Failed to read stdout of child process"),
@@ -81,10 +85,7 @@ pub async fn run_command<W: Write>(
                 line = stderr.next_line(), if !stderr_done => match line {
                     Ok(Some(line)) => {
                         writeln!(u, "{line}")?;
-                        if stderr_buf.len() >= LINE_COUNT {
-                            stderr_buf.pop_front();
-                        }
-                        stderr_buf.push_back(line);
+                        stderr_buf.push(line);
                     },
                     Ok(None) => stderr_done = true,
                     Err(err) => error!(%err, "Failed to read stderr of child process"),
@@ -92,33 +93,76 @@ pub async fn run_command<W: Write>(
                 exit_status = child.wait() => {
                     break exit_status;
                 },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    timed_out = true;
+                    kill_process_tree(&mut child).await;
+                    break child.wait().await;
+                },
             };
         }
-        .wrap_err_with(|| format!("No exit status for '{}'", command))?;
+        .wrap_err_with(|| format!("No exit status for '{}'", command))?
+        .code();
 
         u.flush()?;
 
-        stdout_final = stdout_buf.into_iter().collect::<Vec<_>>().join("\n");
-        stderr_final = stderr_buf.into_iter().collect::<Vec<_>>().join("\n");
+        stdout_final = stdout_buf.finish();
+        stderr_final = stderr_buf.finish();
     } else {
         // Take output all at once since we are not reporting anything in real time
-        let output = child
-            .wait_with_output()
-            .await
-            .wrap_err_with(|| format!("No exit status for '{}'", command))?;
-
-        exit_status = output.status;
-        stdout_final = String::from_utf8_lossy(&output.stdout).to_string();
-        stderr_final = String::from_utf8_lossy(&output.stderr).to_string();
+        let (status, stdout, stderr) = super::wait_with_output(&mut child, deadline).await;
+        exit_status = match status {
+            Some(status) => status
+                .wrap_err_with(|| format!("No exit status for '{}'", command))?
+                .code(),
+            None => {
+                timed_out = true;
+                kill_process_tree(&mut child).await;
+                let _ = child.wait().await;
+                None
+            },
+        };
+        stdout_final = format_output(
+            &String::from_utf8_lossy(&stdout),
+            options.max_output_bytes,
+            options.truncation_strategy,
+        );
+        stderr_final = format_output(
+            &String::from_utf8_lossy(&stderr),
+            options.max_output_bytes,
+            options.truncation_strategy,
+        );
     }
 
     Ok(CommandResult {
-        exit_status: exit_status.code(),
-        stdout: format_output(&stdout_final, max_result_size),
-        stderr: format_output(&stderr_final, max_result_size),
+        exit_status: if timed_out {
+            Some(TIMEOUT_EXIT_STATUS)
+        } else {
+            exit_status
+        },
+        stdout: stdout_final,
+        stderr: stderr_final,
+        timed_out,
     })
 }
 
+/// Kills `child` along with every process it started, since killing cmd.exe alone leaves the
+/// processes it started running.
+async fn kill_process_tree(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        let status = tokio::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if let Err(err) = status {
+            error!(%err, "Failed to kill the process tree of child process");
+        }
+    }
+    let _ = child.start_kill();
+}
+
 #[cfg(test)]
 mod tests {
     use crate::cli::chat::tools::OutputKind;
@@ -136,7 +180,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
 
@@ -154,7 +198,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
 
@@ -172,7 +216,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteCommand>(v)
             .unwrap()
-            .invoke(&os, &mut stdout)
+            .invoke(&os, &mut stdout, None)
             .await
             .unwrap();
         if let OutputKind::Json(json) = out.output {
@@ -1,6 +1,10 @@
 use std::collections::VecDeque;
 use std::fs::Metadata;
 use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
 use crossterm::queue;
 use crossterm::style::{
@@ -11,7 +15,20 @@ use eyre::{
     Result,
     bail,
 };
-use globset::GlobSetBuilder;
+use futures::StreamExt;
+use globset::{
+    GlobSet,
+    GlobSetBuilder,
+};
+use ignore::WalkBuilder;
+use ignore::overrides::{
+    Override,
+    OverrideBuilder,
+};
+use regex::{
+    Regex,
+    RegexBuilder,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -34,6 +51,7 @@ use crate::cli::agent::{
     Agent,
     PermissionEvalResult,
 };
+use crate::cli::chat::consts::MAX_CONCURRENT_TOOL_USES;
 use crate::cli::chat::tools::display_purpose;
 use crate::cli::chat::util::images::{
     handle_images_from_paths,
@@ -61,6 +79,7 @@ pub enum FsReadOperation {
     Line(FsLine),
     Directory(FsDirectory),
     Search(FsSearch),
+    Grep(FsGrep),
     Image(FsImage),
 }
 
@@ -101,6 +120,22 @@ impl FsRead {
         }
     }
 
+    /// Passes the `deniedPaths` of `agent` to operations that read paths other than the ones
+    /// given in the tool use, which [Self::eval_perm] cannot check up front.
+    pub fn set_denied_paths(&mut self, agent: &Agent) {
+        let denied_paths = agent
+            .tools_settings
+            .get("fs_read")
+            .and_then(|settings| settings.get("deniedPaths"))
+            .and_then(|paths| serde_json::from_value::<Vec<String>>(paths.clone()).ok())
+            .unwrap_or_default();
+        for op in &mut self.operations {
+            if let FsReadOperation::Grep(fs_grep) = op {
+                fs_grep.denied_paths = denied_paths.clone();
+            }
+        }
+    }
+
     pub fn eval_perm(&self, os: &Os, agent: &Agent) -> PermissionEvalResult {
         #[derive(Debug, Deserialize)]
         #[serde(rename_all = "camelCase")]
@@ -151,25 +186,7 @@ impl FsRead {
                 builder.build()
             };
 
-            let mut sanitized_deny_list = Vec::<&String>::new();
-            let deny_set = {
-                let mut builder = GlobSetBuilder::new();
-                for path in &denied_paths {
-                    let Ok(processed_path) = directories::canonicalizes_path(os, path) else {
-                        continue;
-                    };
-                    match directories::add_gitignore_globs(&mut builder, processed_path.as_str()) {
-                        Ok(_) => {
-                            // Note that we need to push twice here because for each rule we
-                            // are creating two globs (one for file and one for directory)
-                            sanitized_deny_list.push(path);
-                            sanitized_deny_list.push(path);
-                        },
-                        Err(e) => warn!("Failed to create glob from path given: {path}: {e}. Ignoring."),
-                    }
-                }
-                builder.build()
-            };
+            let (deny_set, sanitized_deny_list) = denied_path_globs(os, &denied_paths);
 
             match (allow_set, deny_set) {
                 (Ok(allow_set), Ok(deny_set)) => {
@@ -180,7 +197,8 @@ impl FsRead {
                         match op {
                             FsReadOperation::Line(FsLine { path, .. })
                             | FsReadOperation::Directory(FsDirectory { path, .. })
-                            | FsReadOperation::Search(FsSearch { path, .. }) => {
+                            | FsReadOperation::Search(FsSearch { path, .. })
+                            | FsReadOperation::Grep(FsGrep { path, .. }) => {
                                 let Ok(path) = directories::canonicalizes_path(os, path) else {
                                     ask = true;
                                     continue;
@@ -278,8 +296,20 @@ impl FsRead {
             let mut success_ops = 0usize;
             let mut failed_ops = 0usize;
 
-            for (i, op) in self.operations.iter().enumerate() {
-                match op.invoke(os, updates).await {
+            // Operations run concurrently, their output is displayed in order once all of them
+            // have completed.
+            let results = futures::stream::iter(self.operations.iter().map(|op| async move {
+                let mut output = Vec::new();
+                let result = op.invoke(os, &mut output).await;
+                (output, result)
+            }))
+            .buffered(MAX_CONCURRENT_TOOL_USES)
+            .collect::<Vec<_>>()
+            .await;
+
+            for (i, (output, result)) in results.into_iter().enumerate() {
+                updates.write_all(&output)?;
+                match result {
                     Ok(result) => {
                         success_ops += 1;
 
@@ -363,6 +393,7 @@ impl FsReadOperation {
             FsReadOperation::Line(fs_line) => fs_line.validate(os).await,
             FsReadOperation::Directory(fs_directory) => fs_directory.validate(os).await,
             FsReadOperation::Search(fs_search) => fs_search.validate(os).await,
+            FsReadOperation::Grep(fs_grep) => fs_grep.validate(os).await,
             FsReadOperation::Image(fs_image) => fs_image.validate(os).await,
         }
     }
@@ -372,6 +403,7 @@ impl FsReadOperation {
             FsReadOperation::Line(fs_line) => fs_line.queue_description(os, updates).await,
             FsReadOperation::Directory(fs_directory) => fs_directory.queue_description(updates),
             FsReadOperation::Search(fs_search) => fs_search.queue_description(updates),
+            FsReadOperation::Grep(fs_grep) => fs_grep.queue_description(updates),
             FsReadOperation::Image(fs_image) => fs_image.queue_description(updates),
         }
     }
@@ -381,6 +413,7 @@ impl FsReadOperation {
             FsReadOperation::Line(fs_line) => fs_line.invoke(os, updates).await,
             FsReadOperation::Directory(fs_directory) => fs_directory.invoke(os, updates).await,
             FsReadOperation::Search(fs_search) => fs_search.invoke(os, updates).await,
+            FsReadOperation::Grep(fs_grep) => fs_grep.invoke(os, updates).await,
             FsReadOperation::Image(fs_image) => fs_image.invoke(updates).await,
         }
     }
@@ -623,22 +656,9 @@ impl FsSearch {
         for (line_num, line) in lines.iter().enumerate() {
             if line.to_lowercase().contains(&pattern_lower) {
                 total_matches += 1;
-                let start = line_num.saturating_sub(self.context_lines());
-                let end = lines.len().min(line_num + self.context_lines() + 1);
-                let mut context_text = Vec::new();
-                (start..end).for_each(|i| {
-                    let prefix = if i == line_num {
-                        Self::MATCHING_LINE_PREFIX
-                    } else {
-                        Self::CONTEXT_LINE_PREFIX
-                    };
-                    let line_text = lines[i].to_string();
-                    context_text.push(format!("{}{}: {}", prefix, i + 1, line_text));
-                });
-                let match_text = context_text.join("");
                 results.push(SearchMatch {
                     line_number: line_num + 1,
-                    context: match_text,
+                    context: Self::format_context(&lines, line_num, self.context_lines()),
                 });
             }
         }
@@ -663,6 +683,250 @@ impl FsSearch {
     fn context_lines(&self) -> usize {
         self.context_lines.unwrap_or(Self::DEFAULT_CONTEXT_LINES)
     }
+
+    /// Formats the line at `line_num` along with `context_lines` lines before and after it.
+    fn format_context(lines: &[&str], line_num: usize, context_lines: usize) -> String {
+        let start = line_num.saturating_sub(context_lines);
+        let end = lines.len().min(line_num + context_lines + 1);
+        (start..end)
+            .map(|i| {
+                let prefix = if i == line_num {
+                    Self::MATCHING_LINE_PREFIX
+                } else {
+                    Self::CONTEXT_LINE_PREFIX
+                };
+                format!("{}{}: {}", prefix, i + 1, lines[i])
+            })
+            .collect()
+    }
+}
+
+/// Search the files under a directory, skipping files ignored by `.gitignore`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsGrep {
+    pub path: String,
+    pub pattern: String,
+    /// Whether `pattern` is a regular expression rather than a literal string.
+    pub regex: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    /// Globs relative to `path`. When given, only matching files are searched.
+    pub include: Option<Vec<String>>,
+    /// Globs relative to `path` for files and directories to skip.
+    pub exclude: Option<Vec<String>>,
+    pub context_lines: Option<usize>,
+    /// Files larger than this many bytes are skipped.
+    pub max_file_size: Option<u64>,
+    pub max_results: Option<usize>,
+    /// Number of matches to skip, used to fetch the next page of results.
+    pub offset: Option<usize>,
+    /// `deniedPaths` of the active agent. Matches under these paths are never returned.
+    #[serde(skip)]
+    pub denied_paths: Vec<String>,
+}
+
+impl FsGrep {
+    const DEFAULT_CONTEXT_LINES: usize = 1;
+    const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+    const DEFAULT_MAX_RESULTS: usize = 50;
+
+    pub async fn validate(&mut self, os: &Os) -> Result<()> {
+        let path = sanitize_path_tool_arg(os, &self.path);
+        let relative_path = format_path(os.env.current_dir()?, &path);
+        if !path.exists() {
+            bail!("Path not found: {}", relative_path);
+        }
+        if self.pattern.is_empty() {
+            bail!("Search pattern cannot be empty");
+        }
+        if self.max_results == Some(0) {
+            bail!("max_results must be greater than 0");
+        }
+        self.matcher()?;
+        self.overrides(&path)?;
+        Ok(())
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Searching recursively: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+            style::Print(format!(" for {}: ", if self.is_regex() { "regex" } else { "pattern" })),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.pattern),
+            style::ResetColor,
+        )?;
+        if let Some(include) = self.include.as_ref().filter(|i| !i.is_empty()) {
+            queue!(updates, style::Print(format!(", including {}", include.join(", "))))?;
+        }
+        if let Some(exclude) = self.exclude.as_ref().filter(|e| !e.is_empty()) {
+            queue!(updates, style::Print(format!(", excluding {}", exclude.join(", "))))?;
+        }
+        Ok(())
+    }
+
+    pub async fn invoke(&self, os: &Os, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let root = sanitize_path_tool_arg(os, &self.path);
+        let (deny_set, _) = denied_path_globs(os, &self.denied_paths);
+        let search = GrepSearch {
+            overrides: self.overrides(&root)?,
+            root,
+            display_root: PathBuf::from(directories::canonicalizes_path(os, &self.path)?),
+            deny_set: deny_set?,
+            matcher: self.matcher()?,
+            max_file_size: self.max_file_size.unwrap_or(Self::DEFAULT_MAX_FILE_SIZE),
+            context_lines: self.context_lines.unwrap_or(Self::DEFAULT_CONTEXT_LINES),
+            offset: self.offset.unwrap_or_default(),
+            max_results: self.max_results.unwrap_or(Self::DEFAULT_MAX_RESULTS),
+        };
+        let result = tokio::task::spawn_blocking(move || search.run()).await?;
+
+        let output = serde_json::to_string(&result)?;
+        let byte_count = output.len();
+        if byte_count > MAX_TOOL_RESPONSE_SIZE {
+            bail!(
+                "This tool only supports reading up to {MAX_TOOL_RESPONSE_SIZE} bytes at a time. The search results are {byte_count} bytes. Try a smaller max_results, fewer context_lines or narrower include globs."
+            );
+        }
+
+        super::queue_function_result(
+            &format!(
+                "Found {} matches for pattern '{}' in {} files under {}{}",
+                result.matches.len(),
+                self.pattern,
+                result.files_searched,
+                self.path,
+                match result.next_offset {
+                    Some(offset) => format!(" (more results from offset {offset})"),
+                    None => String::new(),
+                }
+            ),
+            updates,
+            false,
+            false,
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(output),
+        })
+    }
+
+    fn is_regex(&self) -> bool {
+        self.regex.unwrap_or(false)
+    }
+
+    fn matcher(&self) -> Result<Regex> {
+        let pattern = match self.is_regex() {
+            true => self.pattern.clone(),
+            false => regex::escape(&self.pattern),
+        };
+        Ok(RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive.unwrap_or(false))
+            .build()?)
+    }
+
+    fn overrides(&self, root: &Path) -> Result<Override> {
+        let mut builder = OverrideBuilder::new(root);
+        for glob in self.include.iter().flatten() {
+            builder.add(glob)?;
+        }
+        for glob in self.exclude.iter().flatten() {
+            builder.add(&format!("!{glob}"))?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// A [FsGrep] resolved against the file system, run on a blocking thread.
+struct GrepSearch {
+    root: PathBuf,
+    /// [Self::root] as given by the model, which is what `deniedPaths` are matched against.
+    display_root: PathBuf,
+    deny_set: GlobSet,
+    matcher: Regex,
+    overrides: Override,
+    max_file_size: u64,
+    context_lines: usize,
+    offset: usize,
+    max_results: usize,
+}
+
+impl GrepSearch {
+    /// Size of the prefix checked for null bytes to detect binary files.
+    const BINARY_CHECK_BYTES: usize = 8 * 1024;
+
+    fn run(self) -> GrepResult {
+        let walker = WalkBuilder::new(&self.root)
+            .require_git(false)
+            .overrides(self.overrides.clone())
+            .max_filesize(Some(self.max_file_size))
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry({
+                let (root, display_root, deny_set) =
+                    (self.root.clone(), self.display_root.clone(), self.deny_set.clone());
+                move |entry| !deny_set.is_match(display_path(&root, &display_root, entry.path()))
+            })
+            .build();
+
+        let mut result = GrepResult::default();
+        let mut skipped = 0;
+        'files: for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    debug!(?err, "Skipping entry that could not be read");
+                    continue;
+                },
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let Ok(file_bytes) = std::fs::read(entry.path()) else {
+                continue;
+            };
+            if file_bytes[..file_bytes.len().min(Self::BINARY_CHECK_BYTES)].contains(&0) {
+                continue;
+            }
+
+            result.files_searched += 1;
+            let file_content = String::from_utf8_lossy(&file_bytes);
+            let file_content = sanitize_unicode_tags(&file_content);
+            let lines: Vec<&str> = LinesWithEndings::from(&file_content).collect();
+            for (line_num, line) in lines.iter().enumerate() {
+                let Some(m) = self.matcher.find(line) else {
+                    continue;
+                };
+                if skipped < self.offset {
+                    skipped += 1;
+                    continue;
+                }
+                if result.matches.len() == self.max_results {
+                    result.next_offset = Some(self.offset + self.max_results);
+                    break 'files;
+                }
+                result.matches.push(GrepMatch {
+                    path: display_path(&self.root, &self.display_root, entry.path())
+                        .to_string_lossy()
+                        .to_string(),
+                    line_number: line_num + 1,
+                    column: line[..m.start()].chars().count() + 1,
+                    context: FsSearch::format_context(&lines, line_num, self.context_lines),
+                });
+            }
+        }
+
+        result
+    }
+}
+
+/// Maps `path` under `root` to the same path under `display_root`.
+fn display_path(root: &Path, display_root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => display_root.join(relative),
+        _ => display_root.to_path_buf(),
+    }
 }
 
 /// List directory contents.
@@ -808,6 +1072,28 @@ impl FsDirectory {
     }
 }
 
+/// Builds a glob set matching `denied_paths` and everything under them, along with the denied
+/// path each glob in the set was created from.
+fn denied_path_globs<'a>(os: &Os, denied_paths: &'a [String]) -> (Result<GlobSet, globset::Error>, Vec<&'a String>) {
+    let mut sanitized_deny_list = Vec::<&String>::new();
+    let mut builder = GlobSetBuilder::new();
+    for path in denied_paths {
+        let Ok(processed_path) = directories::canonicalizes_path(os, path) else {
+            continue;
+        };
+        match directories::add_gitignore_globs(&mut builder, processed_path.as_str()) {
+            Ok(_) => {
+                // Note that we need to push twice here because for each rule we
+                // are creating two globs (one for file and one for directory)
+                sanitized_deny_list.push(path);
+                sanitized_deny_list.push(path);
+            },
+            Err(e) => warn!("Failed to create glob from path given: {path}: {e}. Ignoring."),
+        }
+    }
+    (builder.build(), sanitized_deny_list)
+}
+
 /// Converts negative 1-based indices to positive 0-based indices.
 fn convert_negative_index(line_count: usize, i: i32) -> usize {
     if i <= 0 {
@@ -823,6 +1109,22 @@ struct SearchMatch {
     context: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GrepMatch {
+    path: String,
+    line_number: usize,
+    column: usize,
+    context: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GrepResult {
+    matches: Vec<GrepMatch>,
+    files_searched: usize,
+    /// Offset of the next page of results, if there are more matches.
+    next_offset: Option<usize>,
+}
+
 fn format_ftype(md: &Metadata) -> char {
     if md.is_symlink() {
         'l'
@@ -1086,6 +1388,110 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_fs_read_grep_invoke() {
+        let os = Os::new().await.unwrap();
+        let mut stdout = std::io::stdout();
+        os.fs.create_dir_all("/repo/src/secret").await.unwrap();
+        os.fs.create_dir_all("/repo/target").await.unwrap();
+        os.fs.write("/repo/.gitignore", "target/\n").await.unwrap();
+        os.fs
+            .write(
+                "/repo/src/main.rs",
+                "fn main() {\n    let todo = 1;\n    // TODO: fix\n}\n",
+            )
+            .await
+            .unwrap();
+        os.fs.write("/repo/src/notes.txt", "todo list\n").await.unwrap();
+        os.fs
+            .write("/repo/src/secret/key.rs", "// TODO: rotate\n")
+            .await
+            .unwrap();
+        os.fs.write("/repo/src/data.bin", b"TODO\0\0").await.unwrap();
+        os.fs
+            .write("/repo/target/build.rs", "// TODO: generated\n")
+            .await
+            .unwrap();
+
+        macro_rules! invoke_grep {
+            ($value:tt) => {{
+                invoke_grep!($value, vec![])
+            }};
+            ($value:tt, $denied_paths:expr) => {{
+                let mut fs_read = serde_json::from_value::<FsRead>(serde_json::json!({ "operations": [$value] })).unwrap();
+                fs_read.validate(&os).await.unwrap();
+                if let FsReadOperation::Grep(fs_grep) = &mut fs_read.operations[0] {
+                    fs_grep.denied_paths = $denied_paths;
+                }
+                let output = fs_read.invoke(&os, &mut stdout).await.unwrap();
+                if let OutputKind::Text(value) = output.output {
+                    serde_json::from_str::<GrepResult>(&value).unwrap()
+                } else {
+                    panic!("expected Text output")
+                }
+            }};
+        }
+        let paths = |result: &GrepResult| result.matches.iter().map(|m| m.path.clone()).collect::<Vec<_>>();
+
+        // Ignored, binary and denied files are skipped, and the search is case insensitive.
+        let result = invoke_grep!(
+            { "mode": "Grep", "path": "/repo", "pattern": "todo" },
+            vec!["/repo/src/secret".to_string()]
+        );
+        assert_eq!(paths(&result), vec![
+            "/repo/src/main.rs",
+            "/repo/src/main.rs",
+            "/repo/src/notes.txt"
+        ]);
+        assert_eq!(result.files_searched, 2);
+        assert_eq!(result.next_offset, None);
+        assert_eq!((result.matches[1].line_number, result.matches[1].column), (3, 8));
+        assert_eq!(
+            result.matches[1].context,
+            format!(
+                "{}2:     let todo = 1;\n{}3:     // TODO: fix\n{}4: }}\n",
+                FsSearch::CONTEXT_LINE_PREFIX,
+                FsSearch::MATCHING_LINE_PREFIX,
+                FsSearch::CONTEXT_LINE_PREFIX
+            )
+        );
+
+        // Regex, case sensitive and glob filters.
+        let result = invoke_grep!({
+            "mode": "Grep",
+            "path": "/repo",
+            "pattern": r"TODO: \w+",
+            "regex": true,
+            "case_sensitive": true,
+            "include": ["**/*.rs"],
+            "exclude": ["secret"],
+            "context_lines": 0,
+        });
+        assert_eq!(paths(&result), vec!["/repo/src/main.rs"]);
+        assert_eq!(
+            result.matches[0].context,
+            format!("{}3:     // TODO: fix\n", FsSearch::MATCHING_LINE_PREFIX)
+        );
+
+        // Pagination.
+        let result = invoke_grep!({ "mode": "Grep", "path": "/repo", "pattern": "todo", "max_results": 2 });
+        assert_eq!(result.matches.len(), 2);
+        assert_eq!(result.next_offset, Some(2));
+        let result =
+            invoke_grep!({ "mode": "Grep", "path": "/repo", "pattern": "todo", "max_results": 2, "offset": 2 });
+        assert_eq!(paths(&result), vec!["/repo/src/notes.txt", "/repo/src/secret/key.rs"]);
+        assert_eq!(result.next_offset, None);
+
+        // Files larger than the limit are skipped.
+        let result = invoke_grep!({ "mode": "Grep", "path": "/repo/src", "pattern": "todo", "max_file_size": 12 });
+        assert_eq!(paths(&result), vec!["/repo/src/notes.txt"]);
+
+        let invalid = serde_json::from_value::<FsRead>(serde_json::json!({
+            "operations": [{ "mode": "Grep", "path": "/repo", "pattern": "(", "regex": true }]
+        }));
+        assert!(invalid.unwrap().validate(&os).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_fs_read_non_utf8_binary_file() {
         let os = Os::new().await.unwrap();
@@ -1448,6 +1854,21 @@ mod tests {
                 if deny_list.iter().filter(|p| *p == DENIED_PATH_OR_FILE_GLOB).collect::<Vec<_>>().len() == 2
                 && deny_list.iter().filter(|p| *p == DENIED_PATH_OR_FILE).collect::<Vec<_>>().len() == 2
         ));
+        // Recursive searches are checked against the same lists, and pass them on to filter the
+        // results under the searched directory.
+        let mut tool_two = serde_json::from_value::<FsRead>(serde_json::json!({
+            "operations": [{ "path": format!("{DENIED_PATH_OR_FILE}/child"), "mode": "Grep", "pattern": "secret" }],
+        }))
+        .unwrap();
+        assert!(matches!(
+            tool_two.eval_perm(&os, &agent),
+            PermissionEvalResult::Deny(ref deny_list) if deny_list == &[DENIED_PATH_OR_FILE]
+        ));
+        tool_two.set_denied_paths(&agent);
+        assert!(matches!(
+            &tool_two.operations[0],
+            FsReadOperation::Grep(FsGrep { denied_paths, .. }) if denied_paths.len() == 2
+        ));
     }
 
     #[tokio::test]
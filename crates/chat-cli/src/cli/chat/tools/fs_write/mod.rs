@@ -1,4 +1,9 @@
-use std::collections::HashMap;
+mod patch;
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::io::Write;
 use std::path::{
     Path,
@@ -14,6 +19,7 @@ use crossterm::style::{
 use eyre::{
     ContextCompat as _,
     Result,
+    WrapErr as _,
     bail,
     eyre,
 };
@@ -82,6 +88,14 @@ pub enum FsWrite {
         new_str: String,
         summary: Option<String>,
     },
+    /// Applies a unified diff that may change several files. `path` is the directory that
+    /// relative paths in the diff are resolved against.
+    #[serde(rename = "apply_patch")]
+    ApplyPatch {
+        path: String,
+        patch: String,
+        summary: Option<String>,
+    },
 }
 
 impl FsWrite {
@@ -91,9 +105,42 @@ impl FsWrite {
             FsWrite::StrReplace { path, .. } => path.as_str(),
             FsWrite::Insert { path, .. } => path.as_str(),
             FsWrite::Append { path, .. } => path.as_str(),
+            FsWrite::ApplyPatch { path, .. } => path.as_str(),
         })
     }
 
+    /// Returns every file that will be written to or deleted, which for [FsWrite::ApplyPatch] may
+    /// be several files.
+    pub fn paths(&self, os: &Os) -> Vec<PathBuf> {
+        match self {
+            FsWrite::ApplyPatch { .. } => self
+                .patch_file_paths()
+                .unwrap_or_default()
+                .iter()
+                .map(|path| sanitize_path_tool_arg(os, path))
+                .collect(),
+            _ => vec![self.path(os)],
+        }
+    }
+
+    /// Returns the paths of the old and new files of every file in the patch of
+    /// [FsWrite::ApplyPatch], joined with `path`.
+    fn patch_file_paths(&self) -> Result<Vec<String>> {
+        let FsWrite::ApplyPatch { path: root, patch, .. } = self else {
+            return Ok(Vec::new());
+        };
+        let mut paths = Vec::new();
+        for file in patch::parse_patch(patch)? {
+            for path in file.old_path.iter().chain(file.new_path.iter()) {
+                let path = Path::new(root).join(path).to_string_lossy().to_string();
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+
     pub async fn invoke(
         &self,
         os: &Os,
@@ -189,6 +236,31 @@ impl FsWrite {
                 file.push_str(new_str);
                 write_to_file(os, &path, file).await?;
             },
+            FsWrite::ApplyPatch { path: root, patch, .. } => {
+                let changes = plan_patch(os, root, patch)?;
+                for change in &changes {
+                    let (invoke_description, path) = match (&change.old_path, &change.new_path) {
+                        (None, Some(new_path)) => ("Creating: ", format_path(&cwd, new_path)),
+                        (Some(old_path), None) => ("Deleting: ", format_path(&cwd, old_path)),
+                        (Some(old_path), Some(new_path)) if old_path != new_path => (
+                            "Renaming: ",
+                            format!("{} -> {}", format_path(&cwd, old_path), format_path(&cwd, new_path)),
+                        ),
+                        _ => ("Updating: ", format_path(&cwd, change.path())),
+                    };
+                    queue!(
+                        output,
+                        style::Print(invoke_description),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(path),
+                        style::ResetColor,
+                        style::Print("\n"),
+                    )?;
+                }
+
+                write_patch_changes(os, &changes).await?;
+                update_patch_line_trackers(&changes, line_tracker);
+            },
         };
 
         self.update_line_tracker_after_invoke(os, line_tracker).await?;
@@ -201,6 +273,10 @@ impl FsWrite {
         os: &Os,
         line_tracker: &mut HashMap<String, FileLineTracker>,
     ) -> Result<()> {
+        // Patches update the tracker of each file they change, see [update_patch_line_trackers].
+        if matches!(self, FsWrite::ApplyPatch { .. }) {
+            return Ok(());
+        }
         let path = self.path(os);
 
         let curr_lines = if os.fs.exists(&path) {
@@ -236,6 +312,9 @@ impl FsWrite {
         os: &Os,
         line_tracker: &mut HashMap<String, FileLineTracker>,
     ) -> Result<()> {
+        if matches!(self, FsWrite::ApplyPatch { .. }) {
+            return Ok(());
+        }
         let path = self.path(os);
 
         let after_lines = if os.fs.exists(&path) {
@@ -270,18 +349,7 @@ impl FsWrite {
             },
             FsWrite::StrReplace { old_str, new_str, .. } => {
                 // Use actual diff analysis for accurate line counting
-                let diff = similar::TextDiff::from_lines(old_str, new_str);
-                let mut lines_added = 0;
-                let mut lines_removed = 0;
-
-                for change in diff.iter_all_changes() {
-                    match change.tag() {
-                        similar::ChangeTag::Insert => lines_added += 1,
-                        similar::ChangeTag::Delete => lines_removed += 1,
-                        similar::ChangeTag::Equal => {},
-                    }
-                }
-                (lines_added, lines_removed)
+                diff_line_counts(old_str, new_str)
             },
             FsWrite::Insert { new_str, .. } => {
                 // For insert operations, all lines in new_str are added
@@ -293,6 +361,8 @@ impl FsWrite {
                 let lines_added = new_str.lines().count();
                 (lines_added, 0)
             },
+            // Counted per file when the patch is applied.
+            FsWrite::ApplyPatch { .. } => (0, 0),
         };
 
         Ok(result)
@@ -378,6 +448,49 @@ impl FsWrite {
                 // Display summary as purpose if available after the diff
                 super::display_purpose(self.get_summary(), output)?;
 
+                Ok(())
+            },
+            FsWrite::ApplyPatch { path: root, patch, .. } => {
+                // Show the changes to every file so that the whole patch is approved at once.
+                for change in plan_patch(os, root, patch)? {
+                    let relative_path = format_path(&cwd, change.path());
+                    queue!(
+                        output,
+                        style::Print("Path: "),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(&relative_path),
+                        style::SetForegroundColor(Color::DarkGrey),
+                    )?;
+                    match (&change.old_path, &change.new_path) {
+                        (None, _) => queue!(output, style::Print(" (new file)"))?,
+                        (_, None) => queue!(output, style::Print(" (deleted)"))?,
+                        (Some(old_path), Some(new_path)) if old_path != new_path => queue!(
+                            output,
+                            style::Print(format!(" (renamed from {})", format_path(&cwd, old_path)))
+                        )?,
+                        _ => (),
+                    }
+                    queue!(output, style::ResetColor, style::Print("\n\n"))?;
+
+                    let diff = similar::TextDiff::from_lines(&change.old_content, &change.new_content);
+                    let old_lines = LinesWithEndings::from(&change.old_content).collect::<Vec<_>>();
+                    let new_lines = LinesWithEndings::from(&change.new_content).collect::<Vec<_>>();
+                    for group in diff.grouped_ops(3) {
+                        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+                            continue;
+                        };
+                        let old_range = first.old_range().start..last.old_range().end;
+                        let new_range = first.new_range().start..last.new_range().end;
+                        let old = stylize_output_if_able(os, &relative_path, &old_lines[old_range.clone()].concat());
+                        let new = stylize_output_if_able(os, &relative_path, &new_lines[new_range].concat());
+                        print_diff(output, &old, &new, old_range.start + 1)?;
+                    }
+                    queue!(output, style::Print("\n"))?;
+                }
+
+                // Display summary as purpose if available after the diff
+                super::display_purpose(self.get_summary(), output)?;
+
                 Ok(())
             },
         }
@@ -404,6 +517,14 @@ impl FsWrite {
                     bail!("Content to append must not be empty")
                 };
             },
+            FsWrite::ApplyPatch { path, patch, .. } => {
+                if patch.trim().is_empty() {
+                    bail!("Patch must not be empty")
+                };
+                // Apply every hunk in memory so that nothing is written unless the whole patch
+                // applies.
+                plan_patch(os, path, patch)?;
+            },
         }
 
         Ok(())
@@ -416,6 +537,8 @@ impl FsWrite {
             FsWrite::StrReplace { path, .. } => path,
             FsWrite::Insert { path, .. } => path,
             FsWrite::Append { path, .. } => path,
+            // Each file of the patch is printed along with its diff.
+            FsWrite::ApplyPatch { .. } => return Ok(()),
         };
         // Sanitize the path to handle tilde expansion
         let path = sanitize_path_tool_arg(os, path);
@@ -457,6 +580,7 @@ impl FsWrite {
             FsWrite::StrReplace { summary, .. } => summary.as_ref(),
             FsWrite::Insert { summary, .. } => summary.as_ref(),
             FsWrite::Append { summary, .. } => summary.as_ref(),
+            FsWrite::ApplyPatch { summary, .. } => summary.as_ref(),
         }
     }
 
@@ -518,27 +642,35 @@ impl FsWrite {
 
                 match (allow_set, deny_set) {
                     (Ok(allow_set), Ok(deny_set)) => {
-                        match self {
+                        let paths = match self {
                             Self::Create { path, .. }
                             | Self::Insert { path, .. }
                             | Self::Append { path, .. }
-                            | Self::StrReplace { path, .. } => {
-                                let Ok(path) = directories::canonicalizes_path(os, path) else {
-                                    return PermissionEvalResult::Ask;
-                                };
-                                let denied_match_set = deny_set.matches(path.as_ref() as &str);
-                                if !denied_match_set.is_empty() {
-                                    return PermissionEvalResult::Deny({
-                                        denied_match_set
-                                            .iter()
-                                            .filter_map(|i| sanitized_deny_list.get(*i).map(|s| (*s).clone()))
-                                            .collect::<Vec<_>>()
-                                    });
-                                }
-                                if is_in_allowlist || allow_set.is_match(path.as_ref() as &str) {
-                                    return PermissionEvalResult::Allow;
-                                }
+                            | Self::StrReplace { path, .. } => vec![path.clone()],
+                            // A patch is only allowed if every file it touches is allowed.
+                            Self::ApplyPatch { .. } => match self.patch_file_paths() {
+                                Ok(paths) => paths,
+                                Err(_) => return PermissionEvalResult::Ask,
                             },
+                        };
+                        let mut all_allowed = true;
+                        for path in &paths {
+                            let Ok(path) = directories::canonicalizes_path(os, path) else {
+                                return PermissionEvalResult::Ask;
+                            };
+                            let denied_match_set = deny_set.matches(path.as_ref() as &str);
+                            if !denied_match_set.is_empty() {
+                                return PermissionEvalResult::Deny({
+                                    denied_match_set
+                                        .iter()
+                                        .filter_map(|i| sanitized_deny_list.get(*i).map(|s| (*s).clone()))
+                                        .collect::<Vec<_>>()
+                                });
+                            }
+                            all_allowed &= allow_set.is_match(path.as_ref() as &str);
+                        }
+                        if is_in_allowlist || all_allowed {
+                            return PermissionEvalResult::Allow;
                         }
                         PermissionEvalResult::Ask
                     },
@@ -573,6 +705,152 @@ async fn write_to_file(os: &Os, path: impl AsRef<Path>, mut content: String) ->
     Ok(())
 }
 
+/// A file change of [FsWrite::ApplyPatch] that has been applied in memory but not yet written.
+#[derive(Debug)]
+struct PatchChange {
+    /// [None] if the file is created by the patch.
+    old_path: Option<PathBuf>,
+    /// [None] if the file is deleted by the patch.
+    new_path: Option<PathBuf>,
+    old_content: String,
+    new_content: String,
+}
+
+impl PatchChange {
+    /// The path of the file after the patch, or before it if the file is deleted.
+    fn path(&self) -> &Path {
+        self.new_path
+            .as_ref()
+            .or(self.old_path.as_ref())
+            .map_or(Path::new(""), |p| p.as_path())
+    }
+}
+
+/// Parses `patch` and applies every hunk to the current file contents in memory, failing if any
+/// file does not apply cleanly. Relative paths in the patch are resolved against `root`.
+fn plan_patch(os: &Os, root: &str, patch: &str) -> Result<Vec<PatchChange>> {
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    for file in patch::parse_patch(patch)? {
+        let display_path = file.display_path();
+        let resolve = |path: &String| sanitize_path_tool_arg(os, Path::new(root).join(path));
+        let old_path = file.old_path.as_ref().map(resolve);
+        let new_path = file.new_path.as_ref().map(resolve);
+
+        let paths = old_path.iter().chain(new_path.iter()).collect::<HashSet<_>>();
+        if paths.iter().any(|p| seen.contains(*p)) {
+            bail!("{display_path} is changed more than once in the patch");
+        }
+        seen.extend(paths.into_iter().cloned());
+
+        let old_content = match &old_path {
+            Some(path) => os
+                .fs
+                .read_to_string_sync(path)
+                .wrap_err_with(|| format!("failed to read {}", file.old_path.as_deref().unwrap_or_default()))?,
+            None => String::new(),
+        };
+        if let Some(path) = &new_path {
+            if old_path.as_ref() != Some(path) && os.fs.exists(path) {
+                bail!("{display_path} already exists");
+            }
+        }
+
+        let new_content = file
+            .apply(&old_content)
+            .wrap_err_with(|| format!("failed to apply the patch to {display_path}"))?;
+        if new_path.is_none() && !file.hunks.is_empty() && !new_content.is_empty() {
+            bail!("the patch deletes {display_path} but does not remove all of its content");
+        }
+        let new_content = if new_path.is_some() { new_content } else { String::new() };
+
+        changes.push(PatchChange {
+            old_path,
+            new_path,
+            old_content,
+            new_content,
+        });
+    }
+    Ok(changes)
+}
+
+/// Writes every change to disk. If a write fails, the changes that were already written are
+/// reverted so that the patch is either applied completely or not at all.
+async fn write_patch_changes(os: &Os, changes: &[PatchChange]) -> Result<()> {
+    for (i, change) in changes.iter().enumerate() {
+        if let Err(err) = write_patch_change(os, change).await {
+            for change in changes[..=i].iter().rev() {
+                if let Err(err) = revert_patch_change(os, change).await {
+                    error!(?err, "failed to revert {}", change.path().display());
+                }
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+async fn write_patch_change(os: &Os, change: &PatchChange) -> Result<()> {
+    if let Some(new_path) = &change.new_path {
+        if let Some(parent) = new_path.parent() {
+            os.fs.create_dir_all(parent).await?;
+        }
+        os.fs.write(new_path, &change.new_content).await?;
+    }
+    if let Some(old_path) = &change.old_path {
+        if change.new_path.as_ref() != Some(old_path) {
+            os.fs.remove_file(old_path).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn revert_patch_change(os: &Os, change: &PatchChange) -> Result<()> {
+    if let Some(new_path) = &change.new_path {
+        if change.old_path.as_ref() != Some(new_path) && os.fs.exists(new_path) {
+            os.fs.remove_file(new_path).await?;
+        }
+    }
+    if let Some(old_path) = &change.old_path {
+        os.fs.write(old_path, &change.old_content).await?;
+    }
+    Ok(())
+}
+
+/// Updates the line tracker of every file changed by a patch.
+fn update_patch_line_trackers(changes: &[PatchChange], line_tracker: &mut HashMap<String, FileLineTracker>) {
+    for change in changes {
+        let tracker = line_tracker
+            .entry(change.path().to_string_lossy().to_string())
+            .or_default();
+        let before_lines = change.old_content.lines().count();
+        if tracker.is_first_write {
+            tracker.prev_fswrite_lines = before_lines;
+        }
+        tracker.before_fswrite_lines = before_lines;
+        tracker.after_fswrite_lines = change.new_content.lines().count();
+        (tracker.lines_added_by_agent, tracker.lines_removed_by_agent) =
+            diff_line_counts(&change.old_content, &change.new_content);
+        tracker.is_first_write = false;
+    }
+}
+
+/// Returns the number of lines added and removed between `old` and `new`.
+fn diff_line_counts(old: &str, new: &str) -> (usize, usize) {
+    let diff = similar::TextDiff::from_lines(old, new);
+    let mut lines_added = 0;
+    let mut lines_removed = 0;
+
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            similar::ChangeTag::Insert => lines_added += 1,
+            similar::ChangeTag::Delete => lines_removed += 1,
+            similar::ChangeTag::Equal => {},
+        }
+    }
+    (lines_added, lines_removed)
+}
+
 /// Returns a prefix/suffix pair before and after the content dictated by `[start_line, end_line]`
 /// within `content`. The updated start and end lines containing the original context along with
 /// the suffix and prefix are returned.
@@ -1217,6 +1495,83 @@ mod tests {
         assert!(result.is_err(), "Appending to non-existent file should fail");
     }
 
+    #[tokio::test]
+    async fn test_fs_write_tool_apply_patch() {
+        let os = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+        let mut line_tracker = HashMap::new();
+        os.fs.write("/delete_me.txt", "bye\n").await.unwrap();
+        os.fs.write("/rename_me.txt", "same\n").await.unwrap();
+
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -2,2 +2,2 @@
+ 2: This is line 2
+-3: asdf
++3: qwerty
+--- /dev/null
++++ b/aaaa1/new.txt
+@@ -0,0 +1 @@
++new file
+--- a/delete_me.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/rename_me.txt b/renamed.txt
+rename from rename_me.txt
+rename to renamed.txt
+";
+        let mut tool = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": "/",
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        tool.validate(&os).await.unwrap();
+        assert_eq!(tool.paths(&os).len(), 5);
+        tool.queue_description(&os, &mut stdout).unwrap();
+        tool.invoke(&os, &mut stdout, &mut line_tracker).await.unwrap();
+
+        assert_eq!(
+            os.fs.read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("3: asdf", "3: qwerty")
+        );
+        assert_eq!(os.fs.read_to_string("/aaaa1/new.txt").await.unwrap(), "new file\n");
+        assert!(!os.fs.exists("/delete_me.txt"));
+        assert!(!os.fs.exists("/rename_me.txt"));
+        assert_eq!(os.fs.read_to_string("/renamed.txt").await.unwrap(), "same\n");
+
+        let tracker = &line_tracker[&os.fs.chroot_path_str(TEST_FILE_PATH)];
+        assert_eq!((tracker.lines_added_by_agent, tracker.lines_removed_by_agent), (1, 1));
+
+        // Nothing is written if any hunk fails to apply.
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -1 +1 @@
+-1: Hello world!
++1: Goodbye world!
+--- a/renamed.txt
++++ b/renamed.txt
+@@ -1 +1 @@
+-not in the file
++changed
+";
+        let mut tool = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": "/",
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        assert!(tool.validate(&os).await.is_err());
+        assert!(tool.invoke(&os, &mut stdout, &mut line_tracker).await.is_err());
+        assert_eq!(
+            os.fs.read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("3: asdf", "3: qwerty")
+        );
+    }
+
     #[test]
     fn test_lines_with_context() {
         let content = "Hello\nWorld!\nhow\nare\nyou\ntoday?";
@@ -1439,6 +1794,30 @@ mod tests {
         let res = tool_should_allow_glob.eval_perm(&os, &agent);
         assert!(matches!(res, PermissionEvalResult::Allow));
 
+        // Patches are only allowed if every file is allowed, and denied if any file is denied.
+        let patch_tool = |patch: &str| {
+            serde_json::from_value::<FsWrite>(serde_json::json!({
+                "path": "/some",
+                "command": "apply_patch",
+                "patch": patch,
+            }))
+            .unwrap()
+        };
+        let allowed_patch = "--- allow/path/a.txt\n+++ allow/path/b.txt\n";
+        let res = patch_tool(allowed_patch).eval_perm(&os, &agent);
+        assert!(matches!(res, PermissionEvalResult::Allow));
+
+        let res = patch_tool(&format!("{allowed_patch}--- /dev/null\n+++ other/c.txt\n")).eval_perm(&os, &agent);
+        assert!(matches!(res, PermissionEvalResult::Ask));
+
+        let res = patch_tool(&format!(
+            "{allowed_patch}--- denied/path/c.txt\n+++ denied/path/c.txt\n"
+        ))
+        .eval_perm(&os, &agent);
+        assert!(
+            matches!(res, PermissionEvalResult::Deny(ref deny_list) if deny_list.contains(&DENIED_PATH_ONE.to_string()))
+        );
+
         // Test that denied patterns take precedence over allowed tools list
         agent.allowed_tools.insert("fs_write".to_string());
 
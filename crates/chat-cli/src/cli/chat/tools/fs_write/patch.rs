@@ -0,0 +1,471 @@
+//! Parsing and in-memory application of unified diffs for the `apply_patch` command.
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+
+/// Changes to a single file parsed from a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path of the file before the patch, [None] if the patch creates the file.
+    pub old_path: Option<String>,
+    /// Path of the file after the patch, [None] if the patch deletes the file.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-indexed line the hunk starts at in the original file according to its header. Only used
+    /// as a hint for where to start looking for the hunk.
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl Hunk {
+    /// Lines the hunk expects to find in the original file.
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl FilePatch {
+    /// Applies every hunk to `original`, returning the patched content.
+    ///
+    /// Hunks are located by searching outward from the line given in their header, so line
+    /// numbers only need to be approximately right. If the context does not match exactly, the
+    /// search is retried ignoring trailing and then surrounding whitespace. Context lines keep the
+    /// content of the original file.
+    pub fn apply(&self, original: &str) -> Result<String> {
+        let line_ending = if original.contains("\r\n") { "\r\n" } else { "\n" };
+        let lines = original.lines().collect::<Vec<_>>();
+        let mut patched = Vec::<&str>::new();
+
+        let mut pos = 0;
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let old_lines = hunk.old_lines();
+            let Some(start) = find_lines(&lines, &old_lines, pos, hunk.old_start.saturating_sub(1)) else {
+                bail!(
+                    "hunk {} does not match the file content, expected to find:\n{}",
+                    i + 1,
+                    old_lines.join("\n")
+                );
+            };
+
+            patched.extend(&lines[pos..start]);
+            let mut j = start;
+            for line in &hunk.lines {
+                match line {
+                    HunkLine::Context(_) => {
+                        patched.push(lines[j]);
+                        j += 1;
+                    },
+                    HunkLine::Remove(_) => j += 1,
+                    HunkLine::Add(s) => patched.push(s),
+                }
+            }
+            pos = j;
+        }
+        patched.extend(&lines[pos..]);
+
+        let mut content = patched.join(line_ending);
+        if !patched.is_empty() && (original.is_empty() || original.ends_with('\n')) {
+            content.push_str(line_ending);
+        }
+        Ok(content)
+    }
+
+    /// Path to display for the patched file.
+    pub fn display_path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the index of the first line of `needle` within `lines`, starting at `min` and choosing
+/// the occurrence closest to `hint`.
+fn find_lines(lines: &[&str], needle: &[&str], min: usize, hint: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(hint.clamp(min, lines.len().max(min)));
+    }
+    let last = lines.len().checked_sub(needle.len())?;
+    if min > last {
+        return None;
+    }
+    let hint = hint.clamp(min, last);
+
+    let normalizers: [fn(&str) -> &str; 3] = [|s| s, str::trim_end, str::trim];
+    for normalize in normalizers {
+        let matches_at = |start: usize| {
+            lines[start..start + needle.len()]
+                .iter()
+                .zip(needle)
+                .all(|(a, b)| normalize(a) == normalize(b))
+        };
+        for distance in 0..=(last - min) {
+            let before = hint.checked_sub(distance).filter(|i| *i >= min);
+            let after = Some(hint + distance).filter(|i| distance > 0 && *i <= last);
+            if let Some(start) = before.into_iter().chain(after).find(|i| matches_at(*i)) {
+                return Some(start);
+            }
+        }
+    }
+    None
+}
+
+/// Parses a unified diff containing changes to one or more files.
+///
+/// Both plain `---`/`+++` headers and git extended headers are supported, including `/dev/null`
+/// for created and deleted files and `rename from`/`rename to` for renames. Text before the
+/// first file header is ignored.
+pub fn parse_patch(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines = patch.lines().collect::<Vec<_>>();
+    let mut files = Vec::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let mut file = if let Some(header) = lines[i].strip_prefix("diff --git ") {
+            let (old_path, new_path) = parse_git_header(header);
+            i += 1;
+            let mut file = FilePatch {
+                old_path,
+                new_path,
+                hunks: Vec::new(),
+            };
+            while i < lines.len() && !lines[i].starts_with("diff ") && !lines[i].starts_with("@@") {
+                if is_file_header(&lines, i) {
+                    break;
+                }
+                let line = lines[i];
+                if let Some(path) = line.strip_prefix("rename from ") {
+                    file.old_path = Some(path.to_string());
+                } else if let Some(path) = line.strip_prefix("rename to ") {
+                    file.new_path = Some(path.to_string());
+                } else if line.starts_with("new file mode") {
+                    file.old_path = None;
+                } else if line.starts_with("deleted file mode") {
+                    file.new_path = None;
+                }
+                i += 1;
+            }
+            if is_file_header(&lines, i) {
+                (file.old_path, file.new_path) = parse_file_headers(lines[i], lines[i + 1]);
+                i += 2;
+            }
+            file
+        } else if is_file_header(&lines, i) {
+            let (old_path, new_path) = parse_file_headers(lines[i], lines[i + 1]);
+            i += 2;
+            FilePatch {
+                old_path,
+                new_path,
+                hunks: Vec::new(),
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+
+        if file.old_path.is_none() && file.new_path.is_none() {
+            bail!("line {i} of the patch: a file cannot be both created and deleted");
+        }
+        file.hunks = parse_hunks(&lines, &mut i)?;
+        files.push(file);
+    }
+
+    if files.is_empty() {
+        bail!("the patch does not contain any file headers (`--- old` followed by `+++ new`)");
+    }
+    Ok(files)
+}
+
+fn is_file_header(lines: &[&str], i: usize) -> bool {
+    lines.get(i).is_some_and(|l| l.starts_with("--- ")) && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+}
+
+/// Parses the paths from the `a/old b/new` part of a `diff --git` line.
+fn parse_git_header(header: &str) -> (Option<String>, Option<String>) {
+    let (old, new) = match header.split_once(" b/") {
+        Some((old, new)) => (old.to_string(), format!("b/{new}")),
+        None => match header.split_once(' ') {
+            Some((old, new)) => (old.to_string(), new.to_string()),
+            None => return (None, None),
+        },
+    };
+    strip_prefixes(Some(old), Some(new))
+}
+
+/// Parses the paths from a `--- old` and `+++ new` header pair.
+fn parse_file_headers(old: &str, new: &str) -> (Option<String>, Option<String>) {
+    fn header_path(line: &str) -> Option<String> {
+        // Drop the optional timestamp after a tab.
+        let path = line[4..].split('\t').next().unwrap_or_default().trim();
+        (path != "/dev/null" && !path.is_empty()).then(|| path.to_string())
+    }
+    strip_prefixes(header_path(old), header_path(new))
+}
+
+/// Strips the `a/` and `b/` prefixes that git adds to paths, if both paths have them.
+fn strip_prefixes(old: Option<String>, new: Option<String>) -> (Option<String>, Option<String>) {
+    let has_prefixes = old.as_ref().is_none_or(|p| p.starts_with("a/"))
+        && new.as_ref().is_none_or(|p| p.starts_with("b/"))
+        && (old.is_some() || new.is_some());
+    if !has_prefixes {
+        return (old, new);
+    }
+    (old.map(|p| p[2..].to_string()), new.map(|p| p[2..].to_string()))
+}
+
+/// Parses consecutive hunks starting at line `i`, leaving `i` at the first line after them.
+fn parse_hunks(lines: &[&str], i: &mut usize) -> Result<Vec<Hunk>> {
+    let mut hunks = Vec::new();
+    while *i < lines.len() && lines[*i].starts_with("@@") {
+        let header = lines[*i];
+        *i += 1;
+
+        let (old_start, counts) = parse_hunk_header(header);
+        let hunk = Hunk {
+            old_start,
+            lines: match counts {
+                Some((old_count, new_count)) => parse_counted_hunk_lines(lines, i, header, old_count, new_count)?,
+                None => parse_hunk_lines(lines, i)?,
+            },
+        };
+
+        if !hunk.lines.iter().any(|l| !matches!(l, HunkLine::Context(_))) {
+            return Err(eyre!("hunk `{header}` does not add or remove any lines"));
+        }
+        hunks.push(hunk);
+    }
+    Ok(hunks)
+}
+
+/// Parses a hunk body by consuming exactly the number of old and new lines given in its header,
+/// so that removed and added lines such as `--- x` are not mistaken for the next file header.
+fn parse_counted_hunk_lines(
+    lines: &[&str],
+    i: &mut usize,
+    header: &str,
+    mut old_count: usize,
+    mut new_count: usize,
+) -> Result<Vec<HunkLine>> {
+    let mut hunk_lines = Vec::new();
+    while old_count > 0 || new_count > 0 {
+        let Some(line) = lines.get(*i) else {
+            bail!("hunk `{header}` ends before the number of lines given in its header");
+        };
+        let (old, new) = match line.chars().next() {
+            // Empty lines are treated as empty context lines since trailing whitespace is often
+            // stripped from diffs.
+            None => {
+                hunk_lines.push(HunkLine::Context(String::new()));
+                (1, 1)
+            },
+            Some(' ') => {
+                hunk_lines.push(HunkLine::Context(line[1..].to_string()));
+                (1, 1)
+            },
+            Some('-') => {
+                hunk_lines.push(HunkLine::Remove(line[1..].to_string()));
+                (1, 0)
+            },
+            Some('+') => {
+                hunk_lines.push(HunkLine::Add(line[1..].to_string()));
+                (0, 1)
+            },
+            // "\ No newline at end of file"
+            Some('\\') => (0, 0),
+            Some(_) => bail!(
+                "line {} of the patch is not a valid hunk line, every line in a hunk must start with ' ', '-' or '+': {line}",
+                *i + 1
+            ),
+        };
+        if old > old_count || new > new_count {
+            bail!(
+                "line {} of the patch: hunk `{header}` has more lines than given in its header",
+                *i + 1
+            );
+        }
+        old_count -= old;
+        new_count -= new;
+        *i += 1;
+    }
+    while lines.get(*i).is_some_and(|line| line.starts_with('\\')) {
+        *i += 1;
+    }
+    Ok(hunk_lines)
+}
+
+/// Parses a hunk body whose header does not give line counts, which ends at the next hunk or file
+/// header.
+fn parse_hunk_lines(lines: &[&str], i: &mut usize) -> Result<Vec<HunkLine>> {
+    let mut hunk_lines = Vec::new();
+    // Empty lines are treated as empty context lines since trailing whitespace is often stripped
+    // from diffs, except at the end of a hunk.
+    let mut trailing_empty = 0;
+    while *i < lines.len() {
+        let line = lines[*i];
+        if line.starts_with("@@") || line.starts_with("diff ") || is_file_header(lines, *i) {
+            break;
+        }
+        match line.chars().next() {
+            None => {
+                hunk_lines.push(HunkLine::Context(String::new()));
+                trailing_empty += 1;
+                *i += 1;
+                continue;
+            },
+            Some(' ') => hunk_lines.push(HunkLine::Context(line[1..].to_string())),
+            Some('-') => hunk_lines.push(HunkLine::Remove(line[1..].to_string())),
+            Some('+') => hunk_lines.push(HunkLine::Add(line[1..].to_string())),
+            // "\ No newline at end of file"
+            Some('\\') => (),
+            Some(_) => bail!(
+                "line {} of the patch is not a valid hunk line, every line in a hunk must start with ' ', '-' or '+': {line}",
+                *i + 1
+            ),
+        }
+        trailing_empty = 0;
+        *i += 1;
+    }
+    hunk_lines.truncate(hunk_lines.len() - trailing_empty);
+    Ok(hunk_lines)
+}
+
+/// Parses a `@@ -start,count +start,count @@` header into the old start line, or 0 if the header
+/// does not include line numbers, and the numbers of old and new lines if it includes both
+/// ranges. Counts that are left out are 1.
+fn parse_hunk_header(header: &str) -> (usize, Option<(usize, usize)>) {
+    fn parse_range(range: Option<&str>, sign: char) -> Option<(usize, usize)> {
+        let range = range?.strip_prefix(sign)?;
+        let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+        Some((start.parse().ok()?, count.parse().ok()?))
+    }
+
+    let mut ranges = header.trim_start_matches('@').split_whitespace();
+    let old = parse_range(ranges.next(), '-');
+    let new = parse_range(ranges.next(), '+');
+    let counts = old
+        .zip(new)
+        .map(|((_, old_count), (_, new_count))| (old_count, new_count));
+    (old.map_or(0, |(start, _)| start), counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hunk_with_header_like_lines() {
+        let files = parse_patch("--- a/f.txt\n+++ b/f.txt\n@@ -1,3 +1,3 @@\n a\n--- x\n+++ y\n c\n").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].hunks.len(), 1);
+        assert_eq!(files[0].hunks[0].lines, vec![
+            HunkLine::Context("a".to_string()),
+            HunkLine::Remove("-- x".to_string()),
+            HunkLine::Add("++ y".to_string()),
+            HunkLine::Context("c".to_string()),
+        ]);
+
+        // Hunks must have the number of lines given in their header.
+        assert!(parse_patch("--- f\n+++ f\n@@ -1,2 +1,2 @@\n-a\n+A\n").is_err());
+        assert!(parse_patch("--- f\n+++ f\n@@ -1 +1 @@\n-a\n-b\n+A\n").is_err());
+        // Headers without line numbers end at the next header.
+        let files = parse_patch("--- f\n+++ f\n@@\n-a\n+A\n").unwrap();
+        assert_eq!(files[0].hunks[0].lines.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let patch = "\
+Some preamble that is ignored
+diff --git a/src/lib.rs b/src/lib.rs
+index 83db48f..bf269f4 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    println!(\"hello\");
++    println!(\"world\");
+ }
+@@ -10 +10,2 @@ fn other() {
+ a
++b
+
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++one
++two
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+diff --git a/from.txt b/to.txt
+similarity index 100%
+rename from from.txt
+rename to to.txt
+";
+        let files = parse_patch(patch).unwrap();
+        assert_eq!(files.len(), 4);
+
+        assert_eq!(files[0].old_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(files[0].new_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(files[0].hunks[0].old_start, 1);
+        assert_eq!(
+            files[0].hunks[0].lines[1],
+            HunkLine::Remove("    println!(\"hello\");".to_string())
+        );
+        assert_eq!(files[0].hunks[1].old_start, 10);
+        // The trailing empty line is not part of the hunk.
+        assert_eq!(files[0].hunks[1].lines.len(), 2);
+
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].new_path.as_deref(), Some("new.txt"));
+
+        assert_eq!(files[2].old_path.as_deref(), Some("old.txt"));
+        assert_eq!(files[2].new_path, None);
+        assert!(files[2].hunks.is_empty());
+
+        assert_eq!(files[3].old_path.as_deref(), Some("from.txt"));
+        assert_eq!(files[3].new_path.as_deref(), Some("to.txt"));
+
+        assert!(parse_patch("no headers").is_err());
+        assert!(parse_patch("--- a\n+++ b\n@@ -1 +1 @@\n-x\ny\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_fuzzy() {
+        let original = "a\nb\nc\nd\ne\nf\n";
+        // Wrong line numbers and whitespace differences in the context still apply.
+        let patch = parse_patch("--- f\n+++ f\n@@ -1,3 +1,3 @@\n c  \n-d\n+D\n e\n@@ -20 +20 @@\n-f\n+F\n").unwrap();
+        assert_eq!(patch[0].apply(original).unwrap(), "a\nb\nc\nD\ne\nF\n");
+
+        // Line endings of the original are preserved.
+        let patch = parse_patch("--- f\n+++ f\n@@ -1 +1 @@\n-a\n+A\n").unwrap();
+        assert_eq!(patch[0].apply("a\r\nb").unwrap(), "A\r\nb");
+
+        // Hunks must match in order.
+        let patch = parse_patch("--- f\n+++ f\n@@ -5 +5 @@\n-e\n+E\n@@ -1 +1 @@\n-a\n+A\n").unwrap();
+        assert!(patch[0].apply(original).is_err());
+
+        let patch = parse_patch("--- f\n+++ f\n@@ -1 +1 @@\n-x\n+y\n").unwrap();
+        assert!(patch[0].apply(original).is_err());
+    }
+}
@@ -0,0 +1,1446 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::Stdio;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::error;
+
+use super::fs_read::{
+    FsLine,
+    FsRead,
+    FsReadOperation,
+};
+use super::{
+    InvokeOutput,
+    MAX_TOOL_RESPONSE_SIZE,
+    OutputKind,
+    display_purpose,
+    sanitize_path_tool_arg,
+};
+use crate::cli::agent::{
+    Agent,
+    PermissionEvalResult,
+};
+use crate::os::Os;
+use crate::util::pattern_matching::{
+    matches_any_pattern,
+    matches_pattern,
+};
+
+/// Number of commits returned by `log` when the model doesn't ask for a number.
+const DEFAULT_LOG_COUNT: usize = 20;
+const MAX_LOG_COUNT: usize = 200;
+
+/// Separates the fields and the records of formats given to git.
+const FIELD_SEPARATOR: char = '\x1f';
+const RECORD_SEPARATOR: char = '\x1e';
+const COMMIT_FORMAT: &str = "--format=%H%x1f%P%x1f%an%x1f%ae%x1f%aI%x1f%s%x1f%b%x1e";
+
+/// Queries and changes git repositories, returning structured results.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Git {
+    /// Path of the repository, the current directory if not set.
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub operation: GitOperation,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum GitOperation {
+    Status,
+    Diff(GitDiff),
+    Log(GitLog),
+    Show(GitShow),
+    Blame(GitBlame),
+    Branches(GitBranches),
+    Commit(GitCommit),
+    Checkout(GitCheckout),
+    Stash(GitStash),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitDiff {
+    /// Compare the index with `HEAD` instead of the working tree with the index.
+    #[serde(default)]
+    pub staged: bool,
+    /// Compare from this revision, to the working tree unless `to` is set.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    pub context_lines: Option<u32>,
+    /// Only return the files that changed and the number of lines, without patches.
+    #[serde(default)]
+    pub stat_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitLog {
+    /// Revision or range of commits, e.g. `main..HEAD`.
+    pub revision: Option<String>,
+    pub max_count: Option<usize>,
+    pub author: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Only commits whose message matches this pattern.
+    pub grep: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitShow {
+    pub revision: String,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub stat_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitBlame {
+    /// File to blame, relative to the repository.
+    pub file: String,
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub revision: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitBranches {
+    /// Also list remote-tracking branches.
+    #[serde(default)]
+    pub remote: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitCommit {
+    pub message: String,
+    /// Paths staged before committing.
+    #[serde(default)]
+    pub add: Vec<String>,
+    /// Stage every change to tracked files, as `git commit --all`.
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub amend: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitCheckout {
+    /// Branch or commit to switch to, or to restore `paths` from.
+    pub target: String,
+    /// Create `target` as a new branch.
+    #[serde(default)]
+    pub create: bool,
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitStash {
+    pub action: StashAction,
+    pub message: Option<String>,
+    /// Stash entry that `pop`, `apply` and `drop` act on, the latest if not set.
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub include_untracked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StashAction {
+    Push,
+    Pop,
+    Apply,
+    Drop,
+    List,
+}
+
+/// A rule in the `rules` of the git tool. Fields accept globs, and fields that are left out
+/// match any operation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitRule {
+    /// Operation name, e.g. `commit`.
+    operation: Option<String>,
+    /// Branch the operation changes: the branch switched to for `checkout`, the current branch
+    /// otherwise.
+    branch: Option<String>,
+    action: RuleAction,
+    /// Explanation shown to the user when the rule denies an operation.
+    reason: Option<String>,
+}
+
+/// What happens to operations matching a rule. When several rules match, the strictest wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RuleAction {
+    Allow,
+    Ask,
+    Deny,
+}
+
+impl GitRule {
+    fn matches(&self, operation: &str, branch: Option<&str>) -> bool {
+        self.operation
+            .as_deref()
+            .is_none_or(|pattern| matches_pattern(pattern, operation))
+            && match (&self.branch, branch) {
+                (None, _) => true,
+                (Some(pattern), Some(branch)) => matches_pattern(pattern, branch),
+                (Some(_), None) => false,
+            }
+    }
+
+    /// The rule as shown to the user, e.g. `commit branch=main: Changes go through pull requests`.
+    fn description(&self) -> String {
+        let mut description = self.operation.clone().unwrap_or_else(|| "*".to_string());
+        if let Some(branch) = &self.branch {
+            description.push_str(&format!(" branch={branch}"));
+        }
+        match &self.reason {
+            Some(reason) => format!("{description}: {reason}"),
+            None => description,
+        }
+    }
+}
+
+impl Git {
+    pub fn is_read_only(&self) -> bool {
+        match &self.operation {
+            GitOperation::Status
+            | GitOperation::Diff(_)
+            | GitOperation::Log(_)
+            | GitOperation::Show(_)
+            | GitOperation::Blame(_)
+            | GitOperation::Branches(_) => true,
+            GitOperation::Stash(stash) => stash.action == StashAction::List,
+            GitOperation::Commit(_) | GitOperation::Checkout(_) => false,
+        }
+    }
+
+    fn operation_name(&self) -> &'static str {
+        match &self.operation {
+            GitOperation::Status => "status",
+            GitOperation::Diff(_) => "diff",
+            GitOperation::Log(_) => "log",
+            GitOperation::Show(_) => "show",
+            GitOperation::Blame(_) => "blame",
+            GitOperation::Branches(_) => "branches",
+            GitOperation::Commit(_) => "commit",
+            GitOperation::Checkout(_) => "checkout",
+            GitOperation::Stash(_) => "stash",
+        }
+    }
+
+    /// Paths a read-only operation reads, as given by the model: the repository and the files
+    /// the operation is limited to.
+    fn read_paths(&self, os: &Os) -> Vec<PathBuf> {
+        let cwd = os.env.current_dir().unwrap_or_default();
+        let repo = match &self.path {
+            Some(path) if path.starts_with('~') => PathBuf::from(path),
+            Some(path) => cwd.join(path),
+            None => cwd,
+        };
+        let files = match &self.operation {
+            GitOperation::Diff(GitDiff { paths, .. })
+            | GitOperation::Log(GitLog { paths, .. })
+            | GitOperation::Show(GitShow { paths, .. }) => paths.as_slice(),
+            GitOperation::Blame(blame) => std::slice::from_ref(&blame.file),
+            _ => &[],
+        };
+        let files = files.iter().map(|file| repo.join(file)).collect::<Vec<_>>();
+        std::iter::once(repo).chain(files).collect()
+    }
+
+    fn repo_path(&self, os: &Os) -> PathBuf {
+        let cwd = os.env.current_dir().unwrap_or_default();
+        match &self.path {
+            Some(path) => cwd.join(sanitize_path_tool_arg(os, path)),
+            None => cwd,
+        }
+    }
+
+    pub async fn validate(&mut self, os: &Os) -> Result<()> {
+        let revisions = match &self.operation {
+            GitOperation::Diff(diff) => vec![&diff.from, &diff.to],
+            GitOperation::Log(log) => vec![&log.revision],
+            GitOperation::Blame(blame) => vec![&blame.revision],
+            _ => vec![],
+        };
+        // Revisions that start with a dash would be taken as options.
+        for revision in revisions.into_iter().flatten() {
+            if revision.starts_with('-') {
+                bail!("Invalid revision '{revision}'");
+            }
+        }
+        match &self.operation {
+            GitOperation::Show(show) if show.revision.starts_with('-') => {
+                bail!("Invalid revision '{}'", show.revision)
+            },
+            GitOperation::Blame(blame)
+                if blame.start_line == Some(0) || blame.start_line > blame.end_line.or(blame.start_line) =>
+            {
+                bail!("Invalid line range {}", blame.line_range().unwrap_or_default())
+            },
+            GitOperation::Commit(commit) if commit.message.trim().is_empty() => {
+                bail!("The commit message must not be empty")
+            },
+            GitOperation::Checkout(checkout) if checkout.target.starts_with('-') => {
+                bail!("Invalid target '{}'", checkout.target)
+            },
+            GitOperation::Checkout(checkout) if checkout.create && !checkout.paths.is_empty() => {
+                bail!("Paths can't be restored while creating a branch")
+            },
+            _ => {},
+        }
+
+        let path = self.repo_path(os);
+        if !path.is_dir() {
+            bail!("'{}' is not a directory", path.display());
+        }
+        run_git(&path, &["rev-parse", "--git-dir"]).await?;
+        Ok(())
+    }
+
+    /// The git commands equivalent to the operation, as shown to the user.
+    fn display_command(&self) -> String {
+        let quote = |args: Vec<String>| {
+            args.iter()
+                .map(|arg| shlex::try_quote(arg).map_or_else(|_| format!("{arg:?}"), |arg| arg.into_owned()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let with_paths = |mut args: Vec<String>, paths: &[String]| {
+            if !paths.is_empty() {
+                args.push("--".to_string());
+                args.extend(paths.iter().cloned());
+            }
+            args
+        };
+        let args = match &self.operation {
+            GitOperation::Status => vec!["git".to_string(), "status".to_string()],
+            GitOperation::Diff(diff) => {
+                let mut args = vec!["git".to_string(), "diff".to_string()];
+                if diff.staged {
+                    args.push("--staged".to_string());
+                }
+                if diff.stat_only {
+                    args.push("--stat".to_string());
+                }
+                args.extend(diff.from.iter().chain(&diff.to).cloned());
+                with_paths(args, &diff.paths)
+            },
+            GitOperation::Log(log) => {
+                let mut args = vec![
+                    "git".to_string(),
+                    "log".to_string(),
+                    format!("--max-count={}", log.max_count()),
+                ];
+                args.extend(log.filters());
+                args.extend(log.revision.clone());
+                with_paths(args, &log.paths)
+            },
+            GitOperation::Show(show) => {
+                let mut args = vec!["git".to_string(), "show".to_string()];
+                if show.stat_only {
+                    args.push("--stat".to_string());
+                }
+                args.push(show.revision.clone());
+                with_paths(args, &show.paths)
+            },
+            GitOperation::Blame(blame) => {
+                let mut args = vec!["git".to_string(), "blame".to_string()];
+                if let Some(range) = blame.line_range() {
+                    args.push(format!("-L{range}"));
+                }
+                args.extend(blame.revision.clone());
+                with_paths(args, std::slice::from_ref(&blame.file))
+            },
+            GitOperation::Branches(branches) => {
+                let mut args = vec!["git".to_string(), "branch".to_string()];
+                if branches.remote {
+                    args.push("--all".to_string());
+                }
+                args
+            },
+            GitOperation::Commit(commit) => {
+                let mut command = String::new();
+                if !commit.add.is_empty() {
+                    let add = with_paths(vec!["git".to_string(), "add".to_string()], &commit.add);
+                    command = format!("{} && ", quote(add));
+                }
+                let mut args = vec!["git".to_string(), "commit".to_string()];
+                if commit.all {
+                    args.push("--all".to_string());
+                }
+                if commit.amend {
+                    args.push("--amend".to_string());
+                }
+                args.extend(["-m".to_string(), commit.message.clone()]);
+                command.push_str(&quote(args));
+                return command;
+            },
+            GitOperation::Checkout(checkout) => {
+                let mut args = vec!["git".to_string(), "checkout".to_string()];
+                if checkout.create {
+                    args.push("-b".to_string());
+                }
+                args.push(checkout.target.clone());
+                with_paths(args, &checkout.paths)
+            },
+            GitOperation::Stash(stash) => {
+                let mut args = vec!["git".to_string()];
+                args.extend(stash.args());
+                args
+            },
+        };
+        quote(args)
+    }
+
+    pub fn queue_description(&self, os: &Os, output: &mut impl Write) -> Result<()> {
+        queue!(
+            output,
+            style::Print("I will run the following git command in "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(self.repo_path(os).display()),
+            style::ResetColor,
+            style::Print(":\n\n"),
+            style::SetForegroundColor(Color::Green),
+            style::Print(self.display_command()),
+            style::ResetColor,
+            style::Print("\n"),
+        )?;
+        display_purpose(self.summary.as_ref(), output)?;
+        queue!(output, style::Print("\n"))?;
+        Ok(())
+    }
+
+    pub async fn invoke(&self, os: &Os, _updates: impl Write) -> Result<InvokeOutput> {
+        let repo = self.repo_path(os);
+        let repo = repo.as_path();
+        let result = match &self.operation {
+            GitOperation::Status => {
+                let output = run_git(repo, &["status", "--porcelain=v2", "--branch", "-z"]).await?;
+                serde_json::to_value(parse_status(&output))?
+            },
+            GitOperation::Diff(diff) => {
+                let mut args = vec!["diff".to_string()];
+                if diff.staged {
+                    args.push("--cached".to_string());
+                }
+                if let Some(context_lines) = diff.context_lines {
+                    args.push(format!("--unified={context_lines}"));
+                }
+                args.extend(diff.from.iter().chain(&diff.to).cloned());
+                serde_json::json!({
+                    "files": diff_files(repo, &args, &diff.paths, diff.stat_only).await?,
+                })
+            },
+            GitOperation::Log(log) => {
+                let mut args = vec![
+                    "log".to_string(),
+                    COMMIT_FORMAT.to_string(),
+                    format!("--max-count={}", log.max_count()),
+                ];
+                args.extend(log.filters());
+                args.extend(log.revision.clone());
+                args.push("--".to_string());
+                args.extend(log.paths.iter().cloned());
+                let output = run_git(repo, &args).await?;
+                serde_json::json!({ "commits": parse_commits(&output) })
+            },
+            GitOperation::Show(show) => {
+                let output = run_git(repo, &[
+                    "show",
+                    "--no-patch",
+                    COMMIT_FORMAT,
+                    show.revision.as_str(),
+                    "--",
+                ])
+                .await?;
+                let Some(commit) = parse_commits(&output).into_iter().next() else {
+                    bail!("No commit found for '{}'", show.revision);
+                };
+                let args = [
+                    "show",
+                    "--format=",
+                    "--diff-merges=first-parent",
+                    show.revision.as_str(),
+                ]
+                .map(str::to_string);
+                serde_json::json!({
+                    "commit": commit,
+                    "files": diff_files(repo, &args, &show.paths, show.stat_only).await?,
+                })
+            },
+            GitOperation::Blame(blame) => {
+                let mut args = vec![
+                    "blame".to_string(),
+                    "--porcelain".to_string(),
+                    "--no-textconv".to_string(),
+                ];
+                if let Some(range) = blame.line_range() {
+                    args.push(format!("-L{range}"));
+                }
+                args.extend(blame.revision.clone());
+                args.extend(["--".to_string(), blame.file.clone()]);
+                let output = run_git(repo, &args).await?;
+                serde_json::json!({ "file": blame.file, "hunks": parse_blame(&output) })
+            },
+            GitOperation::Branches(branches) => {
+                let format = [
+                    "%(refname)",
+                    "%(refname:short)",
+                    "%(HEAD)",
+                    "%(objectname:short)",
+                    "%(upstream:short)",
+                    "%(upstream:track,nobracket)",
+                    "%(committerdate:iso-strict)",
+                    "%(contents:subject)",
+                ]
+                .join("%1f");
+                let mut args = vec![
+                    "for-each-ref".to_string(),
+                    format!("--format={format}"),
+                    "refs/heads".to_string(),
+                ];
+                if branches.remote {
+                    args.push("refs/remotes".to_string());
+                }
+                let output = run_git(repo, &args).await?;
+                serde_json::json!({ "branches": parse_branches(&output) })
+            },
+            GitOperation::Commit(commit) => {
+                if !commit.add.is_empty() {
+                    let mut args = vec!["add".to_string(), "--".to_string()];
+                    args.extend(commit.add.iter().cloned());
+                    run_git(repo, &args).await?;
+                }
+                let mut args = vec!["commit".to_string()];
+                if commit.all {
+                    args.push("--all".to_string());
+                }
+                if commit.amend {
+                    args.push("--amend".to_string());
+                }
+                args.extend(["-m".to_string(), commit.message.clone()]);
+                let output = run_git(repo, &args).await?;
+                let log = run_git(repo, &["log", "--max-count=1", COMMIT_FORMAT, "HEAD", "--"]).await?;
+                serde_json::json!({
+                    "commit": parse_commits(&log).into_iter().next(),
+                    "output": output.trim_end(),
+                })
+            },
+            GitOperation::Checkout(checkout) => {
+                let mut args = vec!["checkout".to_string()];
+                if checkout.create {
+                    args.push("-b".to_string());
+                }
+                args.push(checkout.target.clone());
+                if !checkout.paths.is_empty() {
+                    args.push("--".to_string());
+                    args.extend(checkout.paths.iter().cloned());
+                }
+                let output = run_git_with_stderr(repo, &args).await?;
+                serde_json::json!({
+                    "branch": current_branch(repo),
+                    "output": output,
+                })
+            },
+            GitOperation::Stash(stash) if stash.action == StashAction::List => {
+                let output = run_git(repo, &["stash", "list", "--format=%gd%x1f%H%x1f%gs"]).await?;
+                let entries = output
+                    .lines()
+                    .filter_map(|line| {
+                        let mut fields = line.splitn(3, FIELD_SEPARATOR);
+                        Some(serde_json::json!({
+                            "name": fields.next()?,
+                            "commit": fields.next()?,
+                            "message": fields.next()?,
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+                serde_json::json!({ "entries": entries })
+            },
+            GitOperation::Stash(stash) => {
+                let output = run_git_with_stderr(repo, &stash.args()).await?;
+                serde_json::json!({ "output": output })
+            },
+        };
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(result),
+        })
+    }
+
+    pub fn eval_perm(&self, os: &Os, agent: &Agent) -> PermissionEvalResult {
+        #[derive(Debug, Default, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Settings {
+            #[serde(default)]
+            rules: Vec<GitRule>,
+        }
+
+        let is_in_allowlist = matches_any_pattern(&agent.allowed_tools, "git");
+        let settings = match agent.tools_settings.get("git") {
+            Some(settings) => match serde_json::from_value::<Settings>(settings.clone()) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    error!("Failed to deserialize tool settings for git: {:?}", e);
+                    return PermissionEvalResult::Ask;
+                },
+            },
+            None => Settings::default(),
+        };
+
+        // The branch is only looked up when a rule needs it.
+        let branch = if settings.rules.iter().any(|rule| rule.branch.is_some()) {
+            match &self.operation {
+                GitOperation::Checkout(checkout) if checkout.paths.is_empty() => Some(checkout.target.clone()),
+                _ => current_branch(&self.repo_path(os)),
+            }
+        } else {
+            None
+        };
+        let operation = self.operation_name();
+        let matching_rules = settings
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(operation, branch.as_deref()))
+            .collect::<Vec<_>>();
+        let denied_by = matching_rules
+            .iter()
+            .filter(|rule| rule.action == RuleAction::Deny)
+            .map(|rule| rule.description())
+            .collect::<Vec<_>>();
+        if !denied_by.is_empty() {
+            return PermissionEvalResult::Deny(denied_by);
+        }
+
+        // Read-only operations return the contents of the repository, so the paths they read go
+        // through the path settings of fs_read.
+        let read_perm = if self.is_read_only() {
+            let fs_read = FsRead {
+                operations: self
+                    .read_paths(os)
+                    .into_iter()
+                    .map(|path| {
+                        FsReadOperation::Line(FsLine {
+                            path: path.to_string_lossy().into_owned(),
+                            start_line: None,
+                            end_line: None,
+                        })
+                    })
+                    .collect(),
+                summary: None,
+            };
+            fs_read.eval_perm(os, agent)
+        } else {
+            PermissionEvalResult::Ask
+        };
+        if let PermissionEvalResult::Deny(denied_paths) = read_perm {
+            return PermissionEvalResult::Deny(denied_paths);
+        }
+
+        if is_in_allowlist {
+            return PermissionEvalResult::Allow;
+        }
+        match matching_rules.iter().map(|rule| rule.action).max() {
+            Some(RuleAction::Ask) => PermissionEvalResult::Ask,
+            Some(RuleAction::Allow) => PermissionEvalResult::Allow,
+            _ => read_perm,
+        }
+    }
+}
+
+impl GitLog {
+    fn max_count(&self) -> usize {
+        self.max_count.unwrap_or(DEFAULT_LOG_COUNT).min(MAX_LOG_COUNT)
+    }
+
+    fn filters(&self) -> Vec<String> {
+        [
+            ("author", &self.author),
+            ("since", &self.since),
+            ("until", &self.until),
+            ("grep", &self.grep),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| format!("--{name}={value}")))
+        .collect()
+    }
+}
+
+impl GitBlame {
+    fn line_range(&self) -> Option<String> {
+        match (self.start_line, self.end_line) {
+            (None, None) => None,
+            (start, end) => Some(format!(
+                "{},{}",
+                start.unwrap_or(1),
+                end.map(|end| end.to_string()).unwrap_or_default()
+            )),
+        }
+    }
+}
+
+impl GitStash {
+    /// Arguments of `git` that run the action.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["stash".to_string()];
+        match self.action {
+            StashAction::Push => {
+                args.push("push".to_string());
+                if self.include_untracked {
+                    args.push("--include-untracked".to_string());
+                }
+                if let Some(message) = &self.message {
+                    args.extend(["--message".to_string(), message.clone()]);
+                }
+            },
+            StashAction::Pop | StashAction::Apply | StashAction::Drop => {
+                let action = match self.action {
+                    StashAction::Pop => "pop",
+                    StashAction::Apply => "apply",
+                    _ => "drop",
+                };
+                args.push(action.to_string());
+                if let Some(index) = self.index {
+                    args.push(format!("stash@{{{index}}}"));
+                }
+            },
+            StashAction::List => args.push("list".to_string()),
+        }
+        args
+    }
+}
+
+/// Runs git in `repo` and returns its standard output, or an error with its standard error if it
+/// fails.
+async fn run_git(repo: &Path, args: &[impl AsRef<std::ffi::OsStr>]) -> Result<String> {
+    let output = git_output(repo, args).await?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Like [run_git], but also returns the standard error, where commands like `checkout` report
+/// what they did.
+async fn run_git_with_stderr(repo: &Path, args: &[impl AsRef<std::ffi::OsStr>]) -> Result<String> {
+    let output = git_output(repo, args).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(format!("{}\n{}", stdout.trim_end(), stderr.trim_end())
+        .trim()
+        .to_string())
+}
+
+async fn git_output(repo: &Path, args: &[impl AsRef<std::ffi::OsStr>]) -> Result<std::process::Output> {
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "core.quotePath=false", "-c", "color.ui=never"])
+        // Repositories can configure programs that git runs when reading the working tree, which
+        // read-only operations shouldn't start.
+        .args(["-c", "core.fsmonitor=false"])
+        .args(args)
+        // Read-only operations shouldn't take locks that other git processes could trip on.
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_EDITOR", "true")
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim_end());
+    }
+    Ok(output)
+}
+
+fn current_branch(repo: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !branch.is_empty()).then_some(branch)
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct Status {
+    /// `None` when `HEAD` is detached.
+    branch: Option<String>,
+    /// `None` before the first commit.
+    commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ahead: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    behind: Option<u32>,
+    staged: Vec<FileChange>,
+    unstaged: Vec<FileChange>,
+    untracked: Vec<String>,
+    conflicted: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct FileChange {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_path: Option<String>,
+    status: &'static str,
+}
+
+fn change_kind(code: char) -> Option<&'static str> {
+    Some(match code {
+        'M' => "modified",
+        'A' => "added",
+        'D' => "deleted",
+        'R' => "renamed",
+        'C' => "copied",
+        'T' => "type_changed",
+        _ => return None,
+    })
+}
+
+/// Parses the output of `git status --porcelain=v2 --branch -z`.
+fn parse_status(output: &str) -> Status {
+    let mut status = Status::default();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        let (kind, rest) = entry.split_once(' ').unwrap_or((entry, ""));
+        match kind {
+            "#" => {
+                let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                match key {
+                    "branch.oid" if value != "(initial)" => status.commit = Some(value.to_string()),
+                    "branch.head" if value != "(detached)" => status.branch = Some(value.to_string()),
+                    "branch.upstream" => status.upstream = Some(value.to_string()),
+                    "branch.ab" => {
+                        let mut counts = value
+                            .split(' ')
+                            .map(|count| count.trim_start_matches(['+', '-']).parse().ok());
+                        status.ahead = counts.next().flatten();
+                        status.behind = counts.next().flatten();
+                    },
+                    _ => {},
+                }
+            },
+            // `1 XY sub mH mI mW hH hI path` and `2 XY sub mH mI mW hH hI Xscore path` followed
+            // by the original path
+            "1" | "2" => {
+                let fields = if kind == "1" { 8 } else { 9 };
+                let mut parts = rest.splitn(fields, ' ');
+                let codes = parts.next().unwrap_or_default().chars().collect::<Vec<_>>();
+                let path = parts.nth(fields - 2).unwrap_or_default().to_string();
+                let original_path = (kind == "2").then(|| entries.next().unwrap_or_default().to_string());
+                for (code, changes) in codes.iter().zip([&mut status.staged, &mut status.unstaged]) {
+                    if let Some(kind) = change_kind(*code) {
+                        changes.push(FileChange {
+                            path: path.clone(),
+                            original_path: original_path.clone().filter(|_| matches!(code, 'R' | 'C')),
+                            status: kind,
+                        });
+                    }
+                }
+            },
+            // `u XY sub m1 m2 m3 mW h1 h2 h3 path`
+            "u" => status
+                .conflicted
+                .extend(rest.splitn(10, ' ').nth(9).map(str::to_string)),
+            "?" => status.untracked.push(rest.to_string()),
+            _ => {},
+        }
+    }
+    status
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct DiffFile {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_path: Option<String>,
+    /// `None` for binary files.
+    additions: Option<u32>,
+    deletions: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patch: Option<String>,
+}
+
+/// Lists the files changed by `git <args>`, a command like `diff` or `show`, with their patches
+/// unless `stat_only` is set.
+async fn diff_files(repo: &Path, args: &[String], paths: &[String], stat_only: bool) -> Result<Vec<DiffFile>> {
+    let command = |extra: &[&str]| {
+        let mut command = args.to_vec();
+        command.extend(
+            ["--no-color", "--no-ext-diff", "--no-textconv"]
+                .iter()
+                .chain(extra)
+                .map(|s| (*s).to_string()),
+        );
+        command.push("--".to_string());
+        command.extend(paths.iter().cloned());
+        command
+    };
+    let mut files = parse_numstat(&run_git(repo, &command(&["--numstat", "-z"])).await?);
+    if stat_only || files.is_empty() {
+        return Ok(files);
+    }
+
+    let patches = split_patches(&run_git(repo, &command(&["--patch"])).await?);
+    if patches.len() != files.len() {
+        error!(
+            files = files.len(),
+            patches = patches.len(),
+            "Unable to match patches with files"
+        );
+        return Ok(files);
+    }
+    // Patches are left out once the output gets too large, the files are always listed.
+    let mut remaining = MAX_TOOL_RESPONSE_SIZE / 2;
+    for (file, patch) in files.iter_mut().zip(patches) {
+        if patch.len() <= remaining {
+            remaining -= patch.len();
+            file.patch = Some(patch);
+        } else {
+            remaining = 0;
+            file.patch = Some("... patch omitted because the diff is too large".to_string());
+        }
+    }
+    Ok(files)
+}
+
+/// Parses the output of `git diff --numstat -z`.
+fn parse_numstat(output: &str) -> Vec<DiffFile> {
+    let mut files = Vec::new();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        let mut fields = entry.splitn(3, '\t');
+        let (Some(additions), Some(deletions), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        // Renames have an empty path, followed by the original and the new path.
+        let (path, original_path) = if path.is_empty() {
+            let original_path = entries.next().unwrap_or_default().to_string();
+            (entries.next().unwrap_or_default().to_string(), Some(original_path))
+        } else {
+            (path.to_string(), None)
+        };
+        files.push(DiffFile {
+            path,
+            original_path,
+            additions: additions.parse().ok(),
+            deletions: deletions.parse().ok(),
+            patch: None,
+        });
+    }
+    files
+}
+
+/// Splits the output of `git diff` into the patch of each file.
+fn split_patches(output: &str) -> Vec<String> {
+    let mut patches = Vec::<String>::new();
+    for line in output.split_inclusive('\n') {
+        if line.starts_with("diff --git ") || line.starts_with("diff --cc ") || line.starts_with("diff --combined ") {
+            patches.push(String::new());
+        }
+        if let Some(patch) = patches.last_mut() {
+            patch.push_str(line);
+        }
+    }
+    patches
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Commit {
+    hash: String,
+    parents: Vec<String>,
+    author: String,
+    email: String,
+    date: String,
+    subject: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    body: String,
+}
+
+/// Parses the output of `git log` with [COMMIT_FORMAT].
+fn parse_commits(output: &str) -> Vec<Commit> {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter_map(|record| {
+            let mut fields = record.trim_start_matches('\n').splitn(7, FIELD_SEPARATOR);
+            Some(Commit {
+                hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+                parents: fields.next()?.split_whitespace().map(str::to_string).collect(),
+                author: fields.next()?.to_string(),
+                email: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+                body: fields.next()?.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BlameHunk {
+    commit: String,
+    author: String,
+    date: String,
+    summary: String,
+    start_line: usize,
+    lines: Vec<String>,
+}
+
+/// Parses the output of `git blame --porcelain` into runs of lines from the same commit.
+fn parse_blame(output: &str) -> Vec<BlameHunk> {
+    let mut hunks = Vec::<BlameHunk>::new();
+    // Details are only given the first time a commit appears.
+    let mut commits = HashMap::<String, HashMap<String, String>>::new();
+    let mut current: Option<(String, usize)> = None;
+    for line in output.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            let Some((commit, line_number)) = current.take() else {
+                continue;
+            };
+            match hunks.last_mut() {
+                Some(hunk) if hunk.commit == commit && hunk.start_line + hunk.lines.len() == line_number => {
+                    hunk.lines.push(content.to_string());
+                },
+                _ => {
+                    let details = commits.get(&commit);
+                    let detail = |key: &str| {
+                        details
+                            .and_then(|details| details.get(key))
+                            .cloned()
+                            .unwrap_or_default()
+                    };
+                    let date = detail("author-time")
+                        .parse()
+                        .ok()
+                        .and_then(|time| chrono::DateTime::from_timestamp(time, 0))
+                        .map(|date| date.to_rfc3339())
+                        .unwrap_or_default();
+                    hunks.push(BlameHunk {
+                        commit: commit.clone(),
+                        author: detail("author"),
+                        date,
+                        summary: detail("summary"),
+                        start_line: line_number,
+                        lines: vec![content.to_string()],
+                    });
+                },
+            }
+            continue;
+        }
+
+        let mut fields = line.split(' ');
+        let first = fields.next().unwrap_or_default();
+        if matches!(first.len(), 40 | 64) && first.chars().all(|c| c.is_ascii_hexdigit()) {
+            if let Some(line_number) = fields.nth(1).and_then(|line_number| line_number.parse().ok()) {
+                commits.entry(first.to_string()).or_default();
+                current = Some((first.to_string(), line_number));
+                continue;
+            }
+        }
+        if let Some((commit, _)) = &current {
+            if let Some((key, value)) = line.split_once(' ') {
+                if let Some(details) = commits.get_mut(commit) {
+                    details.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+    }
+    hunks
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Branch {
+    name: String,
+    remote: bool,
+    current: bool,
+    commit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ahead: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    behind: Option<u32>,
+    /// The upstream branch was deleted.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    upstream_gone: bool,
+    date: String,
+    subject: String,
+}
+
+/// Parses the output of `git for-each-ref` with the format used by the `branches` operation.
+fn parse_branches(output: &str) -> Vec<Branch> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields = line.splitn(8, FIELD_SEPARATOR).collect::<Vec<_>>();
+            let [refname, name, head, commit, upstream, track, date, subject] = fields[..] else {
+                return None;
+            };
+            // `origin/HEAD` only points to another remote branch.
+            if refname.starts_with("refs/remotes/") && refname.ends_with("/HEAD") {
+                return None;
+            }
+            let track_count = |label: &str| {
+                track
+                    .split(", ")
+                    .find_map(|part| part.strip_prefix(label))
+                    .and_then(|count| count.trim().parse().ok())
+            };
+            Some(Branch {
+                name: name.to_string(),
+                remote: refname.starts_with("refs/remotes/"),
+                current: head == "*",
+                commit: commit.to_string(),
+                upstream: (!upstream.is_empty()).then(|| upstream.to_string()),
+                ahead: track_count("ahead "),
+                behind: track_count("behind "),
+                upstream_gone: track == "gone",
+                date: date.to_string(),
+                subject: subject.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::agent::ToolSettingTarget;
+
+    macro_rules! git {
+        ($value:tt) => {
+            serde_json::from_value::<Git>(serde_json::json!($value)).unwrap()
+        };
+    }
+
+    async fn setup_repo(os: &Os) -> PathBuf {
+        os.fs.create_dir_all("/repo").await.unwrap();
+        let repo = sanitize_path_tool_arg(os, "/repo");
+        for args in [
+            &["init", "--quiet", "--initial-branch=main"][..],
+            &["config", "user.name", "Test"],
+            &["config", "user.email", "test@example.com"],
+        ] {
+            run_git(&repo, args).await.unwrap();
+        }
+        os.fs.write("/repo/a.txt", "one\ntwo\nthree\n").await.unwrap();
+        run_git(&repo, &["add", "a.txt"]).await.unwrap();
+        run_git(&repo, &["commit", "--quiet", "-m", "Add a.txt", "-m", "With a body"])
+            .await
+            .unwrap();
+        repo
+    }
+
+    async fn invoke(os: &Os, mut git: Git) -> serde_json::Value {
+        git.validate(os).await.unwrap();
+        let OutputKind::Json(json) = git.invoke(os, std::io::sink()).await.unwrap().output else {
+            panic!("Expected JSON output");
+        };
+        json
+    }
+
+    #[tokio::test]
+    async fn test_git_operations() {
+        let os = Os::new().await.unwrap();
+        setup_repo(&os).await;
+        os.fs.write("/repo/a.txt", "one\n2\nthree\n").await.unwrap();
+        os.fs.write("/repo/new file.txt", "new\n").await.unwrap();
+
+        let status = invoke(&os, git!({ "path": "/repo", "operation": "status" })).await;
+        assert_eq!(status["branch"], "main");
+        assert_eq!(
+            status["unstaged"],
+            serde_json::json!([{ "path": "a.txt", "status": "modified" }])
+        );
+        assert_eq!(status["untracked"], serde_json::json!(["new file.txt"]));
+
+        let diff = invoke(&os, git!({ "path": "/repo", "operation": "diff" })).await;
+        let file = &diff["files"][0];
+        assert_eq!(file["path"], "a.txt");
+        assert_eq!(
+            (file["additions"].as_u64(), file["deletions"].as_u64()),
+            (Some(1), Some(1))
+        );
+        assert!(file["patch"].as_str().unwrap().contains("-two\n+2\n"));
+
+        let commit = git!({ "path": "/repo", "operation": "commit", "message": "Change a.txt", "add": ["a.txt", "new file.txt"] });
+        assert!(!commit.is_read_only());
+        let commit = invoke(&os, commit).await;
+        assert_eq!(commit["commit"]["subject"], "Change a.txt");
+
+        let log = invoke(&os, git!({ "path": "/repo", "operation": "log", "max_count": 5 })).await;
+        let subjects = log["commits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|commit| commit["subject"].clone());
+        assert_eq!(subjects.collect::<Vec<_>>(), ["Change a.txt", "Add a.txt"]);
+        assert_eq!(log["commits"][1]["body"], "With a body");
+
+        let show = invoke(
+            &os,
+            git!({ "path": "/repo", "operation": "show", "revision": "HEAD", "stat_only": true }),
+        )
+        .await;
+        let paths = show["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["path"].clone());
+        assert_eq!(paths.collect::<Vec<_>>(), ["a.txt", "new file.txt"]);
+        assert!(show["files"][0].get("patch").is_none());
+
+        let blame = invoke(
+            &os,
+            git!({ "path": "/repo", "operation": "blame", "file": "a.txt", "start_line": 2, "end_line": 3 }),
+        )
+        .await;
+        assert_eq!(blame["hunks"][0]["summary"], "Change a.txt");
+        assert_eq!(blame["hunks"][0]["lines"], serde_json::json!(["2"]));
+        assert_eq!(blame["hunks"][1]["start_line"], 3);
+        assert_eq!(blame["hunks"][1]["author"], "Test");
+
+        invoke(
+            &os,
+            git!({ "path": "/repo", "operation": "checkout", "target": "feature", "create": true }),
+        )
+        .await;
+        let branches = invoke(&os, git!({ "path": "/repo", "operation": "branches" })).await;
+        let branches = branches["branches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|branch| (branch["name"].as_str().unwrap(), branch["current"].as_bool().unwrap()));
+        assert_eq!(branches.collect::<Vec<_>>(), [("feature", true), ("main", false)]);
+
+        os.fs.write("/repo/a.txt", "stashed\n").await.unwrap();
+        invoke(
+            &os,
+            git!({ "path": "/repo", "operation": "stash", "action": "push", "message": "wip" }),
+        )
+        .await;
+        let stashes = invoke(&os, git!({ "path": "/repo", "operation": "stash", "action": "list" })).await;
+        assert_eq!(stashes["entries"][0]["name"], "stash@{0}");
+        assert!(stashes["entries"][0]["message"].as_str().unwrap().ends_with("wip"));
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let os = Os::new().await.unwrap();
+        setup_repo(&os).await;
+        for value in [
+            serde_json::json!({ "path": "/repo", "operation": "diff", "from": "--output=x" }),
+            serde_json::json!({ "path": "/repo", "operation": "commit", "message": " " }),
+            serde_json::json!({ "path": "/repo", "operation": "blame", "file": "a.txt", "start_line": 3, "end_line": 2 }),
+            serde_json::json!({ "path": "/missing", "operation": "status" }),
+        ] {
+            let mut git = serde_json::from_value::<Git>(value.clone()).unwrap();
+            assert!(git.validate(&os).await.is_err(), "{value}");
+        }
+        os.fs.create_dir_all("/not_a_repo").await.unwrap();
+        assert!(
+            git!({ "path": "/not_a_repo", "operation": "status" })
+                .validate(&os)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eval_perm() {
+        let os = Os::new().await.unwrap();
+        setup_repo(&os).await;
+
+        let status = git!({ "path": "/repo", "operation": "status" });
+        let stash_list = git!({ "path": "/repo", "operation": "stash", "action": "list" });
+        let commit = git!({ "path": "/repo", "operation": "commit", "message": "x" });
+        let checkout_main = git!({ "path": "/repo", "operation": "checkout", "target": "main" });
+        let checkout_feature = git!({ "path": "/repo", "operation": "checkout", "target": "feature/x" });
+
+        let mut agent = Agent::default();
+        assert_eq!(status.eval_perm(&os, &agent), PermissionEvalResult::Allow);
+        assert_eq!(stash_list.eval_perm(&os, &agent), PermissionEvalResult::Allow);
+        assert_eq!(commit.eval_perm(&os, &agent), PermissionEvalResult::Ask);
+
+        agent.tools_settings.insert(
+            ToolSettingTarget("git".to_string()),
+            serde_json::json!({
+                "rules": [
+                    { "operation": "checkout", "branch": "feature/*", "action": "allow" },
+                    { "operation": "commit", "branch": "main", "action": "deny", "reason": "Changes to main go through pull requests" },
+                    { "operation": "blame", "action": "ask" }
+                ]
+            }),
+        );
+        assert_eq!(checkout_feature.eval_perm(&os, &agent), PermissionEvalResult::Allow);
+        assert_eq!(checkout_main.eval_perm(&os, &agent), PermissionEvalResult::Ask);
+        assert_eq!(
+            git!({ "path": "/repo", "operation": "blame", "file": "a.txt" }).eval_perm(&os, &agent),
+            PermissionEvalResult::Ask
+        );
+        assert_eq!(
+            commit.eval_perm(&os, &agent),
+            PermissionEvalResult::Deny(vec![
+                "commit branch=main: Changes to main go through pull requests".to_string()
+            ])
+        );
+
+        // Trusting the tool allows everything that isn't denied.
+        agent.allowed_tools.insert("git".to_string());
+        assert_eq!(checkout_main.eval_perm(&os, &agent), PermissionEvalResult::Allow);
+        assert!(matches!(commit.eval_perm(&os, &agent), PermissionEvalResult::Deny(_)));
+    }
+
+    #[tokio::test]
+    async fn test_eval_perm_read_paths() {
+        let os = Os::new().await.unwrap();
+        setup_repo(&os).await;
+        os.env.set_current_dir_for_test(PathBuf::from("/repo"));
+
+        let mut agent = Agent::default();
+        agent.tools_settings.insert(
+            ToolSettingTarget("fs_read".to_string()),
+            serde_json::json!({ "deniedPaths": ["/repo/secrets"] }),
+        );
+        assert_eq!(
+            git!({ "operation": "status" }).eval_perm(&os, &agent),
+            PermissionEvalResult::Allow
+        );
+        assert_eq!(
+            git!({ "operation": "blame", "file": "secrets/key.txt" }).eval_perm(&os, &agent),
+            PermissionEvalResult::Deny(vec!["/repo/secrets".to_string()])
+        );
+        assert!(matches!(
+            git!({ "operation": "show", "revision": "HEAD", "paths": ["a.txt", "secrets"] }).eval_perm(&os, &agent),
+            PermissionEvalResult::Deny(_)
+        ));
+
+        // Repositories outside the current directory are read after confirmation, unless fs_read
+        // is allowed to read them.
+        let other_repo = git!({ "path": "/other", "operation": "log" });
+        assert_eq!(other_repo.eval_perm(&os, &agent), PermissionEvalResult::Ask);
+        agent.tools_settings.insert(
+            ToolSettingTarget("fs_read".to_string()),
+            serde_json::json!({ "allowedPaths": ["/other"] }),
+        );
+        assert_eq!(other_repo.eval_perm(&os, &agent), PermissionEvalResult::Allow);
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = [
+            "# branch.oid 1234",
+            "# branch.head main",
+            "# branch.upstream origin/main",
+            "# branch.ab +2 -1",
+            "1 M. N... 100644 100644 100644 aaaa bbbb staged.rs",
+            "1 .D N... 100644 100644 000000 aaaa aaaa gone.rs",
+            "2 R. N... 100644 100644 100644 aaaa aaaa R100 new name.rs",
+            "old name.rs",
+            "u UU N... 100644 100644 100644 100644 aaaa bbbb cccc conflict.rs",
+            "? untracked.rs",
+            "",
+        ]
+        .join("\0");
+        let status = parse_status(&output);
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert_eq!((status.ahead, status.behind), (Some(2), Some(1)));
+        assert_eq!(status.staged, [
+            FileChange {
+                path: "staged.rs".to_string(),
+                original_path: None,
+                status: "modified"
+            },
+            FileChange {
+                path: "new name.rs".to_string(),
+                original_path: Some("old name.rs".to_string()),
+                status: "renamed"
+            },
+        ]);
+        assert_eq!(status.unstaged, [FileChange {
+            path: "gone.rs".to_string(),
+            original_path: None,
+            status: "deleted"
+        }]);
+        assert_eq!(status.conflicted, ["conflict.rs"]);
+        assert_eq!(status.untracked, ["untracked.rs"]);
+    }
+
+    #[test]
+    fn test_parse_numstat_and_branches() {
+        let output = ["1\t2\ta.rs", "-\t-\timage.png", "3\t0\t", "old.rs", "new.rs", ""].join("\0");
+        let files = parse_numstat(&output);
+        let files = files
+            .iter()
+            .map(|file| (file.path.as_str(), file.original_path.as_deref(), file.additions))
+            .collect::<Vec<_>>();
+        assert_eq!(files, [
+            ("a.rs", None, Some(1)),
+            ("image.png", None, None),
+            ("new.rs", Some("old.rs"), Some(3))
+        ]);
+
+        let output = [
+            [
+                "refs/heads/main",
+                "main",
+                "*",
+                "abc",
+                "origin/main",
+                "ahead 1, behind 2",
+                "date",
+                "Subject",
+            ]
+            .join("\x1f"),
+            ["refs/heads/old", "old", " ", "def", "origin/old", "gone", "date", "Old"].join("\x1f"),
+            [
+                "refs/remotes/origin/HEAD",
+                "origin",
+                " ",
+                "abc",
+                "",
+                "",
+                "date",
+                "Subject",
+            ]
+            .join("\x1f"),
+        ]
+        .join("\n");
+        let branches = parse_branches(&output);
+        assert_eq!(branches.len(), 2);
+        assert_eq!(
+            (branches[0].current, branches[0].ahead, branches[0].behind),
+            (true, Some(1), Some(2))
+        );
+        assert!(branches[1].upstream_gone);
+    }
+}
@@ -1,8 +1,10 @@
 pub mod custom_tool;
+pub mod delegate;
 pub mod execute;
 pub mod fs_read;
 pub mod fs_write;
 pub mod gh_issue;
+pub mod git;
 pub mod introspect;
 pub mod knowledge;
 pub mod thinking;
@@ -26,11 +28,16 @@ use crossterm::style::{
     Color,
 };
 use custom_tool::CustomTool;
+use delegate::Delegate;
 use execute::ExecuteCommand;
-use eyre::Result;
+use eyre::{
+    Result,
+    bail,
+};
 use fs_read::FsRead;
 use fs_write::FsWrite;
 use gh_issue::GhIssue;
+use git::Git;
 use introspect::Introspect;
 use knowledge::Knowledge;
 use serde::{
@@ -58,7 +65,7 @@ use crate::cli::chat::line_tracker::FileLineTracker;
 use crate::os::Os;
 
 pub const DEFAULT_APPROVE: [&str; 0] = [];
-pub const NATIVE_TOOLS: [&str; 8] = [
+pub const NATIVE_TOOLS: [&str; 10] = [
     "fs_read",
     "fs_write",
     #[cfg(windows)]
@@ -66,6 +73,8 @@ pub const NATIVE_TOOLS: [&str; 8] = [
     #[cfg(not(windows))]
     "execute_bash",
     "use_aws",
+    "git",
+    "delegate",
     "gh_issue",
     "knowledge",
     "thinking",
@@ -80,6 +89,8 @@ pub enum Tool {
     FsWrite(FsWrite),
     ExecuteCommand(ExecuteCommand),
     UseAws(UseAws),
+    Git(Git),
+    Delegate(Delegate),
     Custom(CustomTool),
     GhIssue(GhIssue),
     Introspect(Introspect),
@@ -99,6 +110,8 @@ impl Tool {
             #[cfg(not(windows))]
             Tool::ExecuteCommand(_) => "execute_bash",
             Tool::UseAws(_) => "use_aws",
+            Tool::Git(_) => "git",
+            Tool::Delegate(_) => "delegate",
             Tool::Custom(custom_tool) => &custom_tool.name,
             Tool::GhIssue(_) => "gh_issue",
             Tool::Introspect(_) => "introspect",
@@ -116,6 +129,8 @@ impl Tool {
             Tool::FsWrite(fs_write) => fs_write.eval_perm(os, agent),
             Tool::ExecuteCommand(execute_command) => execute_command.eval_perm(os, agent),
             Tool::UseAws(use_aws) => use_aws.eval_perm(os, agent),
+            Tool::Git(git) => git.eval_perm(os, agent),
+            Tool::Delegate(delegate) => delegate.eval_perm(agent),
             Tool::Custom(custom_tool) => custom_tool.eval_perm(os, agent),
             Tool::GhIssue(_) => PermissionEvalResult::Allow,
             Tool::Introspect(_) => PermissionEvalResult::Allow,
@@ -125,6 +140,21 @@ impl Tool {
         }
     }
 
+    /// Whether the tool is declared to have no side effects, in which case it can be executed
+    /// concurrently with other read-only tools.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Tool::FsRead(_) => true,
+            Tool::Git(git) => git.is_read_only(),
+            Tool::Knowledge(knowledge) => {
+                matches!(knowledge, Knowledge::Search(_) | Knowledge::Show | Knowledge::Status)
+            },
+            Tool::ExecuteCommand(execute_command) => execute_command.is_read_only(),
+            Tool::Custom(custom_tool) => custom_tool.read_only,
+            _ => false,
+        }
+    }
+
     /// Invokes the tool asynchronously
     pub async fn invoke(
         &self,
@@ -136,8 +166,11 @@ impl Tool {
         match self {
             Tool::FsRead(fs_read) => fs_read.invoke(os, stdout).await,
             Tool::FsWrite(fs_write) => fs_write.invoke(os, stdout, line_tracker).await,
-            Tool::ExecuteCommand(execute_command) => execute_command.invoke(os, stdout).await,
+            Tool::ExecuteCommand(execute_command) => execute_command.invoke(os, stdout, agent).await,
             Tool::UseAws(use_aws) => use_aws.invoke(os, stdout).await,
+            Tool::Git(git) => git.invoke(os, stdout).await,
+            // Sub-agents need the chat session to run, which handles this tool itself.
+            Tool::Delegate(_) => bail!("delegate can only be run from a chat session"),
             Tool::Custom(custom_tool) => custom_tool.invoke(os, stdout).await,
             Tool::GhIssue(gh_issue) => gh_issue.invoke(os, stdout).await,
             Tool::Introspect(introspect) => introspect.invoke(os, stdout).await,
@@ -154,6 +187,8 @@ impl Tool {
             Tool::FsWrite(fs_write) => fs_write.queue_description(os, output),
             Tool::ExecuteCommand(execute_command) => execute_command.queue_description(output),
             Tool::UseAws(use_aws) => use_aws.queue_description(output),
+            Tool::Git(git) => git.queue_description(os, output),
+            Tool::Delegate(delegate) => delegate.queue_description(output),
             Tool::Custom(custom_tool) => custom_tool.queue_description(output),
             Tool::GhIssue(gh_issue) => gh_issue.queue_description(output),
             Tool::Introspect(_) => Introspect::queue_description(output),
@@ -170,6 +205,8 @@ impl Tool {
             Tool::FsWrite(fs_write) => fs_write.validate(os).await,
             Tool::ExecuteCommand(execute_command) => execute_command.validate(os).await,
             Tool::UseAws(use_aws) => use_aws.validate(os).await,
+            Tool::Git(git) => git.validate(os).await,
+            Tool::Delegate(delegate) => delegate.validate().await,
             Tool::Custom(custom_tool) => custom_tool.validate(os).await,
             Tool::GhIssue(gh_issue) => gh_issue.validate(os).await,
             Tool::Introspect(introspect) => introspect.validate(os).await,
@@ -199,6 +236,10 @@ pub struct ToolSpec {
     pub input_schema: InputSchema,
     #[serde(skip_serializing, default = "tool_origin")]
     pub tool_origin: ToolOrigin,
+    /// Whether the tool declares that it does not modify its environment, from the
+    /// `readOnlyHint` annotation of MCP tools.
+    #[serde(skip_serializing, default)]
+    pub read_only: bool,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -270,6 +311,8 @@ pub struct QueuedTool {
     pub id: String,
     pub name: String,
     pub accepted: bool,
+    /// Whether the tool was accepted by the agent's permissions rather than by the user.
+    pub auto_approved: bool,
     pub tool: Tool,
     pub tool_input: serde_json::Value,
 }
@@ -367,7 +410,7 @@ fn absolute_to_relative(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> Result
 }
 
 /// Small helper for formatting the path as a relative path, if able.
-fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
+pub fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
     absolute_to_relative(cwd, path.as_ref())
         .map(|p| p.to_string_lossy().to_string())
         // If we have three consecutive ".." then it should probably just stay as an absolute path.
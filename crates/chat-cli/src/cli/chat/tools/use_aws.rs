@@ -29,10 +29,125 @@ use crate::cli::agent::{
     PermissionEvalResult,
 };
 use crate::os::Os;
-use crate::util::pattern_matching::matches_any_pattern;
+use crate::util::pattern_matching::{
+    matches_any_pattern,
+    matches_pattern,
+};
 
 const READONLY_OPS: [&str; 6] = ["get", "describe", "list", "ls", "search", "batch_get"];
 
+/// A rule in the `rules` of `use_aws`. Every field accepts globs, and fields that are left out
+/// match any call.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AwsRule {
+    /// Service name, e.g. `s3`.
+    service: Option<String>,
+    /// Operation name, e.g. `put-object`.
+    operation: Option<String>,
+    region: Option<String>,
+    /// Profile the call is made with. Calls without a profile use `AWS_PROFILE`, or `default`.
+    profile: Option<String>,
+    /// Parameters that must be passed with a matching value, e.g. `{ "bucket": "team-scratch-*" }`.
+    #[serde(default)]
+    parameters: HashMap<String, Patterns>,
+    action: RuleAction,
+    /// Explanation shown to the user when the rule denies a call.
+    reason: Option<String>,
+}
+
+/// What happens to calls matching a rule. When several rules match, the strictest wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RuleAction {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// One pattern, or a list of patterns of which any can match.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Patterns {
+    One(String),
+    Any(Vec<String>),
+}
+
+impl Patterns {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            Patterns::One(pattern) => std::slice::from_ref(pattern),
+            Patterns::Any(patterns) => patterns,
+        }
+    }
+}
+
+impl AwsRule {
+    fn matches(&self, call: &UseAws, profile: &str) -> bool {
+        let field_matches = |pattern: &Option<String>, text: &str| {
+            pattern.as_deref().is_none_or(|pattern| matches_pattern(pattern, text))
+        };
+        if !(field_matches(&self.service, &call.service_name)
+            && field_matches(&self.operation, &call.operation_name)
+            && field_matches(&self.region, &call.region)
+            && field_matches(&self.profile, profile))
+        {
+            return false;
+        }
+
+        // Several parameters can be passed as the same flag, e.g. `Bucket` and `bucket`. Allow rules
+        // must match all of them since the CLI may use any, other rules match if any does.
+        let parameters = call.cli_parameters().unwrap_or_default();
+        self.parameters.iter().all(|(name, patterns)| {
+            let name = cli_flag(name);
+            let mut values = parameters
+                .iter()
+                .filter(|(param_name, _)| *param_name == name)
+                .map(|(_, value)| {
+                    patterns
+                        .as_slice()
+                        .iter()
+                        .any(|pattern| matches_pattern(pattern, value))
+                })
+                .peekable();
+            match self.action {
+                _ if values.peek().is_none() => false,
+                RuleAction::Allow => values.all(|matches| matches),
+                RuleAction::Ask | RuleAction::Deny => values.any(|matches| matches),
+            }
+        })
+    }
+
+    /// The rule as shown to the user, e.g. `s3 put-object --bucket=prod-*: Production buckets
+    /// are managed by the pipeline`.
+    fn description(&self) -> String {
+        let mut description = format!(
+            "{} {}",
+            self.service.as_deref().unwrap_or("*"),
+            self.operation.as_deref().unwrap_or("*")
+        );
+        for (name, value) in [("region", &self.region), ("profile", &self.profile)] {
+            if let Some(value) = value {
+                description.push_str(&format!(" {name}={value}"));
+            }
+        }
+        let mut parameters = self.parameters.iter().collect::<Vec<_>>();
+        parameters.sort_by_key(|(name, _)| name.as_str());
+        for (name, patterns) in parameters {
+            description.push_str(&format!(" {}={}", cli_flag(name), patterns.as_slice().join("|")));
+        }
+        match &self.reason {
+            Some(reason) => format!("{description}: {reason}"),
+            None => description,
+        }
+    }
+}
+
+/// Formats a parameter name as a CLI flag, e.g. `TableName` as `--table-name`.
+fn cli_flag(name: &str) -> String {
+    format!("--{}", name.trim_start_matches("--").to_case(Case::Kebab))
+}
+
 // TODO: we should perhaps composite this struct with an interface that we can use to mock the
 // actual cli with. That will allow us to more thoroughly test it.
 #[derive(Debug, Clone, Deserialize)]
@@ -56,19 +171,7 @@ impl UseAws {
         // Set up environment variables with user agent metadata for CloudTrail tracking
         let env_vars = env_vars_with_user_agent(os);
 
-        command.envs(env_vars).arg("--region").arg(&self.region);
-        if let Some(profile_name) = self.profile_name.as_deref() {
-            command.arg("--profile").arg(profile_name);
-        }
-        command.arg(&self.service_name).arg(&self.operation_name);
-        if let Some(parameters) = self.cli_parameters() {
-            for (name, val) in parameters {
-                command.arg(name);
-                if !val.is_empty() {
-                    command.arg(val);
-                }
-            }
-        }
+        command.envs(env_vars).args(self.cli_args());
         let output = command
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -118,6 +221,10 @@ impl UseAws {
         queue!(
             output,
             style::Print("Running aws cli command:\n\n"),
+            style::SetForegroundColor(style::Color::Green),
+            style::Print(self.command_line()),
+            style::ResetColor,
+            style::Print("\n\n"),
             style::Print(format!("Service name: {}\n", self.service_name)),
             style::Print(format!("Operation name: {}\n", self.operation_name)),
         )?;
@@ -158,23 +265,61 @@ impl UseAws {
         })
     }
 
-    /// Returns the CLI arguments properly formatted as kebab case if parameters is
-    /// [Option::Some], otherwise None
+    /// Returns the CLI arguments properly formatted as kebab case and sorted by name if parameters
+    /// is [Option::Some], otherwise None
     fn cli_parameters(&self) -> Option<Vec<(String, String)>> {
         if let Some(parameters) = &self.parameters {
             let mut params = vec![];
             for (param_name, val) in parameters {
-                let param_name = format!("--{}", param_name.trim_start_matches("--").to_case(Case::Kebab));
+                let param_name = cli_flag(param_name);
                 let param_val = val.as_str().map(|s| s.to_string()).unwrap_or(val.to_string());
                 params.push((param_name, param_val));
             }
+            params.sort();
             Some(params)
         } else {
             None
         }
     }
 
-    pub fn eval_perm(&self, _os: &Os, agent: &Agent) -> PermissionEvalResult {
+    /// Returns the arguments `aws` is invoked with
+    fn cli_args(&self) -> Vec<String> {
+        let mut args = vec!["--region".to_string(), self.region.clone()];
+        if let Some(profile_name) = &self.profile_name {
+            args.extend(["--profile".to_string(), profile_name.clone()]);
+        }
+        args.extend([self.service_name.clone(), self.operation_name.clone()]);
+        for (name, val) in self.cli_parameters().unwrap_or_default() {
+            args.push(name);
+            if !val.is_empty() {
+                args.push(val);
+            }
+        }
+        args
+    }
+
+    /// Returns the `aws` invocation as it could be typed in a shell
+    fn command_line(&self) -> String {
+        let mut command_line = "aws".to_string();
+        for arg in self.cli_args() {
+            command_line.push(' ');
+            match shlex::try_quote(&arg) {
+                Ok(arg) => command_line.push_str(&arg),
+                Err(_) => command_line.push_str(&format!("{arg:?}")),
+            }
+        }
+        command_line
+    }
+
+    /// Returns the profile the call is made with
+    fn profile(&self, os: &Os) -> String {
+        self.profile_name
+            .clone()
+            .or_else(|| os.env.get("AWS_PROFILE").ok())
+            .unwrap_or_else(|| "default".to_string())
+    }
+
+    pub fn eval_perm(&self, os: &Os, agent: &Agent) -> PermissionEvalResult {
         #[derive(Debug, Deserialize)]
         #[serde(rename_all = "camelCase")]
         struct Settings {
@@ -184,6 +329,8 @@ impl UseAws {
             denied_services: Vec<String>,
             #[serde(default)]
             auto_allow_readonly: bool,
+            #[serde(default)]
+            rules: Vec<AwsRule>,
         }
 
         let Self { service_name, .. } = self;
@@ -200,7 +347,29 @@ impl UseAws {
                 if settings.denied_services.contains(service_name) {
                     return PermissionEvalResult::Deny(vec![service_name.clone()]);
                 }
-                if is_in_allowlist || settings.allowed_services.contains(service_name) {
+                let profile = self.profile(os);
+                let matching_rules = settings
+                    .rules
+                    .iter()
+                    .filter(|rule| rule.matches(self, &profile))
+                    .collect::<Vec<_>>();
+                let denied_by = matching_rules
+                    .iter()
+                    .filter(|rule| rule.action == RuleAction::Deny)
+                    .map(|rule| rule.description())
+                    .collect::<Vec<_>>();
+                if !denied_by.is_empty() {
+                    return PermissionEvalResult::Deny(denied_by);
+                }
+                if is_in_allowlist {
+                    return PermissionEvalResult::Allow;
+                }
+                match matching_rules.iter().map(|rule| rule.action).max() {
+                    Some(RuleAction::Ask) => return PermissionEvalResult::Ask,
+                    Some(RuleAction::Allow) => return PermissionEvalResult::Allow,
+                    _ => {},
+                }
+                if settings.allowed_services.contains(service_name) {
                     return PermissionEvalResult::Allow;
                 }
                 // Check auto_allow_readonly setting for read-only operations
@@ -285,6 +454,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_command_line() {
+        let cmd = use_aws! {{
+            "service_name": "s3api",
+            "operation_name": "put-object",
+            "parameters": {
+                "key": "notes/today's.txt",
+                "Bucket": "team-scratch-1",
+                "no-paginate": ""
+            },
+            "region": "us-west-2",
+            "profile_name": "dev",
+            "label": ""
+        }};
+        assert_eq!(
+            cmd.command_line(),
+            r#"aws --region us-west-2 --profile dev s3api put-object --bucket team-scratch-1 --key "notes/today's.txt" --no-paginate"#
+        );
+
+        let mut output = Vec::new();
+        cmd.queue_description(&mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains(&cmd.command_line()));
+    }
+
     #[tokio::test]
     #[ignore = "not in ci"]
     async fn test_aws_read_only() {
@@ -505,4 +698,107 @@ mod tests {
         // Should deny even read-only operations on denied services
         assert!(matches!(res, PermissionEvalResult::Deny(ref services) if services.contains(&"s3".to_string())));
     }
+
+    #[tokio::test]
+    async fn test_eval_perm_rules() {
+        let os = Os::new().await.unwrap();
+        let mut agent = Agent {
+            name: "test_agent".to_string(),
+            tools_settings: {
+                let mut map = HashMap::<ToolSettingTarget, serde_json::Value>::new();
+                map.insert(
+                    ToolSettingTarget("use_aws".to_string()),
+                    serde_json::json!({
+                        "allowedServices": ["lambda"],
+                        "autoAllowReadonly": true,
+                        "rules": [
+                            {
+                                "service": "s3api",
+                                "operation": "put-object",
+                                "parameters": { "bucket": ["team-scratch-*", "sandbox"] },
+                                "action": "allow"
+                            },
+                            {
+                                "region": "us-east-1",
+                                "profile": "prod*",
+                                "action": "deny",
+                                "reason": "Production is changed through the pipeline"
+                            },
+                            { "service": "lambda", "operation": "delete-*", "action": "ask" }
+                        ]
+                    }),
+                );
+                map
+            },
+            ..Default::default()
+        };
+
+        let call = |service: &str, operation: &str, bucket: Option<&str>, region: &str, profile: Option<&str>| {
+            let parameters = bucket.map(|bucket| serde_json::json!({ "Bucket": bucket, "Key": "a.txt" }));
+            use_aws! {{
+                "service_name": service,
+                "operation_name": operation,
+                "parameters": parameters,
+                "region": region,
+                "profile_name": profile,
+                "label": ""
+            }}
+        };
+
+        for (cmd, expected) in [
+            (
+                call("s3api", "put-object", Some("team-scratch-42"), "us-west-2", None),
+                PermissionEvalResult::Allow,
+            ),
+            (
+                call("s3api", "put-object", Some("sandbox"), "us-west-2", Some("dev")),
+                PermissionEvalResult::Allow,
+            ),
+            (
+                call("s3api", "put-object", Some("team-data"), "us-west-2", None),
+                PermissionEvalResult::Ask,
+            ),
+            (
+                call("s3api", "put-object", None, "us-west-2", None),
+                PermissionEvalResult::Ask,
+            ),
+            (
+                call("lambda", "invoke", None, "us-west-2", None),
+                PermissionEvalResult::Allow,
+            ),
+            (
+                call("lambda", "delete-function", None, "us-west-2", None),
+                PermissionEvalResult::Ask,
+            ),
+            (
+                call("s3api", "put-object", Some("team-scratch-42"), "us-east-1", None),
+                PermissionEvalResult::Allow,
+            ),
+            (
+                call("s3api", "list-buckets", None, "us-east-1", Some("prod-admin")),
+                PermissionEvalResult::Deny(vec![
+                    "* * region=us-east-1 profile=prod*: Production is changed through the pipeline".to_string(),
+                ]),
+            ),
+        ] {
+            assert_eq!(cmd.eval_perm(&os, &agent), expected, "{}", cmd.command_line());
+        }
+
+        // Every parameter passed as the bucket must be allowed, since the CLI may use any of them.
+        let duplicated = use_aws! {{
+            "service_name": "s3api",
+            "operation_name": "put-object",
+            "parameters": { "Bucket": "team-scratch-1", "bucket": "tz-prod", "Key": "a.txt" },
+            "region": "us-west-2",
+            "label": ""
+        }};
+        assert_eq!(duplicated.eval_perm(&os, &agent), PermissionEvalResult::Ask);
+
+        // Deny rules apply even when the tool is trusted, other rules don't.
+        agent.allowed_tools.insert("use_aws".to_string());
+        let res = call("lambda", "delete-function", None, "us-west-2", None).eval_perm(&os, &agent);
+        assert_eq!(res, PermissionEvalResult::Allow);
+        let res = call("ec2", "run-instances", None, "us-east-1", Some("prod")).eval_perm(&os, &agent);
+        assert!(matches!(res, PermissionEvalResult::Deny(_)));
+    }
 }
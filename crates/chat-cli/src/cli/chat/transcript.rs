@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use chrono::{
+    DateTime,
+    FixedOffset,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::conversation::ConversationState;
+use super::message::{
+    ToolUseResultBlock,
+    UserMessageContent,
+};
+use super::util::truncate_safe;
+use crate::api_client::model::{
+    ImageSource,
+    ToolResultStatus,
+};
+
+/// Tool results longer than this are truncated in exported transcripts.
+const MAX_TOOL_RESULT_BYTES: usize = 4096;
+
+const REDACTED_TOOL_RESULT: &str = "[redacted]";
+
+/// Format of a conversation transcript written by `/export`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TranscriptFormat {
+    /// Markdown, with tool results collapsed (default)
+    #[default]
+    Md,
+    /// A self-contained HTML page
+    Html,
+    /// Newline-delimited JSON, one entry per line
+    Jsonl,
+}
+
+impl TranscriptFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Md => "md",
+            Self::Html => "html",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Options for [render_transcript].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranscriptOptions {
+    pub format: TranscriptFormat,
+    /// Replace the content of every tool result with a placeholder.
+    pub redact_tool_output: bool,
+}
+
+/// A single entry of an exported transcript, in conversation order.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+enum TranscriptEntry<'a> {
+    /// Summary of the conversation created by `/compact`.
+    Summary {
+        text: &'a str,
+    },
+    Prompt {
+        text: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timestamp: Option<DateTime<FixedOffset>>,
+    },
+    /// An image attached to a prompt. Only a reference is exported, never the image data.
+    Image {
+        format: String,
+        size: usize,
+    },
+    AssistantText {
+        text: &'a str,
+    },
+    ToolUse {
+        tool_use_id: &'a str,
+        name: &'a str,
+        input: &'a serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<&'a str>,
+        status: &'a ToolResultStatus,
+        content: String,
+        truncated: bool,
+    },
+}
+
+/// Renders the history of `conversation` as a human readable transcript.
+pub fn render_transcript(conversation: &ConversationState, options: TranscriptOptions) -> String {
+    let entries = transcript_entries(conversation, options.redact_tool_output);
+    match options.format {
+        TranscriptFormat::Md => render_markdown(conversation, &entries),
+        TranscriptFormat::Html => render_html(conversation, &entries),
+        TranscriptFormat::Jsonl => entries
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .map(|line| format!("{line}\n"))
+            .collect(),
+    }
+}
+
+fn transcript_entries(conversation: &ConversationState, redact_tool_output: bool) -> Vec<TranscriptEntry<'_>> {
+    let mut entries = Vec::new();
+    if let Some(summary) = conversation.latest_summary() {
+        entries.push(TranscriptEntry::Summary { text: summary });
+    }
+
+    let mut tool_names = HashMap::new();
+    for entry in conversation.history() {
+        let user = entry.user();
+        let tool_use_results = match user.content() {
+            UserMessageContent::Prompt { .. } => None,
+            UserMessageContent::CancelledToolUses { tool_use_results, .. }
+            | UserMessageContent::ToolUseResults { tool_use_results } => Some(tool_use_results),
+        };
+        for result in tool_use_results.into_iter().flatten() {
+            let (content, truncated) = match redact_tool_output {
+                true => (REDACTED_TOOL_RESULT.to_string(), false),
+                false => {
+                    let content = tool_result_text(&result.content);
+                    let truncated = truncate_safe(&content, MAX_TOOL_RESULT_BYTES);
+                    match truncated.len() < content.len() {
+                        true => (truncated.to_string(), true),
+                        false => (content, false),
+                    }
+                },
+            };
+            entries.push(TranscriptEntry::ToolResult {
+                tool_use_id: &result.tool_use_id,
+                name: tool_names.get(result.tool_use_id.as_str()).copied(),
+                status: &result.status,
+                content,
+                truncated,
+            });
+        }
+        if let Some(prompt) = user.prompt() {
+            entries.push(TranscriptEntry::Prompt {
+                text: prompt,
+                timestamp: user.timestamp,
+            });
+        }
+        for image in user.images.iter().flatten() {
+            entries.push(TranscriptEntry::Image {
+                format: format!("{:?}", image.format).to_lowercase(),
+                size: match &image.source {
+                    ImageSource::Bytes(bytes) => bytes.len(),
+                    _ => 0,
+                },
+            });
+        }
+
+        let assistant = entry.assistant();
+        if !assistant.content().is_empty() {
+            entries.push(TranscriptEntry::AssistantText {
+                text: assistant.content(),
+            });
+        }
+        for tool_use in assistant.tool_uses().into_iter().flatten() {
+            tool_names.insert(tool_use.id.as_str(), tool_use.name.as_str());
+            entries.push(TranscriptEntry::ToolUse {
+                tool_use_id: &tool_use.id,
+                name: &tool_use.name,
+                input: &tool_use.args,
+            });
+        }
+    }
+
+    entries
+}
+
+fn tool_result_text(content: &[ToolUseResultBlock]) -> String {
+    content
+        .iter()
+        .map(|block| match block {
+            ToolUseResultBlock::Text(text) => text.clone(),
+            ToolUseResultBlock::Json(json) => serde_json::to_string_pretty(json).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn status_label(status: &ToolResultStatus) -> &'static str {
+    match status {
+        ToolResultStatus::Success => "success",
+        ToolResultStatus::Error => "error",
+    }
+}
+
+/// Wraps `content` in a markdown code fence that is longer than any backtick run inside it.
+fn code_fence(content: &str, lang: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{fence}{lang}\n{}\n{fence}\n", content.trim_end_matches('\n'))
+}
+
+fn render_markdown(conversation: &ConversationState, entries: &[TranscriptEntry<'_>]) -> String {
+    let mut out = format!("# Conversation {}\n\n", conversation.conversation_id());
+    if let Some(model) = conversation.model_info.as_ref() {
+        out.push_str(&format!("Model: `{}`\n\n", model.model_id));
+    }
+
+    for entry in entries {
+        match entry {
+            TranscriptEntry::Summary { text } => {
+                out.push_str(&format!("## Summary\n\n{text}\n\n"));
+            },
+            TranscriptEntry::Prompt { text, timestamp } => {
+                out.push_str("## User");
+                if let Some(timestamp) = timestamp {
+                    out.push_str(&format!(" ({})", timestamp.format("%Y-%m-%d %H:%M:%S")));
+                }
+                out.push_str(&format!("\n\n{text}\n\n"));
+            },
+            TranscriptEntry::Image { format, size } => {
+                out.push_str(&format!("_[Image: {format}, {size} bytes]_\n\n"));
+            },
+            TranscriptEntry::AssistantText { text } => {
+                out.push_str(&format!("## Assistant\n\n{text}\n\n"));
+            },
+            TranscriptEntry::ToolUse { name, input, .. } => {
+                let input = serde_json::to_string_pretty(input).unwrap_or_default();
+                out.push_str(&format!("### Tool use: `{name}`\n\n{}\n", code_fence(&input, "json")));
+            },
+            TranscriptEntry::ToolResult {
+                name,
+                status,
+                content,
+                truncated,
+                ..
+            } => {
+                out.push_str(&format!(
+                    "<details>\n<summary>Tool result: {} ({})</summary>\n\n{}",
+                    name.unwrap_or("unknown"),
+                    status_label(status),
+                    code_fence(content, "")
+                ));
+                if *truncated {
+                    out.push_str("\n_Output truncated._\n");
+                }
+                out.push_str("\n</details>\n\n");
+            },
+        }
+    }
+
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; max-width: 960px; margin: auto; padding: 1em; }
+.entry { margin: 1em 0; padding: 0.5em 1em; border-radius: 6px; }
+.user { background: #eef4ff; }
+.assistant { background: #f6f6f6; }
+.summary { background: #fff8e1; }
+.text { white-space: pre-wrap; }
+pre { background: #272822; color: #f8f8f2; padding: 0.75em; overflow-x: auto; }
+.error summary { color: #b00020; }";
+
+fn render_html(conversation: &ConversationState, entries: &[TranscriptEntry<'_>]) -> String {
+    let title = escape_html(&format!("Conversation {}", conversation.conversation_id()));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{HTML_STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    if let Some(model) = conversation.model_info.as_ref() {
+        out.push_str(&format!(
+            "<p>Model: <code>{}</code></p>\n",
+            escape_html(&model.model_id)
+        ));
+    }
+
+    for entry in entries {
+        match entry {
+            TranscriptEntry::Summary { text } => {
+                out.push_str(&format!(
+                    "<div class=\"entry summary\"><h2>Summary</h2><div class=\"text\">{}</div></div>\n",
+                    escape_html(text)
+                ));
+            },
+            TranscriptEntry::Prompt { text, timestamp } => {
+                let timestamp = timestamp
+                    .map(|t| format!(" <small>{}</small>", t.format("%Y-%m-%d %H:%M:%S")))
+                    .unwrap_or_default();
+                out.push_str(&format!(
+                    "<div class=\"entry user\"><h2>User{timestamp}</h2><div class=\"text\">{}</div></div>\n",
+                    escape_html(text)
+                ));
+            },
+            TranscriptEntry::Image { format, size } => {
+                out.push_str(&format!(
+                    "<p><em>[Image: {}, {size} bytes]</em></p>\n",
+                    escape_html(format)
+                ));
+            },
+            TranscriptEntry::AssistantText { text } => {
+                out.push_str(&format!(
+                    "<div class=\"entry assistant\"><h2>Assistant</h2><div class=\"text\">{}</div></div>\n",
+                    escape_html(text)
+                ));
+            },
+            TranscriptEntry::ToolUse { name, input, .. } => {
+                let input = serde_json::to_string_pretty(input).unwrap_or_default();
+                out.push_str(&format!(
+                    "<div class=\"entry assistant\"><h3>Tool use: <code>{}</code></h3><pre>{}</pre></div>\n",
+                    escape_html(name),
+                    escape_html(&input)
+                ));
+            },
+            TranscriptEntry::ToolResult {
+                name,
+                status,
+                content,
+                truncated,
+                ..
+            } => {
+                out.push_str(&format!(
+                    "<details class=\"entry {}\"><summary>Tool result: {} ({})</summary><pre>{}</pre>{}</details>\n",
+                    status_label(status),
+                    escape_html(name.unwrap_or("unknown")),
+                    status_label(status),
+                    escape_html(content),
+                    if *truncated {
+                        "<p><em>Output truncated.</em></p>"
+                    } else {
+                        ""
+                    }
+                ));
+            },
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::agent::Agents;
+    use crate::cli::chat::message::{
+        AssistantMessage,
+        AssistantToolUse,
+        ToolUseResult,
+    };
+    use crate::cli::chat::tool_manager::ToolManager;
+    use crate::os::Os;
+
+    async fn conversation_with_tool_use(os: &mut Os) -> ConversationState {
+        let mut tool_manager = ToolManager::default();
+        let tool_config = tool_manager.load_tools(os, &mut vec![]).await.unwrap();
+        let mut conversation = ConversationState::new(
+            "fake_conv_id",
+            Agents::default(),
+            tool_config,
+            tool_manager,
+            None,
+            os,
+            false,
+        )
+        .await;
+
+        conversation
+            .set_next_user_message("What does <notes.txt> say?".to_string())
+            .await;
+        conversation.push_assistant_message(
+            os,
+            AssistantMessage::new_tool_use(None, "Let me read it.".to_string(), vec![AssistantToolUse {
+                id: "tool_1".to_string(),
+                name: "fs_read".to_string(),
+                args: serde_json::json!({ "path": "notes.txt" }),
+                ..Default::default()
+            }]),
+            None,
+        );
+        conversation.add_tool_results(vec![ToolUseResult {
+            tool_use_id: "tool_1".to_string(),
+            content: vec![ToolUseResultBlock::Text("secret contents".to_string())],
+            status: ToolResultStatus::Success,
+        }]);
+        conversation.push_assistant_message(
+            os,
+            AssistantMessage::new_response(None, "It says hello.".to_string()),
+            None,
+        );
+        conversation
+    }
+
+    #[tokio::test]
+    async fn test_render_transcript() {
+        let mut os = Os::new().await.unwrap();
+        let conversation = conversation_with_tool_use(&mut os).await;
+
+        let md = render_transcript(&conversation, TranscriptOptions::default());
+        assert!(md.contains("## User"));
+        assert!(md.contains("What does <notes.txt> say?"));
+        assert!(md.contains("### Tool use: `fs_read`"));
+        assert!(md.contains("<summary>Tool result: fs_read (success)</summary>"));
+        assert!(md.contains("secret contents"));
+        assert!(md.contains("It says hello."));
+
+        let html = render_transcript(&conversation, TranscriptOptions {
+            format: TranscriptFormat::Html,
+            redact_tool_output: true,
+        });
+        assert!(html.contains("What does &lt;notes.txt&gt; say?"));
+        assert!(!html.contains("secret contents"));
+        assert!(html.contains(REDACTED_TOOL_RESULT));
+
+        let jsonl = render_transcript(&conversation, TranscriptOptions {
+            format: TranscriptFormat::Jsonl,
+            redact_tool_output: false,
+        });
+        let types = jsonl
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["type"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![
+            "prompt",
+            "assistantText",
+            "toolUse",
+            "toolResult",
+            "assistantText"
+        ]);
+    }
+
+    #[test]
+    fn test_code_fence_is_longer_than_content_backticks() {
+        assert_eq!(code_fence("let a = 1;", "rust"), "```rust\nlet a = 1;\n```\n");
+        assert_eq!(code_fence("```\nnested\n```", ""), "````\n```\nnested\n```\n````\n");
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<script>alert('x' & \"y\")</script>"),
+            "&lt;script&gt;alert(&#39;x&#39; &amp; &quot;y&quot;)&lt;/script&gt;"
+        );
+    }
+}
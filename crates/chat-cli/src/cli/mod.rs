@@ -132,7 +132,7 @@ impl RootSubcommand {
     }
 
     pub fn requires_auth(&self) -> bool {
-        matches!(self, Self::Chat(_) | Self::Profile)
+        matches!(self, Self::Chat(ChatArgs { subcommand: None, .. }) | Self::Profile)
     }
 
     pub async fn execute(self, os: &mut Os) -> Result<ExitCode> {
@@ -343,6 +343,12 @@ mod test {
     };
 
     use super::*;
+    use crate::cli::chat::ChatSubcommand;
+    use crate::cli::chat::output_format::ChatOutputFormat;
+    use crate::cli::chat::sessions::{
+        ListArgs,
+        SessionsSubcommand,
+    };
     use crate::util::CHAT_BINARY_NAME;
     use crate::util::test::assert_parse;
 
@@ -375,13 +381,19 @@ mod test {
         assert_eq!(Cli::parse_from([CHAT_BINARY_NAME, "chat", "-vv"]), Cli {
             subcommand: Some(RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })),
             verbose: 2,
             help_all: false,
@@ -415,13 +427,19 @@ mod test {
             ["chat", "--profile", "my-profile"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: Some("my-profile".to_string()),
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -432,13 +450,19 @@ mod test {
             ["chat", "--profile", "my-profile", "Hello"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: Some("Hello".to_string()),
                 agent: Some("my-profile".to_string()),
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -449,13 +473,19 @@ mod test {
             ["chat", "--profile", "my-profile", "--trust-all-tools"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: Some("my-profile".to_string()),
+                params: vec![],
                 model: None,
                 trust_all_tools: true,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -466,26 +496,38 @@ mod test {
             ["chat", "--no-interactive", "--resume"],
             RootSubcommand::Chat(ChatArgs {
                 resume: true,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: true,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
         assert_parse!(
             ["chat", "--non-interactive", "-r"],
             RootSubcommand::Chat(ChatArgs {
                 resume: true,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: true,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -496,13 +538,19 @@ mod test {
             ["chat", "--trust-all-tools"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: true,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -513,13 +561,19 @@ mod test {
             ["chat", "--trust-tools="],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["".to_string()]),
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -530,13 +584,19 @@ mod test {
             ["chat", "--trust-tools=fs_read,fs_write"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["fs_read".to_string(), "fs_write".to_string()]),
                 no_interactive: false,
                 wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
     }
@@ -547,39 +607,216 @@ mod test {
             ["chat", "-w", "never"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: Some(Never),
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
         assert_parse!(
             ["chat", "--wrap", "always"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: Some(Always),
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
             })
         );
         assert_parse!(
             ["chat", "--wrap", "auto"],
             RootSubcommand::Chat(ChatArgs {
                 resume: false,
+                resume_id: None,
+                resume_picker: false,
                 input: None,
                 agent: None,
+                params: vec![],
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
                 no_interactive: false,
                 wrap: Some(Auto),
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_output_format() {
+        assert_parse!(
+            ["chat", "--no-interactive", "--output-format", "stream-json"],
+            RootSubcommand::Chat(ChatArgs {
+                resume: false,
+                resume_id: None,
+                resume_picker: false,
+                input: None,
+                agent: None,
+                params: vec![],
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                no_interactive: true,
+                wrap: None,
+                output_format: Some(ChatOutputFormat::StreamJson),
+                serve_stdio: false,
+                subcommand: None,
+            })
+        );
+        assert!(
+            Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--output-format", "json"]).is_err(),
+            "--output-format requires --no-interactive"
+        );
+    }
+
+    #[test]
+    fn test_chat_serve_stdio() {
+        assert_parse!(
+            ["chat", "--serve-stdio", "--agent", "my-agent"],
+            RootSubcommand::Chat(ChatArgs {
+                resume: false,
+                resume_id: None,
+                resume_picker: false,
+                input: None,
+                agent: Some("my-agent".to_string()),
+                params: vec![],
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                no_interactive: false,
+                wrap: None,
+                output_format: None,
+                serve_stdio: true,
+                subcommand: None,
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--serve-stdio", "hello"]).is_err());
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--serve-stdio", "--no-interactive"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                CHAT_BINARY_NAME,
+                "chat",
+                "--no-interactive",
+                "--output-format",
+                "json-rpc"
+            ])
+            .is_err(),
+            "json-rpc output is only used by --serve-stdio"
+        );
+    }
+
+    #[test]
+    fn test_chat_with_params() {
+        assert_parse!(
+            ["chat", "--agent", "reviewer", "--param", "path=src", "--param", "query=a=b"],
+            RootSubcommand::Chat(ChatArgs {
+                resume: false,
+                resume_id: None,
+                resume_picker: false,
+                input: None,
+                agent: Some("reviewer".to_string()),
+                params: vec![
+                    ("path".to_string(), "src".to_string()),
+                    ("query".to_string(), "a=b".to_string())
+                ],
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                no_interactive: false,
+                wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--param", "path"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_resume_session() {
+        assert_parse!(
+            ["chat", "--resume-id", "1234", "hello"],
+            RootSubcommand::Chat(ChatArgs {
+                resume: false,
+                resume_id: Some("1234".to_string()),
+                resume_picker: false,
+                input: Some("hello".to_string()),
+                agent: None,
+                params: vec![],
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                no_interactive: false,
+                wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
+            })
+        );
+        assert_parse!(
+            ["chat", "--resume", "hello"],
+            RootSubcommand::Chat(ChatArgs {
+                resume: true,
+                resume_id: None,
+                resume_picker: false,
+                input: Some("hello".to_string()),
+                agent: None,
+                params: vec![],
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                no_interactive: false,
+                wrap: None,
+                output_format: None,
+                serve_stdio: false,
+                subcommand: None,
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--resume", "--resume-picker"]).is_err());
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--resume", "--resume-id", "1234"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_sessions_subcommand() {
+        assert_parse!(
+            ["chat", "sessions", "list", "--all"],
+            RootSubcommand::Chat(ChatArgs {
+                subcommand: Some(ChatSubcommand::Sessions(SessionsSubcommand::List(ListArgs { all: true }))),
+                ..Default::default()
+            })
+        );
+        assert_parse!(
+            ["chat", "sessions", "prune", "--older-than-days", "7"],
+            RootSubcommand::Chat(ChatArgs {
+                subcommand: Some(ChatSubcommand::Sessions(SessionsSubcommand::Prune { older_than_days: 7 })),
+                ..Default::default()
+            })
+        );
+        assert_parse!(
+            ["chat", "--", "sessions"],
+            RootSubcommand::Chat(ChatArgs {
+                input: Some("sessions".to_string()),
+                ..Default::default()
             })
         );
     }
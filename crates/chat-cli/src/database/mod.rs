@@ -71,7 +71,8 @@ const MIGRATIONS: &[Migration] = migrations![
     "004_state_table",
     "005_auth_table",
     "006_make_state_blob",
-    "007_conversations_table"
+    "007_conversations_table",
+    "008_sessions_table"
 ];
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -97,6 +98,39 @@ impl From<amzn_codewhisperer_client::types::Profile> for AuthProfile {
     }
 }
 
+/// Metadata of a chat session stored in the sessions table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// The conversation id.
+    pub id: String,
+    /// The directory the session was started from.
+    pub path: String,
+    /// The first prompt of the conversation, or its summary if the history was compacted.
+    pub title: Option<String>,
+    pub agent: Option<String>,
+    pub model: Option<String>,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    /// Unix timestamp in seconds.
+    pub updated_at: i64,
+}
+
+impl SessionInfo {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            title: row.get(2)?,
+            agent: row.get(3)?,
+            model: row.get(4)?,
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+        })
+    }
+}
+
+const SESSION_INFO_COLUMNS: &str = "id, path, title, agent, model, created_at, updated_at";
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct Secret(pub String);
@@ -157,8 +191,9 @@ impl<T> From<PoisonError<T>> for DatabaseError {
 pub enum Table {
     /// The state table contains persistent application state.
     State,
-    /// The conversations tables contains user chat conversations.
-    Conversations,
+    /// The sessions table contains every chat conversation along with its metadata. Supersedes
+    /// the conversations table, which only stored the last conversation per directory.
+    Sessions,
     /// The auth table contains SSO and Builder ID credentials.
     Auth,
 }
@@ -167,7 +202,7 @@ impl std::fmt::Display for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Table::State => write!(f, "state"),
-            Table::Conversations => write!(f, "conversations"),
+            Table::Sessions => write!(f, "sessions"),
             Table::Auth => write!(f, "auth_kv"),
         }
     }
@@ -369,9 +404,9 @@ impl Database {
     //     self.delete_entry(Table::State, LAST_USED_MODEL_ID)
     // }
 
-    /// Get a chat conversation given a path to the conversation.
-    pub fn get_conversation_by_path(
-        &mut self,
+    /// Get the most recently updated chat session started from `path`.
+    pub fn get_latest_session_by_path(
+        &self,
         path: impl AsRef<Path>,
     ) -> Result<Option<ConversationState>, DatabaseError> {
         // We would need to encode this to support non utf8 paths.
@@ -380,22 +415,125 @@ impl Database {
             None => return Ok(None),
         };
 
-        self.get_json_entry(Table::Conversations, path)
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT value FROM {} WHERE path = ?1 ORDER BY updated_at DESC, rowid DESC LIMIT 1",
+            Table::Sessions
+        ))?;
+        match stmt.query_row([path], |row| row.get::<_, String>(0)) {
+            Ok(value) => Ok(Some(serde_json::from_str(&value)?)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Get a chat session given its id, or a prefix of the id that matches a single session.
+    pub fn get_session(&self, id: &str) -> Result<Option<ConversationState>, DatabaseError> {
+        let Some(info) = self.find_session(id)? else {
+            return Ok(None);
+        };
+        self.get_json_entry_by_id(Table::Sessions, &info.id)
+    }
+
+    /// Get the metadata of a chat session given its id, or a prefix of the id that matches a
+    /// single session.
+    pub fn find_session(&self, id: &str) -> Result<Option<SessionInfo>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {SESSION_INFO_COLUMNS} FROM {} WHERE id = ?1 OR substr(id, 1, length(?1)) = ?1 ORDER BY (id = ?1) DESC LIMIT 2",
+            Table::Sessions
+        ))?;
+        let sessions = stmt
+            .query_map([id], SessionInfo::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Ambiguous prefixes don't match anything.
+        Ok(match sessions.as_slice() {
+            [session] => Some(session.clone()),
+            _ => sessions.into_iter().find(|s| s.id == id),
+        })
     }
 
-    /// Set a chat conversation given a path to the conversation.
-    pub fn set_conversation_by_path(
-        &mut self,
-        path: impl AsRef<Path>,
-        state: &ConversationState,
-    ) -> Result<usize, DatabaseError> {
+    /// List chat sessions, most recently updated first. Only sessions started from `path` are
+    /// returned if given.
+    pub fn list_sessions(&self, path: Option<&Path>) -> Result<Vec<SessionInfo>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let sessions = match path {
+            Some(path) => {
+                let Some(path) = path.to_str() else {
+                    return Ok(Vec::new());
+                };
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {SESSION_INFO_COLUMNS} FROM {} WHERE path = ?1 ORDER BY updated_at DESC, rowid DESC",
+                    Table::Sessions
+                ))?;
+                stmt.query_map([path], SessionInfo::from_row)?
+                    .collect::<Result<Vec<_>, _>>()?
+            },
+            None => {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {SESSION_INFO_COLUMNS} FROM {} ORDER BY updated_at DESC, rowid DESC",
+                    Table::Sessions
+                ))?;
+                stmt.query_map([], SessionInfo::from_row)?
+                    .collect::<Result<Vec<_>, _>>()?
+            },
+        };
+
+        Ok(sessions)
+    }
+
+    /// Create or update the chat session for `state`, started from `path`.
+    pub fn set_session(&self, path: impl AsRef<Path>, state: &ConversationState) -> Result<usize, DatabaseError> {
         // We would need to encode this to support non utf8 paths.
         let path = match path.as_ref().to_str() {
             Some(path) => path,
             None => return Ok(0),
         };
 
-        self.set_json_entry(Table::Conversations, path, state)
+        let now = chrono::Utc::now().timestamp();
+        Ok(self.pool.get()?.execute(
+            &format!(
+                "INSERT INTO {} (id, path, title, agent, model, created_at, updated_at, value)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)
+                ON CONFLICT(id) DO UPDATE SET
+                    title = COALESCE(title, excluded.title),
+                    agent = excluded.agent,
+                    model = excluded.model,
+                    updated_at = excluded.updated_at,
+                    value = excluded.value",
+                Table::Sessions
+            ),
+            params![
+                state.conversation_id(),
+                path,
+                state.title(),
+                state.current_profile(),
+                state.model_info.as_ref().map(|m| m.model_id.as_str()),
+                now,
+                serde_json::to_string(state)?,
+            ],
+        )?)
+    }
+
+    /// Delete a chat session given its id. Returns whether a session was deleted.
+    pub fn delete_session(&self, id: &str) -> Result<bool, DatabaseError> {
+        let deleted = self
+            .pool
+            .get()?
+            .execute(&format!("DELETE FROM {} WHERE id = ?1", Table::Sessions), [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Delete every chat session that was last updated before the unix timestamp `before`.
+    /// Returns the number of deleted sessions.
+    pub fn prune_sessions(&self, before: i64) -> Result<usize, DatabaseError> {
+        Ok(self
+            .pool
+            .get()?
+            .execute(&format!("DELETE FROM {} WHERE updated_at < ?1", Table::Sessions), [
+                before,
+            ])?)
     }
 
     pub async fn get_secret(&self, key: &str) -> Result<Option<Secret>, DatabaseError> {
@@ -473,6 +611,16 @@ impl Database {
         })
     }
 
+    fn get_json_entry_by_id<T: DeserializeOwned>(&self, table: Table, id: &str) -> Result<Option<T>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!("SELECT value FROM {table} WHERE id = ?1"))?;
+        match stmt.query_row([id], |row| row.get::<_, String>(0)) {
+            Ok(value) => Ok(Some(serde_json::from_str(&value)?)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     fn set_json_entry(
         &self,
         table: Table,
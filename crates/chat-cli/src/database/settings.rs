@@ -75,10 +75,14 @@ pub enum Setting {
     ChatDefaultAgent,
     #[strum(message = "Disable automatic conversation summarization (boolean)")]
     ChatDisableAutoCompaction,
+    #[strum(message = "Strategy used to compact the conversation history (string)")]
+    ChatCompactionStrategy,
     #[strum(message = "Show conversation history hints (boolean)")]
     ChatEnableHistoryHints,
     #[strum(message = "Enable the todo list feature (boolean)")]
     EnabledTodoList,
+    #[strum(message = "Run shell commands in a persistent shell session (boolean)")]
+    EnabledPersistentShell,
 }
 
 impl AsRef<str> for Setting {
@@ -113,8 +117,10 @@ impl AsRef<str> for Setting {
             Self::ChatDisableMarkdownRendering => "chat.disableMarkdownRendering",
             Self::ChatDefaultAgent => "chat.defaultAgent",
             Self::ChatDisableAutoCompaction => "chat.disableAutoCompaction",
+            Self::ChatCompactionStrategy => "chat.compactionStrategy",
             Self::ChatEnableHistoryHints => "chat.enableHistoryHints",
             Self::EnabledTodoList => "chat.enableTodoList",
+            Self::EnabledPersistentShell => "chat.enablePersistentShell",
         }
     }
 }
@@ -159,8 +165,10 @@ impl TryFrom<&str> for Setting {
             "chat.disableMarkdownRendering" => Ok(Self::ChatDisableMarkdownRendering),
             "chat.defaultAgent" => Ok(Self::ChatDefaultAgent),
             "chat.disableAutoCompaction" => Ok(Self::ChatDisableAutoCompaction),
+            "chat.compactionStrategy" => Ok(Self::ChatCompactionStrategy),
             "chat.enableHistoryHints" => Ok(Self::ChatEnableHistoryHints),
             "chat.enableTodoList" => Ok(Self::EnabledTodoList),
+            "chat.enablePersistentShell" => Ok(Self::EnabledPersistentShell),
             _ => Err(DatabaseError::InvalidSetting(value.to_string())),
         }
     }
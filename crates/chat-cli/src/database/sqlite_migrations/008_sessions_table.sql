@@ -0,0 +1,21 @@
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    title TEXT,
+    agent TEXT,
+    model TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX sessions_path_updated_at ON sessions (path, updated_at);
+INSERT OR IGNORE INTO sessions (id, path, model, created_at, updated_at, value)
+SELECT
+    json_extract(value, '$.conversation_id'),
+    key,
+    json_extract(value, '$.model_info.model_id'),
+    strftime('%s', 'now'),
+    strftime('%s', 'now'),
+    value
+FROM conversations
+WHERE json_valid(value) AND json_extract(value, '$.conversation_id') IS NOT NULL;